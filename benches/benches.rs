@@ -1,19 +1,22 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
 use num_traits::clamp;
 use rand::Rng;
 use ssimulacra2::{
-    compute_frame_ssimulacra2, Blur, ColorPrimaries, Frame, MatrixCoefficients, Plane,
+    compute_frame_ssimulacra2, Blur, BoxDownscaler, ColorPrimaries, CubeRoot, Downscaler, Frame,
+    LinearRgb, MatrixCoefficients, Plane, Reference, Rgb, Srgb8Image, Ssimulacra2,
     TransferCharacteristic, Yuv, YuvConfig,
 };
 
 fn make_yuv(
+    y_dims: (usize, usize),
     ss: (u8, u8),
     full_range: bool,
     mc: MatrixCoefficients,
     tc: TransferCharacteristic,
     cp: ColorPrimaries,
 ) -> Yuv<u8> {
-    let y_dims = (320usize, 240usize);
     let uv_dims = (y_dims.0 >> ss.0, y_dims.1 >> ss.1);
     let mut data: Frame<u8> = Frame {
         planes: [
@@ -82,6 +85,7 @@ fn distort_yuv(input: &Yuv<u8>) -> Yuv<u8> {
 fn bench_ssimulacra2(c: &mut Criterion) {
     c.bench_function("ssimulacra2", |b| {
         let input = make_yuv(
+            (320, 240),
             (0, 0),
             true,
             MatrixCoefficients::BT709,
@@ -106,7 +110,7 @@ fn read_image(path: &str) -> ([Vec<f32>; 3], usize, usize) {
 
     // Convert ImageBuffer to [Vec<f32>; 3]
     let mut img_vec = [Vec::new(), Vec::new(), Vec::new()];
-    for (_i, pixel) in img.pixels().enumerate() {
+    for pixel in img.pixels() {
         img_vec[0].push(pixel[0] as f32);
         img_vec[1].push(pixel[1] as f32);
         img_vec[2].push(pixel[2] as f32);
@@ -126,5 +130,199 @@ fn bench_blur(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_ssimulacra2, bench_blur);
+// Blurring tall, narrow planes, e.g. of sprite sheets or scanned strips.
+fn bench_blur_narrow(c: &mut Criterion) {
+    let mut group = c.benchmark_group("blur_narrow");
+    let height = 16384;
+    for width in [9, 40] {
+        let plane: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 13) % 31) as f32 / 31.0)
+            .collect();
+        let image = [plane.clone(), plane.clone(), plane];
+        group.throughput(Throughput::Elements((width * height) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(width), &image, |b, image| {
+            let mut blur = Blur::new(width, height);
+            b.iter(|| blur.blur(black_box(image)));
+        });
+    }
+
+    group.finish();
+}
+
+// Resolutions of the per-stage benchmarks.
+const SIZES: [(&str, usize, usize); 2] = [("1080p", 1920, 1080), ("4k", 3840, 2160)];
+
+fn make_pair(width: usize, height: usize) -> (Yuv<u8>, Yuv<u8>) {
+    let source = make_yuv(
+        (width, height),
+        (1, 1),
+        false,
+        MatrixCoefficients::BT709,
+        TransferCharacteristic::BT1886,
+        ColorPrimaries::BT709,
+    );
+    let distorted = distort_yuv(&source);
+    (source, distorted)
+}
+
+// Benchmarks every stage of scoring on its own, in the order they run.
+fn bench_stages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stages");
+    group.sample_size(10);
+
+    for (name, width, height) in SIZES {
+        let (source, distorted) = make_pair(width, height);
+        let source_rgb = LinearRgb::try_from(&source).unwrap();
+        let distorted_rgb = LinearRgb::try_from(&distorted).unwrap();
+        let planes = [0usize, 1, 2].map(|c| source_rgb.data().iter().map(|p| p[c]).collect());
+        group.throughput(Throughput::Elements((width * height) as u64));
+
+        // Conversion of the decoded YUV input to linear RGB.
+        group.bench_with_input(
+            BenchmarkId::new("to_linear_rgb", name),
+            &source,
+            |b, yuv| {
+                b.iter(|| LinearRgb::try_from(black_box(yuv)).unwrap());
+            },
+        );
+        // Downscaling to the next scale.
+        group.bench_with_input(
+            BenchmarkId::new("downscale", name),
+            &source_rgb,
+            |b, img| {
+                b.iter(|| BoxDownscaler.downscale(black_box(img)));
+            },
+        );
+        // Blurring the three planes of one scale.
+        group.bench_with_input(BenchmarkId::new("blur", name), &planes, |b, planes| {
+            let mut blur = Blur::new(width, height);
+            b.iter(|| blur.blur(black_box(planes)));
+        });
+        // XYB conversion and blurring of the source at every scale.
+        group.bench_with_input(
+            BenchmarkId::new("reference", name),
+            &source_rgb,
+            |b, img| {
+                b.iter_batched(
+                    || img.clone(),
+                    |img| Reference::new(img).unwrap(),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        // XYB conversion, blurring and error statistics of the distorted image.
+        let reference = Reference::new(source_rgb.clone()).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("compare", name),
+            &distorted_rgb,
+            |b, img| {
+                b.iter_batched(
+                    || img.clone(),
+                    |img| reference.compare(img).unwrap(),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        // Four candidates against the same reference, in parallel.
+        group.bench_with_input(
+            BenchmarkId::new("compare_many_4", name),
+            &distorted_rgb,
+            |b, img| {
+                b.iter_batched(
+                    || vec![img.clone(); 4],
+                    |imgs| reference.compare_many(imgs),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        // All of the above.
+        group.bench_with_input(
+            BenchmarkId::new("full", name),
+            &(source, distorted),
+            |b, (source, distorted)| {
+                b.iter(|| {
+                    compute_frame_ssimulacra2(black_box(source), black_box(distorted)).unwrap()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+// Scoring with each method of computing the cube roots of the XYB conversion.
+fn bench_cube_root(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cube_root");
+    group.sample_size(10);
+
+    let (name, width, height) = SIZES[0];
+    let (source, distorted) = make_pair(width, height);
+    let source = LinearRgb::try_from(&source).unwrap();
+    let distorted = LinearRgb::try_from(&distorted).unwrap();
+    group.throughput(Throughput::Elements((width * height) as u64));
+    for (method, cube_root) in [
+        ("exact", CubeRoot::Exact),
+        ("approximate", CubeRoot::Approximate),
+        ("table", CubeRoot::Table),
+    ] {
+        let mut session = Ssimulacra2::new(width, height)
+            .unwrap()
+            .with_cube_root(cube_root);
+        group.bench_function(BenchmarkId::new(method, name), |b| {
+            b.iter(|| {
+                session
+                    .score_borrowed(black_box(&source), black_box(&distorted))
+                    .unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// Conversion of an 8-bit sRGB image, as for screenshots, via `Rgb` and via
+// the lookup table of `Srgb8Image`.
+fn bench_srgb8(c: &mut Criterion) {
+    let mut group = c.benchmark_group("srgb8");
+    let (width, height) = (256, 256);
+    let mut rng = rand::thread_rng();
+    let bytes: Vec<u8> = (0..width * height * 3).map(|_| rng.gen()).collect();
+    group.throughput(Throughput::Elements((width * height) as u64));
+
+    group.bench_function("rgb", |b| {
+        b.iter(|| {
+            let data = black_box(&bytes)
+                .chunks_exact(3)
+                .map(|pix| [pix[0], pix[1], pix[2]].map(|v| f32::from(v) / 255.0))
+                .collect();
+            let rgb = Rgb::new(
+                data,
+                width,
+                height,
+                TransferCharacteristic::SRGB,
+                ColorPrimaries::BT709,
+            )
+            .unwrap();
+            LinearRgb::try_from(rgb).unwrap()
+        });
+    });
+    group.bench_function("lut", |b| {
+        b.iter(|| {
+            LinearRgb::try_from(Srgb8Image::from_rgb(black_box(&bytes), width, height).unwrap())
+                .unwrap()
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_ssimulacra2,
+    bench_blur,
+    bench_blur_narrow,
+    bench_stages,
+    bench_cube_root,
+    bench_srgb8
+);
 criterion_main!(benches);
@@ -0,0 +1,221 @@
+/*
+ * SSIMULACRA2 filter for libavfilter, built on the C API of the ssimulacra2
+ * crate. See README.md in this directory for how to add it to FFmpeg.
+ *
+ * The first input is the distorted ("main") video, the second input the
+ * reference, as with the ssim and psnr filters. The score of every frame is
+ * attached to the main frame as lavfi.ssimulacra2.score metadata, and a
+ * summary is logged when the filter is closed.
+ */
+
+#include <ssimulacra2.h>
+
+#include "libavutil/opt.h"
+#include "libavutil/pixdesc.h"
+#include "avfilter.h"
+#include "framesync.h"
+#include "internal.h"
+#include "video.h"
+
+typedef struct SSIMULACRA2Context {
+    const AVClass *class;
+    FFFrameSync fs;
+    Ssimulacra2Context *ctx;
+} SSIMULACRA2Context;
+
+static const AVOption ssimulacra2_options[] = {
+    { NULL }
+};
+
+FRAMESYNC_DEFINE_CLASS(ssimulacra2, SSIMULACRA2Context, fs);
+
+static const enum AVPixelFormat pix_fmts[] = {
+    AV_PIX_FMT_YUV420P, AV_PIX_FMT_YUV422P, AV_PIX_FMT_YUV444P,
+    AV_PIX_FMT_YUVJ420P, AV_PIX_FMT_YUVJ422P, AV_PIX_FMT_YUVJ444P,
+    AV_PIX_FMT_YUV420P10, AV_PIX_FMT_YUV422P10, AV_PIX_FMT_YUV444P10,
+    AV_PIX_FMT_YUV420P12, AV_PIX_FMT_YUV422P12, AV_PIX_FMT_YUV444P12,
+    AV_PIX_FMT_NONE
+};
+
+static Ssimulacra2Context *create_context(const AVFrame *frame)
+{
+    const AVPixFmtDescriptor *desc = av_pix_fmt_desc_get(frame->format);
+    /* AVColorSpace, AVColorTransferCharacteristic and AVColorPrimaries use
+     * the code points of ITU-T H.273, unspecified values default to BT.709. */
+    Ssimulacra2Config config = {
+        .bit_depth = desc->comp[0].depth,
+        .subsampling_x = desc->log2_chroma_w,
+        .subsampling_y = desc->log2_chroma_h,
+        .full_range = frame->color_range == AVCOL_RANGE_JPEG ||
+                      frame->format == AV_PIX_FMT_YUVJ420P ||
+                      frame->format == AV_PIX_FMT_YUVJ422P ||
+                      frame->format == AV_PIX_FMT_YUVJ444P,
+        .matrix_coefficients = frame->colorspace == AVCOL_SPC_UNSPECIFIED ?
+                               AVCOL_SPC_BT709 : frame->colorspace,
+        .transfer_characteristics = frame->color_trc == AVCOL_TRC_UNSPECIFIED ?
+                                    AVCOL_TRC_BT709 : frame->color_trc,
+        .color_primaries = frame->color_primaries == AVCOL_PRI_UNSPECIFIED ?
+                           AVCOL_PRI_BT709 : frame->color_primaries,
+    };
+
+    return ssimulacra2_new(frame->width, frame->height, &config);
+}
+
+static Ssimulacra2Planes frame_planes(const AVFrame *frame)
+{
+    Ssimulacra2Planes planes;
+
+    for (int i = 0; i < 3; i++) {
+        planes.data[i] = frame->data[i];
+        planes.stride[i] = frame->linesize[i];
+    }
+
+    return planes;
+}
+
+static int do_ssimulacra2(FFFrameSync *fs)
+{
+    AVFilterContext *ctx = fs->parent;
+    SSIMULACRA2Context *s = ctx->priv;
+    AVFrame *main, *ref;
+    Ssimulacra2Planes main_planes, ref_planes;
+    Ssimulacra2Status status;
+    double score;
+    char value[32];
+    int ret;
+
+    ret = ff_framesync_dualinput_get(fs, &main, &ref);
+    if (ret < 0)
+        return ret;
+    if (ctx->is_disabled || !ref)
+        return ff_filter_frame(ctx->outputs[0], main);
+
+    /* The context is created from the first frame, as the color properties
+     * are not known when the links are configured. */
+    if (!s->ctx) {
+        s->ctx = create_context(ref);
+        if (!s->ctx) {
+            av_log(ctx, AV_LOG_ERROR, "Unsupported frame size or color properties.\n");
+            av_frame_free(&main);
+            return AVERROR(EINVAL);
+        }
+    }
+
+    ref_planes = frame_planes(ref);
+    main_planes = frame_planes(main);
+    status = ssimulacra2_score(s->ctx, &ref_planes, &main_planes, &score);
+    if (status != SSIMULACRA2_STATUS_OK) {
+        av_log(ctx, AV_LOG_ERROR, "Failed to score frame (status %d).\n", status);
+        av_frame_free(&main);
+        return AVERROR_EXTERNAL;
+    }
+
+    snprintf(value, sizeof(value), "%f", score);
+    av_dict_set(&main->metadata, "lavfi.ssimulacra2.score", value, 0);
+
+    return ff_filter_frame(ctx->outputs[0], main);
+}
+
+static av_cold int init(AVFilterContext *ctx)
+{
+    SSIMULACRA2Context *s = ctx->priv;
+
+    s->fs.on_event = do_ssimulacra2;
+    return 0;
+}
+
+static int config_input_ref(AVFilterLink *inlink)
+{
+    AVFilterContext *ctx = inlink->dst;
+
+    if (ctx->inputs[0]->w != ctx->inputs[1]->w ||
+        ctx->inputs[0]->h != ctx->inputs[1]->h) {
+        av_log(ctx, AV_LOG_ERROR, "Width and height of input videos must be same.\n");
+        return AVERROR(EINVAL);
+    }
+    if (ctx->inputs[0]->format != ctx->inputs[1]->format) {
+        av_log(ctx, AV_LOG_ERROR, "Inputs must be of same pixel format.\n");
+        return AVERROR(EINVAL);
+    }
+
+    return 0;
+}
+
+static int config_output(AVFilterLink *outlink)
+{
+    AVFilterContext *ctx = outlink->src;
+    SSIMULACRA2Context *s = ctx->priv;
+    AVFilterLink *mainlink = ctx->inputs[0];
+    int ret;
+
+    ret = ff_framesync_init_dualinput(&s->fs, ctx);
+    if (ret < 0)
+        return ret;
+    outlink->w = mainlink->w;
+    outlink->h = mainlink->h;
+    outlink->time_base = mainlink->time_base;
+    outlink->sample_aspect_ratio = mainlink->sample_aspect_ratio;
+    outlink->frame_rate = mainlink->frame_rate;
+
+    return ff_framesync_configure(&s->fs);
+}
+
+static int activate(AVFilterContext *ctx)
+{
+    SSIMULACRA2Context *s = ctx->priv;
+
+    return ff_framesync_activate(&s->fs);
+}
+
+static av_cold void uninit(AVFilterContext *ctx)
+{
+    SSIMULACRA2Context *s = ctx->priv;
+    Ssimulacra2Summary summary;
+
+    if (s->ctx && ssimulacra2_summary(s->ctx, &summary) == SSIMULACRA2_STATUS_OK) {
+        av_log(ctx, AV_LOG_INFO,
+               "SSIMULACRA2 mean:%f harmonic_mean:%f min:%f max:%f p5:%f frames:%zu\n",
+               summary.mean, summary.harmonic_mean, summary.min, summary.max,
+               summary.p5, summary.frames);
+    }
+
+    ssimulacra2_free(s->ctx);
+    s->ctx = NULL;
+    ff_framesync_uninit(&s->fs);
+}
+
+static const AVFilterPad ssimulacra2_inputs[] = {
+    {
+        .name         = "main",
+        .type         = AVMEDIA_TYPE_VIDEO,
+    },
+    {
+        .name         = "reference",
+        .type         = AVMEDIA_TYPE_VIDEO,
+        .config_props = config_input_ref,
+    },
+};
+
+static const AVFilterPad ssimulacra2_outputs[] = {
+    {
+        .name         = "default",
+        .type         = AVMEDIA_TYPE_VIDEO,
+        .config_props = config_output,
+    },
+};
+
+const AVFilter ff_vf_ssimulacra2 = {
+    .name          = "ssimulacra2",
+    .description   = NULL_IF_CONFIG_SMALL("Calculate the SSIMULACRA2 score between two video streams."),
+    .preinit       = ssimulacra2_framesync_preinit,
+    .init          = init,
+    .uninit        = uninit,
+    .activate      = activate,
+    .priv_size     = sizeof(SSIMULACRA2Context),
+    .priv_class    = &ssimulacra2_class,
+    FILTER_INPUTS(ssimulacra2_inputs),
+    FILTER_OUTPUTS(ssimulacra2_outputs),
+    FILTER_PIXFMTS_ARRAY(pix_fmts),
+    .flags         = AVFILTER_FLAG_SUPPORT_TIMELINE_INTERNAL |
+                     AVFILTER_FLAG_METADATA_ONLY,
+};
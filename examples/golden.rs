@@ -0,0 +1,105 @@
+//! Regenerates the golden vectors in `test_data/golden`: the scores and
+//! per-scale errors of a fixed set of image pairs, and checksums of their
+//! intermediate planes, with the exact bits of every value.
+//!
+//! Run it before and after changing the arithmetic, e.g. a SIMD loop, and
+//! `git diff test_data/golden` shows whether the change is bit-exact:
+//!
+//! ```sh
+//! cargo run --release --example golden --features dump
+//! ```
+//!
+//! The vectors are only bit-exact for the target they were generated on,
+//! which is recorded in their header.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ssimulacra2::{plane_checksums, ColorPrimaries, Rgb, Ssimulacra2, TransferCharacteristic};
+
+// The pinned corpus, as pairs of source and distorted images in `test_data`.
+// The small images have odd sizes and few scales, to cover the edges of the
+// blur and of the downscaling. No libjxl scores are known for them, so they
+// only guard against changes of this crate.
+const CORPUS: &[(&str, &str)] = &[
+    ("tank_source.png", "tank_distorted.png"),
+    ("tank_distorted.png", "tank_source.png"),
+    (
+        "edge_cases/flat_64x64.png",
+        "edge_cases/flat_64x64_block.png",
+    ),
+    (
+        "edge_cases/stripes_33x17.png",
+        "edge_cases/stripes_33x17_contrast.png",
+    ),
+    (
+        "edge_cases/gradient_255x9.png",
+        "edge_cases/gradient_255x9_banded.png",
+    ),
+];
+
+fn test_data() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data")
+}
+
+fn read_image(path: &Path) -> Result<Rgb, Box<dyn Error>> {
+    let img = image::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let data = img
+        .to_rgb32f()
+        .chunks_exact(3)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+        .collect();
+    Ok(Rgb::new(
+        data,
+        img.width() as usize,
+        img.height() as usize,
+        TransferCharacteristic::SRGB,
+        ColorPrimaries::BT709,
+    )?)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let header = format!(
+        "# Generated by `cargo run --release --example golden --features dump`, do not edit.\n\
+         # target: {}, fma: {}\n",
+        std::env::consts::ARCH,
+        cfg!(target_feature = "fma")
+    );
+    let mut scores = header.clone();
+    scores.push_str("source,distorted,score,scales\n");
+    let mut planes = header;
+    planes.push_str("source,distorted,scale,plane,checksum\n");
+
+    for &(source, distorted) in CORPUS {
+        let img1 = read_image(&test_data().join(source))?;
+        let img2 = read_image(&test_data().join(distorted))?;
+
+        // `Display` prints the shortest decimal that parses to the same bits.
+        let detailed = Ssimulacra2::new(img1.width(), img1.height())?
+            .score_detailed(img1.clone(), img2.clone())?;
+        let scales: Vec<String> = detailed.scales.iter().map(f64::to_string).collect();
+        writeln!(
+            scores,
+            "{source},{distorted},{},{}",
+            detailed.score,
+            scales.join(" ")
+        )?;
+
+        for plane in plane_checksums(img1, img2)? {
+            writeln!(
+                planes,
+                "{source},{distorted},{},{},{:016x}",
+                plane.scale, plane.plane, plane.checksum
+            )?;
+        }
+    }
+
+    let dir = test_data().join("golden");
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("scores.csv"), scores)?;
+    fs::write(dir.join("planes.csv"), planes)?;
+    println!("Wrote {} pairs to {}", CORPUS.len(), dir.display());
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Per-frame quality targeting: finds the coarsest quantizer for every frame
+//! of a Y4M video whose encode still reaches a target SSIMULACRA2 score, the
+//! way the rate control of an encoder would use the metric.
+//!
+//! ```sh
+//! cargo run --release --example target_quality --features y4m -- source.y4m 80
+//! ```
+//!
+//! Instead of linking an encoder, frames are "encoded" by quantizing their
+//! samples with a step of the quantizer, which loses detail and bands
+//! gradients as real encoders do at low rates. Swap `encode` for a call into
+//! an encoder and its decoder to target its quantizer instead.
+//!
+//! Every frame uses the hooks for rate control:
+//! - A [`Reference`] is built from the source frame once and compared against
+//!   every trial encode.
+//! - [`Reference::compare_with_target`] rejects encodes below the target early,
+//!   after comparing only the smaller scales.
+//! - [`Reference::compare_bands`] scores the rows of 64x64 superblocks of the
+//!   chosen encode, showing where the remaining distortion is.
+//!
+//! [`QualitySearch`][ssimulacra2::QualitySearch] runs the same search with an
+//! encode callback when the exact score of every step is wanted.
+
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+use ssimulacra2::{Frame, FrameSource, LinearRgb, Reference, Y4mSource, Yuv, YuvConfig};
+
+// The quantizers to choose from, from the finest to the coarsest.
+const QUANTIZERS: std::ops::RangeInclusive<u16> = 1..=64;
+
+// The height of a row of superblocks.
+const SUPERBLOCK_ROWS: usize = 64;
+
+// Quantizes every sample of `frame` to a multiple of `quantizer`, in 8-bit
+// steps scaled to the bit depth.
+fn encode(frame: &Frame<u16>, config: YuvConfig, quantizer: u16) -> Frame<u16> {
+    let step = quantizer << (config.bit_depth - 8);
+    let max = (1u16 << config.bit_depth) - 1;
+    let mut encoded = frame.clone();
+    for plane in &mut encoded.planes {
+        let width = plane.cfg.width;
+        for row in plane.rows_iter_mut() {
+            for sample in &mut row[..width] {
+                *sample = (*sample + step / 2) / step * step;
+                *sample = (*sample).min(max);
+            }
+        }
+    }
+    encoded
+}
+
+fn to_linear(frame: Frame<u16>, config: YuvConfig) -> Result<LinearRgb, Box<dyn Error>> {
+    Ok(LinearRgb::try_from(Yuv::new(frame, config)?)?)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (Some(path), Some(target)) = (args.next(), args.next()) else {
+        return Err("usage: target_quality SOURCE.y4m TARGET_SCORE".into());
+    };
+    let target: f64 = target.parse()?;
+
+    let mut source = Y4mSource::<_, u16>::new(BufReader::new(File::open(&path)?))?;
+    let config = source.config();
+    println!("frame,quantizer,score,encodes,early_exits,worst_superblock_row,worst_row_score");
+
+    for (index, frame) in source.frames().enumerate() {
+        let frame = frame.map_err(|e| format!("{path}: {e:?}"))?;
+        let reference = Reference::new(Yuv::new(frame.clone(), config)?)?;
+
+        // Binary search for the coarsest quantizer reaching the target. The
+        // finest one is assumed to reach it, and kept if none does.
+        let (mut fine, mut coarse) = (*QUANTIZERS.start(), *QUANTIZERS.end() + 1);
+        let mut best = None;
+        let (mut encodes, mut early_exits) = (0, 0);
+        while coarse - fine > 1 {
+            let quantizer = fine + (coarse - fine) / 2;
+            let encoded = to_linear(encode(&frame, config, quantizer), config)?;
+            encodes += 1;
+            match reference.compare_with_target(encoded, target)? {
+                Some(score) => {
+                    fine = quantizer;
+                    best = Some((quantizer, score));
+                }
+                None => {
+                    coarse = quantizer;
+                    early_exits += 1;
+                }
+            }
+        }
+        let (quantizer, score) = match best {
+            Some(best) => best,
+            None => {
+                let finest = *QUANTIZERS.start();
+                let encoded = to_linear(encode(&frame, config, finest), config)?;
+                encodes += 1;
+                (finest, reference.compare(encoded)?)
+            }
+        };
+
+        let encoded = to_linear(encode(&frame, config, quantizer), config)?;
+        let bands = reference.compare_bands(encoded, SUPERBLOCK_ROWS)?;
+        let (worst, worst_score) = bands
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, score));
+        println!("{index},{quantizer},{score:.4},{encodes},{early_exits},{worst},{worst_score:.4}");
+    }
+
+    Ok(())
+}
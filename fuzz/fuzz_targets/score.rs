@@ -0,0 +1,75 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ssimulacra2::{compute_frame_ssimulacra2, LinearRgb, Reference};
+
+// Values that commonly break floating point code.
+const SPECIAL: [f32; 10] = [
+    f32::NAN,
+    f32::INFINITY,
+    f32::NEG_INFINITY,
+    -0.0,
+    -1.0,
+    f32::MAX,
+    f32::MIN,
+    f32::MIN_POSITIVE,
+    1e-40,
+    1e20,
+];
+
+#[derive(Debug, Arbitrary)]
+enum Sample {
+    Normal(u16),
+    Special(u8),
+}
+
+impl Sample {
+    fn value(&self) -> f32 {
+        match *self {
+            Sample::Normal(v) => f32::from(v) / f32::from(u16::MAX),
+            Sample::Special(i) => SPECIAL[usize::from(i) % SPECIAL.len()],
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: u8,
+    height: u8,
+    source: Vec<[Sample; 3]>,
+    distorted: Vec<[Sample; 3]>,
+}
+
+// Repeats `samples` to fill an image of `len` pixels.
+fn pixels(samples: &[[Sample; 3]], len: usize) -> Vec<[f32; 3]> {
+    if samples.is_empty() {
+        return vec![[0.5; 3]; len];
+    }
+    samples
+        .iter()
+        .cycle()
+        .take(len)
+        .map(|pix| [pix[0].value(), pix[1].value(), pix[2].value()])
+        .collect()
+}
+
+fuzz_target!(|input: Input| {
+    // Small sizes, including 0, 1, 7 and other sizes below and above 8.
+    let width = usize::from(input.width % 48);
+    let height = usize::from(input.height % 48);
+    let source = LinearRgb::new(pixels(&input.source, width * height), width, height).unwrap();
+    let distorted =
+        LinearRgb::new(pixels(&input.distorted, width * height), width, height).unwrap();
+
+    // Scoring may fail, but must not panic or return a non-finite score.
+    if let Ok(score) = compute_frame_ssimulacra2(source.clone(), distorted.clone()) {
+        assert!(score.is_finite(), "Score {score}");
+    }
+    if let Ok(reference) = Reference::new(source) {
+        if let Ok((score, map)) = reference.compare_with_map(distorted) {
+            assert!(score.is_finite(), "Score {score}");
+            assert!(map.data().iter().all(|v| !v.is_nan()));
+        }
+    }
+});
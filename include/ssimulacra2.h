@@ -0,0 +1,136 @@
+#ifndef SSIMULACRA2_H
+#define SSIMULACRA2_H
+
+/* Generated with cbindgen. Do not edit this file manually. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Result of a call into the C API.
+typedef enum Ssimulacra2Status {
+  // The call succeeded.
+  SSIMULACRA2_STATUS_OK = 0,
+  // A required pointer argument was null.
+  SSIMULACRA2_STATUS_NULL_POINTER = 1,
+  // The color configuration is invalid or does not match the plane data.
+  SSIMULACRA2_STATUS_INVALID_CONFIG = 2,
+  // The images are smaller than 8x8 pixels.
+  SSIMULACRA2_STATUS_INVALID_IMAGE_SIZE = 3,
+  // The images could not be converted to linear RGB.
+  SSIMULACRA2_STATUS_CONVERSION_FAILED = 4,
+  // An unexpected internal error occurred.
+  SSIMULACRA2_STATUS_PANIC = 5,
+  // No frames have been scored yet.
+  SSIMULACRA2_STATUS_NO_FRAMES = 6,
+  // The comparison was cancelled.
+  SSIMULACRA2_STATUS_CANCELLED = 7,
+} Ssimulacra2Status;
+
+// A scoring context for frames of one resolution and color configuration.
+//
+// Create it with `ssimulacra2_new` and free it with `ssimulacra2_free`.
+// A context reuses its buffers between frames and must not be used from
+// multiple threads at the same time. It also keeps the scores of all frames,
+// which `ssimulacra2_summary` summarizes, e.g. when closing a filter.
+typedef struct Ssimulacra2Context Ssimulacra2Context;
+
+// Color configuration of the YUV frames passed to a context.
+typedef struct Ssimulacra2Config {
+  // Bits per sample, between 8 and 16. Samples of more than 8 bits are
+  // stored as native endian 16-bit values.
+  uint8_t bit_depth;
+  // Horizontal chroma subsampling, as a power of two (1 for 4:2:0 and 4:2:2).
+  uint8_t subsampling_x;
+  // Vertical chroma subsampling, as a power of two (1 for 4:2:0).
+  uint8_t subsampling_y;
+  // Whether the samples use the full range instead of the limited (TV) range.
+  bool full_range;
+  // Matrix coefficients, as defined in ITU-T H.273.
+  uint8_t matrix_coefficients;
+  // Transfer characteristics, as defined in ITU-T H.273.
+  uint8_t transfer_characteristics;
+  // Color primaries, as defined in ITU-T H.273.
+  uint8_t color_primaries;
+} Ssimulacra2Config;
+
+// The Y, U and V planes of a frame.
+//
+// Each plane starts at `data[i]`, with rows `stride[i]` bytes apart. The
+// stride may be negative for bottom-up images, as with the `linesize` of `FFmpeg` frames.
+typedef struct Ssimulacra2Planes {
+  // Pointers to the first sample of each plane.
+  const uint8_t *data[3];
+  // Distance between the starts of two rows of each plane, in bytes.
+  ptrdiff_t stride[3];
+} Ssimulacra2Planes;
+
+// Statistics over all frames scored by a context.
+typedef struct Ssimulacra2Summary {
+  // Number of frames which were scored.
+  size_t frames;
+  // Arithmetic mean of all scores.
+  double mean;
+  // Harmonic mean of all scores, or NaN if any score is not positive.
+  double harmonic_mean;
+  // Population standard deviation of all scores.
+  double std_dev;
+  // Lowest score.
+  double min;
+  // Highest score.
+  double max;
+  // 5th percentile.
+  double p5;
+} Ssimulacra2Summary;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a context for scoring frames of the given size and color configuration.
+//
+// Returns NULL if `config` is NULL or invalid, or if the frames are smaller
+// than 8x8 pixels.
+//
+// # Safety
+// `config` must be NULL or point to a valid `Ssimulacra2Config`.
+struct Ssimulacra2Context *ssimulacra2_new(uint32_t width,
+                                           uint32_t height,
+                                           const struct Ssimulacra2Config *config);
+
+// Computes the SSIMULACRA2 score of `distorted` against `source` and
+// writes it to `score`.
+//
+// # Safety
+// - `ctx` must be NULL or a context returned by `ssimulacra2_new` that has
+//   not been freed yet, and must not be used concurrently.
+// - `source` and `distorted` must be NULL or point to planes which cover
+//   the resolution and subsampling the context was created with, with the
+//   chroma planes of odd sizes rounded up.
+// - `score` must be NULL or valid for writes.
+enum Ssimulacra2Status ssimulacra2_score(struct Ssimulacra2Context *ctx,
+                                         const struct Ssimulacra2Planes *source,
+                                         const struct Ssimulacra2Planes *distorted,
+                                         double *score);
+
+// Writes statistics over all frames scored by `ctx` so far to `summary`.
+//
+// # Safety
+// - `ctx` must be NULL or a context returned by `ssimulacra2_new` that has
+//   not been freed yet.
+// - `summary` must be NULL or valid for writes.
+enum Ssimulacra2Status ssimulacra2_summary(const struct Ssimulacra2Context *ctx,
+                                           struct Ssimulacra2Summary *summary);
+
+// Frees a context created by `ssimulacra2_new`. Passing NULL is a no-op.
+//
+// # Safety
+// `ctx` must be NULL or a context returned by `ssimulacra2_new` that has not
+// been freed yet.
+void ssimulacra2_free(struct Ssimulacra2Context *ctx);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SSIMULACRA2_H */
@@ -0,0 +1,166 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+/// The alignment of the start of an [`AlignedPlane`] in bytes, which is that
+/// of the widest vectors of AVX-512.
+pub const PLANE_ALIGNMENT: usize = 64;
+
+// The number of values in one aligned block.
+const BLOCK_LEN: usize = PLANE_ALIGNMENT / mem::size_of::<f32>();
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Block([f32; BLOCK_LEN]);
+
+// SAFETY: `Block` is an array of `f32` without padding, as its size equals
+// its alignment, so any bit pattern is valid and zero is a valid value.
+unsafe impl bytemuck::Zeroable for Block {}
+// SAFETY: See above; `Block` is `Copy`, `repr(C)` and has no padding.
+unsafe impl bytemuck::Pod for Block {}
+
+/// A buffer of `f32` values starting at an address aligned to
+/// [`PLANE_ALIGNMENT`] bytes, so that SIMD kernels can use aligned loads.
+///
+/// All planes used internally while scoring are stored in these buffers.
+/// Callers that prepare planes themselves, e.g. for an [`ImageF32`], can use
+/// them as well; they dereference to slices like a [`Vec<f32>`].
+///
+/// [`ImageF32`]: crate::ImageF32
+#[derive(Clone, Default)]
+pub struct AlignedPlane {
+    blocks: Vec<Block>,
+    len: usize,
+}
+
+impl AlignedPlane {
+    /// Creates a plane of `len` zeroes.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        let mut plane = AlignedPlane::default();
+        plane.resize(len, 0.0f32);
+        plane
+    }
+
+    /// Creates an empty plane with room for at least `capacity` values.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        AlignedPlane {
+            blocks: Vec::with_capacity(aligned_len(capacity) / BLOCK_LEN),
+            len: 0,
+        }
+    }
+
+    /// Creates a plane holding a copy of `values`.
+    #[must_use]
+    pub fn from_slice(values: &[f32]) -> Self {
+        let mut plane = AlignedPlane::default();
+        plane.extend_from_slice(values);
+        plane
+    }
+
+    /// The number of values the plane can hold without reallocating.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.blocks.capacity() * BLOCK_LEN
+    }
+
+    /// Resizes the plane to `len` values, filling new values with `value`.
+    pub fn resize(&mut self, len: usize, value: f32) {
+        let old_len = self.len;
+        self.blocks
+            .resize(aligned_len(len) / BLOCK_LEN, Block([0.0f32; BLOCK_LEN]));
+        self.len = len;
+        if len > old_len {
+            self[old_len..].fill(value);
+        }
+    }
+
+    /// Shortens the plane to `len` values, keeping the allocated memory. Does
+    /// nothing if the plane is not longer than that.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.resize(len, 0.0f32);
+        }
+    }
+
+    /// Appends `values` to the plane.
+    pub fn extend_from_slice(&mut self, values: &[f32]) {
+        let start = self.len;
+        self.resize(start + values.len(), 0.0f32);
+        self[start..].copy_from_slice(values);
+    }
+
+    /// Removes all values, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.resize(0, 0.0f32);
+    }
+}
+
+impl Deref for AlignedPlane {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        &bytemuck::cast_slice(&self.blocks)[..self.len]
+    }
+}
+
+impl DerefMut for AlignedPlane {
+    fn deref_mut(&mut self) -> &mut [f32] {
+        &mut bytemuck::cast_slice_mut(&mut self.blocks)[..self.len]
+    }
+}
+
+impl fmt::Debug for AlignedPlane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for AlignedPlane {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl From<&[f32]> for AlignedPlane {
+    fn from(values: &[f32]) -> Self {
+        Self::from_slice(values)
+    }
+}
+
+impl From<Vec<f32>> for AlignedPlane {
+    fn from(values: Vec<f32>) -> Self {
+        Self::from_slice(&values)
+    }
+}
+
+// Rounds `len` up to a whole number of aligned blocks, so that a plane
+// following `len` values starts aligned as well.
+pub const fn aligned_len(len: usize) -> usize {
+    (len + BLOCK_LEN - 1) / BLOCK_LEN * BLOCK_LEN
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn aligned_resizes() {
+        let mut plane = AlignedPlane::from_slice(&[1.0f32, 2.0, 3.0]);
+        assert_eq!(plane.as_ptr() as usize % PLANE_ALIGNMENT, 0);
+        assert_eq!(&*plane, &[1.0f32, 2.0, 3.0]);
+
+        plane.resize(1, 0.0f32);
+        plane.resize(40, 5.0f32);
+        assert_eq!(plane.len(), 40);
+        let mut expected = vec![5.0f32; 40];
+        expected[0] = 1.0f32;
+        assert_eq!(&*plane, expected.as_slice());
+        assert_eq!(plane.as_ptr() as usize % PLANE_ALIGNMENT, 0);
+        assert!(plane.capacity() >= 40);
+    }
+}
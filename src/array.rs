@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use ndarray::{Array2, ArrayView3, Axis};
+use yuvxyb::{ColorPrimaries, LinearRgb, TransferCharacteristic};
+
+use crate::transfer::decode_rgb;
+use crate::{DistortionMap, Ssimulacra2Error};
+
+/// An RGB image borrowed from an [`ndarray`] view.
+///
+/// Both channels-last (`H×W×3`) and channels-first (`3×H×W`) layouts are
+/// accepted. If both the first and the last axis have a length of 3, the
+/// view is treated as channels-last. Values are expected to be in the
+/// `0.0..=1.0` range and encoded with the given transfer characteristics.
+#[derive(Debug, Clone)]
+pub struct ArrayImage<'a> {
+    view: ArrayView3<'a, f32>,
+    channels_first: bool,
+    transfer: TransferCharacteristic,
+    primaries: ColorPrimaries,
+}
+
+impl<'a> ArrayImage<'a> {
+    /// Wraps `view`, interpreting it with the given transfer characteristics and primaries.
+    ///
+    /// # Errors
+    /// - If neither the first nor the last axis of `view` has a length of 3
+    pub fn new(
+        view: ArrayView3<'a, f32>,
+        transfer: TransferCharacteristic,
+        primaries: ColorPrimaries,
+    ) -> Result<Self, Ssimulacra2Error> {
+        let channels_first = match *view.shape() {
+            [_, _, 3] => false,
+            [3, _, _] => true,
+            _ => return Err(Ssimulacra2Error::InvalidArrayShape),
+        };
+
+        Ok(ArrayImage {
+            view,
+            channels_first,
+            transfer,
+            primaries,
+        })
+    }
+
+    /// Width of the image in pixels.
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.view
+            .len_of(Axis(if self.channels_first { 2 } else { 1 }))
+    }
+
+    /// Height of the image in pixels.
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.view.len_of(Axis(usize::from(self.channels_first)))
+    }
+}
+
+impl<'a> TryFrom<ArrayView3<'a, f32>> for ArrayImage<'a> {
+    type Error = Ssimulacra2Error;
+
+    /// Wraps `view` as sRGB with BT.709 primaries.
+    fn try_from(view: ArrayView3<'a, f32>) -> Result<Self, Self::Error> {
+        ArrayImage::new(view, TransferCharacteristic::SRGB, ColorPrimaries::BT709)
+    }
+}
+
+impl TryFrom<ArrayImage<'_>> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(image: ArrayImage<'_>) -> Result<Self, Self::Error> {
+        let width = image.width();
+        let height = image.height();
+        let view = if image.channels_first {
+            image.view.permuted_axes([1, 2, 0])
+        } else {
+            image.view
+        };
+
+        let mut data = Vec::with_capacity(width * height);
+        for row in view.outer_iter() {
+            data.extend(row.outer_iter().map(|pix| [pix[0], pix[1], pix[2]]));
+        }
+
+        decode_rgb(data, width, height, image.transfer, image.primaries)
+    }
+}
+
+impl From<DistortionMap> for Array2<f32> {
+    /// Converts the map into an `H×W` array.
+    fn from(map: DistortionMap) -> Self {
+        let shape = (map.height(), map.width());
+        Array2::from_shape_vec(shape, map.into_data()).expect("Resolution and data size match")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array3;
+
+    use super::*;
+    use crate::compute_frame_ssimulacra2_map;
+
+    #[test]
+    fn layouts_are_equivalent() {
+        let source = Array3::from_shape_fn((3, 24, 32), |(c, y, x)| {
+            ((x * 5 + y * 3 + c * 7) % 32) as f32 / 32.0
+        });
+        let distorted = source.mapv(|v| (v * 0.9f32).min(1.0));
+
+        let (first, map_first) = compute_frame_ssimulacra2_map(
+            ArrayImage::try_from(source.view()).unwrap(),
+            ArrayImage::try_from(distorted.view()).unwrap(),
+        )
+        .unwrap();
+        let (last, map_last) = compute_frame_ssimulacra2_map(
+            ArrayImage::try_from(source.view().permuted_axes([1, 2, 0])).unwrap(),
+            ArrayImage::try_from(distorted.view().permuted_axes([1, 2, 0])).unwrap(),
+        )
+        .unwrap();
+
+        assert!((first - last).abs() < f64::EPSILON);
+        assert_eq!(Array2::from(map_first).dim(), (24, 32));
+        assert_eq!(Array2::from(map_last).dim(), (24, 32));
+    }
+}
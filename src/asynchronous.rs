@@ -0,0 +1,387 @@
+use std::num::NonZeroUsize;
+use std::panic;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use yuvxyb::LinearRgb;
+
+use crate::{
+    CancellationToken, DetailedScore, Preprocess, Reference, Ssimulacra2, Ssimulacra2Error,
+};
+
+/// Computes the SSIMULACRA2 score of `distorted` compared to `source` on the
+/// blocking thread pool of the current tokio runtime.
+///
+/// Scoring a large image takes long enough to stall all other tasks of an
+/// async worker thread, so it is moved off the worker with
+/// [`spawn_blocking`][tokio::task::spawn_blocking]. Dropping the returned
+/// future, e.g. when a request times out, cancels the comparison at its next
+/// stage, as a [`CancellationToken`] would.
+///
+/// Every call allocates its working memory anew; services scoring many images
+/// should use an [`AsyncSsimulacra2`] instead.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to XYB successfully
+/// - If the images contain values for which no score can be computed
+/// - If the image is smaller than 8x8 pixels
+/// - If the runtime shuts down before the comparison is done
+///
+/// # Panics
+/// - If called outside of a tokio runtime
+pub async fn score_async<T, U>(source: T, distorted: U) -> Result<f64, Ssimulacra2Error>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    run_blocking(move |token| {
+        Reference::new(source)?
+            .with_cancellation(token)
+            .compare(distorted)
+    })
+    .await
+}
+
+/// A pool of [`Ssimulacra2`] sessions for scoring from async code, e.g. the
+/// handlers of a web service offering an image quality endpoint.
+///
+/// Every comparison runs on the blocking thread pool of the current tokio
+/// runtime with a session taken from the pool, or a new one if all are in
+/// use, so any number of comparisons can run concurrently. Afterwards, the
+/// session is returned to the pool for reuse, preferring sessions of the same
+/// resolution, unless the pool already holds as many idle sessions as
+/// [`with_max_idle_sessions`][Self::with_max_idle_sessions] allows.
+///
+/// The pool is shared by all clones, so that it can be handed to every task
+/// or kept in the state of a web framework. As with [`score_async`],
+/// dropping the future of a comparison cancels it.
+#[derive(Debug, Clone)]
+pub struct AsyncSsimulacra2 {
+    sessions: Arc<Mutex<Vec<Ssimulacra2>>>,
+    max_idle_sessions: usize,
+    validate: bool,
+    preprocess: Preprocess,
+    normalize_levels: bool,
+}
+
+impl Default for AsyncSsimulacra2 {
+    /// Keeps up to one idle session per available CPU core.
+    fn default() -> Self {
+        AsyncSsimulacra2 {
+            sessions: Arc::default(),
+            max_idle_sessions: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            validate: cfg!(debug_assertions),
+            preprocess: Preprocess::None,
+            normalize_levels: false,
+        }
+    }
+}
+
+impl AsyncSsimulacra2 {
+    /// Creates an empty pool, which keeps up to one idle session per
+    /// available CPU core.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of idle sessions kept for reuse. Sessions returned to
+    /// a full pool are dropped, releasing their working memory.
+    #[must_use]
+    pub fn with_max_idle_sessions(self, max_idle_sessions: usize) -> Self {
+        AsyncSsimulacra2 {
+            max_idle_sessions,
+            ..self
+        }
+    }
+
+    /// Enables or disables checking both images for NaN and infinite values,
+    /// see [`Ssimulacra2::with_validation`].
+    #[must_use]
+    pub fn with_validation(self, validate: bool) -> Self {
+        AsyncSsimulacra2 { validate, ..self }
+    }
+
+    /// Applies `preprocess` to both images before scoring them, see
+    /// [`Ssimulacra2::with_preprocess`].
+    #[must_use]
+    pub fn with_preprocess(self, preprocess: Preprocess) -> Self {
+        AsyncSsimulacra2 { preprocess, ..self }
+    }
+
+    /// Enables or disables matching the levels of the distorted image to
+    /// those of the source before scoring them, see
+    /// [`Ssimulacra2::with_level_normalization`].
+    #[must_use]
+    pub fn with_level_normalization(self, normalize_levels: bool) -> Self {
+        AsyncSsimulacra2 {
+            normalize_levels,
+            ..self
+        }
+    }
+
+    /// Number of sessions currently waiting in the pool.
+    #[must_use]
+    pub fn idle_sessions(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the runtime shuts down before the comparison is done
+    ///
+    /// # Panics
+    /// - If called outside of a tokio runtime
+    pub async fn score<T, U>(&self, source: T, distorted: U) -> Result<f64, Ssimulacra2Error>
+    where
+        T: Send + 'static,
+        U: Send + 'static,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_inner(source, distorted, false)
+            .await
+            .map(|detailed| detailed.score)
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`,
+    /// together with the error of each scale.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the runtime shuts down before the comparison is done
+    ///
+    /// # Panics
+    /// - If called outside of a tokio runtime
+    pub async fn score_detailed<T, U>(
+        &self,
+        source: T,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        T: Send + 'static,
+        U: Send + 'static,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_inner(source, distorted, false).await
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`,
+    /// together with the error of each scale and a
+    /// [`DistortionMap`][crate::DistortionMap].
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the runtime shuts down before the comparison is done
+    ///
+    /// # Panics
+    /// - If called outside of a tokio runtime
+    pub async fn score_with_map<T, U>(
+        &self,
+        source: T,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        T: Send + 'static,
+        U: Send + 'static,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_inner(source, distorted, true).await
+    }
+
+    async fn score_inner<T, U>(
+        &self,
+        source: T,
+        distorted: U,
+        with_map: bool,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        T: Send + 'static,
+        U: Send + 'static,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        let pool = self.clone();
+        run_blocking(move |token| {
+            // The source is converted first to pick a session of its resolution.
+            let Ok(source) = LinearRgb::try_from(source) else {
+                return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+            };
+            let mut session = pool.take_session(source.width(), source.height())?;
+            session.set_cancellation(Some(token));
+            let result = if with_map {
+                session.score_with_map(source, distorted)
+            } else {
+                session.score_detailed(source, distorted)
+            };
+            pool.give_session(session);
+            result
+        })
+        .await
+    }
+
+    fn take_session(&self, width: usize, height: usize) -> Result<Ssimulacra2, Ssimulacra2Error> {
+        let session = {
+            let mut sessions = self.lock();
+            let same_size = sessions
+                .iter()
+                .position(|session| session.width() == width && session.height() == height);
+            match same_size {
+                Some(i) => Some(sessions.swap_remove(i)),
+                None => sessions.pop(),
+            }
+        };
+        let session = match session {
+            Some(session) => session,
+            None => Ssimulacra2::new(width, height)?,
+        };
+        Ok(session
+            .with_validation(self.validate)
+            .with_preprocess(self.preprocess)
+            .with_level_normalization(self.normalize_levels))
+    }
+
+    fn give_session(&self, mut session: Ssimulacra2) {
+        session.set_cancellation(None);
+        let mut sessions = self.lock();
+        if sessions.len() < self.max_idle_sessions {
+            sessions.push(session);
+        } else {
+            // Free the memory of the session after releasing the lock.
+            drop(sessions);
+            drop(session);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Ssimulacra2>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Runs `f` on the blocking thread pool, cancelling the token passed to it if
+// the returned future is dropped before `f` is done. Panics of `f` are
+// propagated to the caller.
+async fn run_blocking<R, F>(f: F) -> Result<R, Ssimulacra2Error>
+where
+    R: Send + 'static,
+    F: FnOnce(CancellationToken) -> Result<R, Ssimulacra2Error> + Send + 'static,
+{
+    let token = CancellationToken::new();
+    // Cancelling after `f` is done has no effect, so the guard is simply
+    // dropped at the end.
+    let _guard = CancelOnDrop(token.clone());
+    match tokio::task::spawn_blocking(move || f(token)).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(Ssimulacra2Error::Cancelled),
+    }
+}
+
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::compute_frame_ssimulacra2;
+    use crate::test_images::make_image;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    #[test]
+    fn matches_sync() {
+        let expected = compute_frame_ssimulacra2(make_image(40, 30, 3), make_image(40, 30, 5));
+        let scorer = AsyncSsimulacra2::new().with_max_idle_sessions(1);
+        runtime().block_on(async {
+            let score = assert_send(score_async(make_image(40, 30, 3), make_image(40, 30, 5)));
+            assert_eq!(score.await, expected);
+
+            let scores: Vec<_> = (0..4usize)
+                .map(|_| {
+                    let scorer = scorer.clone();
+                    tokio::spawn(async move {
+                        scorer
+                            .score(make_image(40, 30, 3), make_image(40, 30, 5))
+                            .await
+                    })
+                })
+                .collect();
+            for score in scores {
+                assert_eq!(score.await.unwrap(), expected);
+            }
+            assert_eq!(
+                scorer
+                    .score(make_image(40, 30, 3), make_image(30, 40, 5))
+                    .await,
+                Err(Ssimulacra2Error::NonMatchingImageDimensions)
+            );
+        });
+        assert_eq!(scorer.idle_sessions(), 1);
+    }
+
+    #[test]
+    fn dropping_cancels() {
+        let scorer = AsyncSsimulacra2::new();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        runtime().block_on(async {
+            let task = {
+                let scorer = scorer.clone();
+                tokio::spawn(async move {
+                    scorer
+                        .score(make_image(1024, 1024, 3), make_image(1024, 1024, 5))
+                        .await
+                })
+            };
+            let waiting = {
+                let cancelled = Arc::clone(&cancelled);
+                tokio::spawn(run_blocking(move |token| {
+                    let start = Instant::now();
+                    while !token.is_cancelled() && start.elapsed() < Duration::from_secs(10) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    cancelled.store(token.is_cancelled(), Ordering::Relaxed);
+                    Ok(())
+                }))
+            };
+            // Let both tasks start their blocking work before dropping them.
+            tokio::task::yield_now().await;
+            task.abort();
+            waiting.abort();
+        });
+
+        // Dropping the runtime waits for the blocking work, after which the
+        // session is back in the pool.
+        assert!(cancelled.load(Ordering::Relaxed));
+        assert_eq!(scorer.idle_sessions(), 1);
+    }
+}
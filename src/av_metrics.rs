@@ -0,0 +1,313 @@
+//! SSIMULACRA2 with the interface of the video metrics of the
+//! [`av-metrics`](https://docs.rs/av-metrics) crate.
+//!
+//! [`calculate_frame_ssimulacra2`] and [`calculate_video_ssimulacra2`] take
+//! the same arguments as e.g. `calculate_frame_psnr` and
+//! `calculate_video_psnr` of `av_metrics::video::psnr`, so pipelines built on
+//! its [`Decoder`] trait can score SSIMULACRA2 next to PSNR, SSIM and
+//! CIEDE2000 by calling one more function.
+//!
+//! `av-metrics` carries no color information, so frames are interpreted as
+//! limited range BT.709 of the given bit depth, like
+//! [`FrameRef::from`][crate::FrameRef] does for 8-bit frames. Use
+//! [`FrameRef::new`][crate::FrameRef::new] for frames in other color spaces.
+
+use std::error::Error;
+use std::iter;
+
+use ::av_metrics::video::decode::Decoder;
+use ::av_metrics::video::{ChromaSamplePosition, ChromaSampling};
+use ::av_metrics::MetricsError;
+use yuvxyb::{
+    ColorPrimaries, Frame, LinearRgb, MatrixCoefficients, Pixel, TransferCharacteristic, YuvConfig,
+};
+
+use crate::{
+    compute_frame_ssimulacra2, ChromaSiting, FramePipeline, FrameRef, ScoreAggregator,
+    ScoreSummary, Ssimulacra2Error,
+};
+
+/// Calculates the SSIMULACRA2 score of two frames. Higher is better, with a
+/// maximum of 100 for identical frames.
+///
+/// # Errors
+/// - If the chroma sampling is monochrome, which is not supported
+/// - If `bit_depth` or `chroma_sampling` do not match the frames
+/// - If the frames do not have the same width and height
+/// - If the frames are smaller than 8x8 pixels
+pub fn calculate_frame_ssimulacra2<T: Pixel>(
+    frame1: &Frame<T>,
+    frame2: &Frame<T>,
+    bit_depth: usize,
+    chroma_sampling: ChromaSampling,
+) -> Result<f64, Box<dyn Error>> {
+    let config = config(bit_depth, chroma_sampling)?;
+    let score = compute_frame_ssimulacra2(
+        FrameRef::new(frame1, config)?,
+        FrameRef::new(frame2, config)?,
+    )?;
+    Ok(score)
+}
+
+/// Calculates the SSIMULACRA2 score of every frame of two videos and
+/// summarizes them. Frames are scored on all CPU cores while the videos are
+/// decoded on the calling thread.
+///
+/// Like the video metrics of `av-metrics`, this stops at the end of the
+/// shorter video or after `frame_limit` frames, and calls
+/// `progress_callback` with the number of frames decoded so far after every
+/// frame, and with [`usize::MAX`] once decoding is done.
+///
+/// # Errors
+/// - If the videos differ in bit depth or chroma sampling
+/// - If the chroma sampling is monochrome, which is not supported
+/// - If the videos do not have the same width and height
+/// - If the frames are smaller than 8x8 pixels
+/// - If no frames could be read from one of the videos
+pub fn calculate_video_ssimulacra2<D: Decoder, F: Fn(usize) + Send>(
+    decoder1: &mut D,
+    decoder2: &mut D,
+    frame_limit: Option<usize>,
+    progress_callback: F,
+) -> Result<ScoreSummary, Box<dyn Error>> {
+    let (details1, details2) = (decoder1.get_video_details(), decoder2.get_video_details());
+    if decoder1.get_bit_depth() != decoder2.get_bit_depth() {
+        return Err(MetricsError::InputMismatch {
+            reason: "Bit depths do not match",
+        }
+        .into());
+    }
+    if details1.chroma_sampling != details2.chroma_sampling {
+        return Err(MetricsError::InputMismatch {
+            reason: "Chroma samplings do not match",
+        }
+        .into());
+    }
+
+    let video = Video {
+        config: config(decoder1.get_bit_depth(), details1.chroma_sampling)?,
+        // Without a known position, chroma samples are repeated, as by `Yuv`.
+        siting: match details1.chroma_sample_position {
+            ChromaSamplePosition::Vertical => Some(ChromaSiting::Left),
+            ChromaSamplePosition::Colocated => Some(ChromaSiting::TopLeft),
+            _ => None,
+        },
+        frame_limit,
+    };
+    let scores = if decoder1.get_bit_depth() > 8 {
+        video.score::<D, u16, F>(decoder1, decoder2, &progress_callback)?
+    } else {
+        video.score::<D, u8, F>(decoder1, decoder2, &progress_callback)?
+    };
+
+    scores.summary().ok_or_else(|| {
+        MetricsError::UnsupportedInput {
+            reason: "No readable frames found in one or more input files",
+        }
+        .into()
+    })
+}
+
+// The color configuration of frames with the given bit depth and sampling.
+fn config(bit_depth: usize, chroma_sampling: ChromaSampling) -> Result<YuvConfig, MetricsError> {
+    let (subsampling_x, subsampling_y) = match chroma_sampling {
+        ChromaSampling::Cs420 => (1, 1),
+        ChromaSampling::Cs422 => (1, 0),
+        ChromaSampling::Cs444 => (0, 0),
+        ChromaSampling::Cs400 => {
+            return Err(MetricsError::UnsupportedInput {
+                reason: "Monochrome video is not supported",
+            })
+        }
+    };
+    let Ok(bit_depth) = u8::try_from(bit_depth) else {
+        return Err(MetricsError::UnsupportedInput {
+            reason: "Bit depth is not supported",
+        });
+    };
+    Ok(YuvConfig {
+        bit_depth,
+        subsampling_x,
+        subsampling_y,
+        full_range: false,
+        matrix_coefficients: MatrixCoefficients::BT709,
+        transfer_characteristics: TransferCharacteristic::BT1886,
+        color_primaries: ColorPrimaries::BT709,
+    })
+}
+
+// How to read and interpret the frames of a pair of videos.
+struct Video {
+    config: YuvConfig,
+    siting: Option<ChromaSiting>,
+    frame_limit: Option<usize>,
+}
+
+impl Video {
+    fn score<D: Decoder, T: Pixel, F: Fn(usize)>(
+        &self,
+        decoder1: &mut D,
+        decoder2: &mut D,
+        progress_callback: &F,
+    ) -> Result<ScoreAggregator, Ssimulacra2Error> {
+        let mut decoded = 0;
+        let frames = iter::from_fn(|| {
+            if self.frame_limit.map_or(false, |limit| decoded >= limit) {
+                return None;
+            }
+            let frame1 = decoder1.read_video_frame::<T>()?;
+            let frame2 = decoder2.read_video_frame::<T>()?;
+            decoded += 1;
+            progress_callback(decoded);
+            Some((decoded - 1, self.frame(frame1), self.frame(frame2)))
+        });
+
+        let mut scores = ScoreAggregator::new();
+        let scored = FramePipeline::default().run(frames, |frame, detailed| {
+            scores.push(frame, detailed.score);
+        });
+        progress_callback(usize::MAX);
+        scored?;
+        Ok(scores)
+    }
+
+    const fn frame<T: Pixel>(&self, frame: Frame<T>) -> VideoFrame<T> {
+        VideoFrame {
+            frame,
+            config: self.config,
+            siting: self.siting,
+        }
+    }
+}
+
+// A decoded frame, which is converted to linear RGB on the workers.
+struct VideoFrame<T: Pixel> {
+    frame: Frame<T>,
+    config: YuvConfig,
+    siting: Option<ChromaSiting>,
+}
+
+impl<T: Pixel> TryFrom<VideoFrame<T>> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(input: VideoFrame<T>) -> Result<Self, Self::Error> {
+        let frame = FrameRef::new(&input.frame, input.config)?;
+        LinearRgb::try_from(
+            input
+                .siting
+                .map_or(frame, |siting| frame.with_chroma_siting(siting)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use ::av_metrics::video::decode::VideoDetails;
+
+    use super::*;
+    use crate::Plane;
+
+    fn make_frame<T: Pixel>(width: usize, height: usize, seed: usize) -> Frame<T> {
+        let mut frame: Frame<T> = Frame {
+            planes: [
+                Plane::new(width, height, 0, 0, 16, 16),
+                Plane::new(width / 2, height / 2, 1, 1, 8, 8),
+                Plane::new(width / 2, height / 2, 1, 1, 8, 8),
+            ],
+        };
+        for (i, plane) in frame.planes.iter_mut().enumerate() {
+            let width = plane.cfg.width;
+            for (y, row) in plane.rows_iter_mut().enumerate() {
+                for (x, pix) in row[..width].iter_mut().enumerate() {
+                    *pix = T::cast_from((16 + ((x * 7 + y * 13 + i * 31) * seed) % 220) as u16);
+                }
+            }
+        }
+        frame
+    }
+
+    // Generates a frame for every seed.
+    struct Frames {
+        seeds: Range<usize>,
+        details: VideoDetails,
+    }
+
+    impl Decoder for Frames {
+        fn read_video_frame<T: Pixel>(&mut self) -> Option<Frame<T>> {
+            let seed = self.seeds.next()?;
+            Some(make_frame(self.details.width, self.details.height, seed))
+        }
+
+        fn get_bit_depth(&self) -> usize {
+            self.details.bit_depth
+        }
+
+        fn get_video_details(&self) -> VideoDetails {
+            self.details
+        }
+    }
+
+    #[test]
+    fn matches_frame_scores() {
+        let details = VideoDetails {
+            width: 64,
+            height: 48,
+            ..VideoDetails::default()
+        };
+        let expected: Vec<_> = (1..4)
+            .map(|seed| {
+                let source: Frame<u8> = make_frame(64, 48, seed);
+                let distorted = make_frame(64, 48, seed + 1);
+                calculate_frame_ssimulacra2(&source, &distorted, 8, ChromaSampling::Cs420).unwrap()
+            })
+            .collect();
+        let (source, distorted): (Frame<u8>, Frame<u8>) =
+            (make_frame(64, 48, 1), make_frame(64, 48, 2));
+        assert_eq!(
+            expected[0].to_bits(),
+            compute_frame_ssimulacra2(FrameRef::from(&source), FrameRef::from(&distorted))
+                .unwrap()
+                .to_bits()
+        );
+
+        let progress = AtomicUsize::new(0);
+        let summary = calculate_video_ssimulacra2(
+            &mut Frames {
+                seeds: 1..10,
+                details,
+            },
+            &mut Frames {
+                seeds: 2..11,
+                details,
+            },
+            Some(3),
+            |decoded| {
+                progress.fetch_max(decoded.min(100), Ordering::Relaxed);
+            },
+        )
+        .unwrap();
+        assert_eq!(summary.frames, 3);
+        assert!((summary.mean - expected.iter().sum::<f64>() / 3.0f64).abs() < 1e-9f64);
+        assert_eq!(progress.into_inner(), 100);
+
+        let mismatch = calculate_video_ssimulacra2(
+            &mut Frames {
+                seeds: 1..2,
+                details,
+            },
+            &mut Frames {
+                seeds: 1..2,
+                details: VideoDetails {
+                    bit_depth: 10,
+                    ..details
+                },
+            },
+            None,
+            |_| {},
+        );
+        assert!(mismatch.is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Reads the orientation of an image from its Exif metadata.
+
+use image::DynamicImage;
+
+/// The Exif orientation of an image, i.e. how its pixels have to be rotated
+/// and flipped to display it upright.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    Transpose,
+    Rotate90,
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+    /// Reads the orientation from the Exif metadata of a JPEG, PNG, WebP or
+    /// TIFF image. Images without the tag, or with an invalid one, are
+    /// upright.
+    pub fn read(data: &[u8]) -> Self {
+        exif_data(data)
+            .and_then(tiff_orientation)
+            .and_then(Self::from_tag)
+            .unwrap_or_default()
+    }
+
+    const fn from_tag(tag: u16) -> Option<Self> {
+        Some(match tag {
+            1 => Orientation::Normal,
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => return None,
+        })
+    }
+
+    /// Rotates and flips the image upright.
+    pub fn apply(self, img: DynamicImage) -> DynamicImage {
+        match self {
+            Orientation::Normal => img,
+            Orientation::FlipHorizontal => img.fliph(),
+            Orientation::Rotate180 => img.rotate180(),
+            Orientation::FlipVertical => img.flipv(),
+            Orientation::Transpose => img.rotate90().fliph(),
+            Orientation::Rotate90 => img.rotate90(),
+            Orientation::Transverse => img.rotate270().fliph(),
+            Orientation::Rotate270 => img.rotate270(),
+        }
+    }
+
+    /// Describes the orientation for messages.
+    pub const fn description(self) -> &'static str {
+        match self {
+            Orientation::Normal => "upright",
+            Orientation::FlipHorizontal => "flipped horizontally",
+            Orientation::Rotate180 => "rotated by 180°",
+            Orientation::FlipVertical => "flipped vertically",
+            Orientation::Transpose => "transposed",
+            Orientation::Rotate90 => "rotated by 90° clockwise",
+            Orientation::Transverse => "transversed",
+            Orientation::Rotate270 => "rotated by 90° counterclockwise",
+        }
+    }
+}
+
+// Finds the TIFF structure holding the Exif metadata of an image.
+fn exif_data(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        jpeg_exif(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        webp_exif(data)
+    } else {
+        super::png_chunks(data).exif
+    }
+}
+
+// Walks the marker segments of a JPEG up to the start of the scan.
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut rest = &data[2..];
+    while rest.len() >= 4 && rest[0] == 0xff {
+        let marker = rest[1];
+        if marker == 0xda {
+            break;
+        }
+        let length = usize::from(u16::from_be_bytes([rest[2], rest[3]]));
+        let body = rest.get(4..2 + length)?;
+        if marker == 0xe1 {
+            if let Some(exif) = body.strip_prefix(b"Exif\0\0") {
+                return Some(exif);
+            }
+        }
+        rest = &rest[2 + length..];
+    }
+    None
+}
+
+// Walks the chunks of a WebP file, whose `EXIF` chunk sometimes keeps the
+// `Exif` prefix of JPEG.
+fn webp_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let length = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let body = rest.get(8..8 + length)?;
+        if &rest[..4] == b"EXIF" {
+            return Some(body.strip_prefix(b"Exif\0\0").unwrap_or(body));
+        }
+        rest = rest.get(8 + length + length % 2..).unwrap_or_default();
+    }
+    None
+}
+
+// Reads the orientation tag from the first IFD of a TIFF structure.
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let entries = usize::from(u16_at(ifd)?);
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+}
@@ -0,0 +1,178 @@
+//! Recognizes common RGB ICC profiles, so that images tagged with them can be
+//! linearized without a color management system.
+
+use num_traits::FromPrimitive;
+use ssimulacra2::{ColorPrimaries, TransferCharacteristic};
+
+use super::Encoding;
+
+// Colorants of the primaries adapted to the D50 illuminant of the profile
+// connection space with the Bradford transform, as stored in the `rXYZ`,
+// `gXYZ` and `bXYZ` tags of a profile.
+const COLORANTS: [(ColorPrimaries, [[f32; 3]; 3]); 3] = [
+    (
+        ColorPrimaries::BT709,
+        [
+            [0.4360, 0.2225, 0.0139],
+            [0.3851, 0.7169, 0.0971],
+            [0.1430, 0.0606, 0.7139],
+        ],
+    ),
+    (
+        ColorPrimaries::P3Display,
+        [
+            [0.5151, 0.2412, -0.0011],
+            [0.2920, 0.6922, 0.0419],
+            [0.1571, 0.0666, 0.7841],
+        ],
+    ),
+    (
+        ColorPrimaries::BT2020,
+        [
+            [0.6735, 0.2790, -0.0019],
+            [0.1657, 0.6753, 0.0300],
+            [0.1250, 0.0456, 0.7969],
+        ],
+    ),
+];
+
+// Largest difference to the colorants above which is taken for rounding, as
+// profiles differ in how they compute the chromatic adaptation.
+const COLORANT_TOLERANCE: f32 = 0.003;
+
+/// Identifies the color space of an RGB ICC profile, either from its `cicp`
+/// tag or from its colorants and tone curves. sRGB, Display P3 and BT.2020
+/// primaries are recognized, with the sRGB or a linear tone curve. Returns
+/// [None] for any other profile.
+pub fn encoding(profile: &[u8]) -> Option<Encoding> {
+    if profile.get(16..20)? != b"RGB " {
+        return None;
+    }
+    let tags = Tags(profile);
+
+    if let Some(&[primaries, transfer, 0, full_range]) =
+        tags.get(b"cicp").and_then(|t| t.get(8..12))
+    {
+        return Some(Encoding::Cicp {
+            primaries: ColorPrimaries::from_u8(primaries)?,
+            transfer: TransferCharacteristic::from_u8(transfer)?,
+            full_range: full_range != 0,
+        });
+    }
+
+    let colorants = [tags.xyz(b"rXYZ")?, tags.xyz(b"gXYZ")?, tags.xyz(b"bXYZ")?];
+    let (primaries, _) = COLORANTS.iter().find(|(_, known)| {
+        known
+            .iter()
+            .flatten()
+            .zip(colorants.iter().flatten())
+            .all(|(a, b)| (a - b).abs() <= COLORANT_TOLERANCE)
+    })?;
+
+    let transfer = tone_curve(tags.get(b"rTRC")?)?;
+    if tone_curve(tags.get(b"gTRC")?)? != transfer || tone_curve(tags.get(b"bTRC")?)? != transfer {
+        return None;
+    }
+
+    if (*primaries, transfer) == (ColorPrimaries::BT709, TransferCharacteristic::SRGB) {
+        return Some(Encoding::Srgb);
+    }
+    Some(Encoding::Cicp {
+        primaries: *primaries,
+        transfer,
+        full_range: true,
+    })
+}
+
+// The tag table of a profile.
+struct Tags<'a>(&'a [u8]);
+
+impl<'a> Tags<'a> {
+    fn get(&self, signature: &[u8; 4]) -> Option<&'a [u8]> {
+        let count = read_u32(self.0, 128)? as usize;
+        (0..count)
+            .map(|i| 132 + i * 12)
+            .find(|&entry| self.0.get(entry..entry + 4) == Some(signature))
+            .and_then(|entry| {
+                let offset = read_u32(self.0, entry + 4)? as usize;
+                let size = read_u32(self.0, entry + 8)? as usize;
+                self.0.get(offset..offset.checked_add(size)?)
+            })
+    }
+
+    fn xyz(&self, signature: &[u8; 4]) -> Option<[f32; 3]> {
+        let tag = self.get(signature)?;
+        if tag.get(..4)? != b"XYZ " {
+            return None;
+        }
+        Some([
+            read_s15_fixed16(tag, 8)?,
+            read_s15_fixed16(tag, 12)?,
+            read_s15_fixed16(tag, 16)?,
+        ])
+    }
+}
+
+// Classifies a `curv` or `para` tone curve as the sRGB or linear transfer.
+fn tone_curve(tag: &[u8]) -> Option<TransferCharacteristic> {
+    match tag.get(..4)? {
+        b"curv" => {
+            let count = read_u32(tag, 8)? as usize;
+            match count {
+                0 => Some(TransferCharacteristic::Linear),
+                1 => (read_u16(tag, 12)? == 0x0100).then_some(TransferCharacteristic::Linear),
+                _ => {
+                    // A table, which has to follow the sRGB curve closely.
+                    let last = (count - 1) as f32;
+                    (0..count)
+                        .step_by((count / 16).max(1))
+                        .all(|i| {
+                            read_u16(tag, 12 + i * 2).map_or(false, |v| {
+                                (f32::from(v) / 65535.0 - srgb_to_linear(i as f32 / last)).abs()
+                                    < 0.005
+                            })
+                        })
+                        .then_some(TransferCharacteristic::SRGB)
+                }
+            }
+        }
+        b"para" => {
+            let function = read_u16(tag, 8)?;
+            let gamma = read_s15_fixed16(tag, 12)?;
+            match function {
+                0 if (gamma - 1.0).abs() < 0.01 => Some(TransferCharacteristic::Linear),
+                3 if (gamma - 2.4).abs() < 0.01
+                    && (read_s15_fixed16(tag, 28)? - 0.04045).abs() < 0.002 =>
+                {
+                    Some(TransferCharacteristic::SRGB)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<f32> {
+    Some(read_u32(data, offset)? as i32 as f32 / 65536.0)
+}
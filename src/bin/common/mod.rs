@@ -0,0 +1,308 @@
+//! Helpers shared by the command line tools.
+
+// `ssimulacra2_video` reads video frames only.
+#[allow(dead_code)]
+mod exif;
+#[allow(dead_code)]
+mod icc;
+
+use std::io::Cursor;
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageResult, RgbImage};
+use num_traits::FromPrimitive;
+use serde::Deserialize;
+use ssimulacra2::{
+    Clip, ColorPrimaries, ColorRamp, DistortionMap, Heatmap, LinearRgb, Rgb, Srgb8Image,
+    TransferCharacteristic,
+};
+
+pub use exif::Orientation;
+
+/// Color ramps for heatmaps, see [`ColorRamp`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ramp {
+    Gray,
+    Heat,
+    Viridis,
+    Turbo,
+}
+
+impl From<Ramp> for ColorRamp {
+    fn from(ramp: Ramp) -> Self {
+        match ramp {
+            Ramp::Gray => ColorRamp::Grayscale,
+            Ramp::Heat => ColorRamp::Heat,
+            Ramp::Viridis => ColorRamp::Viridis,
+            Ramp::Turbo => ColorRamp::Turbo,
+        }
+    }
+}
+
+/// The heatmap settings of the `--color-ramp` and `--heatmap-max` options.
+/// Without `max`, the largest value of the map is used as the end of the ramp.
+pub fn heatmap(ramp: Ramp, max: Option<f32>) -> Heatmap {
+    Heatmap {
+        ramp: ramp.into(),
+        clip: max.map_or(Clip::Max, |max| Clip::Range { min: 0.0, max }),
+        overlay: false,
+    }
+}
+
+/// Renders `map` as an RGB image. Without `max`, the largest value of the
+/// map is used as the end of the ramp.
+pub fn heatmap_image(map: &DistortionMap, ramp: Ramp, max: Option<f32>) -> RgbImage {
+    let pixels = map
+        .to_rgba8(&heatmap(ramp, max))
+        .into_iter()
+        .flat_map(|[r, g, b, _]| [r, g, b])
+        .collect();
+    RgbImage::from_raw(map.width() as u32, map.height() as u32, pixels)
+        .expect("Resolution and data size match")
+}
+
+/// Writes `map` as an RGB image, in a format chosen by the extension of `path`.
+/// Without `max`, the largest value of the map is used as the end of the ramp.
+// `ssimulacra2_server` returns heatmaps in its responses instead.
+#[allow(dead_code)]
+pub fn save_heatmap(
+    map: &DistortionMap,
+    path: &Path,
+    ramp: Ramp,
+    max: Option<f32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    heatmap_image(map, ramp, max)
+        .save(path)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(())
+}
+
+/// How the samples of a decoded image map to light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// sRGB, assumed for images which don't say otherwise.
+    Srgb,
+    /// A pure power function with the given decoding exponent, e.g. 2.2.
+    Gamma(f32),
+    /// Color primaries and transfer function, as given by the code points of
+    /// a `cICP` chunk or a recognized ICC profile.
+    Cicp {
+        primaries: ColorPrimaries,
+        transfer: TransferCharacteristic,
+        full_range: bool,
+    },
+    /// Linear light with BT.709 primaries, as in OpenEXR and Radiance HDR files.
+    Linear,
+}
+
+/// An image decoded by [`decode_image`].
+// `ssimulacra2_video` reads video frames only.
+#[allow(dead_code)]
+pub struct Decoded {
+    /// The pixels, rotated and flipped upright according to `orientation`.
+    pub image: DynamicImage,
+    pub encoding: Encoding,
+    /// The Exif orientation of the file, which has been applied already.
+    // `ssimulacra2_server` only needs the pixels.
+    #[allow(dead_code)]
+    pub orientation: Orientation,
+    /// Whether the image has an ICC profile which was not recognized, and
+    /// which was ignored in favor of sRGB.
+    #[allow(dead_code)]
+    pub unknown_icc: bool,
+}
+
+/// Decodes an image in any format supported by the `image` crate, together
+/// with its [`Encoding`], and applies its Exif orientation.
+///
+/// The encoding is taken from the `cICP` chunk of a PNG, then from an ICC
+/// profile in any format, then from the `sRGB` and `gAMA` chunks of a PNG.
+/// ICC profiles are recognized by their `cicp` tag or their colorants and
+/// tone curves, see [`icc::encoding`]. Images without any of these are taken
+/// as sRGB, and floating point images as linear light.
+///
+/// # Errors
+/// - If the image cannot be decoded
+/// - If a `cICP` or `gAMA` chunk is invalid
+// `ssimulacra2_video` reads video frames only.
+#[allow(dead_code)]
+pub fn decode_image(data: &[u8]) -> Result<Decoded, Box<dyn std::error::Error>> {
+    let (img, icc) = decode_with_icc(data)?;
+    let chunks = png_chunks(data);
+    let mut unknown_icc = false;
+    let encoding = match (&img, chunks.cicp, icc) {
+        (DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_), _, _) => Encoding::Linear,
+        (_, Some(cicp), _) => cicp_encoding(cicp)?,
+        (_, None, Some(profile)) => icc::encoding(&profile).unwrap_or_else(|| {
+            unknown_icc = true;
+            Encoding::Srgb
+        }),
+        (_, None, None) => png_encoding(&chunks)?,
+    };
+    let orientation = Orientation::read(data);
+    Ok(Decoded {
+        image: orientation.apply(img),
+        encoding,
+        orientation,
+        unknown_icc,
+    })
+}
+
+// Decodes an image together with its ICC profile, for the formats whose
+// decoders read profiles.
+fn decode_with_icc(data: &[u8]) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    fn with_icc<'a>(
+        mut decoder: impl ImageDecoder<'a>,
+    ) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+        let icc = decoder.icc_profile();
+        Ok((DynamicImage::from_decoder(decoder)?, icc))
+    }
+
+    match image::guess_format(data)? {
+        ImageFormat::Png => with_icc(PngDecoder::new(data)?),
+        ImageFormat::Jpeg => with_icc(JpegDecoder::new(data)?),
+        ImageFormat::WebP => with_icc(WebPDecoder::new(data)?),
+        ImageFormat::Tiff => with_icc(TiffDecoder::new(Cursor::new(data))?),
+        format => Ok((image::load_from_memory_with_format(data, format)?, None)),
+    }
+}
+
+fn cicp_encoding(cicp: &[u8]) -> Result<Encoding, Box<dyn std::error::Error>> {
+    let &[primaries, transfer, 0, full_range] = cicp else {
+        return Err("invalid cICP chunk, only RGB is supported".into());
+    };
+    let primaries = ColorPrimaries::from_u8(primaries)
+        .filter(|&primaries| primaries != ColorPrimaries::Unspecified)
+        .ok_or_else(|| format!("unsupported color primaries {primaries} in cICP chunk"))?;
+    let transfer = TransferCharacteristic::from_u8(transfer)
+        .filter(|&transfer| transfer != TransferCharacteristic::Unspecified)
+        .ok_or_else(|| format!("unsupported transfer function {transfer} in cICP chunk"))?;
+    Ok(Encoding::Cicp {
+        primaries,
+        transfer,
+        full_range: full_range != 0,
+    })
+}
+
+// Reads the encoding of a PNG without `cICP` chunk or ICC profile from its
+// `sRGB` and `gAMA` chunks.
+fn png_encoding(chunks: &PngChunks<'_>) -> Result<Encoding, Box<dyn std::error::Error>> {
+    if chunks.srgb {
+        return Ok(Encoding::Srgb);
+    }
+    match chunks.gamma {
+        Some(&[a, b, c, d]) => {
+            let gamma = u32::from_be_bytes([a, b, c, d]);
+            if gamma == 0 {
+                return Err("invalid gamma 0 in gAMA chunk".into());
+            }
+            Ok(Encoding::Gamma(100_000.0 / gamma as f32))
+        }
+        _ => Ok(Encoding::Srgb),
+    }
+}
+
+#[derive(Default)]
+struct PngChunks<'a> {
+    cicp: Option<&'a [u8]>,
+    gamma: Option<&'a [u8]>,
+    exif: Option<&'a [u8]>,
+    srgb: bool,
+}
+
+// Collects the color and Exif chunks before the image data of a PNG. Anything that is
+// not a PNG, or is truncated, ends the search without an error, as the image
+// has been decoded already.
+fn png_chunks(data: &[u8]) -> PngChunks<'_> {
+    let mut chunks = PngChunks::default();
+    let Some(mut rest) = data.strip_prefix(b"\x89PNG\r\n\x1a\n") else {
+        return chunks;
+    };
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let Some(body) = rest.get(8..8 + length) else {
+            break;
+        };
+        match &rest[4..8] {
+            b"cICP" => chunks.cicp = Some(body),
+            b"gAMA" => chunks.gamma = Some(body),
+            b"eXIf" => chunks.exif = Some(body),
+            b"sRGB" => chunks.srgb = true,
+            b"IDAT" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + length..).unwrap_or_default();
+    }
+    chunks
+}
+
+/// Converts a decoded image to linear RGB. 8-bit sRGB images take the lookup
+/// table path of `Srgb8Image`, everything else is converted to float first,
+/// so that 16-bit images keep their precision.
+// `ssimulacra2_video` reads video frames only.
+#[allow(dead_code)]
+pub fn to_linear_rgb(
+    img: DynamicImage,
+    encoding: Encoding,
+) -> Result<LinearRgb, Box<dyn std::error::Error>> {
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+    let bit_depth = if img.color().bytes_per_pixel() > img.color().channel_count() {
+        16
+    } else {
+        8
+    };
+    match (&img, encoding) {
+        (DynamicImage::ImageRgb8(buf), Encoding::Srgb) => {
+            return Ok(Srgb8Image::from_rgb(buf.as_raw(), width, height)?.try_into()?);
+        }
+        (DynamicImage::ImageRgba8(buf), Encoding::Srgb) => {
+            return Ok(Srgb8Image::from_rgba(buf.as_raw(), width, height)?.try_into()?);
+        }
+        _ => {}
+    }
+
+    let mut data: Vec<[f32; 3]> = img
+        .into_rgb32f()
+        .chunks_exact(3)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+        .collect();
+    let (transfer, primaries) = match encoding {
+        Encoding::Srgb => (TransferCharacteristic::SRGB, ColorPrimaries::BT709),
+        Encoding::Linear => (TransferCharacteristic::Linear, ColorPrimaries::BT709),
+        Encoding::Gamma(exponent) => {
+            for pix in data.iter_mut().flatten() {
+                *pix = pix.max(0.0).powf(exponent);
+            }
+            (TransferCharacteristic::Linear, ColorPrimaries::BT709)
+        }
+        Encoding::Cicp {
+            primaries,
+            transfer,
+            full_range,
+        } => {
+            if !full_range {
+                expand_range(&mut data, bit_depth);
+            }
+            (transfer, primaries)
+        }
+    };
+    let rgb = Rgb::new(data, width, height, transfer, primaries)?;
+    Ok(LinearRgb::try_from(rgb)?)
+}
+
+// Expands limited range samples, normalized to the full range of the bit
+// depth, to the full range.
+fn expand_range(data: &mut [[f32; 3]], bit_depth: u32) {
+    let max = ((1u32 << bit_depth) - 1) as f32;
+    let scale = (1u32 << (bit_depth - 8)) as f32;
+    for pix in data.iter_mut().flatten() {
+        *pix = pix.mul_add(max, -16.0 * scale) / (219.0 * scale);
+    }
+}
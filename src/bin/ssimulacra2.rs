@@ -0,0 +1,668 @@
+use std::fs;
+use std::io::Read;
+use std::num::NonZeroU16;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use ssimulacra2::{
+    CacheKey, CubeRoot, Denoise, DiskScoreCache, LinearRgb, Reference, Report, Rescale,
+    ResizeFilter, ScoreCache, Settings, ShiftCheck, Ssimulacra2Builder, Viewing,
+};
+
+mod common;
+
+use common::{decode_image, heatmap, save_heatmap, to_linear_rgb, Decoded, Orientation, Ramp};
+
+/// Computes the SSIMULACRA2 score of a distorted image compared to its source.
+///
+/// Scores range from 100 (identical) down to negative values for very strong
+/// distortion. Any format supported by the `image` crate (PNG, JPEG, WebP, ...) can be
+/// read. AVIF and JPEG XL images are decoded with `avifdec` and `djxl` at full bit depth.
+///
+/// If multiple distorted images or a directory are given, every image is scored
+/// against the source and a table sorted from best to worst is printed.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// The original image
+    source: PathBuf,
+
+    /// The distorted images, or directories containing them
+    #[arg(required = true)]
+    distorted: Vec<PathBuf>,
+
+    /// Print the result as JSON instead of plain text
+    #[arg(long)]
+    json: bool,
+
+    /// Write the scores of all distorted images to a CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
+
+    /// Write a self-contained HTML report with the scores, the errors of
+    /// every scale and a heatmap thumbnail of every distorted image
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Write a heatmap of the distortion to an image file
+    #[arg(long, value_name = "FILE")]
+    heatmap: Option<PathBuf>,
+
+    /// Color ramp of the heatmap
+    #[arg(long, value_enum, default_value = "heat")]
+    color_ramp: Ramp,
+
+    /// Distortion value at the end of the color ramp [default: maximum of the map]
+    #[arg(long, value_name = "VALUE")]
+    heatmap_max: Option<f32>,
+
+    /// Match the brightness and contrast of the distorted image to the source
+    /// before scoring, and print the fitted gain and offset
+    #[arg(long)]
+    normalize_levels: bool,
+
+    /// Check the distorted image for a global shift relative to the source,
+    /// and warn about it or move it back before scoring
+    #[arg(long, value_enum, value_name = "MODE")]
+    shift: Option<ShiftMode>,
+
+    /// Scale distorted images of another resolution to the resolution of the
+    /// source with the given filter, instead of failing
+    #[arg(long, value_enum, value_name = "FILTER")]
+    rescale: Option<Filter>,
+
+    /// Crop black bars or other uniform borders that only one of the images
+    /// has from both images before scoring
+    #[arg(long)]
+    crop_borders: bool,
+
+    /// Denoise the source with a light edge-preserving filter before scoring,
+    /// so that encoders which remove the noise of a noisy capture are not
+    /// penalized for it
+    #[arg(long, value_enum, value_name = "STRENGTH", default_value = "none")]
+    denoise_reference: DenoiseStrength,
+
+    /// Score the images as seen on a display with this many pixels per degree
+    /// of the field of view, e.g. about 80 for a phone at 25 cm, instead of 1:1
+    /// on a desktop monitor with about 40
+    #[arg(long, value_name = "PPD")]
+    pixels_per_degree: Option<NonZeroU16>,
+
+    /// How to compute the cube roots of the XYB conversion; the approximate
+    /// methods change scores by up to about 0.06
+    #[arg(long, value_enum, value_name = "METHOD", default_value = "exact")]
+    cube_root: CubeRootMethod,
+
+    /// Blur and compare large images in tiles of rows on all cores, which
+    /// changes scores only by rounding
+    #[arg(long)]
+    tiles: bool,
+
+    /// Reuse the scores of images scored before with the same source and
+    /// options from this file, and add new scores to it
+    #[arg(long, value_name = "FILE")]
+    cache: Option<PathBuf>,
+
+    /// Path of the `avifdec` executable used to decode AVIF images
+    #[arg(long, value_name = "PATH", default_value = "avifdec")]
+    avifdec: PathBuf,
+
+    /// Path of the `djxl` executable used to decode JPEG XL images
+    #[arg(long, value_name = "PATH", default_value = "djxl")]
+    djxl: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Filter {
+    /// Lanczos with three lobes, sharp when upscaling
+    Lanczos,
+    /// Area averaging, for downscaling by whole factors
+    Area,
+}
+
+impl From<Filter> for ResizeFilter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Lanczos => ResizeFilter::Lanczos3,
+            Filter::Area => ResizeFilter::Area,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ShiftMode {
+    /// Warn if the images are shifted, but score them as they are
+    Warn,
+    /// Move the distorted image back by the shift before scoring
+    Compensate,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DenoiseStrength {
+    /// Score against the source as it is
+    None,
+    /// Removes the grain of well-lit captures
+    Light,
+    /// For captures in low light
+    Strong,
+}
+
+impl From<DenoiseStrength> for Denoise {
+    fn from(strength: DenoiseStrength) -> Self {
+        match strength {
+            DenoiseStrength::None => Denoise::None,
+            DenoiseStrength::Light => Denoise::Light,
+            DenoiseStrength::Strong => Denoise::Strong,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CubeRootMethod {
+    /// Full precision, like the reference implementation
+    Exact,
+    /// A single refinement step, which converts to XYB a quarter faster
+    Approximate,
+    /// Interpolation in a table of cube roots
+    Table,
+}
+
+impl From<CubeRootMethod> for CubeRoot {
+    fn from(method: CubeRootMethod) -> Self {
+        match method {
+            CubeRootMethod::Exact => CubeRoot::Exact,
+            CubeRootMethod::Approximate => CubeRoot::Approximate,
+            CubeRootMethod::Table => CubeRoot::Table,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn viewing(args: &Args) -> Viewing {
+    args.pixels_per_degree
+        .map_or(Viewing::Native, Viewing::PixelsPerDegree)
+}
+
+// Brings a distorted image to the resolution it is scored at under the
+// viewing conditions, rescaling it to the source first like a session does.
+fn view_distorted(args: &Args, distorted: LinearRgb, source: (usize, usize)) -> LinearRgb {
+    let viewing = viewing(args);
+    if viewing == Viewing::Native {
+        return distorted;
+    }
+    let distorted = match args.rescale {
+        Some(filter) if (distorted.width(), distorted.height()) != source => {
+            ResizeFilter::from(filter).resize(&distorted, source.0, source.1)
+        }
+        _ => distorted,
+    };
+    viewing.apply(&distorted).unwrap_or(distorted)
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let distorted = expand_directories(&args.distorted)?;
+    match distorted.as_slice() {
+        [single] if args.csv.is_none() && args.cache.is_none() && !args.distorted[0].is_dir() => {
+            run_single(args, single)
+        }
+        _ => run_batch(args, &distorted),
+    }
+}
+
+fn run_single(args: &Args, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let (source, source_orientation) = load_image(args, &args.source)?;
+    let (distorted, distorted_orientation) = load_image(args, path)?;
+    check_orientation(
+        (&source, source_orientation),
+        (&distorted, distorted_orientation),
+        path,
+    )?;
+    let mut session = Ssimulacra2Builder::new(source.width(), source.height())
+        .with_level_normalization(args.normalize_levels)
+        .with_shift_check(match args.shift {
+            None => ShiftCheck::None,
+            Some(ShiftMode::Warn) => ShiftCheck::Detect,
+            Some(ShiftMode::Compensate) => ShiftCheck::Compensate,
+        })
+        .with_rescale(
+            args.rescale
+                .map_or(Rescale::None, |filter| Rescale::ToSource(filter.into())),
+        )
+        .with_border_crop(args.crop_borders)
+        .with_cube_root(args.cube_root.into())
+        .with_reference_denoise(args.denoise_reference.into())
+        .with_viewing(viewing(args))
+        .with_tiling(args.tiles)
+        .build()?;
+    let detailed = if args.heatmap.is_some() || args.report.is_some() {
+        session.score_with_map(source, distorted)?
+    } else {
+        session.score_detailed(source, distorted)?
+    };
+    if let (Some(path), Some(map)) = (&args.heatmap, &detailed.map) {
+        save_heatmap(map, path, args.color_ramp, args.heatmap_max)?;
+    }
+    if let Some(report_path) = &args.report {
+        let mut report = new_report(args);
+        report.add_image(path.display().to_string(), &detailed);
+        report
+            .save(report_path)
+            .map_err(|e| format!("{}: {e}", report_path.display()))?;
+    }
+
+    if let Some(shift) = detailed.shift.filter(|shift| shift.is_significant()) {
+        eprintln!(
+            "Warning: the distorted image is shifted by ({}, {}) pixels relative to the source{}",
+            shift.x,
+            shift.y,
+            if shift.compensated {
+                ", which was compensated"
+            } else {
+                "; pass --shift compensate to score it without the shift"
+            }
+        );
+    }
+
+    if let Some(crop) = detailed.crop.filter(|crop| !crop.is_empty()) {
+        eprintln!(
+            "Cropped {} pixels at the left, {} at the top, {} at the right and {} at the bottom",
+            crop.left, crop.top, crop.right, crop.bottom
+        );
+    }
+
+    let score = detailed.score;
+    match (args.json, detailed.levels) {
+        (true, Some(levels)) => println!(
+            "{{\"score\":{score:.8},\"gain\":{:.6},\"offset\":{:.6}}}",
+            levels.gain, levels.offset
+        ),
+        (true, None) => println!("{{\"score\":{score:.8}}}"),
+        (false, Some(levels)) => println!(
+            "{score:.8}\nGain: {:.6}, offset: {:.6}",
+            levels.gain, levels.offset
+        ),
+        (false, None) => println!("{score:.8}"),
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BatchResult {
+    path: String,
+    score: f64,
+}
+
+// Scores every image against one shared reference, best first.
+fn run_batch(args: &Args, distorted: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    if args.heatmap.is_some() || args.normalize_levels || args.shift.is_some() || args.crop_borders
+    {
+        return Err(
+            "--heatmap, --normalize-levels, --shift and --crop-borders can only be used with a \
+             single distorted image"
+                .into(),
+        );
+    }
+
+    let mut cache = args
+        .cache
+        .as_ref()
+        .map(|path| DiskScoreCache::open(path).map_err(|e| format!("{}: {e}", path.display())))
+        .transpose()?;
+    let (source, source_orientation) = load_image(args, &args.source)?;
+    let source_dimensions = (source.width(), source.height());
+    let source_hash = cache.as_ref().map(|_| CacheKey::image_hash(&source));
+    let settings = Settings {
+        rescale: args
+            .rescale
+            .map_or(Rescale::None, |filter| Rescale::ToSource(filter.into())),
+        cube_root: args.cube_root.into(),
+        reference_denoise: args.denoise_reference.into(),
+        viewing: viewing(args),
+        ..Settings::default()
+    };
+    // Only built once an image is not found in the cache.
+    let mut source = Some(source);
+    let mut reference = None;
+    let mut results = Vec::with_capacity(distorted.len());
+    // Filled in the order of the images, rendering their thumbnails right
+    // away instead of keeping their maps.
+    let mut report = args.report.as_ref().map(|_| new_report(args));
+    let scored = distorted.iter().try_for_each(|path| {
+        let (distorted, orientation) = load_image(args, path)?;
+        check_orientation(
+            (source_dimensions, source_orientation),
+            (&distorted, orientation),
+            path,
+        )?;
+        let key = source_hash.map(|reference| CacheKey {
+            reference,
+            distorted: CacheKey::image_hash(&distorted),
+            settings: CacheKey::settings_hash(settings),
+        });
+        let cached = cache
+            .as_ref()
+            .zip(key)
+            .and_then(|(cache, key)| cache.get(&key));
+        let score = match cached {
+            Some(score) => score,
+            None => {
+                let reference = match (&mut reference, source.take()) {
+                    (Some(reference), _) => reference,
+                    (None, Some(mut source)) => {
+                        if let Some(scaled) = settings.viewing.apply(&source) {
+                            source = scaled;
+                        }
+                        settings.reference_denoise.apply(&mut source);
+                        let mut new = Reference::with_cube_root(source, settings.cube_root)?
+                            .with_tiling(args.tiles);
+                        if let Some(filter) = args.rescale {
+                            new = new.with_rescale(filter.into());
+                        }
+                        reference.insert(new)
+                    }
+                    (None, None) => unreachable!("the reference is built from the source"),
+                };
+                let distorted = view_distorted(args, distorted, source_dimensions);
+                let score = match &mut report {
+                    Some(report) => {
+                        let detailed = reference
+                            .compare_detailed_with_map(distorted)
+                            .map_err(|e| format!("{}: {e}", path.display()))?;
+                        report.add_image(path.display().to_string(), &detailed);
+                        detailed.score
+                    }
+                    None => reference
+                        .compare(distorted)
+                        .map_err(|e| format!("{}: {e}", path.display()))?,
+                };
+                if let (Some(cache), Some(key)) = (&mut cache, key) {
+                    cache.insert(key, score);
+                }
+                score
+            }
+        };
+        if let (Some(report), Some(score)) = (&mut report, cached) {
+            report.add_score(path.display().to_string(), score);
+        }
+        results.push(BatchResult {
+            path: path.display().to_string(),
+            score,
+        });
+        Ok::<_, Box<dyn std::error::Error>>(())
+    });
+    // Keep the scores computed so far, even if scoring an image failed.
+    if let Some(cache) = &mut cache {
+        cache
+            .save()
+            .map_err(|e| format!("{}: {e}", cache.path().display()))?;
+    }
+    scored?;
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    if let Some(path) = &args.csv {
+        let mut writer =
+            csv::Writer::from_path(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        for result in &results {
+            writer.serialize(result)?;
+        }
+        writer.flush()?;
+    }
+
+    if let (Some(path), Some(report)) = (&args.report, &report) {
+        report
+            .save(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string(&results)?);
+    } else {
+        for result in &results {
+            println!("{:>12.8}  {}", result.score, result.path);
+        }
+    }
+
+    Ok(())
+}
+
+// Starts a report on the distorted images, with thumbnails in the colors of
+// the heatmap options.
+fn new_report(args: &Args) -> Report {
+    Report::new(format!("Comparison against {}", args.source.display()))
+        .with_heatmap(heatmap(args.color_ramp, args.heatmap_max))
+}
+
+// Replaces directories with the images they contain, sorted by name.
+fn expand_directories(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            expanded.push(path.clone());
+            continue;
+        }
+
+        let entries = fs::read_dir(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut images = Vec::new();
+        for entry in entries {
+            let entry = entry?.path();
+            let is_jxl = entry
+                .extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("jxl"));
+            if entry.is_file() && (is_jxl || image::ImageFormat::from_path(&entry).is_ok()) {
+                images.push(entry);
+            }
+        }
+        images.sort();
+        expanded.extend(images);
+    }
+
+    if expanded.is_empty() {
+        return Err("no distorted images found".into());
+    }
+    Ok(expanded)
+}
+
+// Loads an image as linear RGB, rotated and flipped upright according to its
+// Exif orientation.
+fn load_image(
+    args: &Args,
+    path: &Path,
+) -> Result<(LinearRgb, Orientation), Box<dyn std::error::Error>> {
+    let decoded = match still_format(path)? {
+        Some(format) => decode_external(args, path, format)?,
+        None => fs::read(path)
+            .map_err(|e| e.into())
+            .and_then(|data| decode_image(&data))
+            .map_err(|e| format!("{}: {e}", path.display()))?,
+    };
+    if decoded.unknown_icc {
+        eprintln!(
+            "Warning: the ICC profile of {} is not sRGB, Display P3 or BT.2020 and is not \
+             applied; the image is scored as sRGB",
+            path.display()
+        );
+    }
+    let image = to_linear_rgb(decoded.image, decoded.encoding)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok((image, decoded.orientation))
+}
+
+// Fails if the images only have different resolutions because their Exif
+// orientations disagree, and notes on stderr when orientations that differ
+// have been applied, as either image may have had its pixels rotated without
+// updating the tag.
+fn check_orientation(
+    source: (impl Dimensions, Orientation),
+    distorted: (impl Dimensions, Orientation),
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if source.1 == distorted.1 {
+        return Ok(());
+    }
+    let (width, height) = source.0.dimensions();
+    if distorted.0.dimensions() == (height, width) && width != height {
+        return Err(format!(
+            "{}: the Exif orientation of the image ({}) disagrees with that of the source ({}), \
+             which leaves them rotated against each other after applying it; remove the \
+             orientation tag from the image whose pixels are already upright",
+            path.display(),
+            distorted.1.description(),
+            source.1.description()
+        )
+        .into());
+    }
+    eprintln!(
+        "Note: applied the Exif orientations of {} ({}) and of the source ({})",
+        path.display(),
+        distorted.1.description(),
+        source.1.description()
+    );
+    Ok(())
+}
+
+trait Dimensions {
+    fn dimensions(&self) -> (usize, usize);
+}
+
+impl Dimensions for &LinearRgb {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+}
+
+impl Dimensions for (usize, usize) {
+    fn dimensions(&self) -> (usize, usize) {
+        *self
+    }
+}
+
+// Still image formats which the `image` crate cannot decode without native
+// libraries, and which are decoded with their reference decoders instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StillFormat {
+    Avif,
+    Jxl,
+}
+
+impl StillFormat {
+    const fn name(self) -> &'static str {
+        match self {
+            StillFormat::Avif => "AVIF",
+            StillFormat::Jxl => "JPEG XL",
+        }
+    }
+}
+
+// Tells AVIF and JPEG XL images apart by their signature, not their extension.
+fn still_format(path: &Path) -> Result<Option<StillFormat>, Box<dyn std::error::Error>> {
+    let mut header = Vec::with_capacity(64);
+    fs::File::open(path)
+        .and_then(|file| file.take(64).read_to_end(&mut header))
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(detect_still_format(&header))
+}
+
+fn detect_still_format(header: &[u8]) -> Option<StillFormat> {
+    const JXL_CONTAINER: &[u8] = b"\0\0\0\x0cJXL \r\n\x87\n";
+    if header.starts_with(&[0xff, 0x0a]) || header.starts_with(JXL_CONTAINER) {
+        return Some(StillFormat::Jxl);
+    }
+
+    // An ISOBMFF `ftyp` box listing `avif` or `avis` as major or compatible brand.
+    if header.get(4..8) != Some(b"ftyp") {
+        return None;
+    }
+    let size = u32::from_be_bytes(header[..4].try_into().expect("Four bytes")) as usize;
+    let brands = header.get(8..size.min(header.len()))?;
+    brands
+        .chunks_exact(4)
+        .any(|brand| brand == b"avif" || brand == b"avis")
+        .then_some(StillFormat::Avif)
+}
+
+// Decodes an AVIF or JPEG XL image to a temporary 16-bit PNG, so that images of
+// more than 8 bits keep their precision. `djxl` converts the image to sRGB with
+// the color management of libjxl, taking any ICC profile into account.
+// `avifdec` copies an ICC profile into the PNG, where it is recognized like
+// that of any other PNG.
+fn decode_external(
+    args: &Args,
+    path: &Path,
+    format: StillFormat,
+) -> Result<Decoded, Box<dyn std::error::Error>> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let output = std::env::temp_dir().join(format!(
+        "ssimulacra2-{}-{}.png",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let (decoder, mut command) = match format {
+        StillFormat::Avif => {
+            let mut command = Command::new(&args.avifdec);
+            command.args(["--depth", "16"]).arg(path).arg(&output);
+            (&args.avifdec, command)
+        }
+        StillFormat::Jxl => {
+            let mut command = Command::new(&args.djxl);
+            command
+                .arg(path)
+                .arg(&output)
+                .args(["--bits_per_sample=16", "--color_space=RGB_D65_SRG_Rel_SRG"]);
+            (&args.djxl, command)
+        }
+    };
+    let result = run_decoder(&mut command, decoder, format).and_then(|()| read_png(&output));
+    let _ = fs::remove_file(&output);
+
+    Ok(result.map_err(|e| format!("{}: {e}", path.display()))?)
+}
+
+fn run_decoder(
+    command: &mut Command,
+    decoder: &Path,
+    format: StillFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = command.stdin(Stdio::null()).output().map_err(|e| {
+        format!(
+            "decoding {} images requires {}, but it could not be run: {e}; \
+             install it, pass its path with --{} or convert the image to PNG",
+            format.name(),
+            decoder.display(),
+            if format == StillFormat::Avif {
+                "avifdec"
+            } else {
+                "djxl"
+            },
+        )
+    })?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed ({}): {}",
+            decoder.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn read_png(path: &Path) -> Result<Decoded, Box<dyn std::error::Error>> {
+    decode_image(&fs::read(path)?)
+}
@@ -0,0 +1,224 @@
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+
+use axum::body::Bytes;
+use axum::extract::multipart::MultipartRejection;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{DefaultBodyLimit, Multipart, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use ssimulacra2::{AsyncSsimulacra2, LinearRgb, Ssimulacra2Error};
+use tokio::sync::Semaphore;
+
+mod common;
+
+use common::{decode_image, heatmap_image, to_linear_rgb, Ramp};
+
+/// Serves SSIMULACRA2 scores over HTTP.
+///
+/// `POST /score` takes a multipart form with a `source` and a `distorted`
+/// image, in any format supported by the `image` crate, and responds with
+/// JSON such as `{"score":87.5}`. Errors are returned as `{"error":"..."}`.
+///
+/// With `?heatmap=true`, the response also contains a PNG heatmap of the
+/// distortion, base64-encoded, in `heatmap`. Its colors are chosen with the
+/// `color_ramp` (gray, heat, viridis or turbo) and `heatmap_max` query parameters,
+/// as with the `ssimulacra2` tool.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// Maximum number of requests scored at once; further requests wait [default: number of CPU cores]
+    #[arg(long)]
+    workers: Option<NonZeroUsize>,
+
+    /// Maximum size of a request in bytes
+    #[arg(long, value_name = "BYTES", default_value = "67108864")]
+    max_request_size: usize,
+}
+
+#[derive(Clone)]
+struct AppState {
+    scorer: AsyncSsimulacra2,
+    workers: Arc<Semaphore>,
+}
+
+#[derive(Deserialize)]
+struct ScoreParams {
+    #[serde(default)]
+    heatmap: bool,
+    color_ramp: Option<Ramp>,
+    heatmap_max: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct ScoreResponse {
+    score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heatmap: Option<String>,
+}
+
+// An error response with a JSON body.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.1 }));
+        (self.0, body).into_response()
+    }
+}
+
+impl From<Ssimulacra2Error> for ApiError {
+    fn from(err: Ssimulacra2Error) -> Self {
+        let status = match err {
+            Ssimulacra2Error::Cancelled => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let workers = args.workers.map_or_else(
+        || thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        NonZeroUsize::get,
+    );
+    let state = AppState {
+        scorer: AsyncSsimulacra2::new().with_max_idle_sessions(workers),
+        workers: Arc::new(Semaphore::new(workers)),
+    };
+    let app = Router::new()
+        .route("/score", post(score))
+        .layer(DefaultBodyLimit::max(args.max_request_size))
+        .with_state(state);
+
+    let server = axum::Server::try_bind(&args.listen)?.serve(app.into_make_service());
+    eprintln!("Listening on http://{}", server.local_addr());
+    server.await?;
+    Ok(())
+}
+
+async fn score(
+    State(state): State<AppState>,
+    params: Result<Query<ScoreParams>, QueryRejection>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Json<ScoreResponse>, ApiError> {
+    // Rejections are turned into errors here to respond with JSON as well.
+    let Query(params) = params.map_err(|e| ApiError(e.status(), e.body_text()))?;
+    let multipart = multipart.map_err(|e| ApiError(e.status(), e.body_text()))?;
+    let (source, distorted) = read_images(multipart).await?;
+
+    // Decoding takes about as long as scoring, so it is limited by the
+    // workers as well and kept off the async threads.
+    let _permit = state
+        .workers
+        .acquire()
+        .await
+        .expect("The semaphore is never closed");
+    let (source, distorted) = blocking(move || {
+        Ok::<_, ApiError>((decode("source", &source)?, decode("distorted", &distorted)?))
+    })
+    .await??;
+
+    if !params.heatmap {
+        let score = state.scorer.score(source, distorted).await?;
+        return Ok(Json(ScoreResponse {
+            score,
+            heatmap: None,
+        }));
+    }
+
+    let detailed = state.scorer.score_with_map(source, distorted).await?;
+    let map = detailed.map.expect("Scoring with a map returns a map");
+    let ramp = params.color_ramp.unwrap_or(Ramp::Heat);
+    let heatmap = blocking(move || {
+        let mut png = Cursor::new(Vec::new());
+        heatmap_image(&map, ramp, params.heatmap_max)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .map(|()| BASE64.encode(png.into_inner()))
+    })
+    .await?
+    .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(ScoreResponse {
+        score: detailed.score,
+        heatmap: Some(heatmap),
+    }))
+}
+
+// Reads the `source` and `distorted` fields of the form.
+async fn read_images(mut multipart: Multipart) -> Result<(Bytes, Bytes), ApiError> {
+    let bad_request = |message: String| ApiError(StatusCode::BAD_REQUEST, message);
+
+    let mut source = None;
+    let mut distorted = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| bad_request(e.to_string()))?
+    {
+        let image = match field.name() {
+            Some("source") => &mut source,
+            Some("distorted") => &mut distorted,
+            name => {
+                return Err(bad_request(format!(
+                    "unexpected field {}",
+                    name.unwrap_or_default()
+                )))
+            }
+        };
+        *image = Some(
+            field
+                .bytes()
+                .await
+                .map_err(|e| bad_request(e.to_string()))?,
+        );
+    }
+
+    match (source, distorted) {
+        (Some(source), Some(distorted)) => Ok((source, distorted)),
+        (None, _) => Err(bad_request("missing field source".to_owned())),
+        (_, None) => Err(bad_request("missing field distorted".to_owned())),
+    }
+}
+
+fn decode(name: &str, data: &[u8]) -> Result<LinearRgb, ApiError> {
+    decode_image(data)
+        .and_then(|decoded| to_linear_rgb(decoded.image, decoded.encoding))
+        .map_err(|e| ApiError(StatusCode::UNPROCESSABLE_ENTITY, format!("{name}: {e}")))
+}
+
+// Runs `f` on the blocking thread pool.
+async fn blocking<R: Send + 'static>(
+    f: impl FnOnce() -> R + Send + 'static,
+) -> Result<R, ApiError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
@@ -0,0 +1,2667 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitCode, Stdio};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use ssimulacra2::{
+    compute_frame_xpsnr, ColorPrimaries, CompanionMetrics, DetailedScore, Frame, FramePipeline,
+    FrameScore, FrameSelection, LinearRgb, LumaHistogram, MatrixCoefficients, Pixel, Plane,
+    Progress, Report, SceneDetector, SceneSummary, ScoreAggregator, ScoreSummary, Ssimulacra2,
+    Ssimulacra2Error, TransferCharacteristic, XpsnrScore, Yuv, YuvConfig,
+};
+
+mod common;
+
+use common::{save_heatmap, Ramp};
+
+/// Computes SSIMULACRA2 scores for every frame of a distorted video compared to its source.
+///
+/// Both inputs must have the same resolution. Frames are paired by index,
+/// or by timestamp with `--pair-by timestamp`; if one video is longer than
+/// the other, the extra frames are ignored.
+///
+/// Inputs are read as Y4M if they start with a Y4M header. Matroska, WebM,
+/// MP4, MOV, AVI, IVF and MPEG-TS files are decoded with the `ffmpeg`
+/// executable. Anything else is read as raw planar frames described by
+/// `--width`, `--height` and `--pix-fmt`. Use `-` to read from stdin, e.g.
+/// `ffmpeg -i in.mkv -f rawvideo -pix_fmt yuv420p -`.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// The original video, or `-` for stdin
+    source: PathBuf,
+
+    /// The distorted video, or `-` for stdin
+    distorted: PathBuf,
+
+    /// Width of raw input frames
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Height of raw input frames
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Pixel format of raw input frames
+    #[arg(long, value_enum)]
+    pix_fmt: Option<PixelFormat>,
+
+    /// Frame rate of raw input, used for the timestamps in per-frame output
+    #[arg(long)]
+    fps: Option<f64>,
+
+    /// Treat both inputs as full range, regardless of their headers
+    #[arg(long)]
+    full_range: bool,
+
+    /// Score interlaced input field by field, in the given field order.
+    /// `auto` takes the order from the Y4M headers. Fields are scored at
+    /// their own, halved height, and frame numbers, counts and timestamps
+    /// refer to fields
+    #[arg(long, value_enum, value_name = "ORDER")]
+    fields: Option<FieldOrder>,
+
+    /// How to pair the frames of the inputs
+    #[arg(long, value_enum, default_value = "index")]
+    pair_by: PairBy,
+
+    /// Largest difference in seconds between the timestamps of paired
+    /// frames [default: half the frame duration of the source]
+    #[arg(long, value_name = "SECONDS")]
+    timestamp_tolerance: Option<f64>,
+
+    /// Timestamps of the source frames in the `timestamp format v2` of
+    /// mkvmerge, for pairing variable frame rate input by timestamp
+    #[arg(long, value_name = "FILE", conflicts_with = "fields")]
+    source_timestamps: Option<PathBuf>,
+
+    /// Timestamps of the distorted frames in the `timestamp format v2` of
+    /// mkvmerge, for pairing variable frame rate input by timestamp
+    #[arg(long, value_name = "FILE", conflicts_with = "fields")]
+    distorted_timestamps: Option<PathBuf>,
+
+    /// Only score every Nth frame, for a faster estimate of the overall quality
+    #[arg(long, value_name = "N", default_value = "1")]
+    every: NonZeroUsize,
+
+    /// Index of the first frame to score
+    #[arg(long, conflicts_with_all = ["start_time", "end_time"])]
+    start_frame: Option<usize>,
+
+    /// Index of the frame at which to stop scoring (exclusive)
+    #[arg(long, conflicts_with_all = ["start_time", "end_time"])]
+    end_frame: Option<usize>,
+
+    /// Time in seconds of the first frame to score
+    #[arg(long)]
+    start_time: Option<f64>,
+
+    /// Time in seconds at which to stop scoring (exclusive)
+    #[arg(long)]
+    end_time: Option<f64>,
+
+    /// The FFmpeg executable used to decode container formats
+    #[arg(long, value_name = "PATH", default_value = "ffmpeg")]
+    ffmpeg: PathBuf,
+
+    /// Number of threads used for scoring [default: number of CPU cores]
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+
+    /// Number of frames of each input that may be decoded ahead of the
+    /// scoring threads. Higher values keep the threads busier when decoding
+    /// speed varies, at the cost of memory [default: twice the threads]
+    #[arg(long, value_name = "FRAMES")]
+    queue_depth: Option<NonZeroUsize>,
+
+    /// Metrics to compute, separated by commas
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "METRICS",
+        default_value = "ssimulacra2"
+    )]
+    metrics: Vec<Metric>,
+
+    /// Number of worst frames to list in the summary
+    #[arg(long, default_value = "5")]
+    worst: usize,
+
+    /// Detect scene cuts in the source and summarize the scores of every
+    /// scene, to find the worst scene
+    #[arg(long)]
+    scenes: bool,
+
+    /// Change of the luma histogram between frames, from 0 to 1, above which
+    /// a new scene starts
+    #[arg(
+        long,
+        value_name = "DISTANCE",
+        default_value = "0.3",
+        requires = "scenes"
+    )]
+    scene_threshold: f64,
+
+    /// Minimum number of frames of a scene
+    #[arg(long, value_name = "FRAMES", default_value = "1", requires = "scenes")]
+    min_scene_length: usize,
+
+    /// Print the result as JSON instead of plain text
+    #[arg(long)]
+    json: bool,
+
+    /// Don't show a progress bar
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Write the score of every frame to a CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
+
+    /// Write the score of every frame to a JSON Lines file
+    #[arg(long, value_name = "FILE")]
+    jsonl: Option<PathBuf>,
+
+    /// Include the error of every scale in the per-frame output
+    #[arg(long)]
+    scales: bool,
+
+    /// Include the error of the X, Y and B planes in the per-frame output
+    #[arg(long)]
+    planes: bool,
+
+    /// Also compute PSNR and SSIM of every frame, in the same pass, and
+    /// include them in the per-frame output and the summary
+    #[arg(long)]
+    psnr_ssim: bool,
+
+    /// Write a heatmap of the distortion of every frame to image files named
+    /// by a pattern with a frame number placeholder, e.g. `heatmap_%04d.png`
+    #[arg(long, value_name = "PATTERN")]
+    heatmap: Option<String>,
+
+    /// Color ramp of the heatmaps
+    #[arg(long, value_enum, default_value = "heat")]
+    color_ramp: Ramp,
+
+    /// Distortion value at the end of the color ramp. Set this to make the
+    /// heatmaps of different frames comparable [default: maximum of each map]
+    #[arg(long, value_name = "VALUE")]
+    heatmap_max: Option<f32>,
+
+    /// Write the SSIMULACRA2 scores summarized per second, or per
+    /// `--timeline-frames` frames, to a CSV or JSON file, chosen by its
+    /// extension
+    #[arg(long, value_name = "FILE")]
+    timeline: Option<PathBuf>,
+
+    /// Length of the intervals of the timeline in seconds
+    #[arg(long, value_name = "SECONDS", default_value = "1")]
+    timeline_interval: f64,
+
+    /// Length of the intervals of the timeline in frames, e.g. the GOP size
+    /// of the encoder, instead of `--timeline-interval`
+    #[arg(long, value_name = "FRAMES", conflicts_with = "timeline_interval")]
+    timeline_frames: Option<NonZeroUsize>,
+
+    /// Draw the mean score of every interval of the timeline as a sparkline
+    /// to an SVG or PNG file, chosen by its extension
+    #[arg(long, value_name = "FILE")]
+    sparkline: Option<PathBuf>,
+
+    /// Write a self-contained HTML report with the summary and a timeline of
+    /// the scores of all frames
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// Save the progress to a file regularly, and resume from it if it
+    /// exists, e.g. after a crash. Resuming needs the same arguments, and
+    /// the file is removed once all frames are scored
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Number of frames scored between saving checkpoints
+    #[arg(long, value_name = "N", default_value = "100", requires = "checkpoint")]
+    checkpoint_interval: NonZeroUsize,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if is_stdin(&args.source) && is_stdin(&args.distorted) {
+        return Err("only one input can be read from stdin".into());
+    }
+
+    let mut source = VideoReader::open(&args.source, args)?;
+    let mut distorted = VideoReader::open(&args.distorted, args)?;
+    if let Some(path) = &args.source_timestamps {
+        source.timestamps = Some(read_timestamps(path)?);
+    }
+    if let Some(path) = &args.distorted_timestamps {
+        distorted.timestamps = Some(read_timestamps(path)?);
+    }
+    let mut pairing = Pairing::new(args, &source, &distorted)?;
+
+    let arguments = checkpoint_arguments(args);
+    let checkpoint = match &args.checkpoint {
+        Some(path) => load_checkpoint(path, &arguments)?,
+        None => None,
+    };
+    let mut outputs = checkpoint
+        .as_ref()
+        .map(|checkpoint| checkpoint.outputs.iter().copied());
+    let mut resume = || outputs.as_mut().and_then(Iterator::next);
+
+    let mut writers = Vec::new();
+    if let Some(path) = &args.csv {
+        writers.push(RecordWriter::csv(path, resume())?);
+    }
+    if let Some(path) = &args.jsonl {
+        writers.push(RecordWriter::json_lines(path, resume())?);
+    }
+
+    let selection = frame_selection(args, source.framerate)?;
+
+    if let Some(pattern) = &args.heatmap {
+        if frame_path(pattern, 0).is_none() {
+            return Err("--heatmap must contain a frame number placeholder such as %04d".into());
+        }
+    }
+    let ssimulacra2 = args.metrics.contains(&Metric::Ssimulacra2);
+    let xpsnr = args.metrics.contains(&Metric::Xpsnr);
+    if !ssimulacra2 && (args.heatmap.is_some() || args.scales || args.planes || args.psnr_ssim) {
+        return Err(
+            "--heatmap, --scales, --planes and --psnr-ssim require the ssimulacra2 metric".into(),
+        );
+    }
+    if !ssimulacra2 && args.report.is_some() {
+        return Err("--report requires the ssimulacra2 metric".into());
+    }
+    let timeline = args.timeline.is_some() || args.sparkline.is_some();
+    if timeline {
+        if !ssimulacra2 {
+            return Err("--timeline and --sparkline require the ssimulacra2 metric".into());
+        }
+        if args.timeline_frames.is_none() && source.framerate.is_none() {
+            return Err(
+                "a timeline per second requires a known frame rate; use --fps for raw \
+                        input or --timeline-frames"
+                    .into(),
+            );
+        }
+        if !args.timeline_interval.is_finite() || args.timeline_interval <= 0.0 {
+            return Err("--timeline-interval must be positive".into());
+        }
+        if let Some(path) = &args.timeline {
+            if !has_extension(path, &["csv", "json"]) {
+                return Err("--timeline must be a .csv or .json file".into());
+            }
+        }
+        if let Some(path) = &args.sparkline {
+            if !has_extension(path, &["svg", "png"]) {
+                return Err("--sparkline must be a .svg or .png file".into());
+            }
+        }
+    }
+
+    let mut pipeline = args
+        .threads
+        .map_or_else(FramePipeline::default, FramePipeline::new)
+        .with_distortion_maps(args.heatmap.is_some())
+        .with_companion_metrics(args.psnr_ssim);
+    if let Some(queue_depth) = args.queue_depth {
+        pipeline = pipeline.with_queue_depth(queue_depth);
+    }
+    let framerate = source.framerate;
+    let mut frame = 0;
+    let mut previous_source = None;
+    let mut state = State {
+        scenes: SceneDetector::new()
+            .with_threshold(args.scene_threshold)
+            .with_min_length(args.min_scene_length),
+        ..State::default()
+    };
+
+    // Both inputs are decoded on threads of their own, ahead of the scoring
+    // threads. Only the frames that will be scored are converted, as well as
+    // the source frames before them for XPSNR. Frames paired by timestamp
+    // are only known once the timestamps are compared, so every distorted
+    // frame is converted then.
+    let (resume_source, resume_distorted) = checkpoint
+        .as_ref()
+        .map_or((0, 0), |c| (c.next_frame, c.next_distorted_frame));
+    let queue_depth = pipeline.queue_depth();
+    let source = source.read_ahead(queue_depth, move |frame| {
+        frame + 1 >= resume_source
+            && (selection.contains(frame) || (xpsnr && selection.contains(frame + 1)))
+    });
+    let pair_by = args.pair_by;
+    let distorted = distorted.read_ahead(queue_depth, move |frame| {
+        frame >= resume_distorted && (pair_by == PairBy::Timestamp || selection.contains(frame))
+    });
+    let (mut source, mut distorted) = (source, distorted);
+
+    if let Some(checkpoint) = checkpoint {
+        skip_scored(
+            &mut source,
+            &mut distorted,
+            (checkpoint.next_frame, checkpoint.next_distorted_frame),
+            xpsnr.then_some(&mut previous_source),
+        )?;
+        frame = checkpoint.next_frame;
+        pairing.distorted_frame = checkpoint.next_distorted_frame;
+        state = checkpoint.state;
+    }
+
+    // The progress only counts the frames scored since resuming, so that
+    // the speed and ETA are those of this run. Frames paired by timestamp
+    // are counted as if all source frames had a partner.
+    let resumed = state.mean.count;
+    let total_frames = match args.pair_by {
+        PairBy::Index => source
+            .frame_count
+            .zip(distorted.frame_count)
+            .map(|(src, dst)| src.min(dst)),
+        PairBy::Timestamp => source.frame_count,
+    }
+    .map(|frames| selection.count(frames).saturating_sub(resumed));
+    let progress_bar = progress_bar(args, total_frames);
+
+    let mut read_error = None;
+    let mut write_error = None;
+    let jobs = std::iter::from_fn(|| {
+        next_selected(
+            &mut source,
+            &mut distorted,
+            &selection,
+            &mut frame,
+            &mut pairing,
+            xpsnr.then_some(&mut previous_source),
+        )
+        .unwrap_or_else(|e| {
+            read_error = Some(e);
+            None
+        })
+    });
+
+    let work = |session: &mut Option<Ssimulacra2>, job: FrameJob| {
+        let xpsnr = if xpsnr {
+            Some(
+                job.source
+                    .xpsnr(&job.distorted, job.previous_source.as_deref())?,
+            )
+        } else {
+            None
+        };
+        let detailed = if ssimulacra2 {
+            Some(pipeline.score(session, &*job.source, job.distorted)?)
+        } else {
+            None
+        };
+        let histogram = args.scenes.then(|| job.source.luma_histogram());
+        Ok(FrameScores {
+            detailed,
+            xpsnr,
+            histogram,
+            distorted_frame: job.distorted_frame,
+            unmatched: job.unmatched,
+        })
+    };
+
+    let start = Instant::now();
+    let on_result = |frame, mut scores: FrameScores| {
+        if !args.json {
+            let line = scores.to_string();
+            progress_bar.suspend(|| println!("Frame {frame}: {line}"));
+        }
+        state.frames.push(scores.to_json(frame));
+        state.unmatched.extend(&scores.unmatched);
+        if let Some(histogram) = scores.histogram.take() {
+            state.scenes.push_histogram(frame, histogram);
+        }
+
+        if write_error.is_none() {
+            let record = FrameRecord::new(frame, framerate, &scores, args.scales, args.planes);
+            write_error = writers.iter_mut().find_map(|w| w.write(&record).err());
+        }
+        if let Some(ref detailed) = scores.detailed {
+            if let (None, Some(pattern), Some(map)) = (&write_error, &args.heatmap, &detailed.map) {
+                let path = frame_path(pattern, frame).expect("Pattern was validated");
+                write_error =
+                    save_heatmap(map, Path::new(&path), args.color_ramp, args.heatmap_max).err();
+            }
+            if let Some(metrics) = detailed.companion {
+                state.companion.push(metrics);
+            }
+            state.aggregator.push(frame, detailed.score);
+        }
+        if let Some(score) = scores.xpsnr {
+            state.xpsnr.push(score);
+        }
+
+        // The progress shows the mean SSIMULACRA2 score, or the mean XPSNR of
+        // luma if only XPSNR is computed.
+        state.mean.push(scores.detailed.as_ref().map_or_else(
+            || scores.xpsnr.map_or(0.0, |score| score.y),
+            |detailed| detailed.score,
+        ));
+        show_progress(
+            &progress_bar,
+            Progress {
+                frames_done: state.mean.count - resumed,
+                total_frames,
+                elapsed: start.elapsed(),
+                mean_score: state.mean.value(),
+            },
+        );
+
+        if let (None, Some(path)) = (&write_error, &args.checkpoint) {
+            if state.frames.len() % args.checkpoint_interval.get() == 0 {
+                let next_frames = (frame + 1, scores.distorted_frame + 1);
+                write_error =
+                    save_checkpoint(path, &arguments, next_frames, &mut writers, &state).err();
+            }
+        }
+    };
+    let result = pipeline.run_jobs(jobs, work, on_result);
+    progress_bar.finish_and_clear();
+    result?;
+    state.unmatched.extend(&pairing.unmatched);
+
+    if let Some(e) = read_error.or(write_error) {
+        return Err(e);
+    }
+    for writer in writers {
+        writer.finish()?;
+    }
+    if let Some(path) = &args.checkpoint {
+        if path.exists() {
+            fs::remove_file(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+    }
+
+    if state.frames.is_empty() {
+        return Err("no frames could be read from the inputs".into());
+    }
+    let summary = state.aggregator.summary();
+    let worst = state.aggregator.worst(args.worst);
+    let summary = summary.as_ref().map(|summary| (summary, worst.as_slice()));
+    let scenes = args
+        .scenes
+        .then(|| state.aggregator.scenes(state.scenes.cuts()));
+
+    if timeline {
+        let timeline = score_timeline(&state.aggregator, args, framerate);
+        if let Some(path) = &args.timeline {
+            write_timeline(path, &timeline).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        if let Some(path) = &args.sparkline {
+            save_sparkline(path, &timeline).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+    }
+    if let Some(path) = &args.report {
+        let mut report = Report::new(format!(
+            "{} against {}",
+            args.distorted.display(),
+            args.source.display()
+        ));
+        report.add_video(args.distorted.display().to_string(), &state.aggregator);
+        report
+            .save(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+
+    let companion = state.companion.means();
+    let xpsnr = state.xpsnr.means();
+    let unmatched = (args.pair_by == PairBy::Timestamp).then_some(&state.unmatched);
+    if args.json {
+        print_json(
+            &state.frames,
+            summary,
+            scenes.as_deref(),
+            companion,
+            xpsnr,
+            unmatched,
+        );
+    } else {
+        print_summary(
+            state.frames.len(),
+            summary,
+            scenes.as_deref(),
+            companion,
+            xpsnr,
+            unmatched,
+        );
+    }
+
+    Ok(())
+}
+
+fn show_progress(progress_bar: &ProgressBar, progress: Progress) {
+    progress_bar.set_position(progress.frames_done as u64);
+    let mut message = format!(
+        "mean {:.4}, {:.2} fps",
+        progress.mean_score,
+        progress.frames_per_second()
+    );
+    if let Some(eta) = progress.eta() {
+        let secs = eta.as_secs();
+        message += &format!(
+            ", ETA {}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        );
+    }
+    progress_bar.set_message(message);
+}
+
+// A progress bar on stderr, which is hidden if stderr is not a terminal.
+fn progress_bar(args: &Args, total_frames: Option<usize>) -> ProgressBar {
+    if args.no_progress {
+        return ProgressBar::hidden();
+    }
+
+    let (bar, template) = match total_frames {
+        Some(total) => (
+            ProgressBar::new(total as u64),
+            "{bar:40.cyan/blue} {pos}/{len} frames, {msg}",
+        ),
+        None => (ProgressBar::new_spinner(), "{spinner} {pos} frames, {msg}"),
+    };
+    bar.with_style(ProgressStyle::with_template(template).expect("Template is valid"))
+}
+
+// A pair of frames to score, with the source frame before it for the
+// temporal activity of XPSNR.
+struct FrameJob {
+    source: Arc<VideoFrame>,
+    previous_source: Option<Arc<VideoFrame>>,
+    distorted: VideoFrame,
+    /// Index of the distorted frame.
+    distorted_frame: usize,
+    /// The frames found without a partner since the previous job.
+    unmatched: Unmatched,
+}
+
+/// How the frames of the inputs are paired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "lower")]
+enum PairBy {
+    /// The Nth frame of the source with the Nth frame of the distorted video
+    Index,
+    /// Frames whose timestamps differ by at most `--timestamp-tolerance`,
+    /// skipping frames dropped from or added to the distorted video
+    Timestamp,
+}
+
+/// The frames of either input without a partner in the other one.
+#[derive(Default, Serialize, Deserialize)]
+struct Unmatched {
+    /// Selected source frames that were dropped from the distorted video.
+    source: Vec<usize>,
+    /// Distorted frames that were added or duplicated.
+    distorted: Vec<usize>,
+}
+
+impl Unmatched {
+    fn extend(&mut self, other: &Self) {
+        self.source.extend(&other.source);
+        self.distorted.extend(&other.distorted);
+    }
+}
+
+// The distorted frame paired with a source frame.
+enum Partner {
+    /// The distorted frame, if it was decoded.
+    Found(Option<Box<VideoFrame>>),
+    /// No distorted frame is within the tolerance of the source frame.
+    Missing,
+    /// The end of either input was reached.
+    End,
+}
+
+// Finds the distorted frame to pair with every source frame.
+struct Pairing {
+    /// The largest difference of the timestamps of paired frames, or `None`
+    /// to pair frames by index.
+    tolerance: Option<f64>,
+    /// Index of the next distorted frame.
+    distorted_frame: usize,
+    /// The frames found without a partner since the last job.
+    unmatched: Unmatched,
+}
+
+impl Pairing {
+    fn new(
+        args: &Args,
+        source: &VideoReader,
+        distorted: &VideoReader,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let tolerance = match args.pair_by {
+            PairBy::Index => None,
+            PairBy::Timestamp => {
+                if source.timestamp(0).is_none() || distorted.timestamp(0).is_none() {
+                    return Err("--pair-by timestamp requires the frame rate or timestamps of both \
+                                inputs; use --fps or --source-timestamps and --distorted-timestamps"
+                        .into());
+                }
+                let tolerance = args
+                    .timestamp_tolerance
+                    .or_else(|| source.framerate.map(|fps| 0.5 / fps))
+                    .ok_or("--timestamp-tolerance is required without a known source frame rate")?;
+                Some(tolerance)
+            }
+        };
+        Ok(Pairing {
+            tolerance,
+            distorted_frame: 0,
+            unmatched: Unmatched::default(),
+        })
+    }
+
+    // Reads the distorted frame paired with source frame `frame`, or only
+    // skips it unless `decode` is set. Distorted frames shown before it that
+    // were not paired with an earlier source frame are skipped.
+    fn partner(
+        &mut self,
+        source: &VideoReader,
+        frame: usize,
+        distorted: &mut VideoReader,
+        decode: bool,
+    ) -> Result<Partner, Box<dyn std::error::Error>> {
+        if let Some(tolerance) = self.tolerance {
+            let Some(time) = source.timestamp(frame) else {
+                return Ok(Partner::End);
+            };
+            loop {
+                let Some(distorted_time) = distorted.timestamp(self.distorted_frame) else {
+                    return Ok(Partner::End);
+                };
+                if distorted_time > time + tolerance {
+                    return Ok(Partner::Missing);
+                }
+                if distorted_time >= time - tolerance {
+                    break;
+                }
+                if !distorted.skip_frame()? {
+                    return Ok(Partner::End);
+                }
+                self.unmatched.distorted.push(self.distorted_frame);
+                self.distorted_frame += 1;
+            }
+        }
+
+        let partner = if decode {
+            distorted.next_frame()?.map(|frame| Some(Box::new(frame)))
+        } else {
+            distorted.skip_frame()?.then_some(None)
+        };
+        self.distorted_frame += 1;
+        Ok(partner.map_or(Partner::End, Partner::Found))
+    }
+}
+
+// Reads up to the next selected pair of frames, skipping all others. If
+// `previous_source` is given, the source frame before every selected frame is
+// kept in it, decoding it if it is not selected itself.
+fn next_selected(
+    source: &mut VideoReader,
+    distorted: &mut VideoReader,
+    selection: &FrameSelection,
+    frame: &mut usize,
+    pairing: &mut Pairing,
+    mut previous_source: Option<&mut Option<Arc<VideoFrame>>>,
+) -> Result<Option<(usize, FrameJob)>, Box<dyn std::error::Error>> {
+    while !selection.is_past_end(*frame) {
+        let current = *frame;
+        *frame += 1;
+
+        if selection.contains(current) {
+            let Some(src) = source.next_frame()? else {
+                return Ok(None);
+            };
+            let src = Arc::new(src);
+            let previous = previous_source
+                .as_mut()
+                .and_then(|previous| previous.replace(Arc::clone(&src)));
+            match pairing.partner(source, current, distorted, true)? {
+                Partner::Found(dst) => {
+                    let job = FrameJob {
+                        source: src,
+                        previous_source: previous,
+                        distorted: *dst.expect("Frame was decoded"),
+                        distorted_frame: pairing.distorted_frame - 1,
+                        unmatched: mem::take(&mut pairing.unmatched),
+                    };
+                    return Ok(Some((current, job)));
+                }
+                Partner::Missing => {
+                    pairing.unmatched.source.push(current);
+                    continue;
+                }
+                Partner::End => return Ok(None),
+            }
+        }
+        if let Some(ref mut previous) = previous_source {
+            **previous = None;
+            if selection.contains(current + 1) {
+                let Some(src) = source.next_frame()? else {
+                    return Ok(None);
+                };
+                if let Partner::End = pairing.partner(source, current, distorted, false)? {
+                    return Ok(None);
+                }
+                **previous = Some(Arc::new(src));
+                continue;
+            }
+        }
+        if !source.skip_frame()? {
+            return Ok(None);
+        }
+        if let Partner::End = pairing.partner(source, current, distorted, false)? {
+            return Ok(None);
+        }
+    }
+    Ok(None)
+}
+
+// Skips the source and distorted frames read before a checkpoint. If
+// `previous_source` is given, the last skipped source frame is kept in it, as
+// `next_selected` would have done.
+fn skip_scored(
+    source: &mut VideoReader,
+    distorted: &mut VideoReader,
+    (source_frames, distorted_frames): (usize, usize),
+    mut previous_source: Option<&mut Option<Arc<VideoFrame>>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let short = || "the inputs have fewer frames than the checkpoint".into();
+    for frame in 0..source_frames {
+        let read = match previous_source {
+            Some(ref mut previous) if frame + 1 == source_frames => {
+                **previous = source.next_frame()?.map(Arc::new);
+                previous.is_some()
+            }
+            _ => source.skip_frame()?,
+        };
+        if !read {
+            return Err(short());
+        }
+    }
+    for _ in 0..distorted_frames {
+        if !distorted.skip_frame()? {
+            return Err(short());
+        }
+    }
+    Ok(())
+}
+
+// Reads a timestamp file in the `timestamp format v2` of mkvmerge, which
+// lists the presentation time of every frame in milliseconds, one per line.
+fn read_timestamps(path: &Path) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            line.trim()
+                .parse::<f64>()
+                .map(|ms| ms / 1000.0)
+                .map_err(|e| format!("{}:{}: invalid timestamp: {e}", path.display(), i + 1).into())
+        })
+        .collect()
+}
+
+// Replaces the first `%d` or `%0Nd` placeholder in `pattern` with the frame
+// number, zero padded to N digits. Returns `None` if there is no placeholder.
+fn frame_path(pattern: &str, frame: usize) -> Option<String> {
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let width = match &rest[..end] {
+        "" => 0,
+        digits => digits.parse().ok()?,
+    };
+
+    Some(format!(
+        "{}{frame:0width$}{}",
+        &pattern[..start],
+        &rest[end + 1..]
+    ))
+}
+
+fn frame_selection(
+    args: &Args,
+    framerate: Option<f64>,
+) -> Result<FrameSelection, Box<dyn std::error::Error>> {
+    let selection = FrameSelection::all().with_step(args.every);
+    if args.start_time.is_none() && args.end_time.is_none() {
+        return Ok(selection.with_range(args.start_frame.unwrap_or(0), args.end_frame));
+    }
+
+    let Some(fps) = framerate else {
+        return Err(
+            "--start-time and --end-time require a known frame rate; use --fps for raw input"
+                .into(),
+        );
+    };
+    Ok(selection.with_time_range(args.start_time.unwrap_or(0.0), args.end_time, fps))
+}
+
+fn print_summary(
+    frames: usize,
+    summary: Option<(&ScoreSummary, &[FrameScore])>,
+    scenes: Option<&[SceneSummary]>,
+    companion: Option<CompanionMetrics>,
+    xpsnr: Option<XpsnrScore>,
+    unmatched: Option<&Unmatched>,
+) {
+    println!();
+    println!("Frames:        {frames}");
+    if let Some(unmatched) = unmatched {
+        println!("Dropped:       {}", frame_list(&unmatched.source));
+        println!("Added:         {}", frame_list(&unmatched.distorted));
+    }
+    if let Some((summary, _)) = summary {
+        println!("Mean:          {:.8}", summary.mean);
+        match summary.harmonic_mean {
+            Some(harmonic_mean) => println!("Harmonic mean: {harmonic_mean:.8}"),
+            None => println!("Harmonic mean: n/a"),
+        }
+        if let Some((lower, upper)) = summary.mean_ci95 {
+            println!("Mean 95% CI:   {lower:.8} - {upper:.8}");
+        }
+        println!("Std. dev.:     {:.8}", summary.std_dev);
+        println!("Min:           {:.8}", summary.min);
+        println!("Max:           {:.8}", summary.max);
+        println!("P1:            {:.8}", summary.p1);
+        println!("P5:            {:.8}", summary.p5);
+        println!("P25:           {:.8}", summary.p25);
+        println!("P50 (median):  {:.8}", summary.p50);
+    }
+    if let Some(companion) = companion {
+        println!("Mean PSNR:     {:.4} dB", companion.psnr);
+        println!("Mean SSIM:     {:.8}", companion.ssim);
+    }
+    if let Some(xpsnr) = xpsnr {
+        println!("Mean XPSNR Y:  {:.4} dB", xpsnr.y);
+        println!("Mean XPSNR U:  {:.4} dB", xpsnr.u);
+        println!("Mean XPSNR V:  {:.4} dB", xpsnr.v);
+    }
+    if let Some((_, worst)) = summary.filter(|(_, worst)| !worst.is_empty()) {
+        println!();
+        println!("Worst frames:");
+        for frame in worst {
+            println!("  Frame {}: {:.8}", frame.frame, frame.score);
+        }
+    }
+    if let Some(scenes) = scenes.filter(|scenes| !scenes.is_empty()) {
+        println!();
+        println!("Scenes:");
+        for scene in scenes {
+            println!(
+                "  Frames {}-{}: mean {:.8}, min {:.8}",
+                scene.start,
+                scene.end - 1,
+                scene.summary.mean,
+                scene.summary.min
+            );
+        }
+        let worst = worst_scene(scenes).expect("Scenes are not empty");
+        println!(
+            "Worst scene:   frames {}-{}, mean {:.8}",
+            worst.start,
+            worst.end - 1,
+            worst.summary.mean
+        );
+    }
+}
+
+// The scene with the lowest mean score.
+fn worst_scene(scenes: &[SceneSummary]) -> Option<&SceneSummary> {
+    scenes
+        .iter()
+        .min_by(|a, b| a.summary.mean.total_cmp(&b.summary.mean))
+}
+
+fn print_json(
+    frames: &[String],
+    summary: Option<(&ScoreSummary, &[FrameScore])>,
+    scenes: Option<&[SceneSummary]>,
+    companion: Option<CompanionMetrics>,
+    xpsnr: Option<XpsnrScore>,
+    unmatched: Option<&Unmatched>,
+) {
+    let mut fields = vec![format!("\"frames\":[{}]", frames.join(","))];
+    if let Some(unmatched) = unmatched {
+        let list = |frames: &[usize]| {
+            frames
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        fields.push(format!(
+            "\"unmatched_source\":[{}],\"unmatched_distorted\":[{}]",
+            list(&unmatched.source),
+            list(&unmatched.distorted)
+        ));
+    }
+    if let Some((summary, worst)) = summary {
+        let frame_json =
+            |f: &FrameScore| format!("{{\"frame\":{},\"score\":{:.8}}}", f.frame, f.score);
+        let worst: Vec<_> = worst.iter().map(frame_json).collect();
+        let harmonic_mean = summary
+            .harmonic_mean
+            .map_or_else(|| "null".to_owned(), |h| format!("{h:.8}"));
+        let mean_ci95 = summary
+            .mean_ci95
+            .map_or_else(|| "null".to_owned(), |(l, u)| format!("[{l:.8},{u:.8}]"));
+        fields.push(format!(
+            "\"mean\":{:.8},\"harmonic_mean\":{harmonic_mean},\"mean_ci95\":{mean_ci95},\
+             \"std_dev\":{:.8},\
+             \"min\":{:.8},\"max\":{:.8},\"p1\":{:.8},\"p5\":{:.8},\"p25\":{:.8},\"p50\":{:.8},\
+             \"worst\":[{}]",
+            summary.mean,
+            summary.std_dev,
+            summary.min,
+            summary.max,
+            summary.p1,
+            summary.p5,
+            summary.p25,
+            summary.p50,
+            worst.join(",")
+        ));
+    }
+    if let Some(scenes) = scenes {
+        let scene_json = |s: &SceneSummary| {
+            format!(
+                "{{\"start\":{},\"end\":{},\"frames\":{},\"mean\":{:.8},\"min\":{:.8},\"p5\":{:.8}}}",
+                s.start, s.end, s.summary.frames, s.summary.mean, s.summary.min, s.summary.p5
+            )
+        };
+        let worst = worst_scene(scenes).map_or_else(|| "null".to_owned(), scene_json);
+        let scenes: Vec<_> = scenes.iter().map(scene_json).collect();
+        fields.push(format!(
+            "\"scenes\":[{}],\"worst_scene\":{worst}",
+            scenes.join(",")
+        ));
+    }
+    if let Some(c) = companion {
+        fields.push(format!(
+            "\"mean_psnr\":{},\"mean_ssim\":{:.8}",
+            json_number(c.psnr),
+            c.ssim
+        ));
+    }
+    if let Some(x) = xpsnr {
+        fields.push(format!("\"mean_xpsnr\":{}", xpsnr_json(x)));
+    }
+    println!("{{{}}}", fields.join(","));
+}
+
+// Formats the count of unmatched frames followed by the first few of them.
+fn frame_list(frames: &[usize]) -> String {
+    const SHOWN: usize = 20;
+
+    if frames.is_empty() {
+        return "0".to_owned();
+    }
+    let shown: Vec<_> = frames.iter().take(SHOWN).map(ToString::to_string).collect();
+    let more = if frames.len() > SHOWN { ", ..." } else { "" };
+    format!("{} ({}{more})", frames.len(), shown.join(", "))
+}
+
+// Formats a number for JSON. PSNR and XPSNR are infinite for identical
+// frames, which JSON can't represent, so those become null.
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value:.8}")
+    } else {
+        "null".to_owned()
+    }
+}
+
+fn xpsnr_json(score: XpsnrScore) -> String {
+    format!(
+        "{{\"y\":{},\"u\":{},\"v\":{}}}",
+        json_number(score.y),
+        json_number(score.u),
+        json_number(score.v)
+    )
+}
+
+/// The scores of an interval of the video, for `--timeline`.
+#[derive(Serialize)]
+struct TimelineEntry {
+    /// Index of the first frame of the interval.
+    start_frame: usize,
+    /// Index of the frame after the last frame of the interval.
+    end_frame: usize,
+    /// Presentation time of the first frame in seconds, if the frame rate is known.
+    start_time: Option<f64>,
+    /// Presentation time of the frame after the interval in seconds.
+    end_time: Option<f64>,
+    /// Number of frames of the interval which were scored.
+    frames: usize,
+    mean: f64,
+    min: f64,
+    p5: f64,
+}
+
+// Summarizes the scores of every interval of `--timeline-interval` seconds or
+// `--timeline-frames` frames. Intervals without scored frames are left out.
+fn score_timeline(
+    aggregator: &ScoreAggregator,
+    args: &Args,
+    framerate: Option<f64>,
+) -> Vec<TimelineEntry> {
+    let end = aggregator
+        .scores()
+        .iter()
+        .map(|s| s.frame + 1)
+        .max()
+        .unwrap_or(0);
+    let starts: Vec<_> = (1..)
+        .map(|i: usize| match (args.timeline_frames, framerate) {
+            (Some(frames), _) => i * frames.get(),
+            (None, Some(fps)) => (i as f64 * args.timeline_interval * fps).ceil() as usize,
+            (None, None) => unreachable!("Frame rate was checked"),
+        })
+        .take_while(|&start| start < end)
+        .collect();
+
+    let time = |frame: usize| framerate.map(|fps| frame as f64 / fps);
+    aggregator
+        .scenes(&starts)
+        .into_iter()
+        .map(|interval| TimelineEntry {
+            start_frame: interval.start,
+            end_frame: interval.end,
+            start_time: time(interval.start),
+            end_time: time(interval.end),
+            frames: interval.summary.frames,
+            mean: interval.summary.mean,
+            min: interval.summary.min,
+            p5: interval.summary.p5,
+        })
+        .collect()
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            extensions.iter().any(|e| ext.eq_ignore_ascii_case(e))
+        })
+}
+
+// Writes the timeline as CSV or JSON, chosen by the extension of `path`.
+fn write_timeline(
+    path: &Path,
+    timeline: &[TimelineEntry],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if has_extension(path, &["csv"]) {
+        let mut writer = csv::Writer::from_path(path)?;
+        for entry in timeline {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+    } else {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, timeline)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// Size of sparklines in pixels.
+const SPARKLINE_WIDTH: u32 = 640;
+const SPARKLINE_HEIGHT: u32 = 120;
+const SPARKLINE_COLOR: [u8; 3] = [0xd6, 0x27, 0x28];
+
+// Places the mean score of every interval of the timeline in the sparkline.
+// The vertical axis runs from 100 at the top down to the lowest score,
+// rounded down to a multiple of 10. Returns the points and that lower end.
+fn sparkline_points(timeline: &[TimelineEntry]) -> (Vec<(f64, f64)>, f64) {
+    let lowest = timeline.iter().map(|e| e.mean).fold(100.0f64, f64::min);
+    let low = ((lowest / 10.0).floor() * 10.0).min(90.0);
+    let (width, height) = (
+        f64::from(SPARKLINE_WIDTH - 1),
+        f64::from(SPARKLINE_HEIGHT - 1),
+    );
+    let last = timeline.len().saturating_sub(1).max(1) as f64;
+    let points = timeline
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let x = if timeline.len() == 1 {
+                width / 2.0
+            } else {
+                i as f64 / last * width
+            };
+            let y = (100.0 - entry.mean.min(100.0)) / (100.0 - low) * height;
+            (x, y)
+        })
+        .collect();
+    (points, low)
+}
+
+// Draws the timeline as an SVG or PNG sparkline, chosen by the extension of `path`.
+fn save_sparkline(
+    path: &Path,
+    timeline: &[TimelineEntry],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (points, low) = sparkline_points(timeline);
+    if has_extension(path, &["svg"]) {
+        let [r, g, b] = SPARKLINE_COLOR;
+        let points: Vec<_> = points
+            .iter()
+            .map(|(x, y)| format!("{x:.1},{y:.1}"))
+            .collect();
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SPARKLINE_WIDTH}\" \
+             height=\"{SPARKLINE_HEIGHT}\" viewBox=\"0 0 {SPARKLINE_WIDTH} {SPARKLINE_HEIGHT}\">\n\
+             <title>SSIMULACRA2 over time, from {low} to 100</title>\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+             <polyline fill=\"none\" stroke=\"#{r:02x}{g:02x}{b:02x}\" stroke-width=\"1.5\" \
+             points=\"{}\"/>\n</svg>\n",
+            points.join(" ")
+        );
+        fs::write(path, svg)?;
+    } else {
+        let mut image = image::RgbImage::from_pixel(
+            SPARKLINE_WIDTH,
+            SPARKLINE_HEIGHT,
+            image::Rgb([255, 255, 255]),
+        );
+        let mut plot = |x: f64, y: f64| {
+            image.put_pixel(
+                x.round() as u32,
+                y.round() as u32,
+                image::Rgb(SPARKLINE_COLOR),
+            );
+        };
+        if let [(x, y)] = points[..] {
+            plot(x, y);
+        }
+        for pair in points.windows(2) {
+            let [(x0, y0), (x1, y1)] = [pair[0], pair[1]];
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0);
+            for step in 0..=steps as u32 {
+                let t = f64::from(step) / steps;
+                plot((x1 - x0).mul_add(t, x0), (y1 - y0).mul_add(t, y0));
+            }
+        }
+        image.save(path)?;
+    }
+    Ok(())
+}
+
+/// The metrics which can be computed for every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "lower")]
+enum Metric {
+    /// SSIMULACRA2 score
+    Ssimulacra2,
+    /// Extended perceptually weighted PSNR of the Y, U and V planes
+    Xpsnr,
+}
+
+// The metrics computed for a frame.
+struct FrameScores {
+    detailed: Option<DetailedScore>,
+    xpsnr: Option<XpsnrScore>,
+    /// The luma histogram of the source, for `--scenes`.
+    histogram: Option<LumaHistogram>,
+    distorted_frame: usize,
+    unmatched: Unmatched,
+}
+
+impl FrameScores {
+    fn to_json(&self, frame: usize) -> String {
+        let mut json = format!("{{\"frame\":{frame}");
+        if let Some(ref detailed) = self.detailed {
+            json += &format!(",\"score\":{:.8}", detailed.score);
+        }
+        if let Some(xpsnr) = self.xpsnr {
+            json += &format!(",\"xpsnr\":{}", xpsnr_json(xpsnr));
+        }
+        json + "}"
+    }
+}
+
+impl std::fmt::Display for FrameScores {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(ref detailed) = self.detailed {
+            parts.push(match detailed.companion {
+                Some(metrics) => format!(
+                    "{:.8} (PSNR {:.4} dB, SSIM {:.6})",
+                    detailed.score, metrics.psnr, metrics.ssim
+                ),
+                None => format!("{:.8}", detailed.score),
+            });
+        }
+        if let Some(xpsnr) = self.xpsnr {
+            parts.push(format!(
+                "XPSNR Y {:.4} dB, U {:.4} dB, V {:.4} dB",
+                xpsnr.y, xpsnr.u, xpsnr.v
+            ));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// A running mean.
+#[derive(Default, Serialize, Deserialize)]
+struct Mean {
+    count: usize,
+    #[serde(with = "float")]
+    sum: f64,
+}
+
+impl Mean {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn value(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// A row of the per-frame output.
+///
+/// The scale and plane errors are flattened into separate columns, so that CSV and JSON
+/// Lines files have the same layout when loaded into a data frame.
+#[derive(Serialize)]
+struct FrameRecord {
+    frame: usize,
+    /// Presentation time of the frame in seconds, if the frame rate is known.
+    timestamp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_0: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_1: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_2: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_3: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_4: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale_5: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plane_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plane_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plane_b: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    psnr: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ssim: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xpsnr_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xpsnr_u: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xpsnr_v: Option<f64>,
+}
+
+impl FrameRecord {
+    fn new(
+        frame: usize,
+        framerate: Option<f64>,
+        scores: &FrameScores,
+        scales: bool,
+        planes: bool,
+    ) -> Self {
+        let detailed = scores.detailed.as_ref();
+        let scale = |i: usize| {
+            if scales {
+                detailed.and_then(|detailed| detailed.scales.get(i).copied())
+            } else {
+                None
+            }
+        };
+        let plane = |i: usize| {
+            detailed
+                .filter(|_| planes)
+                .map(|detailed| detailed.planes[i])
+        };
+        let companion = detailed.and_then(|detailed| detailed.companion);
+
+        FrameRecord {
+            frame,
+            timestamp: framerate.map(|fps| frame as f64 / fps),
+            score: detailed.map(|detailed| detailed.score),
+            scale_0: scale(0),
+            scale_1: scale(1),
+            scale_2: scale(2),
+            scale_3: scale(3),
+            scale_4: scale(4),
+            scale_5: scale(5),
+            plane_x: plane(0),
+            plane_y: plane(1),
+            plane_b: plane(2),
+            psnr: companion.map(|c| c.psnr),
+            ssim: companion.map(|c| c.ssim),
+            xpsnr_y: scores.xpsnr.map(|x| x.y),
+            xpsnr_u: scores.xpsnr.map(|x| x.u),
+            xpsnr_v: scores.xpsnr.map(|x| x.v),
+        }
+    }
+}
+
+/// Running means of the PSNR and SSIM of all frames.
+#[derive(Default, Serialize, Deserialize)]
+struct CompanionMeans {
+    frames: usize,
+    #[serde(with = "float")]
+    psnr: f64,
+    ssim: f64,
+}
+
+impl CompanionMeans {
+    fn push(&mut self, metrics: CompanionMetrics) {
+        self.frames += 1;
+        self.psnr += metrics.psnr;
+        self.ssim += metrics.ssim;
+    }
+
+    fn means(&self) -> Option<CompanionMetrics> {
+        (self.frames > 0).then(|| CompanionMetrics {
+            psnr: self.psnr / self.frames as f64,
+            ssim: self.ssim / self.frames as f64,
+        })
+    }
+}
+
+/// Running means of the XPSNR of all frames.
+#[derive(Default, Serialize, Deserialize)]
+struct XpsnrMeans {
+    y: Mean,
+    u: Mean,
+    v: Mean,
+}
+
+impl XpsnrMeans {
+    fn push(&mut self, score: XpsnrScore) {
+        self.y.push(score.y);
+        self.u.push(score.u);
+        self.v.push(score.v);
+    }
+
+    fn means(&self) -> Option<XpsnrScore> {
+        (self.y.count > 0).then(|| XpsnrScore {
+            y: self.y.value(),
+            u: self.u.value(),
+            v: self.v.value(),
+        })
+    }
+}
+
+/// Everything computed from the frames scored so far, which is all a
+/// checkpoint needs to resume scoring.
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    aggregator: ScoreAggregator,
+    companion: CompanionMeans,
+    xpsnr: XpsnrMeans,
+    mean: Mean,
+    /// The JSON output of every frame.
+    frames: Vec<String>,
+    unmatched: Unmatched,
+    scenes: SceneDetector,
+}
+
+/// The contents of a `--checkpoint` file.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<S> {
+    /// The arguments that determine the scores, which must be the same to resume.
+    arguments: String,
+    /// Index of the source frame after the last scored one.
+    next_frame: usize,
+    /// Index of the distorted frame after the last scored one.
+    next_distorted_frame: usize,
+    /// Lengths of the per-frame output files, which may have grown since
+    /// the checkpoint was saved.
+    outputs: Vec<u64>,
+    state: S,
+}
+
+// The arguments that determine which frames are scored and what the outputs
+// contain.
+fn checkpoint_arguments(args: &Args) -> String {
+    format!(
+        "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} \
+         {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+        args.source,
+        args.distorted,
+        args.width,
+        args.height,
+        args.pix_fmt,
+        args.fps,
+        args.full_range,
+        args.fields,
+        args.pair_by,
+        args.timestamp_tolerance,
+        args.source_timestamps,
+        args.distorted_timestamps,
+        args.every,
+        args.start_frame,
+        args.end_frame,
+        args.start_time,
+        args.end_time,
+        args.metrics,
+        args.scales,
+        args.planes,
+        args.psnr_ssim,
+        args.scenes,
+        args.scene_threshold,
+        args.min_scene_length,
+    )
+}
+
+// Reads the checkpoint at `path`, if there is one.
+fn load_checkpoint(
+    path: &Path,
+    arguments: &str,
+) -> Result<Option<Checkpoint<State>>, Box<dyn std::error::Error>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {e}", path.display()).into()),
+    };
+    let checkpoint: Checkpoint<State> = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    if checkpoint.arguments != arguments {
+        return Err(format!(
+            "{}: the checkpoint was saved with different arguments",
+            path.display()
+        )
+        .into());
+    }
+    Ok(Some(checkpoint))
+}
+
+// Flushes the per-frame outputs and saves the checkpoint to `path`. It is
+// written to a temporary file first, so that a crash while saving keeps the
+// previous checkpoint.
+fn save_checkpoint(
+    path: &Path,
+    arguments: &str,
+    (next_frame, next_distorted_frame): (usize, usize),
+    writers: &mut [RecordWriter],
+    state: &State,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let outputs = writers
+        .iter_mut()
+        .map(RecordWriter::flush)
+        .collect::<io::Result<_>>()?;
+    let checkpoint = Checkpoint {
+        arguments: arguments.to_owned(),
+        next_frame,
+        next_distorted_frame,
+        outputs,
+        state,
+    };
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer(&mut writer, &checkpoint)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    };
+    write().map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+// Stores floats that JSON cannot represent, such as the infinite PSNR of
+// identical frames, as strings.
+mod float {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Float {
+            Number(f64),
+            String(String),
+        }
+
+        match Float::deserialize(deserializer)? {
+            Float::Number(value) => Ok(value),
+            Float::String(value) => value.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+enum RecordWriter {
+    Csv(Box<csv::Writer<File>>),
+    JsonLines(BufWriter<File>),
+}
+
+impl RecordWriter {
+    // Creates a CSV file, or continues the one at `path` from the length
+    // `resume` saved in a checkpoint.
+    fn csv(path: &Path, resume: Option<u64>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = open_output(path, resume).map_err(|e| format!("{}: {e}", path.display()))?;
+        let writer = csv::WriterBuilder::new()
+            .has_headers(resume.unwrap_or(0) == 0)
+            .from_writer(file);
+        Ok(Self::Csv(Box::new(writer)))
+    }
+
+    fn json_lines(path: &Path, resume: Option<u64>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = open_output(path, resume).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self::JsonLines(BufWriter::new(file)))
+    }
+
+    fn write(&mut self, record: &FrameRecord) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Csv(writer) => writer.serialize(record)?,
+            Self::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes out the records written so far, returning the length of the file.
+    fn flush(&mut self) -> io::Result<u64> {
+        let file = match self {
+            Self::Csv(writer) => {
+                writer.flush()?;
+                writer.get_ref()
+            }
+            Self::JsonLines(writer) => {
+                writer.flush()?;
+                writer.get_ref()
+            }
+        };
+        Ok(file.metadata()?.len())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush(),
+            Self::JsonLines(mut writer) => writer.flush(),
+        }
+    }
+}
+
+// Opens a per-frame output file. When resuming, the records written after
+// the checkpoint are cut off, so that they are not written twice.
+fn open_output(path: &Path, resume: Option<u64>) -> io::Result<File> {
+    let Some(len) = resume else {
+        return File::create(path);
+    };
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(len)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+/// Field orders of interlaced input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "lower")]
+enum FieldOrder {
+    /// Top field first
+    Tff,
+    /// Bottom field first
+    Bff,
+    /// As given in the Y4M header
+    Auto,
+}
+
+/// Pixel formats accepted for raw input, named as in FFmpeg.
+/// All high bit depth formats are little endian.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "lower")]
+enum PixelFormat {
+    Gray,
+    Gray10le,
+    Gray12le,
+    Yuv420p,
+    Yuv420p10le,
+    Yuv420p12le,
+    Yuv422p,
+    Yuv422p10le,
+    Yuv422p12le,
+    Yuv444p,
+    Yuv444p10le,
+    Yuv444p12le,
+}
+
+impl PixelFormat {
+    const fn bit_depth(self) -> u8 {
+        match self {
+            Self::Gray | Self::Yuv420p | Self::Yuv422p | Self::Yuv444p => 8,
+            Self::Gray10le | Self::Yuv420p10le | Self::Yuv422p10le | Self::Yuv444p10le => 10,
+            Self::Gray12le | Self::Yuv420p12le | Self::Yuv422p12le | Self::Yuv444p12le => 12,
+        }
+    }
+
+    const fn subsampling(self) -> (u8, u8) {
+        match self {
+            Self::Yuv444p | Self::Yuv444p10le | Self::Yuv444p12le => (0, 0),
+            Self::Yuv422p | Self::Yuv422p10le | Self::Yuv422p12le => (1, 0),
+            _ => (1, 1),
+        }
+    }
+
+    const fn is_mono(self) -> bool {
+        matches!(self, Self::Gray | Self::Gray10le | Self::Gray12le)
+    }
+}
+
+enum Input {
+    Y4m(y4m::Decoder<Box<dyn BufRead + Send>>),
+    Raw {
+        reader: Box<dyn BufRead + Send>,
+        buf: Vec<u8>,
+    },
+    /// The frames decoded by a thread of their own, see `read_ahead`. Frames
+    /// that were only skipped are `None`.
+    Thread(mpsc::Receiver<Result<Option<VideoFrame>, String>>),
+}
+
+struct VideoReader {
+    input: Input,
+    width: usize,
+    height: usize,
+    mono: bool,
+    config: YuvConfig,
+    framerate: Option<f64>,
+    /// Presentation times of the frames in seconds, if they were given.
+    timestamps: Option<Vec<f64>>,
+    /// Number of frames in the input, if it is a regular file.
+    frame_count: Option<usize>,
+    /// Whether the top field comes first, according to the Y4M header.
+    header_top_field_first: Option<bool>,
+    /// Whether the top field comes first, if frames are split into fields.
+    top_field_first: Option<bool>,
+    /// The second field of the last frame read, if it has not been returned yet.
+    pending_field: Option<VideoFrame>,
+}
+
+impl VideoReader {
+    fn open(path: &Path, args: &Args) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file_len = None;
+        let mut reader: Box<dyn BufRead + Send> = if is_stdin(path) {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+            let metadata = file.metadata()?;
+            file_len = metadata.is_file().then_some(metadata.len() as usize);
+            Box::new(BufReader::new(file))
+        };
+
+        let header = reader.fill_buf()?;
+        if !is_stdin(path) && is_container(header) {
+            let reader = decode_with_ffmpeg(&args.ffmpeg, path)?;
+            let reader = Self::open_y4m(reader, path, args.full_range)?;
+            return reader.with_fields(args.fields, path);
+        }
+        if header.starts_with(b"YUV4MPEG2 ") {
+            let header_len = header.iter().position(|&b| b == b'\n').map(|pos| pos + 1);
+            let mut reader = Self::open_y4m(reader, path, args.full_range)?;
+            reader.frame_count = file_len.zip(header_len).map(|(file_len, header_len)| {
+                // Assumes that frame headers have no parameters, which is practically always the case
+                file_len.saturating_sub(header_len) / (b"FRAME\n".len() + reader.frame_len())
+            });
+            reader.with_fields(args.fields, path)
+        } else {
+            let (Some(width), Some(height), Some(pix_fmt)) =
+                (args.width, args.height, args.pix_fmt)
+            else {
+                return Err(format!(
+                    "{} is not a Y4M file; --width, --height and --pix-fmt are required for raw input",
+                    path.display()
+                )
+                .into());
+            };
+            let mut reader =
+                Self::open_raw(reader, width, height, pix_fmt, args.full_range, args.fps);
+            reader.frame_count = file_len.map(|file_len| file_len / reader.frame_len());
+            reader.with_fields(args.fields, path)
+        }
+    }
+
+    fn open_y4m(
+        reader: Box<dyn BufRead + Send>,
+        path: &Path,
+        full_range: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let decoder =
+            y4m::Decoder::new(reader).map_err(|e| format!("{}: {e:?}", path.display()))?;
+
+        let (subsampling_x, subsampling_y) = match decoder.get_colorspace() {
+            y4m::Colorspace::C444 | y4m::Colorspace::C444p10 | y4m::Colorspace::C444p12 => (0, 0),
+            y4m::Colorspace::C422 | y4m::Colorspace::C422p10 | y4m::Colorspace::C422p12 => (1, 0),
+            _ => (1, 1),
+        };
+        let full_range = full_range || header_is_full_range(decoder.get_raw_params());
+        let mono = matches!(
+            decoder.get_colorspace(),
+            y4m::Colorspace::Cmono | y4m::Colorspace::Cmono12
+        );
+        let framerate = decoder.get_framerate();
+        let header_top_field_first = header_field_order(decoder.get_raw_params());
+
+        Ok(VideoReader {
+            width: decoder.get_width(),
+            height: decoder.get_height(),
+            mono,
+            config: unspecified_config(
+                decoder.get_bit_depth() as u8,
+                (subsampling_x, subsampling_y),
+                full_range,
+            ),
+            framerate: (framerate.num > 0 && framerate.den > 0)
+                .then(|| framerate.num as f64 / framerate.den as f64),
+            frame_count: None,
+            header_top_field_first,
+            top_field_first: None,
+            timestamps: None,
+            pending_field: None,
+            input: Input::Y4m(decoder),
+        })
+    }
+
+    fn open_raw(
+        reader: Box<dyn BufRead + Send>,
+        width: usize,
+        height: usize,
+        pix_fmt: PixelFormat,
+        full_range: bool,
+        framerate: Option<f64>,
+    ) -> Self {
+        let config = unspecified_config(pix_fmt.bit_depth(), pix_fmt.subsampling(), full_range);
+        let (luma_len, chroma_len) = plane_lengths(width, height, config, pix_fmt.is_mono());
+
+        VideoReader {
+            input: Input::Raw {
+                reader,
+                buf: vec![0; luma_len + 2 * chroma_len],
+            },
+            width,
+            height,
+            mono: pix_fmt.is_mono(),
+            config,
+            framerate,
+            frame_count: None,
+            header_top_field_first: None,
+            top_field_first: None,
+            timestamps: None,
+            pending_field: None,
+        }
+    }
+
+    // Splits frames into fields if requested, so that the reader returns
+    // fields of half the height at twice the frame rate.
+    fn with_fields(
+        mut self,
+        order: Option<FieldOrder>,
+        path: &Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let top_field_first = match order {
+            None => {
+                if self.header_top_field_first.is_some() {
+                    eprintln!(
+                        "Warning: {} is interlaced; use --fields auto to score it field by field",
+                        path.display()
+                    );
+                }
+                return Ok(self);
+            }
+            Some(FieldOrder::Tff) => true,
+            Some(FieldOrder::Bff) => false,
+            Some(FieldOrder::Auto) => self.header_top_field_first.ok_or_else(|| {
+                format!(
+                    "{} has no field order in its header; use --fields tff or --fields bff",
+                    path.display()
+                )
+            })?,
+        };
+
+        let chroma_height = chroma_dimensions(self.width, self.height, self.config).1;
+        if self.height % 2 != 0 || (!self.mono && chroma_height % 2 != 0) {
+            return Err(format!(
+                "{}: frames of height {} cannot be split into fields",
+                path.display(),
+                self.height
+            )
+            .into());
+        }
+
+        self.top_field_first = Some(top_field_first);
+        self.framerate = self.framerate.map(|fps| fps * 2.0);
+        self.frame_count = self.frame_count.map(|frames| frames * 2);
+        Ok(self)
+    }
+
+    // Presentation time of the given frame in seconds, from the timestamps
+    // if they were given, or from the frame rate otherwise.
+    fn timestamp(&self, frame: usize) -> Option<f64> {
+        match self.timestamps {
+            Some(ref timestamps) => timestamps.get(frame).copied(),
+            None => self.framerate.map(|fps| frame as f64 / fps),
+        }
+    }
+
+    // Size of a frame in bytes, without any headers.
+    fn frame_len(&self) -> usize {
+        let (luma_len, chroma_len) = plane_lengths(self.width, self.height, self.config, self.mono);
+        luma_len + 2 * chroma_len
+    }
+
+    // Moves decoding to a thread of its own, which runs up to `queue_depth`
+    // frames ahead. Frames for which `decode` returns `false` are only
+    // skipped, and must not be read with `next_frame`.
+    fn read_ahead<D>(mut self, queue_depth: usize, decode: D) -> Self
+    where
+        D: Fn(usize) -> bool + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(queue_depth);
+        // Fields are split on the thread as well.
+        let reader = VideoReader {
+            input: Input::Thread(receiver),
+            timestamps: self.timestamps.take(),
+            top_field_first: None,
+            pending_field: None,
+            ..self
+        };
+
+        thread::spawn(move || {
+            for frame in 0.. {
+                let next = if decode(frame) {
+                    self.next_frame().map(|next| next.map(Some))
+                } else {
+                    self.skip_frame().map(|read| read.then_some(None))
+                };
+                let next = match next {
+                    Ok(Some(next)) => Ok(next),
+                    Ok(None) => break,
+                    Err(e) => Err(e.to_string()),
+                };
+                let failed = next.is_err();
+                if sender.send(next).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        reader
+    }
+
+    // Reads the next frame without converting it. Returns `false` at the end of the stream.
+    fn skip_frame(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        // The second field of a frame is returned after the first one is
+        // skipped, so the frame has to be split anyway.
+        if self.top_field_first.is_some() {
+            return Ok(self.next_frame()?.is_some());
+        }
+
+        match self.input {
+            Input::Y4m(ref mut decoder) => match decoder.read_frame() {
+                Ok(_) => Ok(true),
+                Err(y4m::Error::EOF) => Ok(false),
+                Err(e) => Err(format!("failed to read frame: {e:?}").into()),
+            },
+            Input::Raw {
+                ref mut reader,
+                ref mut buf,
+            } => Ok(read_raw_frame(reader, buf)?),
+            Input::Thread(ref receiver) => match receiver.recv() {
+                Ok(next) => Ok(next.map(|_| true)?),
+                Err(mpsc::RecvError) => Ok(false),
+            },
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<VideoFrame>, Box<dyn std::error::Error>> {
+        if let Some(field) = self.pending_field.take() {
+            return Ok(Some(field));
+        }
+
+        let (width, height, config, mono) = (self.width, self.height, self.config, self.mono);
+        let top_field_first = self.top_field_first;
+        let (frame, second_field) = match self.input {
+            Input::Y4m(ref mut decoder) => match decoder.read_frame() {
+                Ok(frame) => {
+                    let planes = [
+                        frame.get_y_plane(),
+                        frame.get_u_plane(),
+                        frame.get_v_plane(),
+                    ];
+                    to_frame_or_fields(planes, width, height, config, mono, top_field_first)?
+                }
+                Err(y4m::Error::EOF) => return Ok(None),
+                Err(e) => return Err(format!("failed to read frame: {e:?}").into()),
+            },
+            Input::Raw {
+                ref mut reader,
+                ref mut buf,
+            } => {
+                if !read_raw_frame(reader, buf)? {
+                    return Ok(None);
+                }
+                let (luma_len, chroma_len) = plane_lengths(width, height, config, mono);
+                let (y, uv) = buf.split_at(luma_len);
+                let (u, v) = uv.split_at(chroma_len);
+                to_frame_or_fields([y, u, v], width, height, config, mono, top_field_first)?
+            }
+            Input::Thread(ref receiver) => match receiver.recv() {
+                Ok(next) => return Ok(Some(next?.expect("Frame was decoded ahead"))),
+                Err(mpsc::RecvError) => return Ok(None),
+            },
+        };
+
+        self.pending_field = second_field;
+        Ok(Some(frame))
+    }
+}
+
+// A decoded frame. The conversion to linear RGB is left to the scoring threads.
+enum VideoFrame {
+    Low(Yuv<u8>),
+    High(Yuv<u16>),
+}
+
+impl VideoFrame {
+    fn luma_histogram(&self) -> LumaHistogram {
+        match self {
+            VideoFrame::Low(frame) => LumaHistogram::new(frame),
+            VideoFrame::High(frame) => LumaHistogram::new(frame),
+        }
+    }
+
+    fn xpsnr(
+        &self,
+        distorted: &VideoFrame,
+        previous: Option<&VideoFrame>,
+    ) -> Result<XpsnrScore, Ssimulacra2Error> {
+        match (self, distorted, previous) {
+            (VideoFrame::Low(src), VideoFrame::Low(dst), None) => {
+                compute_frame_xpsnr(src, dst, None)
+            }
+            (VideoFrame::Low(src), VideoFrame::Low(dst), Some(VideoFrame::Low(prev))) => {
+                compute_frame_xpsnr(src, dst, Some(prev))
+            }
+            (VideoFrame::High(src), VideoFrame::High(dst), None) => {
+                compute_frame_xpsnr(src, dst, None)
+            }
+            (VideoFrame::High(src), VideoFrame::High(dst), Some(VideoFrame::High(prev))) => {
+                compute_frame_xpsnr(src, dst, Some(prev))
+            }
+            _ => Err(Ssimulacra2Error::InvalidFrameConfig),
+        }
+    }
+}
+
+impl TryFrom<VideoFrame> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(frame: VideoFrame) -> Result<Self, Self::Error> {
+        let result = match frame {
+            VideoFrame::Low(yuv) => LinearRgb::try_from(yuv),
+            VideoFrame::High(yuv) => LinearRgb::try_from(yuv),
+        };
+        result.map_err(|_| Ssimulacra2Error::LinearRgbConversionFailed)
+    }
+}
+
+impl TryFrom<&VideoFrame> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(frame: &VideoFrame) -> Result<Self, Self::Error> {
+        let result = match *frame {
+            VideoFrame::Low(ref yuv) => LinearRgb::try_from(yuv),
+            VideoFrame::High(ref yuv) => LinearRgb::try_from(yuv),
+        };
+        result.map_err(|_| Ssimulacra2Error::LinearRgbConversionFailed)
+    }
+}
+
+fn to_video_frame(
+    planes: [&[u8]; 3],
+    width: usize,
+    height: usize,
+    config: YuvConfig,
+    mono: bool,
+) -> Result<VideoFrame, Box<dyn std::error::Error>> {
+    let frame = if config.bit_depth > 8 {
+        let frame = to_frame::<u16>(planes, width, height, config, mono);
+        VideoFrame::High(Yuv::new(frame, config)?)
+    } else {
+        let frame = to_frame::<u8>(planes, width, height, config, mono);
+        VideoFrame::Low(Yuv::new(frame, config)?)
+    };
+
+    Ok(frame)
+}
+
+// Converts a frame, or splits it into its two fields in temporal order if
+// `top_field_first` is given.
+fn to_frame_or_fields(
+    planes: [&[u8]; 3],
+    width: usize,
+    height: usize,
+    config: YuvConfig,
+    mono: bool,
+    top_field_first: Option<bool>,
+) -> Result<(VideoFrame, Option<VideoFrame>), Box<dyn std::error::Error>> {
+    let Some(top_field_first) = top_field_first else {
+        return Ok((to_video_frame(planes, width, height, config, mono)?, None));
+    };
+
+    let bytes = if config.bit_depth > 8 { 2 } else { 1 };
+    let chroma_width = chroma_dimensions(width, height, config).0;
+    let field = |parity| {
+        let [y, u, v] = planes;
+        let y = field_rows(y, width * bytes, parity);
+        let u = field_rows(u, chroma_width * bytes, parity);
+        let v = field_rows(v, chroma_width * bytes, parity);
+        to_video_frame([&y, &u, &v], width, height / 2, config, mono)
+    };
+    let (first, second) = if top_field_first { (0, 1) } else { (1, 0) };
+    Ok((field(first)?, Some(field(second)?)))
+}
+
+// Takes every other row of a plane, starting with row `parity`.
+fn field_rows(plane: &[u8], row_len: usize, parity: usize) -> Vec<u8> {
+    plane
+        .chunks_exact(row_len)
+        .skip(parity)
+        .step_by(2)
+        .flatten()
+        .copied()
+        .collect()
+}
+
+// Reads one full frame into `buf`. Returns `false` at the end of the stream.
+fn read_raw_frame(reader: &mut dyn BufRead, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "input ended in the middle of a frame",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// Whether `header`, the start of a file, is that of a container format which
+// FFmpeg has to decode.
+fn is_container(header: &[u8]) -> bool {
+    let ebml = header.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]);
+    let iso_media = header.len() >= 8 && matches!(&header[4..8], b"ftyp" | b"moov" | b"mdat");
+    let avi = header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"AVI ";
+    let ivf = header.starts_with(b"DKIF");
+    let transport_stream = header.len() > 188 && header[0] == 0x47 && header[188] == 0x47;
+    ebml || iso_media || avi || ivf || transport_stream
+}
+
+// Decodes the first video stream of `path` with FFmpeg, returning its output
+// as Y4M. Decoded frames are piped, so no intermediate file is written. This
+// stands in for demuxing and decoding in-process, see the README.
+fn decode_with_ffmpeg(
+    ffmpeg: &Path,
+    path: &Path,
+) -> Result<Box<dyn BufRead + Send>, Box<dyn std::error::Error>> {
+    let child = Command::new(ffmpeg)
+        .args(["-nostdin", "-v", "error", "-i"])
+        .arg(path)
+        .args(["-map", "0:v:0", "-f", "yuv4mpegpipe", "-strict", "-1", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            format!(
+                "{}: decoding containers requires FFmpeg, but {} could not be run: {e}; \
+                 install FFmpeg, pass its path with --ffmpeg or convert the input to Y4M",
+                path.display(),
+                ffmpeg.display()
+            )
+        })?;
+    Ok(Box::new(BufReader::new(FfmpegOutput {
+        child,
+        path: path.to_owned(),
+        exited: false,
+    })))
+}
+
+// The output of an FFmpeg process. Its exit status is checked at the end of
+// the output, so that a video FFmpeg fails to decode completely is an error
+// instead of a shorter video.
+struct FfmpegOutput {
+    child: Child,
+    path: PathBuf,
+    exited: bool,
+}
+
+impl Read for FfmpegOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let stdout = self.child.stdout.as_mut().expect("Output is piped");
+        let len = stdout.read(buf)?;
+        if len > 0 || buf.is_empty() || self.exited {
+            return Ok(len);
+        }
+
+        self.exited = true;
+        let status = self.child.wait()?;
+        if status.success() {
+            Ok(0)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{}: FFmpeg failed to decode the video ({status})",
+                    self.path.display()
+                ),
+            ))
+        }
+    }
+}
+
+impl Drop for FfmpegOutput {
+    // Stops FFmpeg if not all of its output was read, e.g. as the other video
+    // is shorter, and reaps the process.
+    fn drop(&mut self) {
+        if !self.exited {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+const fn unspecified_config(bit_depth: u8, subsampling: (u8, u8), full_range: bool) -> YuvConfig {
+    YuvConfig {
+        bit_depth,
+        subsampling_x: subsampling.0,
+        subsampling_y: subsampling.1,
+        full_range,
+        matrix_coefficients: MatrixCoefficients::Unspecified,
+        transfer_characteristics: TransferCharacteristic::Unspecified,
+        color_primaries: ColorPrimaries::Unspecified,
+    }
+}
+
+// Byte lengths of the luma plane and of each chroma plane.
+fn plane_lengths(width: usize, height: usize, config: YuvConfig, mono: bool) -> (usize, usize) {
+    let bytes = if config.bit_depth > 8 { 2 } else { 1 };
+    let (chroma_width, chroma_height) = chroma_dimensions(width, height, config);
+    let chroma_len = if mono {
+        0
+    } else {
+        chroma_width * chroma_height * bytes
+    };
+    (width * height * bytes, chroma_len)
+}
+
+fn chroma_dimensions(width: usize, height: usize, config: YuvConfig) -> (usize, usize) {
+    let ss_x = usize::from(config.subsampling_x);
+    let ss_y = usize::from(config.subsampling_y);
+    ((width + ss_x) >> ss_x, (height + ss_y) >> ss_y)
+}
+
+// The field order given by the `I` parameter of a Y4M header, `true` if the
+// top field comes first. Progressive and mixed content give `None`.
+fn header_field_order(params: &[u8]) -> Option<bool> {
+    params.split(|&b| b == b' ').find_map(|param| match param {
+        b"It" => Some(true),
+        b"Ib" => Some(false),
+        _ => None,
+    })
+}
+
+fn header_is_full_range(params: &[u8]) -> bool {
+    params
+        .split(|&b| b == b' ')
+        .any(|param| param.eq_ignore_ascii_case(b"XCOLORRANGE=FULL"))
+}
+
+fn to_frame<T: Pixel>(
+    planes: [&[u8]; 3],
+    width: usize,
+    height: usize,
+    config: YuvConfig,
+    mono: bool,
+) -> Frame<T> {
+    let bytes = std::mem::size_of::<T>();
+    let ss_x = usize::from(config.subsampling_x);
+    let ss_y = usize::from(config.subsampling_y);
+    let (chroma_width, chroma_height) = chroma_dimensions(width, height, config);
+
+    let mut out = [
+        Plane::new(width, height, 0, 0, 0, 0),
+        Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+        Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+    ];
+
+    out[0].copy_from_raw_u8(planes[0], width * bytes, bytes);
+    if mono {
+        let neutral = T::cast_from(1u16 << (config.bit_depth - 1));
+        out[1].data_origin_mut().fill(neutral);
+        out[2].data_origin_mut().fill(neutral);
+    } else {
+        out[1].copy_from_raw_u8(planes[1], chroma_width * bytes, bytes);
+        out[2].copy_from_raw_u8(planes[2], chroma_width * bytes, bytes);
+    }
+
+    Frame { planes: out }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn ffmpeg_output(script: &str) -> FfmpegOutput {
+        let child = Command::new("sh")
+            .args(["-c", script])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        FfmpegOutput {
+            child,
+            path: PathBuf::from("video.mkv"),
+            exited: false,
+        }
+    }
+
+    // An 8x8 grayscale video of the given timestamps, whose frames are
+    // filled with their index.
+    fn raw_video(timestamps: &[f64]) -> VideoReader {
+        let data = (0..timestamps.len())
+            .flat_map(|frame| [frame as u8; 64])
+            .collect::<Vec<_>>();
+        let mut video = VideoReader::open_raw(
+            Box::new(io::Cursor::new(data)),
+            8,
+            8,
+            PixelFormat::Gray,
+            false,
+            None,
+        );
+        video.timestamps = Some(timestamps.to_vec());
+        video
+    }
+
+    // Pairs every source frame, returning the index of the distorted frame
+    // paired with it, or `None` if it has no partner.
+    fn pair(
+        source: &[f64],
+        distorted: &[f64],
+        tolerance: Option<f64>,
+    ) -> (Vec<Option<u8>>, Unmatched) {
+        let source = raw_video(source);
+        let mut distorted = raw_video(distorted);
+        let mut pairing = Pairing {
+            tolerance,
+            distorted_frame: 0,
+            unmatched: Unmatched::default(),
+        };
+        let mut partners = Vec::new();
+        for frame in 0.. {
+            match pairing
+                .partner(&source, frame, &mut distorted, true)
+                .unwrap()
+            {
+                Partner::Found(Some(partner)) => {
+                    let VideoFrame::Low(ref yuv) = *partner else {
+                        panic!("8-bit frame decoded as high bit depth");
+                    };
+                    partners.push(Some(yuv.data()[0].data_origin()[0]));
+                }
+                Partner::Found(None) => unreachable!(),
+                Partner::Missing => {
+                    pairing.unmatched.source.push(frame);
+                    partners.push(None);
+                }
+                Partner::End => break,
+            }
+        }
+        (partners, pairing.unmatched)
+    }
+
+    #[test]
+    fn timestamp_pairing() {
+        let source = [0.0, 0.04, 0.08, 0.12, 0.16];
+
+        // Identical timestamps, and ones off by less than the tolerance.
+        let (partners, unmatched) = pair(&source, &source, Some(0.02));
+        assert_eq!(partners, [Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert!(unmatched.source.is_empty() && unmatched.distorted.is_empty());
+        let jittered = [0.001, 0.039, 0.09, 0.11, 0.16];
+        let (partners, _) = pair(&source, &jittered, Some(0.02));
+        assert_eq!(partners, [Some(0), Some(1), Some(2), Some(3), Some(4)]);
+
+        // A dropped frame.
+        let (partners, unmatched) = pair(&source, &[0.0, 0.04, 0.12, 0.16], Some(0.02));
+        assert_eq!(partners, [Some(0), Some(1), None, Some(2), Some(3)]);
+        assert_eq!(unmatched.source, [2]);
+        assert!(unmatched.distorted.is_empty());
+
+        // A duplicated frame, shown right after the original.
+        let (partners, unmatched) =
+            pair(&source, &[0.0, 0.04, 0.06, 0.08, 0.12, 0.16], Some(0.015));
+        assert_eq!(partners, [Some(0), Some(1), Some(3), Some(4), Some(5)]);
+        assert!(unmatched.source.is_empty());
+        assert_eq!(unmatched.distorted, [2]);
+
+        // A duplicated frame in place of a dropped one, which is too late
+        // for the dropped frame and too early for the next one.
+        let (partners, unmatched) = pair(&source, &[0.0, 0.04, 0.1, 0.12, 0.16], Some(0.015));
+        assert_eq!(partners, [Some(0), Some(1), None, Some(3), Some(4)]);
+        assert_eq!(unmatched.source, [2]);
+        assert_eq!(unmatched.distorted, [2]);
+
+        // The source frames after the end of the distorted video.
+        let (partners, unmatched) = pair(&source, &[0.0, 0.04], Some(0.02));
+        assert_eq!(partners, [Some(0), Some(1)]);
+        assert!(unmatched.source.is_empty());
+    }
+
+    #[test]
+    fn index_pairing() {
+        // The timestamps are ignored without a tolerance, up to the end of
+        // the distorted video.
+        let (partners, unmatched) = pair(&[0.0, 0.04, 0.08, 0.12], &[0.0, 1.0, 2.0], None);
+        assert_eq!(partners, [Some(0), Some(1), Some(2)]);
+        assert!(unmatched.source.is_empty() && unmatched.distorted.is_empty());
+    }
+
+    // A 32x32 grayscale video of `frames` frames, with the distorted video
+    // off by a few levels.
+    fn gray_video(frames: usize, distorted: bool) -> Vec<u8> {
+        (0..frames * 32 * 32)
+            .map(|i| {
+                let (frame, y, x) = (i / 1024, i / 32 % 32, i % 32);
+                let value = (x * 7 + y * 3 + frame * 5) % 200 + 20;
+                let offset = if distorted { (x + y + frame) % 5 } else { 2 };
+                (value + offset - 2) as u8
+            })
+            .collect()
+    }
+
+    fn checkpoint_args(dir: &Path, csv: &str, extra: &[&str]) -> Args {
+        let path = |name: &str| dir.join(name).into_os_string();
+        let mut args = vec![
+            "ssimulacra2_video".into(),
+            path("source.gray"),
+            path("distorted.gray"),
+            "--width=32".into(),
+            "--height=32".into(),
+            "--pix-fmt=gray".into(),
+            "--json".into(),
+            "--no-progress".into(),
+            "--csv".into(),
+            path(csv),
+            "--checkpoint".into(),
+            path("checkpoint.json"),
+            "--checkpoint-interval=2".into(),
+        ];
+        args.extend(extra.iter().map(Into::into));
+        Args::parse_from(args)
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let dir =
+            std::env::temp_dir().join(format!("ssimulacra2-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("checkpoint.json");
+        fs::write(dir.join("source.gray"), gray_video(6, false)).unwrap();
+        let distorted = gray_video(6, true);
+        fs::write(dir.join("distorted.gray"), &distorted).unwrap();
+
+        run(&checkpoint_args(&dir, "uninterrupted.csv", &[])).unwrap();
+        assert!(!checkpoint.exists());
+        let uninterrupted = fs::read_to_string(dir.join("uninterrupted.csv")).unwrap();
+        assert_eq!(uninterrupted.lines().count(), 7);
+
+        // A distorted video that ends in the middle of its last frame stops
+        // scoring with the checkpoint after frame 3, while frame 4 is already
+        // written to the CSV file.
+        fs::write(dir.join("distorted.gray"), &distorted[..5 * 1024 + 100]).unwrap();
+        let err = run(&checkpoint_args(&dir, "resumed.csv", &[])).unwrap_err();
+        assert!(err.to_string().contains("middle of a frame"), "{err}");
+        let arguments = checkpoint_arguments(&checkpoint_args(&dir, "resumed.csv", &[]));
+        let saved = load_checkpoint(&checkpoint, &arguments).unwrap().unwrap();
+        assert_eq!((saved.next_frame, saved.next_distorted_frame), (4, 4));
+        assert_eq!(saved.state.frames.len(), 4);
+        assert_eq!(
+            fs::read_to_string(dir.join("resumed.csv"))
+                .unwrap()
+                .lines()
+                .count(),
+            6
+        );
+
+        // Other arguments that determine the scores are rejected.
+        let err = run(&checkpoint_args(&dir, "resumed.csv", &["--every=2"])).unwrap_err();
+        assert!(err.to_string().contains("different arguments"), "{err}");
+
+        fs::write(dir.join("distorted.gray"), &distorted).unwrap();
+        run(&checkpoint_args(&dir, "resumed.csv", &[])).unwrap();
+        assert!(!checkpoint.exists());
+        let resumed = fs::read_to_string(dir.join("resumed.csv")).unwrap();
+        assert_eq!(resumed, uninterrupted);
+
+        // A checkpoint cut off while it was written is an error rather than
+        // a reason to start over.
+        let state = State::default();
+        let mut writers = [RecordWriter::csv(&dir.join("truncated.csv"), None).unwrap()];
+        save_checkpoint(&checkpoint, &arguments, (2, 2), &mut writers, &state).unwrap();
+        let saved = load_checkpoint(&checkpoint, &arguments).unwrap().unwrap();
+        assert_eq!((saved.next_frame, saved.next_distorted_frame), (2, 2));
+        let contents = fs::read(&checkpoint).unwrap();
+        fs::write(&checkpoint, &contents[..contents.len() / 2]).unwrap();
+        let err = load_checkpoint(&checkpoint, &arguments).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with(&checkpoint.display().to_string()),
+            "{err}"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn raw_plane_lengths() {
+        let lengths = |pix_fmt: PixelFormat, width, height| {
+            let config = unspecified_config(pix_fmt.bit_depth(), pix_fmt.subsampling(), false);
+            plane_lengths(width, height, config, pix_fmt.is_mono())
+        };
+        assert_eq!(lengths(PixelFormat::Gray, 16, 8), (128, 0));
+        assert_eq!(lengths(PixelFormat::Gray12le, 16, 8), (256, 0));
+        assert_eq!(lengths(PixelFormat::Yuv420p, 16, 8), (128, 32));
+        assert_eq!(lengths(PixelFormat::Yuv422p, 16, 8), (128, 64));
+        assert_eq!(lengths(PixelFormat::Yuv444p, 16, 8), (128, 128));
+        assert_eq!(lengths(PixelFormat::Yuv420p10le, 16, 8), (256, 64));
+        // Chroma planes of odd sizes are rounded up, like FFmpeg does.
+        assert_eq!(lengths(PixelFormat::Yuv420p, 15, 7), (105, 32));
+        assert_eq!(lengths(PixelFormat::Yuv422p10le, 15, 7), (210, 112));
+    }
+
+    #[test]
+    fn raw_high_bit_depth() {
+        // Little endian samples of 0x0123, 0x0345 and 0x0067 in the luma,
+        // Cb and Cr planes.
+        let mut data = Vec::new();
+        data.extend([0x23u8, 0x01].repeat(16 * 8));
+        data.extend([0x45u8, 0x03].repeat(8 * 4));
+        data.extend([0x67u8, 0x00].repeat(8 * 4));
+        let mut video = VideoReader::open_raw(
+            Box::new(io::Cursor::new(data)),
+            16,
+            8,
+            PixelFormat::Yuv420p10le,
+            false,
+            None,
+        );
+        let Some(VideoFrame::High(yuv)) = video.next_frame().unwrap() else {
+            panic!("10-bit frame not decoded as high bit depth");
+        };
+        assert_eq!(yuv.config().bit_depth, 10);
+        for (plane, value) in yuv.data().iter().zip([0x0123u16, 0x0345, 0x0067]) {
+            let width = plane.cfg.width;
+            assert!(plane
+                .rows_iter()
+                .all(|row| row[..width] == vec![value; width]));
+        }
+        assert!(video.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn raw_short_final_frame() {
+        let mut reader = io::Cursor::new(vec![1u8; 10]);
+        let mut buf = [0u8; 4];
+        assert!(read_raw_frame(&mut reader, &mut buf).unwrap());
+        assert!(read_raw_frame(&mut reader, &mut buf).unwrap());
+        let err = read_raw_frame(&mut reader, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // The input ending after a whole frame is its end.
+        let mut reader = io::Cursor::new(vec![1u8; 8]);
+        assert!(read_raw_frame(&mut reader, &mut buf).unwrap());
+        assert!(read_raw_frame(&mut reader, &mut buf).unwrap());
+        assert!(!read_raw_frame(&mut reader, &mut buf).unwrap());
+
+        let mut video = VideoReader::open_raw(
+            Box::new(io::Cursor::new(vec![0u8; 64 + 32])),
+            8,
+            8,
+            PixelFormat::Gray,
+            false,
+            None,
+        );
+        assert!(video.next_frame().unwrap().is_some());
+        assert!(video.next_frame().is_err());
+    }
+
+    #[test]
+    fn field_rows_of_odd_heights() {
+        // Five rows of two bytes, filled with their index.
+        let plane = [0u8, 0, 1, 1, 2, 2, 3, 3, 4, 4];
+        assert_eq!(field_rows(&plane, 2, 0), [0, 0, 2, 2, 4, 4]);
+        assert_eq!(field_rows(&plane, 2, 1), [1, 1, 3, 3]);
+
+        // Frames of odd heights, or with chroma planes of odd heights, cannot
+        // be split into fields of the same size.
+        let open = |height, pix_fmt| {
+            VideoReader::open_raw(Box::new(io::empty()), 8, height, pix_fmt, false, None)
+                .with_fields(Some(FieldOrder::Tff), Path::new("video.yuv"))
+        };
+        assert!(open(5, PixelFormat::Gray).is_err());
+        assert!(open(6, PixelFormat::Yuv420p).is_err());
+        assert!(open(6, PixelFormat::Yuv422p).is_ok());
+        assert!(open(8, PixelFormat::Yuv420p).is_ok());
+    }
+
+    #[test]
+    fn field_order() {
+        // An 8x4 4:2:0 frame whose rows are filled with their index, plus 10
+        // in the Cb and 20 in the Cr plane.
+        let y = (0..4u8).flat_map(|row| [row; 8]).collect::<Vec<_>>();
+        let u = [10u8, 10, 10, 10, 11, 11, 11, 11];
+        let v = [20u8, 20, 20, 20, 21, 21, 21, 21];
+        let config = unspecified_config(8, (1, 1), false);
+        let rows = |field: &VideoFrame| {
+            let VideoFrame::Low(yuv) = field else {
+                panic!("8-bit field decoded as high bit depth");
+            };
+            assert_eq!((yuv.width(), yuv.height()), (8, 2));
+            yuv.data()
+                .iter()
+                .flat_map(|plane| plane.rows_iter().map(|row| row[0]))
+                .collect::<Vec<_>>()
+        };
+
+        let (top, bottom) =
+            to_frame_or_fields([&y, &u, &v], 8, 4, config, false, Some(true)).unwrap();
+        assert_eq!(rows(&top), [0, 2, 10, 20]);
+        assert_eq!(rows(&bottom.unwrap()), [1, 3, 11, 21]);
+
+        let (bottom, top) =
+            to_frame_or_fields([&y, &u, &v], 8, 4, config, false, Some(false)).unwrap();
+        assert_eq!(rows(&bottom), [1, 3, 11, 21]);
+        assert_eq!(rows(&top.unwrap()), [0, 2, 10, 20]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ffmpeg_exit_status() {
+        let mut output = Vec::new();
+        ffmpeg_output("printf frames")
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"frames");
+
+        // The output so far is read before the failure is reported.
+        let mut failing = ffmpeg_output("printf frames; exit 1");
+        let mut output = [0u8; 6];
+        failing.read_exact(&mut output).unwrap();
+        assert_eq!(&output, b"frames");
+        let err = failing.read(&mut output).unwrap_err();
+        assert!(
+            err.to_string().starts_with("video.mkv: FFmpeg failed"),
+            "{err}"
+        );
+    }
+}
@@ -1,89 +1,167 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::{array, mem};
+
 mod consts {
     #![allow(clippy::unreadable_literal)]
     include!(concat!(env!("OUT_DIR"), "/recursive_gaussian.rs"));
 }
 
+// The radius of the kernel, beyond which rows and columns have no effect.
+pub const RADIUS: usize = consts::RADIUS;
+
+// The number of columns of the narrowest strips of the vertical pass.
+const NARROW_COLUMNS: usize = 8;
+
 /// Implements "Recursive Implementation of the Gaussian Filter Using Truncated
 /// Cosine Functions" by Charalampidis [2016].
+#[derive(Debug)]
 pub struct RecursiveGaussian;
 
 impl RecursiveGaussian {
-    #[cfg(feature = "rayon")]
-    pub fn horizontal_pass(&self, input: &[f32], output: &mut [f32], width: usize) {
-        use rayon::iter::{IndexedParallelIterator, ParallelIterator};
-        use rayon::prelude::ParallelSliceMut;
-        use rayon::slice::ParallelSlice;
+    /// Blurs the rows of `input`, which start every `stride` values, into
+    /// the rows of `output`, which has no padding.
+    pub fn horizontal_pass(&self, input: &[f32], stride: usize, output: &mut [f32], width: usize) {
+        assert_eq!(input.len() * width, output.len() * stride);
+
+        #[cfg(feature = "rayon")]
+        if output.len() >= crate::PARALLEL_MIN_VALUES {
+            use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+            use rayon::prelude::ParallelSliceMut;
+            use rayon::slice::ParallelSlice;
 
-        assert_eq!(input.len(), output.len());
+            input
+                .par_chunks_exact(stride)
+                .zip(output.par_chunks_exact_mut(width))
+                .for_each(|(input, output)| {
+                    self.horizontal_row::<_, 1>(&&input[..width], output, width);
+                });
+            return;
+        }
 
-        input
-            .par_chunks_exact(width)
-            .zip(output.par_chunks_exact_mut(width))
-            .for_each(|(input, output)| self.horizontal_row(input, output, width));
+        for (input, output) in input
+            .chunks_exact(stride)
+            .zip(output.chunks_exact_mut(width))
+        {
+            self.horizontal_row::<_, 1>(&&input[..width], output, width);
+        }
     }
 
-    #[cfg(not(feature = "rayon"))]
-    pub fn horizontal_pass(&self, input: &[f32], output: &mut [f32], width: usize) {
-        assert_eq!(input.len(), output.len());
+    /// Like [`horizontal_pass`][Self::horizontal_pass] on the element-wise
+    /// product of `input1` and `input2`, computing the product on the fly
+    /// instead of reading it from memory.
+    pub fn horizontal_pass_product(
+        &self,
+        input1: &[f32],
+        input2: &[f32],
+        stride: usize,
+        output: &mut [f32],
+        width: usize,
+    ) {
+        assert_eq!(input1.len() * width, output.len() * stride);
+        assert_eq!(input2.len(), input1.len());
+
+        #[cfg(feature = "rayon")]
+        if output.len() >= crate::PARALLEL_MIN_VALUES {
+            use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+            use rayon::prelude::ParallelSliceMut;
+            use rayon::slice::ParallelSlice;
 
-        for (input, output) in input
-            .chunks_exact(width)
+            input1
+                .par_chunks_exact(stride)
+                .zip(input2.par_chunks_exact(stride))
+                .zip(output.par_chunks_exact_mut(width))
+                .for_each(|((input1, input2), output)| {
+                    self.horizontal_row::<_, 1>(
+                        &Product::<1>(&input1[..width], &input2[..width]),
+                        output,
+                        width,
+                    );
+                });
+            return;
+        }
+
+        for ((input1, input2), output) in input1
+            .chunks_exact(stride)
+            .zip(input2.chunks_exact(stride))
             .zip(output.chunks_exact_mut(width))
         {
-            self.horizontal_row(input, output, width);
+            self.horizontal_row::<_, 1>(
+                &Product::<1>(&input1[..width], &input2[..width]),
+                output,
+                width,
+            );
         }
     }
 
-    fn horizontal_row(&self, input: &[f32], output: &mut [f32], width: usize) {
+    // Blurs a row of `width` values, or of `width` groups of `LANES`
+    // interleaved values, which are blurred independently of each other.
+    #[inline(always)]
+    fn horizontal_row<R: Row, const LANES: usize>(
+        &self,
+        input: &R,
+        output: &mut [f32],
+        width: usize,
+    ) {
+        assert_eq!(input.len(), width * LANES);
+        assert_eq!(output.len(), width * LANES);
         let big_n = consts::RADIUS as isize;
-        let mut prev_1 = 0f32;
-        let mut prev_3 = 0f32;
-        let mut prev_5 = 0f32;
-        let mut prev2_1 = 0f32;
-        let mut prev2_3 = 0f32;
-        let mut prev2_5 = 0f32;
+        let mut prev_1 = [0f32; LANES];
+        let mut prev_3 = [0f32; LANES];
+        let mut prev_5 = [0f32; LANES];
+        let mut prev2_1 = [0f32; LANES];
+        let mut prev2_3 = [0f32; LANES];
+        let mut prev2_5 = [0f32; LANES];
 
         let mut n = (-big_n) + 1;
         while n < width as isize {
             let left = n - big_n - 1;
             let right = n + big_n - 1;
-            let left_val = if left >= 0 {
+            let left_vals = if left >= 0 {
                 // SAFETY: `left` can never be bigger than `width`
-                unsafe { *input.get_unchecked(left as usize) }
+                array::from_fn(|lane| unsafe { input.get_unchecked(left as usize * LANES + lane) })
             } else {
-                0f32
+                [0f32; LANES]
             };
-            let right_val = if right < width as isize {
+            let right_vals = if right < width as isize {
                 // SAFETY: this branch ensures that `right` is not bigger than `width`
-                unsafe { *input.get_unchecked(right as usize) }
+                array::from_fn(|lane| unsafe { input.get_unchecked(right as usize * LANES + lane) })
             } else {
-                0f32
+                [0f32; LANES]
             };
-            let sum = left_val + right_val;
-
-            let mut out_1 = sum * consts::MUL_IN_1;
-            let mut out_3 = sum * consts::MUL_IN_3;
-            let mut out_5 = sum * consts::MUL_IN_5;
-
-            out_1 = consts::MUL_PREV2_1.mul_add(prev2_1, out_1);
-            out_3 = consts::MUL_PREV2_3.mul_add(prev2_3, out_3);
-            out_5 = consts::MUL_PREV2_5.mul_add(prev2_5, out_5);
-            prev2_1 = prev_1;
-            prev2_3 = prev_3;
-            prev2_5 = prev_5;
-
-            out_1 = consts::MUL_PREV_1.mul_add(prev_1, out_1);
-            out_3 = consts::MUL_PREV_3.mul_add(prev_3, out_3);
-            out_5 = consts::MUL_PREV_5.mul_add(prev_5, out_5);
-            prev_1 = out_1;
-            prev_3 = out_3;
-            prev_5 = out_5;
+
+            let mut outputs = [0f32; LANES];
+            for lane in 0..LANES {
+                let sum = left_vals[lane] + right_vals[lane];
+
+                let mut out_1 = sum * consts::MUL_IN_1;
+                let mut out_3 = sum * consts::MUL_IN_3;
+                let mut out_5 = sum * consts::MUL_IN_5;
+
+                out_1 = consts::MUL_PREV2_1.mul_add(prev2_1[lane], out_1);
+                out_3 = consts::MUL_PREV2_3.mul_add(prev2_3[lane], out_3);
+                out_5 = consts::MUL_PREV2_5.mul_add(prev2_5[lane], out_5);
+                prev2_1[lane] = prev_1[lane];
+                prev2_3[lane] = prev_3[lane];
+                prev2_5[lane] = prev_5[lane];
+
+                out_1 = consts::MUL_PREV_1.mul_add(prev_1[lane], out_1);
+                out_3 = consts::MUL_PREV_3.mul_add(prev_3[lane], out_3);
+                out_5 = consts::MUL_PREV_5.mul_add(prev_5[lane], out_5);
+                prev_1[lane] = out_1;
+                prev_3[lane] = out_3;
+                prev_5[lane] = out_5;
+
+                outputs[lane] = out_1 + out_3 + out_5;
+            }
 
             if n >= 0 {
-                // SAFETY: We know that this chunk of output is of size `width`,
-                // which `n` cannot be larger than.
+                // SAFETY: We know that this chunk of output is of size
+                // `width * LANES`, which `n` cannot reach.
                 unsafe {
-                    *output.get_unchecked_mut(n as usize) = out_1 + out_3 + out_5;
+                    output
+                        .get_unchecked_mut(n as usize * LANES..(n as usize + 1) * LANES)
+                        .copy_from_slice(&outputs);
                 }
             }
 
@@ -91,96 +169,426 @@ impl RecursiveGaussian {
         }
     }
 
+    /// Blurs the columns of `input`, a plane of `width`x`height` values, and
+    /// writes the blurred `rows` to `output`, which holds only those rows.
     pub fn vertical_pass_chunked<const J: usize, const K: usize>(
         &self,
         input: &[f32],
         output: &mut [f32],
         width: usize,
         height: usize,
+        rows: Range<usize>,
+    ) {
+        #[cfg(all(feature = "std", target_arch = "aarch64"))]
+        if crate::sve::detected() {
+            // SAFETY: the CPU supports SVE.
+            unsafe { self.vertical_pass_chunked_sve::<J, K>(input, output, width, height, rows) };
+            return;
+        }
+        self.vertical_pass_chunked_impl::<J, K>(input, output, width, height, rows);
+    }
+
+    // `vertical_pass_chunked` compiled with SVE, see the `sve` module.
+    #[cfg(all(feature = "std", target_arch = "aarch64"))]
+    #[target_feature(enable = "sve")]
+    unsafe fn vertical_pass_chunked_sve<const J: usize, const K: usize>(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: usize,
+        height: usize,
+        rows: Range<usize>,
+    ) {
+        self.vertical_pass_chunked_impl::<J, K>(input, output, width, height, rows);
+    }
+
+    #[inline(always)]
+    fn vertical_pass_chunked_impl<const J: usize, const K: usize>(
+        &self,
+        input: &[f32],
+        output: &mut [f32],
+        width: usize,
+        height: usize,
+        rows: Range<usize>,
     ) {
         assert!(J > K);
         assert!(K > 0);
 
-        assert_eq!(input.len(), output.len());
+        assert_eq!(input.len(), width * height);
+        assert_eq!(output.len(), width * rows.len());
+        assert!(rows.end <= height);
 
         let mut x = 0;
         while x + J <= width {
-            self.vertical_pass::<J>(&input[x..], &mut output[x..], width, height);
+            self.vertical_pass::<J>(&input[x..], &mut output[x..], width, height, rows.clone());
             x += J;
         }
 
         while x + K <= width {
-            self.vertical_pass::<K>(&input[x..], &mut output[x..], width, height);
+            self.vertical_pass::<K>(&input[x..], &mut output[x..], width, height, rows.clone());
             x += K;
         }
 
+        // Narrow strips keep most columns of narrow planes, and the last
+        // columns of others, from being blurred one by one.
+        while x + NARROW_COLUMNS <= width {
+            self.vertical_pass::<NARROW_COLUMNS>(
+                &input[x..],
+                &mut output[x..],
+                width,
+                height,
+                rows.clone(),
+            );
+            x += NARROW_COLUMNS;
+        }
+
         while x < width {
-            self.vertical_pass::<1>(&input[x..], &mut output[x..], width, height);
+            self.vertical_pass::<1>(&input[x..], &mut output[x..], width, height, rows.clone());
             x += 1;
         }
     }
 
-    // Apply 1D vertical scan on COLUMNS elements at a time
+    // Apply 1D vertical scan on COLUMNS elements at a time, storing only
+    // `rows`, from the first row of `output` on. The scan stops after them.
+    #[inline(always)]
     pub fn vertical_pass<const COLUMNS: usize>(
         &self,
         input: &[f32],
         output: &mut [f32],
         width: usize,
         height: usize,
+        rows: Range<usize>,
     ) {
-        assert_eq!(input.len(), output.len());
-
         let big_n = consts::RADIUS as isize;
 
-        let zeroes = vec![0f32; COLUMNS];
-        let mut prev = vec![0f32; 3 * COLUMNS];
-        let mut prev2 = vec![0f32; 3 * COLUMNS];
-        let mut out = vec![0f32; 3 * COLUMNS];
+        // Kept on the stack, as this is called for every few columns.
+        let zeroes = [0f32; COLUMNS];
+        let mut prev = [[0f32; COLUMNS]; 3];
+        let mut prev2 = [[0f32; COLUMNS]; 3];
+        let mut out = [[0f32; COLUMNS]; 3];
 
         let mut n = (-big_n) + 1;
-        while n < height as isize {
+        while n < rows.end as isize {
             let top = n - big_n - 1;
             let bottom = n + big_n - 1;
             let top_row = if top >= 0 {
-                &input[top as usize * width..][..COLUMNS]
+                load::<COLUMNS>(input, top as usize * width)
             } else {
-                &zeroes
+                zeroes
             };
 
             let bottom_row = if bottom < height as isize {
-                &input[bottom as usize * width..][..COLUMNS]
+                load::<COLUMNS>(input, bottom as usize * width)
             } else {
-                &zeroes
+                zeroes
             };
 
             for i in 0..COLUMNS {
                 let sum = top_row[i] + bottom_row[i];
 
-                let i1 = i;
-                let i3 = i1 + COLUMNS;
-                let i5 = i3 + COLUMNS;
+                let out1 = prev[0][i].mul_add(consts::VERT_MUL_PREV_1, prev2[0][i]);
+                let out3 = prev[1][i].mul_add(consts::VERT_MUL_PREV_3, prev2[1][i]);
+                let out5 = prev[2][i].mul_add(consts::VERT_MUL_PREV_5, prev2[2][i]);
 
-                let out1 = prev[i1].mul_add(consts::VERT_MUL_PREV_1, prev2[i1]);
-                let out3 = prev[i3].mul_add(consts::VERT_MUL_PREV_3, prev2[i3]);
-                let out5 = prev[i5].mul_add(consts::VERT_MUL_PREV_5, prev2[i5]);
-
-                let out1 = sum.mul_add(consts::VERT_MUL_IN_1, -out1);
-                let out3 = sum.mul_add(consts::VERT_MUL_IN_3, -out3);
-                let out5 = sum.mul_add(consts::VERT_MUL_IN_5, -out5);
-
-                out[i1] = out1;
-                out[i3] = out3;
-                out[i5] = out5;
+                out[0][i] = sum.mul_add(consts::VERT_MUL_IN_1, -out1);
+                out[1][i] = sum.mul_add(consts::VERT_MUL_IN_3, -out3);
+                out[2][i] = sum.mul_add(consts::VERT_MUL_IN_5, -out5);
+            }
 
-                if n >= 0 {
-                    output[n as usize * width + i] = out1 + out3 + out5;
+            if n >= rows.start as isize {
+                let mut row = [0f32; COLUMNS];
+                for (i, value) in row.iter_mut().enumerate() {
+                    *value = out[0][i] + out[1][i] + out[2][i];
                 }
+                store(output, (n as usize - rows.start) * width, &row);
             }
 
-            prev2.copy_from_slice(&prev);
-            prev.copy_from_slice(&out);
+            prev2 = prev;
+            prev = out;
 
             n += 1;
         }
     }
 }
+
+// Loads the `N` values of `values` starting at `offset` with a single bounds
+// check, so that the loops over them can be vectorized.
+#[inline(always)]
+fn load<const N: usize>(values: &[f32], offset: usize) -> [f32; N] {
+    let mut row = [0f32; N];
+    row.copy_from_slice(&values[offset..offset + N]);
+    row
+}
+
+// Stores `row` to `values` starting at `offset`, the counterpart of `load`.
+#[inline(always)]
+fn store<const N: usize>(values: &mut [f32], offset: usize, row: &[f32; N]) {
+    values[offset..offset + N].copy_from_slice(row);
+}
+
+/// Blurs an image row by row, keeping only the horizontally blurred rows that
+/// the vertical pass still needs.
+///
+/// This produces the same values as
+/// [`vertical_pass_chunked`][RecursiveGaussian::vertical_pass_chunked] after
+/// [`horizontal_pass`][RecursiveGaussian::horizontal_pass], but each output
+/// row can be consumed while its inputs are still in cache.
+///
+/// It can also blur several images of the same size at once, whose values
+/// are interleaved, so that `lanes` values make up a pixel.
+#[derive(Debug, Default)]
+pub struct RowBlur {
+    width: usize,
+    height: usize,
+    lanes: usize,
+    // Ring of horizontally blurred rows, indexed by row modulo `RING_ROWS`.
+    rows: Vec<f32>,
+    zeroes: Vec<f32>,
+    // State of the vertical pass of every column, three values per column.
+    prev: [Vec<f32>; 3],
+    prev2: [Vec<f32>; 3],
+    out: [Vec<f32>; 3],
+    output: Vec<f32>,
+    n: isize,
+}
+
+// A row is needed again as the top row 2 * RADIUS steps after it was first
+// needed as the bottom row.
+const RING_ROWS: usize = 2 * consts::RADIUS + 2;
+
+impl RowBlur {
+    /// Prepares for blurring an image of the given size from its first row.
+    pub fn reset(&mut self, width: usize, height: usize) {
+        self.reset_lanes(width, height, 1);
+    }
+
+    /// Prepares for blurring `lanes` interleaved images of the given size
+    /// from their first row.
+    pub fn reset_lanes(&mut self, width: usize, height: usize, lanes: usize) {
+        let len = width * lanes;
+        self.width = width;
+        self.height = height;
+        self.lanes = lanes;
+        self.rows.resize(RING_ROWS * len, 0.0f32);
+        self.zeroes.clear();
+        self.zeroes.resize(len, 0.0f32);
+        for state in [&mut self.prev, &mut self.prev2, &mut self.out] {
+            for values in state.iter_mut() {
+                values.clear();
+                values.resize(len, 0.0f32);
+            }
+        }
+        self.output.resize(len, 0.0f32);
+        self.n = 1 - consts::RADIUS as isize;
+    }
+
+    /// The number of `f32` values of the buffers for images of the given width.
+    pub const fn buffer_len(width: usize) -> usize {
+        // The ring of rows, `zeroes`, `output` and three values per column
+        // in each of `prev`, `prev2` and `out`.
+        (RING_ROWS + 11) * width
+    }
+
+    /// Returns the next row of the blurred `plane`.
+    pub fn next_row(&mut self, plane: &[f32]) -> &[f32] {
+        self.next_row_lanes::<1>(plane)
+    }
+
+    /// Returns the next row of the blurred element-wise product of `plane1`
+    /// and `plane2`.
+    pub fn next_row_product(&mut self, plane1: &[f32], plane2: &[f32]) -> &[f32] {
+        self.next_row_lanes_product::<1>(plane1, plane2)
+    }
+
+    /// Returns the next row of the blurred `LANES` interleaved images of
+    /// `plane`, which must match the lanes of the last reset.
+    #[inline(always)]
+    pub fn next_row_lanes<const LANES: usize>(&mut self, plane: &[f32]) -> &[f32] {
+        let len = self.width * LANES;
+        self.next_row_with::<_, _, LANES>(|y| &plane[y * len..(y + 1) * len])
+    }
+
+    /// Returns the next row of the blurred squares of the `LANES`
+    /// interleaved images of `plane`.
+    #[inline(always)]
+    pub fn next_row_lanes_square<const LANES: usize>(&mut self, plane: &[f32]) -> &[f32] {
+        let len = self.width * LANES;
+        self.next_row_with::<_, _, LANES>(|y| {
+            let row = &plane[y * len..(y + 1) * len];
+            Product::<1>(row, row)
+        })
+    }
+
+    /// Returns the next row of the blurred element-wise product of `plane1`
+    /// with each of the `LANES` interleaved images of `plane2`.
+    #[inline(always)]
+    pub fn next_row_lanes_product<const LANES: usize>(
+        &mut self,
+        plane1: &[f32],
+        plane2: &[f32],
+    ) -> &[f32] {
+        let width = self.width;
+        self.next_row_with::<_, _, LANES>(|y| {
+            Product::<LANES>(
+                &plane1[y * width..(y + 1) * width],
+                &plane2[y * width * LANES..(y + 1) * width * LANES],
+            )
+        })
+    }
+
+    #[inline(always)]
+    fn next_row_with<R: Row, F: Fn(usize) -> R, const LANES: usize>(
+        &mut self,
+        input_row: F,
+    ) -> &[f32] {
+        assert_eq!(self.lanes, LANES, "Lanes must match the last reset");
+        let big_n = consts::RADIUS as isize;
+        let width = self.width;
+        let len = width * LANES;
+        loop {
+            let n = self.n;
+            assert!(n < self.height as isize, "All rows have been blurred");
+            self.n += 1;
+
+            let bottom = n + big_n - 1;
+            if bottom < self.height as isize {
+                let slot = bottom as usize % RING_ROWS;
+                RecursiveGaussian.horizontal_row::<_, LANES>(
+                    &input_row(bottom as usize),
+                    &mut self.rows[slot * len..(slot + 1) * len],
+                    width,
+                );
+            }
+            let top = n - big_n - 1;
+
+            let top_row = if top >= 0 {
+                let slot = top as usize % RING_ROWS;
+                &self.rows[slot * len..(slot + 1) * len]
+            } else {
+                &self.zeroes
+            };
+            let bottom_row = if bottom < self.height as isize {
+                let slot = bottom as usize % RING_ROWS;
+                &self.rows[slot * len..(slot + 1) * len]
+            } else {
+                &self.zeroes
+            };
+
+            let state = VerticalState {
+                prev: &self.prev,
+                prev2: &self.prev2,
+                out: &mut self.out,
+                output: &mut self.output,
+            };
+            #[cfg(all(feature = "std", target_arch = "aarch64"))]
+            if crate::sve::detected() {
+                // SAFETY: the CPU supports SVE.
+                unsafe { state.step_sve(top_row, bottom_row) };
+            } else {
+                state.step(top_row, bottom_row);
+            }
+            #[cfg(not(all(feature = "std", target_arch = "aarch64")))]
+            state.step(top_row, bottom_row);
+
+            // prev2 <- prev <- out, the old prev2 is overwritten next step.
+            mem::swap(&mut self.prev2, &mut self.prev);
+            mem::swap(&mut self.prev, &mut self.out);
+
+            if n >= 0 {
+                return &self.output;
+            }
+        }
+    }
+}
+
+// The state of the vertical pass of a `RowBlur` for one step.
+struct VerticalState<'a> {
+    prev: &'a [Vec<f32>; 3],
+    prev2: &'a [Vec<f32>; 3],
+    out: &'a mut [Vec<f32>; 3],
+    output: &'a mut [f32],
+}
+
+impl VerticalState<'_> {
+    // `step` compiled with SVE, see the `sve` module.
+    #[cfg(all(feature = "std", target_arch = "aarch64"))]
+    #[target_feature(enable = "sve")]
+    unsafe fn step_sve(self, top_row: &[f32], bottom_row: &[f32]) {
+        self.step(top_row, bottom_row);
+    }
+
+    // Computes the next values of the vertical pass of every column from the
+    // horizontally blurred rows at the top and bottom of the window.
+    #[inline(always)]
+    fn step(self, top_row: &[f32], bottom_row: &[f32]) {
+        // Slicing every row to the same length up front lets the compiler
+        // drop the bounds checks, and so vectorize the loop.
+        let len = self.output.len();
+        let (top_row, bottom_row) = (&top_row[..len], &bottom_row[..len]);
+        let [ref prev1, ref prev3, ref prev5] = *self.prev;
+        let (prev1, prev3, prev5) = (&prev1[..len], &prev3[..len], &prev5[..len]);
+        let [ref prev2_1, ref prev2_3, ref prev2_5] = *self.prev2;
+        let (prev2_1, prev2_3, prev2_5) = (&prev2_1[..len], &prev2_3[..len], &prev2_5[..len]);
+        let [ref mut out1_row, ref mut out3_row, ref mut out5_row] = *self.out;
+        let (out1_row, out3_row, out5_row) = (
+            &mut out1_row[..len],
+            &mut out3_row[..len],
+            &mut out5_row[..len],
+        );
+        let output = &mut self.output[..len];
+        for i in 0..len {
+            let sum = top_row[i] + bottom_row[i];
+
+            let out1 = prev1[i].mul_add(consts::VERT_MUL_PREV_1, prev2_1[i]);
+            let out3 = prev3[i].mul_add(consts::VERT_MUL_PREV_3, prev2_3[i]);
+            let out5 = prev5[i].mul_add(consts::VERT_MUL_PREV_5, prev2_5[i]);
+
+            let out1 = sum.mul_add(consts::VERT_MUL_IN_1, -out1);
+            let out3 = sum.mul_add(consts::VERT_MUL_IN_3, -out3);
+            let out5 = sum.mul_add(consts::VERT_MUL_IN_5, -out5);
+
+            out1_row[i] = out1;
+            out3_row[i] = out3;
+            out5_row[i] = out5;
+            output[i] = out1 + out3 + out5;
+        }
+    }
+}
+
+// A row of values read by the horizontal pass.
+trait Row {
+    fn len(&self) -> usize;
+
+    /// # Safety
+    /// `index` must be less than [`len`][Self::len].
+    unsafe fn get_unchecked(&self, index: usize) -> f32;
+}
+
+impl Row for &[f32] {
+    fn len(&self) -> usize {
+        <[f32]>::len(self)
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, index: usize) -> f32 {
+        // SAFETY: the caller guarantees that `index` is in bounds.
+        unsafe { *<[f32]>::get_unchecked(self, index) }
+    }
+}
+
+// The element-wise product of two rows, where the second one holds `LANES`
+// interleaved rows, which are each multiplied with the first one.
+struct Product<'a, const LANES: usize>(&'a [f32], &'a [f32]);
+
+impl<const LANES: usize> Row for Product<'_, LANES> {
+    fn len(&self) -> usize {
+        (self.0.len() * LANES).min(self.1.len())
+    }
+
+    #[inline(always)]
+    unsafe fn get_unchecked(&self, index: usize) -> f32 {
+        // SAFETY: the caller guarantees that `index` is less than the length
+        // of the second row and `LANES` times that of the first.
+        unsafe { *self.0.get_unchecked(index / LANES) * *self.1.get_unchecked(index) }
+    }
+}
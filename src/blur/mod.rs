@@ -1,6 +1,13 @@
 mod gaussian;
 
-use gaussian::RecursiveGaussian;
+use alloc::vec::Vec;
+use core::array;
+use core::ops::Range;
+
+pub use gaussian::RowBlur;
+use gaussian::{RecursiveGaussian, RADIUS};
+
+use crate::{AlignedPlane, ImageF32};
 
 /// Structure handling image blur.
 ///
@@ -10,9 +17,15 @@ use gaussian::RecursiveGaussian;
 /// Note that the width and height of the image passed to [blur][Self::blur] needs to exactly
 /// match the width and height of this instance. If you reduce the image size (e.g. via
 /// downscaling), [`shrink_to`][Self::shrink_to] can be used to resize the internal buffers.
+///
+/// The coefficients of the kernel are constants computed at build time, so
+/// they are shared by all instances without any synchronization. Each instance
+/// only owns its temporary plane of `width * height` values, which is all that
+/// one blur per thread duplicates.
+#[derive(Debug)]
 pub struct Blur {
     kernel: RecursiveGaussian,
-    temp: Vec<f32>,
+    temp: AlignedPlane,
     width: usize,
     height: usize,
 }
@@ -24,7 +37,7 @@ impl Blur {
     pub fn new(width: usize, height: usize) -> Self {
         Blur {
             kernel: RecursiveGaussian,
-            temp: vec![0.0f32; width * height],
+            temp: AlignedPlane::new(width * height),
             width,
             height,
         }
@@ -40,21 +53,451 @@ impl Blur {
         self.height = height;
     }
 
+    /// Resizes the internal buffers to fit images of the given width and height,
+    /// growing them if necessary.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.temp.resize(width * height, 0.0);
+        self.width = width;
+        self.height = height;
+    }
+
     /// Blur the given image.
-    pub fn blur(&mut self, img: &[Vec<f32>; 3]) -> [Vec<f32>; 3] {
-        [
-            self.blur_plane(&img[0]),
-            self.blur_plane(&img[1]),
-            self.blur_plane(&img[2]),
-        ]
+    ///
+    /// Images are given as any number of planes, e.g. the three planes of XYB
+    /// or a single plane of luma, which are blurred independently.
+    pub fn blur<const N: usize>(&mut self, img: &[Vec<f32>; N]) -> [Vec<f32>; N] {
+        let mut out = array::from_fn(|_| Vec::new());
+        self.blur_into(img, &mut out);
+        out
+    }
+
+    /// Blur the given image into `out`, reusing its buffers.
+    pub fn blur_into<const N: usize>(&mut self, img: &[Vec<f32>; N], out: &mut [Vec<f32>; N]) {
+        for (plane, out) in img.iter().zip(out.iter_mut()) {
+            out.resize(self.width * self.height, 0.0);
+            self.blur_plane(plane, out);
+        }
+    }
+
+    /// Blur the element-wise product of two images.
+    ///
+    /// This gives the same result as multiplying the images and blurring the
+    /// product with [blur][Self::blur], without storing the product.
+    pub fn blur_product<const N: usize>(
+        &mut self,
+        img1: &[Vec<f32>; N],
+        img2: &[Vec<f32>; N],
+    ) -> [Vec<f32>; N] {
+        let mut out = array::from_fn(|_| Vec::new());
+        self.blur_product_into(img1, img2, &mut out);
+        out
+    }
+
+    /// Blur the element-wise product of two images into `out`, reusing its
+    /// buffers.
+    pub fn blur_product_into<const N: usize>(
+        &mut self,
+        img1: &[Vec<f32>; N],
+        img2: &[Vec<f32>; N],
+        out: &mut [Vec<f32>; N],
+    ) {
+        for ((plane1, plane2), out) in img1.iter().zip(img2.iter()).zip(out.iter_mut()) {
+            out.resize(self.width * self.height, 0.0);
+            self.blur_plane_product(plane1, plane2, out);
+        }
+    }
+
+    /// Blur the planes of `img` into `out`, which is resized to an image of
+    /// the same size and number of planes without padding. Unlike
+    /// [`blur_into`][Self::blur_into], this reads strided images as they are.
+    ///
+    /// # Panics
+    /// - If the width or height of `img` differs from that of this instance
+    pub fn blur_image_into(&mut self, img: &ImageF32, out: &mut ImageF32) {
+        self.blur_image_into_tiled(img, out, false);
+    }
+
+    // Like `blur_image_into`, blurring the planes in tiles if `tiling` is
+    // set, see `Tile`.
+    pub(crate) fn blur_image_into_tiled(
+        &mut self,
+        img: &ImageF32,
+        out: &mut ImageF32,
+        tiling: bool,
+    ) {
+        self.check_size(img);
+        out.reset(self.width, self.height, img.planes());
+        for c in 0..img.planes() {
+            self.kernel
+                .horizontal_pass(img.plane(c), img.stride(), &mut self.temp, self.width);
+            self.vertical_pass(out.plane_mut(c), tiling);
+        }
     }
 
-    fn blur_plane(&mut self, plane: &[f32]) -> Vec<f32> {
-        let mut out = vec![0f32; self.width * self.height];
+    /// Blur the element-wise product of two images into `out`, like
+    /// [`blur_image_into`][Self::blur_image_into].
+    ///
+    /// # Panics
+    /// - If the width or height of the images differs from that of this instance
+    /// - If the images differ in stride or number of planes
+    pub fn blur_image_product_into(
+        &mut self,
+        img1: &ImageF32,
+        img2: &ImageF32,
+        out: &mut ImageF32,
+    ) {
+        self.blur_image_product_into_tiled(img1, img2, out, false);
+    }
+
+    // Like `blur_image_product_into`, blurring the planes in tiles if
+    // `tiling` is set, see `Tile`.
+    pub(crate) fn blur_image_product_into_tiled(
+        &mut self,
+        img1: &ImageF32,
+        img2: &ImageF32,
+        out: &mut ImageF32,
+        tiling: bool,
+    ) {
+        self.check_size(img1);
+        self.check_size(img2);
+        assert_eq!(
+            (img1.stride(), img1.planes()),
+            (img2.stride(), img2.planes()),
+            "Images must have the same layout"
+        );
+        out.reset(self.width, self.height, img1.planes());
+        for c in 0..img1.planes() {
+            self.kernel.horizontal_pass_product(
+                img1.plane(c),
+                img2.plane(c),
+                img1.stride(),
+                &mut self.temp,
+                self.width,
+            );
+            self.vertical_pass(out.plane_mut(c), tiling);
+        }
+    }
+
+    fn check_size(&self, img: &ImageF32) {
+        assert_eq!(
+            (img.width(), img.height()),
+            (self.width, self.height),
+            "Image size must match the blur"
+        );
+    }
+
+    // Blurs the product of two planes without padding into `out`, which has
+    // the same length.
+    pub(crate) fn blur_plane_product(&mut self, plane1: &[f32], plane2: &[f32], out: &mut [f32]) {
+        self.blur_strided_product(plane1, plane2, self.width, out);
+    }
+
+    // Blurs a plane without padding into `out`, which has the same length.
+    pub(crate) fn blur_plane(&mut self, plane: &[f32], out: &mut [f32]) {
+        self.blur_strided(plane, self.width, out);
+    }
+
+    fn blur_strided_product(
+        &mut self,
+        plane1: &[f32],
+        plane2: &[f32],
+        stride: usize,
+        out: &mut [f32],
+    ) {
         self.kernel
-            .horizontal_pass(plane, &mut self.temp, self.width);
+            .horizontal_pass_product(plane1, plane2, stride, &mut self.temp, self.width);
+        self.vertical_pass(out, false);
+    }
+
+    fn blur_strided(&mut self, plane: &[f32], stride: usize, out: &mut [f32]) {
         self.kernel
-            .vertical_pass_chunked::<128, 32>(&self.temp, &mut out, self.width, self.height);
-        out
+            .horizontal_pass(plane, stride, &mut self.temp, self.width);
+        self.vertical_pass(out, false);
+    }
+
+    // Blurs the columns of the horizontally blurred plane in `temp` into
+    // `out`, tile by tile if `tiling` is set and the plane is tall enough.
+    fn vertical_pass(&self, out: &mut [f32], tiling: bool) {
+        let (width, height) = (self.width, self.height);
+        if !Tile::is_tiled(height, tiling) {
+            self.kernel
+                .vertical_pass_chunked::<128, 32>(&self.temp, out, width, height, 0..height);
+            return;
+        }
+
+        let blur_tile = |(index, out): (usize, &mut [f32])| {
+            let Tile { rows, window } = Tile::new(index, height);
+            self.kernel.vertical_pass_chunked::<128, 32>(
+                &self.temp[window.start * width..window.end * width],
+                out,
+                width,
+                window.len(),
+                rows.start - window.start..rows.end - window.start,
+            );
+        };
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::iter::{IndexedParallelIterator, ParallelIterator};
+            use rayon::slice::ParallelSliceMut;
+
+            out.par_chunks_mut(TILE_ROWS * width)
+                .enumerate()
+                .for_each(blur_tile);
+        }
+        #[cfg(not(feature = "rayon"))]
+        out.chunks_mut(TILE_ROWS * width)
+            .enumerate()
+            .for_each(blur_tile);
+    }
+}
+
+// The number of rows of a tile, see `Tile`.
+pub const TILE_ROWS: usize = 256;
+
+// A band of rows of a plane which is blurred and compared on its own, so that
+// the bands of tall planes can be processed in parallel, see
+// `Ssimulacra2::with_tiling`.
+//
+// The recursive Gaussian is a filter of finite support: a row only affects
+// the blurred rows within `RADIUS` of it. So the vertical pass of a tile only
+// has to start `RADIUS` rows above it, in its `window`, to compute the same
+// values as that of the whole plane, up to rounding. The tiles of a plane are
+// `TILE_ROWS` rows each, except for the last one, and only depend on its
+// height, so that tiled results do not depend on the number of threads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tile {
+    // The rows of the tile.
+    pub rows: Range<usize>,
+    // The rows the vertical pass reads to blur them.
+    pub window: Range<usize>,
+}
+
+impl Tile {
+    // Whether planes of `height` rows are split into tiles, which only pays
+    // off for at least two tiles.
+    pub const fn is_tiled(height: usize, tiling: bool) -> bool {
+        tiling && height >= 2 * TILE_ROWS
+    }
+
+    // The number of tiles of a plane of `height` rows.
+    pub const fn count(height: usize) -> usize {
+        (height + TILE_ROWS - 1) / TILE_ROWS
+    }
+
+    // The tile at `index` of a plane of `height` rows.
+    pub fn new(index: usize, height: usize) -> Self {
+        Self::rows(
+            index * TILE_ROWS..((index + 1) * TILE_ROWS).min(height),
+            height,
+        )
+    }
+
+    // A tile of any `rows` of a plane of `height` rows.
+    pub fn rows(rows: Range<usize>, height: usize) -> Self {
+        let window = rows.start.saturating_sub(RADIUS)..(rows.end + RADIUS).min(height);
+        Tile { rows, window }
+    }
+
+    // The single tile of a plane of `height` rows which is not split.
+    pub const fn whole(height: usize) -> Self {
+        Tile {
+            rows: 0..height,
+            window: 0..height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn row_blur_matches_blur() {
+        let (width, height) = (37usize, 29usize);
+        let plane1: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 13) % 31) as f32 / 31.0f32)
+            .collect();
+        let plane2: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7) % 17) as f32 / 17.0f32)
+            .collect();
+        let img1 = [plane1.clone(), plane1.clone(), plane1];
+        let img2 = [plane2.clone(), plane2.clone(), plane2];
+
+        let mut blur = Blur::new(width, height);
+        let blurred = blur.blur(&img1);
+        let product = blur.blur_product(&img1, &img2);
+
+        let mut row_blur = RowBlur::default();
+        row_blur.reset(width, height);
+        for y in 0..height {
+            let row = row_blur.next_row(&img1[0]);
+            assert_eq!(row, &blurred[0][y * width..(y + 1) * width]);
+        }
+        row_blur.reset(width, height);
+        for y in 0..height {
+            let row = row_blur.next_row_product(&img1[0], &img2[0]);
+            assert_eq!(row, &product[0][y * width..(y + 1) * width]);
+        }
+    }
+
+    #[test]
+    fn row_blur_lanes_match_single_images() {
+        let (width, height) = (37usize, 29usize);
+        let source: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 5) % 19) as f32 / 19.0f32)
+            .collect();
+        let images: [Vec<f32>; 3] = array::from_fn(|lane| {
+            (0..width * height)
+                .map(|i| ((i * (lane + 7)) % 23) as f32 / 23.0f32)
+                .collect()
+        });
+        let mut interleaved = vec![0.0f32; 3 * width * height];
+        for (lane, image) in images.iter().enumerate() {
+            for (i, &value) in image.iter().enumerate() {
+                interleaved[3 * i + lane] = value;
+            }
+        }
+
+        let (mut lanes, mut single) = (RowBlur::default(), RowBlur::default());
+        let mut products = (RowBlur::default(), RowBlur::default());
+        let mut squares = (RowBlur::default(), RowBlur::default());
+        for (lane, image) in images.iter().enumerate() {
+            for blur in [&mut lanes, &mut products.0, &mut squares.0] {
+                blur.reset_lanes(width, height, 3);
+            }
+            for blur in [&mut single, &mut products.1, &mut squares.1] {
+                blur.reset(width, height);
+            }
+            for _ in 0..height {
+                let row = lanes.next_row_lanes::<3>(&interleaved);
+                let expected = single.next_row(image);
+                for (x, &value) in expected.iter().enumerate() {
+                    assert_eq!(row[3 * x + lane].to_bits(), value.to_bits());
+                }
+                let row = products
+                    .0
+                    .next_row_lanes_product::<3>(&source, &interleaved);
+                let expected = products.1.next_row_product(&source, image);
+                for (x, &value) in expected.iter().enumerate() {
+                    assert_eq!(row[3 * x + lane].to_bits(), value.to_bits());
+                }
+                let row = squares.0.next_row_lanes_square::<3>(&interleaved);
+                let expected = squares.1.next_row_product(image, image);
+                for (x, &value) in expected.iter().enumerate() {
+                    assert_eq!(row[3 * x + lane].to_bits(), value.to_bits());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn planes_are_blurred_independently() {
+        let (width, height) = (23usize, 19usize);
+        let img: [Vec<f32>; 3] = array::from_fn(|c| {
+            (0..width * height)
+                .map(|i| ((i * (c + 3)) % 13) as f32 / 13.0f32)
+                .collect()
+        });
+
+        let mut blur = Blur::new(width, height);
+        let blurred = blur.blur(&img);
+        let product = blur.blur_product(&img, &img);
+        for c in 0..3 {
+            let plane = [img[c].clone()];
+            assert_eq!(blur.blur(&plane)[0], blurred[c]);
+            assert_eq!(blur.blur_product(&plane, &plane)[0], product[c]);
+        }
+    }
+
+    #[test]
+    fn strided_images_match_planes() {
+        let (width, height, stride) = (21usize, 17usize, 24usize);
+        let img: [Vec<f32>; 2] = array::from_fn(|c| {
+            (0..width * height)
+                .map(|i| ((i * (c + 5)) % 11) as f32 / 11.0f32)
+                .collect()
+        });
+        let mut data = vec![f32::NAN; 2 * stride * height];
+        for (c, plane) in img.iter().enumerate() {
+            for (y, row) in plane.chunks_exact(width).enumerate() {
+                let start = (c * height + y) * stride;
+                data[start..start + width].copy_from_slice(row);
+            }
+        }
+        let strided = ImageF32::from_strided(data, width, height, stride, 2).unwrap();
+
+        let mut blur = Blur::new(width, height);
+        let mut out = ImageF32::default();
+        let hash = strided.content_hash();
+        blur.blur_image_into(&strided, &mut out);
+        let blurred = blur.blur(&img);
+        assert_eq!(out.stride(), width);
+        assert_eq!(out.plane(0), blurred[0].as_slice());
+        assert_eq!(out.plane(1), blurred[1].as_slice());
+
+        blur.blur_image_product_into(&strided, &strided, &mut out);
+        let product = blur.blur_product(&img, &img);
+        assert_eq!(out.plane(1), product[1].as_slice());
+        assert_eq!(strided.content_hash(), hash);
+    }
+
+    #[test]
+    fn kernel_is_not_stored_per_instance() {
+        assert_eq!(core::mem::size_of::<RecursiveGaussian>(), 0);
+        assert_eq!(Blur::new(16, 8).temp.len(), 16 * 8);
+    }
+
+    #[test]
+    fn strips_match_single_columns() {
+        // One strip of 32 columns, one of 8 and 5 single columns.
+        let (width, height) = (45usize, 23usize);
+        let plane: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 11) % 29) as f32 / 29.0f32)
+            .collect();
+        let mut strips = vec![0.0f32; width * height];
+        RecursiveGaussian.vertical_pass_chunked::<128, 32>(
+            &plane,
+            &mut strips,
+            width,
+            height,
+            0..height,
+        );
+        let mut columns = vec![0.0f32; width * height];
+        for x in 0..width {
+            RecursiveGaussian.vertical_pass::<1>(
+                &plane[x..],
+                &mut columns[x..],
+                width,
+                height,
+                0..height,
+            );
+        }
+        assert_eq!(strips, columns);
+    }
+
+    #[test]
+    fn tiles_match_whole_planes() {
+        // Two full tiles and a partial one.
+        let (width, height) = (19usize, 2 * TILE_ROWS + 37);
+        let plane: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7) % 23) as f32 / 23.0f32)
+            .collect();
+        let img = ImageF32::from_strided(plane, width, height, width, 1).unwrap();
+
+        let mut blur = Blur::new(width, height);
+        let (mut whole, mut tiled) = (ImageF32::default(), ImageF32::default());
+        blur.blur_image_into(&img, &mut whole);
+        blur.blur_image_into_tiled(&img, &mut tiled, true);
+        for (whole, tiled) in whole.plane(0).iter().zip(tiled.plane(0)) {
+            assert!((whole - tiled).abs() < 1e-5f32, "{whole} vs. {tiled}");
+        }
+
+        assert_eq!(Tile::count(height), 3);
+        let last = Tile::new(2, height);
+        assert_eq!(last.rows, 2 * TILE_ROWS..height);
+        assert_eq!(last.window, 2 * TILE_ROWS - RADIUS..height);
+        assert!(!Tile::is_tiled(2 * TILE_ROWS - 1, true));
     }
 }
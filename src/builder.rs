@@ -0,0 +1,279 @@
+use alloc::sync::Arc;
+#[cfg(feature = "rayon")]
+use core::num::NonZeroUsize;
+
+use crate::{
+    CancellationToken, CubeRoot, Denoise, Downscaler, Preprocess, Rescale, Settings, ShiftCheck,
+    Ssimulacra2, Ssimulacra2Error, Viewing,
+};
+
+/// Collects the options of a [`Ssimulacra2`] session and checks them all at
+/// once when the session is [built][Self::build].
+///
+/// This is an alternative to chaining the `with_*` methods of [`Ssimulacra2`]
+/// on a new session, for when the options are gathered in several places,
+/// e.g. from a configuration file and command line arguments, before the
+/// session is needed. Options that are not set keep the defaults of
+/// [`Ssimulacra2::new`].
+///
+/// ```
+/// # use ssimulacra2::{Preprocess, Ssimulacra2Builder};
+/// let session = Ssimulacra2Builder::new(1920, 1080)
+///     .with_preprocess(Preprocess::Blur)
+///     .with_companion_metrics(true)
+///     .build()?;
+/// # Ok::<(), ssimulacra2::Ssimulacra2Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Ssimulacra2Builder {
+    width: usize,
+    height: usize,
+    preallocate: bool,
+    settings: Settings,
+    validate: Option<bool>,
+    downscaler: Option<Arc<dyn Downscaler>>,
+    tiling: bool,
+    cancellation: Option<CancellationToken>,
+    #[cfg(feature = "rayon")]
+    parallelism: Parallelism,
+}
+
+// Where a session runs the parallel parts of scoring.
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+enum Parallelism {
+    Global,
+    Threads(NonZeroUsize),
+    Pool(Arc<rayon::ThreadPool>),
+}
+
+impl Ssimulacra2Builder {
+    /// Starts building a session for images of the given width and height.
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Ssimulacra2Builder {
+            width,
+            height,
+            preallocate: false,
+            settings: Settings::default(),
+            validate: None,
+            downscaler: None,
+            tiling: false,
+            cancellation: None,
+            #[cfg(feature = "rayon")]
+            parallelism: Parallelism::Global,
+        }
+    }
+
+    /// Allocates all working memory of the session up front, see
+    /// [`Ssimulacra2::preallocated`].
+    #[must_use]
+    pub fn with_preallocation(self, preallocate: bool) -> Self {
+        Ssimulacra2Builder {
+            preallocate,
+            ..self
+        }
+    }
+
+    /// See [`Ssimulacra2::with_downscaler`].
+    #[must_use]
+    pub fn with_downscaler<D: Downscaler + 'static>(self, downscaler: D) -> Self {
+        Ssimulacra2Builder {
+            downscaler: Some(Arc::new(downscaler)),
+            ..self
+        }
+    }
+
+    /// See [`Ssimulacra2::with_validation`].
+    #[must_use]
+    pub fn with_validation(self, validate: bool) -> Self {
+        Ssimulacra2Builder {
+            validate: Some(validate),
+            ..self
+        }
+    }
+
+    /// See [`Ssimulacra2::with_preprocess`].
+    #[must_use]
+    pub const fn with_preprocess(mut self, preprocess: Preprocess) -> Self {
+        self.settings.preprocess = preprocess;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_companion_metrics`].
+    #[must_use]
+    pub const fn with_companion_metrics(mut self, companion_metrics: bool) -> Self {
+        self.settings.companion_metrics = companion_metrics;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_level_normalization`].
+    #[must_use]
+    pub const fn with_level_normalization(mut self, normalize_levels: bool) -> Self {
+        self.settings.normalize_levels = normalize_levels;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_shift_check`].
+    #[must_use]
+    pub const fn with_shift_check(mut self, shift_check: ShiftCheck) -> Self {
+        self.settings.shift_check = shift_check;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_rescale`].
+    #[must_use]
+    pub const fn with_rescale(mut self, rescale: Rescale) -> Self {
+        self.settings.rescale = rescale;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_border_crop`].
+    #[must_use]
+    pub const fn with_border_crop(mut self, crop_borders: bool) -> Self {
+        self.settings.crop_borders = crop_borders;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_cube_root`].
+    #[must_use]
+    pub const fn with_cube_root(mut self, cube_root: CubeRoot) -> Self {
+        self.settings.cube_root = cube_root;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_reference_denoise`].
+    #[must_use]
+    pub const fn with_reference_denoise(mut self, denoise: Denoise) -> Self {
+        self.settings.reference_denoise = denoise;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_viewing`].
+    #[must_use]
+    pub const fn with_viewing(mut self, viewing: Viewing) -> Self {
+        self.settings.viewing = viewing;
+        self
+    }
+
+    /// Replaces all options that are part of [`Settings`] at once.
+    #[must_use]
+    pub fn with_settings(self, settings: Settings) -> Self {
+        Ssimulacra2Builder { settings, ..self }
+    }
+
+    /// See [`Ssimulacra2::with_tiling`].
+    #[must_use]
+    pub const fn with_tiling(mut self, tiling: bool) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    /// See [`Ssimulacra2::with_cancellation`].
+    #[must_use]
+    pub fn with_cancellation(self, token: CancellationToken) -> Self {
+        Ssimulacra2Builder {
+            cancellation: Some(token),
+            ..self
+        }
+    }
+
+    /// Runs the parallel parts of scoring on a thread pool of its own with
+    /// the given number of threads, which is created when the session is
+    /// built. Replaces any [thread pool][Self::with_thread_pool] set before.
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn with_threads(self, threads: NonZeroUsize) -> Self {
+        Ssimulacra2Builder {
+            parallelism: Parallelism::Threads(threads),
+            ..self
+        }
+    }
+
+    /// See [`Ssimulacra2::with_thread_pool`]. Replaces any
+    /// [number of threads][Self::with_threads] set before.
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn with_thread_pool(self, thread_pool: Arc<rayon::ThreadPool>) -> Self {
+        Ssimulacra2Builder {
+            parallelism: Parallelism::Pool(thread_pool),
+            ..self
+        }
+    }
+
+    /// Creates the session with all options set on this builder.
+    ///
+    /// # Errors
+    /// - If the width or height is less than 8 pixels
+    /// - If the thread pool for [`with_threads`][Self::with_threads] cannot be created
+    pub fn build(self) -> Result<Ssimulacra2, Ssimulacra2Error> {
+        let mut session = if self.preallocate {
+            Ssimulacra2::preallocated(self.width, self.height)?
+        } else {
+            Ssimulacra2::new(self.width, self.height)?
+        }
+        .with_settings(self.settings)
+        .with_tiling(self.tiling);
+
+        if let Some(validate) = self.validate {
+            session = session.with_validation(validate);
+        }
+        if let Some(downscaler) = self.downscaler {
+            session = session.with_shared_downscaler(downscaler);
+        }
+        if let Some(token) = self.cancellation {
+            session = session.with_cancellation(token);
+        }
+        #[cfg(feature = "rayon")]
+        match self.parallelism {
+            Parallelism::Global => {}
+            Parallelism::Threads(threads) => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads.get())
+                    .build()
+                    .map_err(|_err| Ssimulacra2Error::ThreadPoolCreationFailed)?;
+                session = session.with_thread_pool(Arc::new(pool));
+            }
+            Parallelism::Pool(pool) => session = session.with_thread_pool(pool),
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::wave_image;
+
+    #[test]
+    fn builds_configured_session() {
+        assert_eq!(
+            Ssimulacra2Builder::new(7, 45).build().err(),
+            Some(Ssimulacra2Error::InvalidImageSize)
+        );
+
+        let settings = Settings {
+            preprocess: Preprocess::Blur,
+            companion_metrics: true,
+            ..Settings::default()
+        };
+        let builder = Ssimulacra2Builder::new(67, 45)
+            .with_settings(settings)
+            .with_preallocation(true);
+        #[cfg(feature = "rayon")]
+        let builder = builder.with_threads(NonZeroUsize::new(2).unwrap());
+        let mut session = builder.build().unwrap();
+        assert_eq!(session.settings(), settings);
+
+        let expected = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .with_settings(settings)
+            .score_detailed(wave_image(67, 45, 0.0), wave_image(67, 45, 0.5))
+            .unwrap();
+        let detailed = session
+            .score_detailed(wave_image(67, 45, 0.0), wave_image(67, 45, 0.5))
+            .unwrap();
+        assert_eq!(detailed.score.to_bits(), expected.score.to_bits());
+        assert!(detailed.companion.is_some());
+    }
+}
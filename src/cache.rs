@@ -0,0 +1,386 @@
+use core::hash::Hasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use yuvxyb::LinearRgb;
+
+use crate::{
+    CubeRoot, Denoise, PlaneHasher, Preprocess, Rescale, ResizeFilter, Settings, ShiftCheck,
+    Viewing,
+};
+
+/// Identifies a comparison by hashes of both images and of the settings it
+/// was scored with, see [`ScoreCache`].
+///
+/// The fields are public so that the hash of a source image can be computed
+/// once with [`image_hash`][Self::image_hash] and combined with those of many
+/// distorted images, e.g. when scoring them against a [`Reference`][crate::Reference].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheKey {
+    /// The [`image_hash`][Self::image_hash] of the source image.
+    pub reference: u64,
+    /// The [`image_hash`][Self::image_hash] of the distorted image.
+    pub distorted: u64,
+    /// The [`settings_hash`][Self::settings_hash] of the settings.
+    pub settings: u64,
+}
+
+impl CacheKey {
+    /// Creates the key of comparing `distorted` to `reference` with `settings`.
+    #[must_use]
+    pub fn new(reference: &LinearRgb, distorted: &LinearRgb, settings: Settings) -> Self {
+        CacheKey {
+            reference: Self::image_hash(reference),
+            distorted: Self::image_hash(distorted),
+            settings: Self::settings_hash(settings),
+        }
+    }
+
+    /// The hash of the size and the exact bits of all values of `image`, see
+    /// [`PlaneHasher`].
+    ///
+    /// The hash is taken after the conversion to linear RGB, so the same
+    /// pixels decoded from files of different formats have the same hash.
+    #[must_use]
+    pub fn image_hash(image: &LinearRgb) -> u64 {
+        let mut hasher = PlaneHasher::new();
+        hasher.write_u64(image.width() as u64);
+        hasher.write_u64(image.height() as u64);
+        hasher.update(bytemuck::cast_slice(image.data()));
+        hasher.finish()
+    }
+
+    /// The hash of the settings that change the score, and of the version
+    /// of this crate, so that scores cached by another version
+    /// are not reused. [`Settings::companion_metrics`] does not change the
+    /// score and is left out.
+    #[must_use]
+    pub fn settings_hash(settings: Settings) -> u64 {
+        let preprocess = match settings.preprocess {
+            Preprocess::None => 0u8,
+            Preprocess::Blur => 1,
+            Preprocess::Decimate => 2,
+        };
+        let shift_check = match settings.shift_check {
+            ShiftCheck::None => 0u8,
+            ShiftCheck::Detect => 1,
+            ShiftCheck::Compensate => 2,
+        };
+        let filter = |filter| match filter {
+            ResizeFilter::Area => 0u8,
+            ResizeFilter::Lanczos3 => 1,
+        };
+        let rescale = match settings.rescale {
+            Rescale::None => [0u8, 0],
+            Rescale::ToSource(f) => [1, filter(f)],
+            Rescale::ToDistorted(f) => [2, filter(f)],
+        };
+        let cube_root = match settings.cube_root {
+            CubeRoot::Exact => 0u8,
+            CubeRoot::Approximate => 1,
+            CubeRoot::Table => 2,
+        };
+        let reference_denoise = match settings.reference_denoise {
+            Denoise::None => 0u8,
+            Denoise::Light => 1,
+            Denoise::Strong => 2,
+        };
+        let viewing = match settings.viewing {
+            Viewing::Native => [0u8; 2],
+            Viewing::PixelsPerDegree(ppd) => ppd.get().to_le_bytes(),
+        };
+        let bytes = [
+            preprocess,
+            u8::from(settings.normalize_levels),
+            shift_check,
+            rescale[0],
+            rescale[1],
+            u8::from(settings.crop_borders),
+            cube_root,
+            reference_denoise,
+            viewing[0],
+            viewing[1],
+        ];
+        let mut hasher = PlaneHasher::new();
+        hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.write(&bytes);
+        hasher.finish()
+    }
+}
+
+/// A store of scores of earlier comparisons, so that comparing the same
+/// images with the same settings again, e.g. in repeated benchmark runs over
+/// a corpus, can skip scoring them.
+///
+/// [`Ssimulacra2::score_cached`][crate::Ssimulacra2::score_cached] looks up
+/// scores and inserts new ones. [`MemoryScoreCache`] keeps scores for the
+/// lifetime of a process and [`DiskScoreCache`] in a file across runs.
+///
+/// The key covers the [`Settings`] of a session, but not its
+/// [downscaler][crate::Ssimulacra2::with_downscaler]; sessions with a custom
+/// downscaler need a cache of their own.
+pub trait ScoreCache {
+    /// The score cached for `key`, if any.
+    fn get(&self, key: &CacheKey) -> Option<f64>;
+
+    /// Caches `score` for `key`, replacing any score cached before.
+    fn insert(&mut self, key: CacheKey, score: f64);
+}
+
+/// A [`ScoreCache`] in memory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryScoreCache {
+    scores: BTreeMap<CacheKey, f64>,
+}
+
+impl MemoryScoreCache {
+    /// Creates an empty cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of cached scores.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Whether no scores are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+}
+
+impl ScoreCache for MemoryScoreCache {
+    fn get(&self, key: &CacheKey) -> Option<f64> {
+        self.scores.get(key).copied()
+    }
+
+    fn insert(&mut self, key: CacheKey, score: f64) {
+        self.scores.insert(key, score);
+    }
+}
+
+/// A [`ScoreCache`] stored in a file, which is read when the cache is
+/// [opened][Self::open] and written by [`save`][Self::save].
+///
+/// The file has one line per score, holding the three hashes of its key in
+/// hexadecimal followed by the score. Lines that cannot be read, e.g. those
+/// of a newer version of this format, are skipped. The hashes depend on the
+/// `xxhash` feature, so builds with and without it do not find each other's
+/// scores. The cache is not meant to be shared by processes running at the
+/// same time; the last one to save it wins.
+#[derive(Debug, Clone)]
+pub struct DiskScoreCache {
+    path: PathBuf,
+    scores: MemoryScoreCache,
+    modified: bool,
+}
+
+impl DiskScoreCache {
+    /// Reads the cache stored in the file at `path`. If the file does not
+    /// exist yet, the cache starts out empty and the file is created when it
+    /// is saved.
+    ///
+    /// # Errors
+    /// - If the file exists but cannot be read
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut scores = MemoryScoreCache::new();
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                for (key, score) in contents.lines().filter_map(parse_line) {
+                    scores.insert(key, score);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        Ok(DiskScoreCache {
+            path,
+            scores,
+            modified: false,
+        })
+    }
+
+    /// The path of the file of the cache.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of cached scores.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Whether no scores are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Writes all cached scores to the file, if any were inserted since the
+    /// cache was opened or last saved.
+    ///
+    /// The scores are written to a temporary file next to it first, which
+    /// then replaces the file, so that an interrupted save does not lose the
+    /// scores saved before.
+    ///
+    /// # Errors
+    /// - If the file cannot be written
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let mut contents = Vec::new();
+        for (key, score) in &self.scores.scores {
+            writeln!(
+                contents,
+                "{:016x} {:016x} {:016x} {score}",
+                key.reference, key.distorted, key.settings
+            )?;
+        }
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, &self.path)?;
+        self.modified = false;
+        Ok(())
+    }
+}
+
+impl ScoreCache for DiskScoreCache {
+    fn get(&self, key: &CacheKey) -> Option<f64> {
+        self.scores.get(key)
+    }
+
+    fn insert(&mut self, key: CacheKey, score: f64) {
+        self.scores.insert(key, score);
+        self.modified = true;
+    }
+}
+
+fn parse_line(line: &str) -> Option<(CacheKey, f64)> {
+    let mut fields = line.split_ascii_whitespace();
+    let mut hash = || u64::from_str_radix(fields.next()?, 16).ok();
+    let key = CacheKey {
+        reference: hash()?,
+        distorted: hash()?,
+        settings: hash()?,
+    };
+    let score = fields.next()?.parse().ok()?;
+    fields.next().is_none().then_some((key, score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::wave_image;
+    use crate::Ssimulacra2;
+
+    #[test]
+    fn keys_follow_content_and_settings() {
+        let key = CacheKey::new(
+            &wave_image(16, 12, 0.0),
+            &wave_image(16, 12, 0.5),
+            Settings::default(),
+        );
+        assert_eq!(
+            key,
+            CacheKey::new(
+                &wave_image(16, 12, 0.0),
+                &wave_image(16, 12, 0.5),
+                Settings::default()
+            )
+        );
+        assert_ne!(
+            key.distorted,
+            CacheKey::image_hash(&wave_image(16, 12, 0.25))
+        );
+        assert_ne!(
+            key.reference,
+            CacheKey::image_hash(&wave_image(12, 16, 0.0))
+        );
+
+        let companion = Settings {
+            companion_metrics: true,
+            ..Settings::default()
+        };
+        let blur = Settings {
+            preprocess: Preprocess::Blur,
+            ..Settings::default()
+        };
+        assert_eq!(key.settings, CacheKey::settings_hash(companion));
+        assert_ne!(key.settings, CacheKey::settings_hash(blur));
+        let approximate = Settings {
+            cube_root: CubeRoot::Approximate,
+            ..Settings::default()
+        };
+        assert_ne!(key.settings, CacheKey::settings_hash(approximate));
+        let denoise = Settings {
+            reference_denoise: Denoise::Light,
+            ..Settings::default()
+        };
+        assert_ne!(key.settings, CacheKey::settings_hash(denoise));
+        let phone = Settings {
+            viewing: Viewing::from_distance(460.0f64, 10.0f64),
+            ..Settings::default()
+        };
+        assert_ne!(key.settings, CacheKey::settings_hash(phone));
+    }
+
+    #[test]
+    #[cfg_attr(target_os = "wasi", ignore = "WASI has no temporary directory")]
+    fn cached_scores_survive_saving() {
+        let mut session = Ssimulacra2::new(32, 24).unwrap();
+        let mut memory = MemoryScoreCache::new();
+        let score = session
+            .score_cached(
+                wave_image(32, 24, 0.0),
+                wave_image(32, 24, 0.5),
+                &mut memory,
+            )
+            .unwrap();
+        assert_eq!(memory.len(), 1);
+        let key = CacheKey::new(
+            &wave_image(32, 24, 0.0),
+            &wave_image(32, 24, 0.5),
+            session.settings(),
+        );
+        assert_eq!(memory.get(&key).map(f64::to_bits), Some(score.to_bits()));
+
+        // A cached score is returned as is, without scoring the images again.
+        memory.insert(key, 42.0f64);
+        let cached = session
+            .score_cached(
+                wave_image(32, 24, 0.0),
+                wave_image(32, 24, 0.5),
+                &mut memory,
+            )
+            .unwrap();
+        assert_eq!(cached.to_bits(), 42.0f64.to_bits());
+
+        let path = std::env::temp_dir().join(format!("ssimulacra2-cache-{}", std::process::id()));
+        let mut disk = DiskScoreCache::open(&path).unwrap();
+        assert!(disk.is_empty());
+        disk.insert(key, score);
+        disk.save().unwrap();
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "truncated line\n",
+        )
+        .unwrap();
+
+        let disk = DiskScoreCache::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(disk.len(), 1);
+        assert_eq!(disk.get(&key).map(f64::to_bits), Some(score.to_bits()));
+    }
+}
@@ -0,0 +1,85 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Ssimulacra2Error;
+
+/// A flag for aborting comparisons from another thread, e.g. when the user
+/// of a GUI closes the image or a server request times out.
+///
+/// Clones of a token share the same flag. Sessions and references given a
+/// token with [`Ssimulacra2::with_cancellation`][crate::Ssimulacra2::with_cancellation]
+/// or [`Reference::with_cancellation`][crate::Reference::with_cancellation]
+/// check it between the stages of every scale and fail with
+/// [`Ssimulacra2Error::Cancelled`] once it is set, so even a comparison of
+/// 8K images stops within a fraction of its run time.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token which is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all comparisons checking this token or one of its clones,
+    /// including those started afterwards.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`cancel`][Self::cancel] has been called.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Fails with `Cancelled` if `token` is given and has been cancelled.
+pub fn check_cancelled(token: Option<&CancellationToken>) -> Result<(), Ssimulacra2Error> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(Ssimulacra2Error::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+    use crate::{Reference, Ssimulacra2};
+
+    #[test]
+    fn cancelled() {
+        let token = CancellationToken::new();
+        let mut session = Ssimulacra2::new(40, 30)
+            .unwrap()
+            .with_cancellation(token.clone());
+        let reference = Reference::new(make_image(40, 30, 3))
+            .unwrap()
+            .with_cancellation(token.clone());
+        let expected = reference.compare(make_image(40, 30, 5)).unwrap();
+        assert!(
+            (session
+                .score(make_image(40, 30, 3), make_image(40, 30, 5))
+                .unwrap()
+                - expected)
+                .abs()
+                < 1e-9_f64
+        );
+        let pooled = session.pool_stats().pooled;
+
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(
+            session.score(make_image(40, 30, 3), make_image(40, 30, 5)),
+            Err(Ssimulacra2Error::Cancelled)
+        );
+        assert_eq!(
+            reference.compare(make_image(40, 30, 5)),
+            Err(Ssimulacra2Error::Cancelled)
+        );
+        // The buffers of the aborted comparison are back in the pool.
+        assert_eq!(session.pool_stats().pooled, pooled);
+    }
+}
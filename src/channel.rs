@@ -0,0 +1,516 @@
+use alloc::vec::Vec;
+use core::{array, mem};
+
+use crate::blur::{RowBlur, Tile, TILE_ROWS};
+use crate::prefetch::{prefetch, LINE};
+use crate::{edge_diff, ssim_error, DistortionMap};
+
+// The planes of one channel of a scale of the source image.
+#[derive(Debug, Clone, Copy)]
+pub struct SourceChannel<'a> {
+    pub img: &'a [f32],
+    pub mu: &'a [f32],
+    pub sigma_sq: &'a [f32],
+}
+
+// Row buffers for comparing one channel of a scale.
+#[derive(Debug, Default)]
+pub struct ChannelScratch {
+    sigma2_sq: RowBlur,
+    sigma12: RowBlur,
+    mu2: RowBlur,
+}
+
+impl ChannelScratch {
+    // Allocates the row buffers for images of up to the given width.
+    pub fn reserve(&mut self, width: usize) {
+        for blur in [&mut self.sigma2_sq, &mut self.sigma12, &mut self.mu2] {
+            blur.reset(width, 0);
+        }
+    }
+
+    // The number of bytes of the row buffers for images of the given width.
+    pub const fn required_bytes(width: usize) -> usize {
+        3 * RowBlur::buffer_len(width) * mem::size_of::<f32>()
+    }
+}
+
+// The 1-norm and 4-norm of the SSIM error, and of the artifact and detail
+// lost errors, of one channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelErrors {
+    pub ssim: [f64; 2],
+    pub edge_diff: [f64; 4],
+}
+
+// Compares the channels of a scale, usually the three of XYB. They are
+// compared in parallel, unless the channels of `map` have to be filled in one
+// after another or the scale is too small to be worth it. The weights of
+// `map` are those of the XYB channels.
+//
+// If `tiling` is set, tall scales are compared in tiles, see `Tile`, which
+// are compared in parallel even for the channels of `map`.
+pub fn compare_channels<const N: usize>(
+    width: usize,
+    height: usize,
+    source: [SourceChannel<'_>; N],
+    img2: [&[f32]; N],
+    scratch: &mut [ChannelScratch; N],
+    map: Option<(&mut DistortionMap, usize)>,
+    tiling: bool,
+) -> [ChannelErrors; N] {
+    if Tile::is_tiled(height, tiling) {
+        return compare_channels_tiled(width, height, source, img2, map);
+    }
+
+    let whole = Tile::whole(height);
+    let mut errors = [ChannelErrors::default(); N];
+
+    if let Some((map, num_scales)) = map {
+        assert_eq!(N, 3, "Distortion maps are weighted for the XYB channels");
+        for (c, (errors, scratch)) in errors.iter_mut().zip(scratch.iter_mut()).enumerate() {
+            let weights = DistortionMap::channel_weights(c, num_scales);
+            *errors = compare_channel(
+                width,
+                &whole,
+                source[c],
+                img2[c],
+                scratch,
+                Some((map.data_mut(), weights)),
+            )
+            .errors(width * height);
+        }
+        return errors;
+    }
+
+    #[cfg(feature = "rayon")]
+    if width * height >= crate::PARALLEL_MIN_VALUES {
+        use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+        errors
+            .par_iter_mut()
+            .zip(scratch.par_iter_mut())
+            .enumerate()
+            .for_each(|(c, (errors, scratch))| {
+                *errors = compare_channel(width, &whole, source[c], img2[c], scratch, None)
+                    .errors(width * height);
+            });
+        return errors;
+    }
+
+    for (c, (errors, scratch)) in errors.iter_mut().zip(scratch.iter_mut()).enumerate() {
+        *errors = compare_channel(width, &whole, source[c], img2[c], scratch, None)
+            .errors(width * height);
+    }
+
+    errors
+}
+
+// Compares the channels of a scale tile by tile, with the tiles of all
+// channels in parallel. The sums of the tiles are added up in a fixed order,
+// so that the errors do not depend on the number of threads. Every tile
+// needs its own row buffers, which are allocated per thread.
+fn compare_channels_tiled<const N: usize>(
+    width: usize,
+    height: usize,
+    source: [SourceChannel<'_>; N],
+    img2: [&[f32]; N],
+    map: Option<(&mut DistortionMap, usize)>,
+) -> [ChannelErrors; N] {
+    let tiles = Tile::count(height);
+    let compare_tile = |scratch: &mut ChannelScratch,
+                        c: usize,
+                        index: usize,
+                        map: Option<(&mut [f32], [f64; 3])>| {
+        let tile = Tile::new(index, height);
+        compare_channel(width, &tile, source[c], img2[c], scratch, map)
+    };
+
+    // The sums of the tiles of the first channel, then of the second, etc.
+    let mut sums = Vec::with_capacity(N * tiles);
+    if let Some((map, num_scales)) = map {
+        assert_eq!(N, 3, "Distortion maps are weighted for the XYB channels");
+        for c in 0..N {
+            let weights = DistortionMap::channel_weights(c, num_scales);
+            #[cfg(feature = "rayon")]
+            {
+                use rayon::iter::{IndexedParallelIterator, ParallelExtend, ParallelIterator};
+                use rayon::slice::ParallelSliceMut;
+
+                sums.par_extend(
+                    map.data_mut()
+                        .par_chunks_mut(TILE_ROWS * width)
+                        .enumerate()
+                        .map_init(ChannelScratch::default, |scratch, (index, map)| {
+                            compare_tile(scratch, c, index, Some((map, weights)))
+                        }),
+                );
+            }
+            #[cfg(not(feature = "rayon"))]
+            {
+                let mut scratch = ChannelScratch::default();
+                sums.extend(
+                    map.data_mut()
+                        .chunks_mut(TILE_ROWS * width)
+                        .enumerate()
+                        .map(|(index, map)| {
+                            compare_tile(&mut scratch, c, index, Some((map, weights)))
+                        }),
+                );
+            }
+        }
+    } else {
+        let jobs: Vec<_> = (0..N * tiles)
+            .map(|i| (i / tiles, Tile::new(i % tiles, height)))
+            .collect();
+        sums = compare_tiles(width, source, img2, &jobs);
+    }
+
+    array::from_fn(|c| merge_tiles(&sums[c * tiles..(c + 1) * tiles], width * height))
+}
+
+// Compares the tiles of `jobs`, each given with the index of its channel, in
+// parallel, and returns their sums in the same order.
+pub fn compare_tiles<const N: usize>(
+    width: usize,
+    source: [SourceChannel<'_>; N],
+    img2: [&[f32]; N],
+    jobs: &[(usize, Tile)],
+) -> Vec<ChannelSums> {
+    let compare_tile = |scratch: &mut ChannelScratch, &(c, ref tile): &(usize, Tile)| {
+        compare_channel(width, tile, source[c], img2[c], scratch, None)
+    };
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+        jobs.par_iter()
+            .map_init(ChannelScratch::default, compare_tile)
+            .collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        let mut scratch = ChannelScratch::default();
+        jobs.iter()
+            .map(|job| compare_tile(&mut scratch, job))
+            .collect()
+    }
+}
+
+// The errors of a channel of `pixels` pixels from the sums of its tiles.
+pub fn merge_tiles(tiles: &[ChannelSums], pixels: usize) -> ChannelErrors {
+    let mut total = tiles[0];
+    for sums in &tiles[1..] {
+        total.merge(sums);
+    }
+    total.errors(pixels)
+}
+
+// Compares the rows of `tile` of one channel of the distorted image `img2`
+// against the source row by row. The blurred planes of the distorted image
+// are produced one row at a time and consumed right away, so only a few rows
+// of them are kept in cache instead of writing whole planes to memory and
+// reading them back for the error sums. If given, the weighted errors are
+// added to `map`, which holds the rows of the tile.
+fn compare_channel(
+    width: usize,
+    tile: &Tile,
+    source: SourceChannel<'_>,
+    img2: &[f32],
+    scratch: &mut ChannelScratch,
+    mut map: Option<(&mut [f32], [f64; 3])>,
+) -> ChannelSums {
+    let Tile {
+        ref rows,
+        ref window,
+    } = *tile;
+    // The row blurs only see the rows of the window.
+    let window_values = window.start * width..window.end * width;
+    let (img1_window, img2_window) = (&source.img[window_values.clone()], &img2[window_values]);
+    scratch.sigma2_sq.reset(width, window.len());
+    scratch.sigma12.reset(width, window.len());
+    scratch.mu2.reset(width, window.len());
+
+    let mut sums = ChannelSums::default();
+    for y in window.start..rows.end {
+        let s22 = scratch.sigma2_sq.next_row_product(img2_window, img2_window);
+        let s12 = scratch.sigma12.next_row_product(img1_window, img2_window);
+        let m2 = scratch.mu2.next_row(img2_window);
+        if y < rows.start {
+            continue;
+        }
+        let row = y * width..(y + 1) * width;
+        let map_row = (y - rows.start) * width;
+        let p1 = &source.img[row.clone()];
+        let m1 = &source.mu[row.clone()];
+        let s11 = &source.sigma_sq[row.clone()];
+        let p2 = &img2[row.clone()];
+
+        // The source planes that no row blur reads ahead are prefetched a
+        // row ahead, one cache line per block of the current row.
+        for start in (0..width).step_by(LINE) {
+            prefetch(source.mu, row.end + start);
+            prefetch(source.sigma_sq, row.end + start);
+
+            for x in start..(start + LINE).min(width) {
+                let [d, artifact, detail_lost] =
+                    sums.add_pixel([p1[x], m1[x], s11[x]], [p2[x], m2[x], s22[x], s12[x]]);
+
+                if let Some((ref mut map, [w_ssim, w_ringing, w_blurring])) = map {
+                    let sum =
+                        w_blurring.mul_add(detail_lost, w_ringing.mul_add(artifact, w_ssim * d));
+                    map[map_row + x] += sum as f32;
+                }
+            }
+        }
+    }
+
+    sums
+}
+
+// Compares the channels of a scale of `LANES` distorted images at once,
+// whose planes in `img2` hold their values interleaved, and returns the
+// errors of every channel of every image. The channels are compared in
+// parallel like in `compare_channels`, which gives the same errors for each
+// image, but the source planes are only read once for all images.
+pub fn compare_channels_lanes<const N: usize, const LANES: usize>(
+    width: usize,
+    height: usize,
+    source: [SourceChannel<'_>; N],
+    img2: [&[f32]; N],
+    scratch: &mut [ChannelScratch; N],
+) -> [[ChannelErrors; N]; LANES] {
+    let mut sums = [[ChannelSums::default(); LANES]; N];
+
+    #[cfg(feature = "rayon")]
+    if width * height >= crate::PARALLEL_MIN_VALUES {
+        use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+        sums.par_iter_mut()
+            .zip(scratch.par_iter_mut())
+            .enumerate()
+            .for_each(|(c, (sums, scratch))| {
+                *sums = compare_channel_lanes(width, height, source[c], img2[c], scratch);
+            });
+        return array::from_fn(|lane| array::from_fn(|c| sums[c][lane].errors(width * height)));
+    }
+
+    for (c, (sums, scratch)) in sums.iter_mut().zip(scratch.iter_mut()).enumerate() {
+        *sums = compare_channel_lanes(width, height, source[c], img2[c], scratch);
+    }
+    array::from_fn(|lane| array::from_fn(|c| sums[c][lane].errors(width * height)))
+}
+
+// Compares one channel of `LANES` distorted images, interleaved in `img2`,
+// against the source, like `compare_channel` compares one image. The row
+// blurs run on all images at once, and every value of the source is read
+// once for all of them.
+fn compare_channel_lanes<const LANES: usize>(
+    width: usize,
+    height: usize,
+    source: SourceChannel<'_>,
+    img2: &[f32],
+    scratch: &mut ChannelScratch,
+) -> [ChannelSums; LANES] {
+    #[cfg(all(
+        feature = "std",
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(target_feature = "fma")
+    ))]
+    if crate::fma::detected() {
+        // SAFETY: the CPU supports FMA and AVX2.
+        return unsafe { compare_channel_lanes_fma(width, height, source, img2, scratch) };
+    }
+    compare_channel_lanes_impl(width, height, source, img2, scratch)
+}
+
+// `compare_channel_lanes` compiled with FMA and AVX2, see the `fma` module.
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(target_feature = "fma")
+))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn compare_channel_lanes_fma<const LANES: usize>(
+    width: usize,
+    height: usize,
+    source: SourceChannel<'_>,
+    img2: &[f32],
+    scratch: &mut ChannelScratch,
+) -> [ChannelSums; LANES] {
+    compare_channel_lanes_impl(width, height, source, img2, scratch)
+}
+
+#[inline(always)]
+fn compare_channel_lanes_impl<const LANES: usize>(
+    width: usize,
+    height: usize,
+    source: SourceChannel<'_>,
+    img2: &[f32],
+    scratch: &mut ChannelScratch,
+) -> [ChannelSums; LANES] {
+    scratch.sigma2_sq.reset_lanes(width, height, LANES);
+    scratch.sigma12.reset_lanes(width, height, LANES);
+    scratch.mu2.reset_lanes(width, height, LANES);
+
+    let mut sums = [ChannelSums::default(); LANES];
+    for y in 0..height {
+        let s22 = scratch.sigma2_sq.next_row_lanes_square::<LANES>(img2);
+        let s12 = scratch
+            .sigma12
+            .next_row_lanes_product::<LANES>(source.img, img2);
+        let m2 = scratch.mu2.next_row_lanes::<LANES>(img2);
+        let row = y * width..(y + 1) * width;
+        let p1 = &source.img[row.clone()];
+        let m1 = &source.mu[row.clone()];
+        let s11 = &source.sigma_sq[row];
+        let p2 = &img2[y * width * LANES..(y + 1) * width * LANES];
+
+        // Lane by lane, so that the sums of a lane stay in registers, while
+        // the rows of the source stay in cache.
+        for (lane, sums) in sums.iter_mut().enumerate() {
+            for x in 0..width {
+                let i = x * LANES + lane;
+                sums.add_pixel([p1[x], m1[x], s11[x]], [p2[i], m2[i], s22[i], s12[i]]);
+            }
+        }
+    }
+
+    sums
+}
+
+// The sums of the errors of the rows of a channel compared so far.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelSums {
+    ssim: [CompensatedSum; 2],
+    edge_diff: [CompensatedSum; 4],
+    // Clamping the errors to positive values turns NaN into zero, which would
+    // hide non-finite inputs, so they are tracked separately.
+    finite: bool,
+}
+
+impl Default for ChannelSums {
+    fn default() -> Self {
+        ChannelSums {
+            ssim: Default::default(),
+            edge_diff: Default::default(),
+            finite: true,
+        }
+    }
+}
+
+impl ChannelSums {
+    // Adds the errors of a pixel, given by the source value, mean and
+    // variance, and the distorted value, mean, variance and covariance.
+    // Returns the SSIM error and the artifact and detail lost errors.
+    #[inline(always)]
+    fn add_pixel(&mut self, [p1, m1, s11]: [f32; 3], [p2, m2, s22, s12]: [f32; 4]) -> [f64; 3] {
+        let d = ssim_error(m1, m2, s11, s22, s12);
+        self.ssim[0].add(d);
+        self.ssim[1].add(d.powi(4));
+
+        let d1 = edge_diff(p1, m1, p2, m2);
+        self.finite &= d1.is_finite();
+        // d1 > 0: distorted has an edge where original is smooth
+        //         (indicating ringing, color banding, blockiness, etc)
+        let artifact = d1.max(0.0);
+        self.edge_diff[0].add(artifact);
+        self.edge_diff[1].add(artifact.powi(4));
+        // d1 < 0: original has an edge where distorted is smooth
+        //         (indicating smoothing, blurring, smearing, etc)
+        let detail_lost = (-d1).max(0.0);
+        self.edge_diff[2].add(detail_lost);
+        self.edge_diff[3].add(detail_lost.powi(4));
+
+        [d, artifact, detail_lost]
+    }
+
+    // Adds the sums of other rows of the same channel.
+    fn merge(&mut self, other: &Self) {
+        for (sum, other) in self.ssim.iter_mut().zip(&other.ssim) {
+            sum.merge(other);
+        }
+        for (sum, other) in self.edge_diff.iter_mut().zip(&other.edge_diff) {
+            sum.merge(other);
+        }
+        self.finite &= other.finite;
+    }
+
+    // The norms of the errors of a channel of `pixels` pixels.
+    fn errors(self, pixels: usize) -> ChannelErrors {
+        if !self.finite {
+            return ChannelErrors {
+                ssim: [f64::NAN; 2],
+                edge_diff: [f64::NAN; 4],
+            };
+        }
+
+        let one_per_pixels = 1.0f64 / pixels as f64;
+        let ssim_sums = self.ssim.map(CompensatedSum::value);
+        let edge_sums = self.edge_diff.map(CompensatedSum::value);
+        ChannelErrors {
+            ssim: [
+                one_per_pixels * ssim_sums[0],
+                (one_per_pixels * ssim_sums[1]).sqrt().sqrt(),
+            ],
+            edge_diff: [
+                one_per_pixels * edge_sums[0],
+                (one_per_pixels * edge_sums[1]).sqrt().sqrt(),
+                one_per_pixels * edge_sums[2],
+                (one_per_pixels * edge_sums[3]).sqrt().sqrt(),
+            ],
+        }
+    }
+}
+
+// Neumaier's compensated sum. Summing the errors of millions of pixels in
+// plain floating point loses the low bits of every term once the sum is
+// large, so the result would depend on the image size and summation order.
+#[derive(Debug, Clone, Copy, Default)]
+struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    #[inline(always)]
+    fn add(&mut self, value: f64) {
+        let sum = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - sum) + value;
+        } else {
+            self.compensation += (value - sum) + self.sum;
+        }
+        self.sum = sum;
+    }
+
+    // Adds the terms summed by `other`.
+    fn merge(&mut self, other: &Self) {
+        self.add(other.sum);
+        self.compensation += other.compensation;
+    }
+
+    fn value(self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compensated_sum() {
+        let mut compensated = CompensatedSum::default();
+        let mut plain = 1.0f64;
+        compensated.add(1.0f64);
+        for _ in 0..10_000usize {
+            compensated.add(1e-17f64);
+            plain += 1e-17f64;
+        }
+
+        assert!((plain - 1.0f64).abs() < f64::EPSILON);
+        assert!((compensated.value() - (1.0f64 + 1e-13f64)).abs() < 1e-16f64);
+    }
+}
@@ -0,0 +1,171 @@
+use wide::{f32x8, CmpLe};
+use yuvxyb::LinearRgb;
+
+use crate::{Blur, ScratchPool};
+
+// The stabilizing constants of SSIM for values in `0.0..=1.0`.
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+/// PSNR and SSIM of a pair of images, computed in the same pass as their
+/// SSIMULACRA2 score, see
+/// [`Ssimulacra2::with_companion_metrics`][crate::Ssimulacra2::with_companion_metrics].
+///
+/// Both metrics are computed on the luma of the images encoded with the sRGB
+/// transfer function, with BT.709 weights and values clamped to `0.0..=1.0`,
+/// as tools comparing 8-bit RGB images do. SSIM uses the Gaussian blur of the
+/// first scale of SSIMULACRA2, which has the standard deviation of 1.5 pixels
+/// of the reference implementation of SSIM, as its window, and is averaged
+/// over the whole image without downsampling first. The results are therefore
+/// comparable to, but not exactly the same as, those of tools working on the
+/// Y plane of a video.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompanionMetrics {
+    /// Peak signal-to-noise ratio in dB, infinite for identical images.
+    pub psnr: f64,
+    /// Mean structural similarity, 1.0 for identical images.
+    pub ssim: f64,
+}
+
+impl CompanionMetrics {
+    // Computes both metrics, taking temporary planes from `pool` and blurring
+    // with `blur`, which is resized to the resolution of the images.
+    pub(crate) fn compute(
+        source: &LinearRgb,
+        distorted: &LinearRgb,
+        blur: &mut Blur,
+        pool: &mut ScratchPool,
+    ) -> Self {
+        let (width, height) = (source.width(), source.height());
+        let len = width * height;
+        let mut luma = pool.take_image(width, height, 2);
+        srgb_luma(source, luma.plane_mut(0));
+        srgb_luma(distorted, luma.plane_mut(1));
+        let (luma1, luma2) = (luma.plane(0), luma.plane(1));
+
+        let squared_error: f64 = luma1
+            .iter()
+            .zip(luma2)
+            .map(|(&a, &b)| f64::from(a - b).powi(2))
+            .sum();
+        let mse = squared_error / len as f64;
+        let psnr = if mse > 0.0f64 {
+            -10.0f64 * mse.log10()
+        } else {
+            f64::INFINITY
+        };
+
+        blur.resize(width, height);
+        let mut mu = pool.take_image(width, height, 2);
+        let mut squares = pool.take_image(width, height, 2);
+        let mut sigma12 = pool.take_plane(len);
+        blur.blur_image_into(&luma, &mut mu);
+        blur.blur_image_product_into(&luma, &luma, &mut squares);
+        blur.blur_plane_product(luma1, luma2, &mut sigma12);
+
+        let (mu1, mu2) = (mu.plane(0), mu.plane(1));
+        let (sigma11, sigma22) = (squares.plane(0), squares.plane(1));
+        let mut ssim_sum = 0.0f64;
+        for i in 0..len {
+            let mu1 = f64::from(mu1[i]);
+            let mu2 = f64::from(mu2[i]);
+            let sigma11 = mu1.mul_add(-mu1, f64::from(sigma11[i]));
+            let sigma22 = mu2.mul_add(-mu2, f64::from(sigma22[i]));
+            let sigma12 = mu1.mul_add(-mu2, f64::from(sigma12[i]));
+            let num = 2.0f64.mul_add(mu1 * mu2, SSIM_C1) * 2.0f64.mul_add(sigma12, SSIM_C2);
+            let den = (mu1.mul_add(mu1, mu2 * mu2) + SSIM_C1) * (sigma11 + sigma22 + SSIM_C2);
+            ssim_sum += num / den;
+        }
+
+        for image in [luma, mu, squares] {
+            pool.give_image(image);
+        }
+        pool.give_plane(sigma12);
+        CompanionMetrics {
+            psnr,
+            ssim: ssim_sum / len as f64,
+        }
+    }
+}
+
+// Writes the luma of the sRGB-encoded pixels of `img` to `out`.
+fn srgb_luma(img: &LinearRgb, out: &mut [f32]) {
+    for (pixels, out) in img.data().chunks(8).zip(out.chunks_mut(8)) {
+        let mut channels = [[0.0f32; 8]; 3];
+        for (i, pix) in pixels.iter().enumerate() {
+            for (channel, &value) in channels.iter_mut().zip(pix) {
+                channel[i] = value;
+            }
+        }
+        let [r, g, b] = channels.map(|channel| srgb_encode(f32x8::from(channel)));
+        let luma = r * f32x8::splat(0.2126) + g * f32x8::splat(0.7152) + b * f32x8::splat(0.0722);
+        out.copy_from_slice(&luma.as_array_ref()[..out.len()]);
+    }
+}
+
+pub fn srgb_encode(x: f32x8) -> f32x8 {
+    let x = x.max(f32x8::ZERO).min(f32x8::ONE);
+    let low = x * f32x8::splat(12.92);
+    let high = x.powf(2.4f32.recip()) * f32x8::splat(1.055) - f32x8::splat(0.055);
+    x.cmp_le(f32x8::splat(0.003_130_8)).blend(low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(source: &LinearRgb, distorted: &LinearRgb) -> CompanionMetrics {
+        let mut blur = Blur::new(source.width(), source.height());
+        CompanionMetrics::compute(source, distorted, &mut blur, &mut ScratchPool::new())
+    }
+
+    fn grey(width: usize, height: usize, value: impl Fn(usize) -> f32) -> LinearRgb {
+        let data = (0..width * height).map(|i| [value(i); 3]).collect();
+        LinearRgb::new(data, width, height).unwrap()
+    }
+
+    #[test]
+    fn identical_images() {
+        let img = grey(37, 23, |i| (i % 13) as f32 / 13.0);
+        let metrics = compute(&img, &img);
+        assert!(metrics.psnr.is_infinite());
+        assert!((metrics.ssim - 1.0).abs() < 1e-6f64);
+    }
+
+    #[test]
+    fn known_psnr() {
+        // Grey levels encoded as 0.5 and 0.6 differ by 0.1 in luma everywhere,
+        // which is a PSNR of 20 dB.
+        let encoded = |v: f32| {
+            let mut linear = [v];
+            crate::TransferFunction::Srgb.to_linear(&mut linear);
+            linear[0]
+        };
+        let source = grey(40, 30, |_| encoded(0.5));
+        let distorted = grey(40, 30, |_| encoded(0.6));
+        let metrics = compute(&source, &distorted);
+        assert!((metrics.psnr - 20.0).abs() < 1e-3f64, "{}", metrics.psnr);
+        // A uniform offset only changes the means, which SSIM barely weighs.
+        assert!(
+            metrics.ssim > 0.95f64 && metrics.ssim < 1.0f64,
+            "{}",
+            metrics.ssim
+        );
+    }
+
+    #[test]
+    fn noise_lowers_ssim() {
+        let source = grey(64, 48, |i| 0.2 + 0.5 * (i % 64) as f32 / 64.0);
+        let noisy = |amplitude: f32| {
+            grey(64, 48, |i| {
+                let noise = ((i * 7919) % 17) as f32 / 8.0 - 1.0;
+                noise.mul_add(amplitude, 0.2 + 0.5 * (i % 64) as f32 / 64.0)
+            })
+        };
+        let less = compute(&source, &noisy(0.01));
+        let more = compute(&source, &noisy(0.05));
+        assert!(more.ssim < less.ssim && less.ssim < 1.0f64);
+        assert!(more.psnr < less.psnr);
+    }
+}
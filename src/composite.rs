@@ -0,0 +1,298 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use yuvxyb::LinearRgb;
+
+use crate::{Ssimulacra2, Ssimulacra2Error};
+
+/// The metrics which can be combined by a [`CompositeMetric`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Metric {
+    /// The SSIMULACRA2 score.
+    Ssimulacra2,
+    /// PSNR in dB, see [`CompanionMetrics`][crate::CompanionMetrics]. This is
+    /// infinite for identical images, so it should be clamped by a transform.
+    Psnr,
+    /// SSIM, see [`CompanionMetrics`][crate::CompanionMetrics].
+    Ssim,
+    /// The largest distance of any pixel, see
+    /// [`max_norm_distortion`][crate::experimental::max_norm_distortion].
+    /// Unlike the other metrics, higher values mean more distortion.
+    #[cfg(feature = "experimental")]
+    MaxNorm,
+}
+
+// Maps the value of a metric to the scale of the composite score.
+type Transform = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+
+/// A weighted combination of SSIMULACRA2 and other metrics into one number,
+/// for quality targets which blend several metrics.
+///
+/// The composite score is the weighted mean of the transformed values of its
+/// components. A new composite only contains SSIMULACRA2 with a weight of 1.0;
+/// components are added or replaced with
+/// [`with_component`][Self::with_component] and
+/// [`with_transformed_component`][Self::with_transformed_component]. All
+/// components are computed in one pass where possible, with a [`Ssimulacra2`]
+/// session that is kept for further comparisons.
+///
+/// ```
+/// # use ssimulacra2::{CompositeMetric, Metric};
+/// // 80% SSIMULACRA2, 20% PSNR mapped from 30..50 dB to 0..100.
+/// let composite = CompositeMetric::new()
+///     .with_component(Metric::Ssimulacra2, 0.8)
+///     .with_transformed_component(Metric::Psnr, 0.2, |psnr| {
+///         ((psnr - 30.0) * 5.0).clamp(0.0, 100.0)
+///     });
+/// ```
+pub struct CompositeMetric {
+    components: Vec<Component>,
+    session: Option<Ssimulacra2>,
+}
+
+struct Component {
+    metric: Metric,
+    weight: f64,
+    transform: Option<Transform>,
+}
+
+/// The result of a [`CompositeMetric`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompositeScore {
+    /// The weighted mean of the transformed values of all components.
+    pub score: f64,
+    /// The components in the order in which they were added.
+    pub components: Vec<ComponentScore>,
+}
+
+/// The value of one component of a [`CompositeScore`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComponentScore {
+    /// The metric of the component.
+    pub metric: Metric,
+    /// The value of the metric.
+    pub value: f64,
+    /// The value after the transform of the component, which is what the
+    /// composite score is computed from.
+    pub transformed: f64,
+    /// The weight of the component.
+    pub weight: f64,
+}
+
+impl Default for CompositeMetric {
+    fn default() -> Self {
+        CompositeMetric {
+            components: alloc::vec![Component {
+                metric: Metric::Ssimulacra2,
+                weight: 1.0f64,
+                transform: None,
+            }],
+            session: None,
+        }
+    }
+}
+
+impl fmt::Debug for CompositeMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components: Vec<_> = self
+            .components
+            .iter()
+            .map(|c| (c.metric, c.weight, c.transform.is_some()))
+            .collect();
+        f.debug_struct("CompositeMetric")
+            .field("components", &components)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CompositeMetric {
+    /// Creates a composite of SSIMULACRA2 alone, with a weight of 1.0.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `metric` with the given weight, or changes the weight of it,
+    /// keeping its transform.
+    #[must_use]
+    pub fn with_component(mut self, metric: Metric, weight: f64) -> Self {
+        let transform = self.remove(metric).and_then(|c| c.transform);
+        self.components.push(Component {
+            metric,
+            weight,
+            transform,
+        });
+        self
+    }
+
+    /// Adds `metric` with the given weight, mapping its values with
+    /// `transform` before they are weighted, or replaces it.
+    #[must_use]
+    pub fn with_transformed_component<F>(
+        mut self,
+        metric: Metric,
+        weight: f64,
+        transform: F,
+    ) -> Self
+    where
+        F: Fn(f64) -> f64 + Send + Sync + 'static,
+    {
+        self.remove(metric);
+        self.components.push(Component {
+            metric,
+            weight,
+            transform: Some(Arc::new(transform)),
+        });
+        self
+    }
+
+    /// Removes `metric`, e.g. to replace SSIMULACRA2 by other metrics
+    /// entirely.
+    #[must_use]
+    pub fn without_component(mut self, metric: Metric) -> Self {
+        self.remove(metric);
+        self
+    }
+
+    /// The metrics of the composite, in the order in which they were added.
+    pub fn metrics(&self) -> impl Iterator<Item = Metric> + '_ {
+        self.components.iter().map(|c| c.metric)
+    }
+
+    /// Computes all components and the composite score of `distorted`
+    /// compared to `source`.
+    ///
+    /// # Errors
+    /// - If the composite has no components
+    /// - If the source and distorted image width and height do not match
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If the image is smaller than 8x8 pixels
+    pub fn compute<T, U>(
+        &mut self,
+        source: T,
+        distorted: U,
+    ) -> Result<CompositeScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        if self.components.is_empty() {
+            return Err(Ssimulacra2Error::EmptyComposite);
+        }
+        let Ok(source) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        let Ok(distorted) = LinearRgb::try_from(distorted) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+
+        #[cfg(feature = "experimental")]
+        let max_norm = if self.metrics().any(|m| m == Metric::MaxNorm) {
+            Some(crate::experimental::max_norm_distortion(source.clone(), distorted.clone())?.max)
+        } else {
+            None
+        };
+
+        let companion = self
+            .metrics()
+            .any(|m| matches!(m, Metric::Psnr | Metric::Ssim));
+        let session = match self.session.take() {
+            Some(session) => session,
+            None => Ssimulacra2::new(source.width(), source.height())?,
+        };
+        let mut session = session.with_companion_metrics(companion);
+        let result = session.score_detailed(source, distorted);
+        self.session = Some(session);
+        let detailed = result?;
+
+        let components: Vec<_> = self
+            .components
+            .iter()
+            .map(|c| {
+                let value = match c.metric {
+                    Metric::Ssimulacra2 => detailed.score,
+                    Metric::Psnr => detailed.companion.map_or(f64::NAN, |m| m.psnr),
+                    Metric::Ssim => detailed.companion.map_or(f64::NAN, |m| m.ssim),
+                    #[cfg(feature = "experimental")]
+                    Metric::MaxNorm => max_norm.unwrap_or(f64::NAN),
+                };
+                let transformed = c.transform.as_ref().map_or(value, |t| t(value));
+                ComponentScore {
+                    metric: c.metric,
+                    value,
+                    transformed,
+                    weight: c.weight,
+                }
+            })
+            .collect();
+        let weights: f64 = components.iter().map(|c| c.weight).sum();
+        let weighted: f64 = components.iter().map(|c| c.weight * c.transformed).sum();
+        Ok(CompositeScore {
+            score: weighted / weights,
+            components,
+        })
+    }
+
+    fn remove(&mut self, metric: Metric) -> Option<Component> {
+        let index = self.components.iter().position(|c| c.metric == metric)?;
+        Some(self.components.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_frame_ssimulacra2;
+
+    fn make_image(offset: f32) -> LinearRgb {
+        let data = (0..40usize * 30)
+            .map(|i| {
+                let v = ((i * 7) % 29) as f32 / 29.0f32;
+                [v, (v + offset).min(1.0f32), 0.5f32]
+            })
+            .collect();
+        LinearRgb::new(data, 40, 30).unwrap()
+    }
+
+    #[test]
+    fn weighted_mean() {
+        let expected = compute_frame_ssimulacra2(make_image(0.0), make_image(0.05)).unwrap();
+        let mut composite = CompositeMetric::new();
+        let result = composite
+            .compute(make_image(0.0), make_image(0.05))
+            .unwrap();
+        assert!((result.score - expected).abs() < 1e-9f64);
+
+        let mut composite = composite
+            .with_component(Metric::Ssimulacra2, 3.0)
+            .with_transformed_component(Metric::Psnr, 1.0, |psnr| psnr / 2.0f64)
+            .with_component(Metric::Ssim, 1.0);
+        let result = composite
+            .compute(make_image(0.0), make_image(0.05))
+            .unwrap();
+        let metrics: Vec<_> = result.components.iter().map(|c| c.metric).collect();
+        assert_eq!(metrics, [Metric::Ssimulacra2, Metric::Psnr, Metric::Ssim]);
+        let [ssimulacra2, psnr, ssim] = [0, 1, 2].map(|i| result.components[i]);
+        assert!((ssimulacra2.value - expected).abs() < 1e-9f64);
+        assert!((psnr.transformed - psnr.value / 2.0f64).abs() < 1e-9f64);
+        let mean = (ssimulacra2.value.mul_add(3.0f64, psnr.transformed) + ssim.value) / 5.0f64;
+        assert!((result.score - mean).abs() < 1e-9f64);
+
+        // Changing the weight keeps the transform.
+        let mut composite = composite.with_component(Metric::Psnr, 2.0);
+        let result = composite
+            .compute(make_image(0.0), make_image(0.05))
+            .unwrap();
+        assert!((result.components[2].transformed - psnr.value / 2.0f64).abs() < 1e-9f64);
+
+        let mut empty = CompositeMetric::new().without_component(Metric::Ssimulacra2);
+        assert_eq!(
+            empty.compute(make_image(0.0), make_image(0.05)),
+            Err(Ssimulacra2Error::EmptyComposite)
+        );
+    }
+}
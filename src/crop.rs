@@ -0,0 +1,184 @@
+use alloc::vec::Vec;
+
+use yuvxyb::LinearRgb;
+
+// The largest difference of sRGB-encoded values within a border, which
+// allows for the noise that lossy encoders add to flat areas.
+const TOLERANCE: f32 = 3.0f32 / 255.0f32;
+
+/// The number of pixels cropped from each edge of both images, see
+/// [`Ssimulacra2::with_border_crop`][crate::Ssimulacra2::with_border_crop].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Crop {
+    /// Columns removed at the left edge.
+    pub left: usize,
+    /// Rows removed at the top edge.
+    pub top: usize,
+    /// Columns removed at the right edge.
+    pub right: usize,
+    /// Rows removed at the bottom edge.
+    pub bottom: usize,
+}
+
+impl Crop {
+    /// Whether nothing was cropped.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.left == 0 && self.top == 0 && self.right == 0 && self.bottom == 0
+    }
+
+    // Finds the uniform borders that only one of the images has. On each
+    // edge, the wider border is cropped if the borders differ, so that both
+    // images keep the same content. Nothing is cropped if less than 8x8
+    // pixels would remain.
+    pub(crate) fn detect(source: &LinearRgb, distorted: &LinearRgb) -> Self {
+        let source = Borders::of(source);
+        let distorted = Borders::of(distorted);
+        let side = |a: usize, b: usize| if a == b { 0 } else { a.max(b) };
+        let crop = Crop {
+            left: side(source.left, distorted.left),
+            top: side(source.top, distorted.top),
+            right: side(source.right, distorted.right),
+            bottom: side(source.bottom, distorted.bottom),
+        };
+        let width = source.width.saturating_sub(crop.left + crop.right);
+        let height = source.height.saturating_sub(crop.top + crop.bottom);
+        if width < 8 || height < 8 {
+            Crop::default()
+        } else {
+            crop
+        }
+    }
+
+    // Removes the cropped rows and columns from `img`.
+    pub(crate) fn apply(&self, img: &mut LinearRgb) {
+        if self.is_empty() {
+            return;
+        }
+        let width = img.width() - self.left - self.right;
+        let height = img.height() - self.top - self.bottom;
+        let data: Vec<_> = img
+            .data()
+            .chunks_exact(img.width())
+            .skip(self.top)
+            .take(height)
+            .flat_map(|row| &row[self.left..][..width])
+            .copied()
+            .collect();
+        *img = LinearRgb::new(data, width, height).expect("Resolution and data size match");
+    }
+}
+
+// The width of the uniform border on each edge of an image.
+struct Borders {
+    width: usize,
+    height: usize,
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Borders {
+    fn of(img: &LinearRgb) -> Self {
+        let width = img.width();
+        let height = img.height();
+        let data = img.data();
+        let row = |y: usize| (0..width).map(move |x| data[y * width + x]);
+        let column = |x: usize| (0..height).map(move |y| data[y * width + x]);
+        Borders {
+            width,
+            height,
+            left: border(width, column),
+            top: border(height, row),
+            right: border(width, |i| column(width - 1 - i)),
+            bottom: border(height, |i| row(height - 1 - i)),
+        }
+    }
+}
+
+// The number of lines from an edge that have the same, uniform color, where
+// `line(i)` returns the pixels of the `i`-th line from the edge. A border
+// covers less than half of the lines.
+fn border<I, F>(lines: usize, line: F) -> usize
+where
+    I: Iterator<Item = [f32; 3]>,
+    F: Fn(usize) -> I,
+{
+    let Some(color) = line(0).next().map(|pix| pix.map(encode)) else {
+        return 0;
+    };
+    let uniform = |i: usize| {
+        line(i).all(|pix| {
+            pix.iter()
+                .zip(color)
+                .all(|(&value, color)| (encode(value) - color).abs() <= TOLERANCE)
+        })
+    };
+    (0..lines / 2).take_while(|&i| uniform(i)).count()
+}
+
+// Encodes a linear value with the sRGB transfer function, which makes the
+// tolerance about equally visible for dark and bright borders.
+fn encode(value: f32) -> f32 {
+    let value = value.clamp(0.0f32, 1.0f32);
+    if value <= 0.003_130_8f32 {
+        value * 12.92f32
+    } else {
+        value.powf(2.4f32.recip()).mul_add(1.055f32, -0.055f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ssimulacra2;
+
+    fn content(x: usize, y: usize) -> [f32; 3] {
+        let v = ((x * 7 + y * 13) % 31) as f32 / 40.0f32 + 0.1f32;
+        [v, 0.5f32 * v, 0.3f32]
+    }
+
+    // A 64x48 image whose top and bottom `bars` rows are black.
+    fn letterboxed(bars: usize) -> LinearRgb {
+        let data = (0..64 * 48)
+            .map(|i| {
+                let (x, y) = (i % 64, i / 64);
+                if y < bars || y >= 48 - bars {
+                    [0.0f32; 3]
+                } else {
+                    content(x, y)
+                }
+            })
+            .collect();
+        LinearRgb::new(data, 64, 48).unwrap()
+    }
+
+    #[test]
+    fn crops_bars_of_one_image() {
+        let crop = Crop::detect(&letterboxed(0), &letterboxed(6));
+        let expected = Crop {
+            left: 0,
+            top: 6,
+            right: 0,
+            bottom: 6,
+        };
+        assert_eq!(crop, expected);
+        // Bars present in both images are kept.
+        assert!(Crop::detect(&letterboxed(6), &letterboxed(6)).is_empty());
+
+        let mut session = Ssimulacra2::new(64, 48).unwrap();
+        let plain = session
+            .score_detailed(letterboxed(0), letterboxed(6))
+            .unwrap();
+        assert!(plain.crop.is_none());
+        let mut session = session.with_border_crop(true);
+        let cropped = session
+            .score_detailed(letterboxed(0), letterboxed(6))
+            .unwrap();
+        assert_eq!(cropped.crop, Some(expected));
+        assert!(cropped.score > 99.0f64, "{}", cropped.score);
+        assert!(plain.score < 80.0f64, "{}", plain.score);
+    }
+}
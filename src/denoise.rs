@@ -0,0 +1,150 @@
+use yuvxyb::LinearRgb;
+
+use crate::ScratchPool;
+
+/// A light denoise applied to the source image only, see
+/// [`Ssimulacra2::with_reference_denoise`][crate::Ssimulacra2::with_reference_denoise].
+///
+/// When the source is a noisy camera capture, encoders that remove the noise
+/// are penalized as if they had lost detail, although their output often
+/// looks better. Scoring against a source denoised with the same filter for
+/// every encode, as some studios do for VMAF, compares the encodes against
+/// the content rather than the noise. Encodes which keep the noise score
+/// lower instead, so this should only be enabled for noisy sources.
+///
+/// The filter is a 3x3 sigma filter. Each value of each channel is replaced
+/// by the average of the values in its 3x3 neighborhood that differ from it
+/// by at most a threshold. At the edges of the image, the neighborhood only
+/// extends along the edge, so that it stays centered. The values are compared by
+/// their square roots, which roughly follow the gamma of sRGB, so that the
+/// threshold applies to dark and bright areas alike. Noise below the
+/// threshold is averaged out, while edges and texture of higher contrast
+/// are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Denoise {
+    /// Score against the source as it is, like the reference implementation.
+    #[default]
+    None,
+    /// Average values within a threshold of 0.02, about 5 code values of
+    /// 8-bit sRGB in the mid tones, which removes the grain of well-lit
+    /// captures.
+    Light,
+    /// Average values within a threshold of 0.05, about 13 code values of
+    /// 8-bit sRGB in the mid tones, for captures in low light.
+    Strong,
+}
+
+impl Denoise {
+    /// Applies the filter to `img` in place, e.g. to look at the source the
+    /// encodes are scored against.
+    pub fn apply(self, img: &mut LinearRgb) {
+        self.apply_pooled(img, &mut ScratchPool::new());
+    }
+
+    // Like `apply`, taking the temporary buffer from `pool`.
+    pub(crate) fn apply_pooled(self, img: &mut LinearRgb, pool: &mut ScratchPool) {
+        let threshold = match self {
+            Denoise::None => return,
+            Denoise::Light => 0.02f32,
+            Denoise::Strong => 0.05f32,
+        };
+        sigma_filter(img, threshold, pool);
+    }
+}
+
+fn sigma_filter(img: &mut LinearRgb, threshold: f32, pool: &mut ScratchPool) {
+    let width = img.width();
+    let height = img.height();
+    let mut original = pool.take_pixels(width * height);
+    original.clear();
+    original.extend_from_slice(img.data());
+
+    let root = |value: f32| value.max(0.0f32).sqrt();
+    // The neighborhood of `i` in `0..len`, which is narrowed to `i` itself
+    // at the edges, so that it stays centered and gradients are not shifted.
+    let neighborhood = |i: usize, len: usize| {
+        let radius = usize::from(i > 0 && i + 1 < len);
+        i - radius..i + radius + 1
+    };
+    for (y, out) in img.data_mut().chunks_exact_mut(width).enumerate() {
+        let rows = neighborhood(y, height);
+        for (x, out) in out.iter_mut().enumerate() {
+            let cols = neighborhood(x, width);
+            let center = original[y * width + x];
+            for (c, out) in out.iter_mut().enumerate() {
+                let center_root = root(center[c]);
+                let mut sum = 0.0f32;
+                let mut count = 0.0f32;
+                for row in original[rows.start * width..rows.end * width].chunks_exact(width) {
+                    for pix in &row[cols.clone()] {
+                        if (root(pix[c]) - center_root).abs() <= threshold {
+                            sum += pix[c];
+                            count += 1.0f32;
+                        }
+                    }
+                }
+                // The center itself always counts.
+                *out = sum / count;
+            }
+        }
+    }
+
+    pool.give_pixels(original);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A step edge between two flat areas, with noise of the given amplitude
+    // added to every value.
+    fn image(noise: f32) -> LinearRgb {
+        let (width, height) = (32, 24);
+        let data = (0..width * height)
+            .map(|i| {
+                let base = if i % width < width / 2 {
+                    0.1f32
+                } else {
+                    0.6f32
+                };
+                let sign = if (i * 7 + i / width) % 3 == 0 {
+                    -1.0f32
+                } else {
+                    1.0f32
+                };
+                [base + sign * noise; 3]
+            })
+            .collect();
+        LinearRgb::new(data, width, height).unwrap()
+    }
+
+    fn mean_error(a: &LinearRgb, b: &LinearRgb) -> f32 {
+        let sum: f32 = a
+            .data()
+            .iter()
+            .zip(b.data())
+            .flat_map(|(a, b)| [0usize, 1, 2].map(|c| (a[c] - b[c]).abs()))
+            .sum();
+        sum / (3 * a.data().len()) as f32
+    }
+
+    #[test]
+    fn removes_noise_and_keeps_edges() {
+        let clean = image(0.0f32);
+        let noisy = image(0.004f32);
+
+        let mut none = noisy.clone();
+        Denoise::None.apply(&mut none);
+        assert_eq!(none.data(), noisy.data());
+
+        let mut light = noisy.clone();
+        Denoise::Light.apply(&mut light);
+        assert!(mean_error(&light, &clean) < 0.5f32 * mean_error(&noisy, &clean));
+
+        // The edge is far above the threshold, so clean images stay as they are.
+        let mut filtered = clean.clone();
+        Denoise::Strong.apply(&mut filtered);
+        assert!(mean_error(&filtered, &clean) < 1e-6f32);
+    }
+}
@@ -0,0 +1,239 @@
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use wide::f32x8;
+use yuvxyb::LinearRgb;
+
+/// Halves the resolution of an image between two scales of the metric.
+///
+/// SSIMULACRA2 scores the images at up to six scales, each downscaled by a
+/// factor of two from the previous one in linear RGB. [`BoxDownscaler`] is
+/// used by default and matches the reference implementation. Other
+/// downscalers can be passed to [`Reference::with_downscaler`][crate::Reference::with_downscaler]
+/// and [`Ssimulacra2::with_downscaler`][crate::Ssimulacra2::with_downscaler],
+/// e.g. to measure how much aliasing of the box filter affects the scores.
+/// Scores computed with a different downscaler are not comparable to regular
+/// SSIMULACRA2 scores.
+pub trait Downscaler: Debug + Send + Sync {
+    /// Downscales `image` by a factor of two.
+    ///
+    /// The result must be `(width + 1) / 2` pixels wide and `(height + 1) / 2`
+    /// pixels high, i.e. an odd width or height is rounded up. Scoring panics
+    /// if it is not.
+    fn downscale(&self, image: &LinearRgb) -> LinearRgb;
+
+    /// Downscales `image` like [`downscale`][Self::downscale], writing the
+    /// result into the allocation of `buffer` where possible.
+    ///
+    /// The buffer is the pixel data of an image that is no longer needed, so
+    /// its contents are unspecified. The default implementation ignores it.
+    fn downscale_into(&self, image: &LinearRgb, buffer: Vec<[f32; 3]>) -> LinearRgb {
+        drop(buffer);
+        self.downscale(image)
+    }
+}
+
+// Number of output pixels downscaled at a time, whose 24 values fill three
+// vectors.
+const PIXELS: usize = 8;
+
+/// Averages every 2x2 block of pixels, repeating the last row and column for
+/// odd sizes. This is the downscaler of the reference implementation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BoxDownscaler;
+
+impl Downscaler for BoxDownscaler {
+    fn downscale(&self, image: &LinearRgb) -> LinearRgb {
+        self.downscale_into(image, Vec::new())
+    }
+
+    fn downscale_into(&self, image: &LinearRgb, mut buffer: Vec<[f32; 3]>) -> LinearRgb {
+        let in_w = image.width();
+        let in_h = image.height();
+        let out_w = (in_w + 1) / 2;
+        let out_h = (in_h + 1) / 2;
+        buffer.clear();
+        buffer.resize(out_w * out_h, [0.0f32; 3]);
+
+        // The four pixels are summed up in the same order as in the reference
+        // implementation, so that the results are rounded the same way. Each
+        // lane of the vectors holds one value of an output pixel, so the
+        // vectorized sums are rounded exactly like the scalar ones.
+        let in_data: &[f32] = bytemuck::cast_slice(image.data());
+        let body = in_w / 2 / PIXELS * PIXELS;
+        let out_rows = bytemuck::cast_slice_mut::<_, f32>(&mut buffer).chunks_exact_mut(3 * out_w);
+        for (oy, out_row) in out_rows.enumerate() {
+            let y0 = 2 * oy;
+            let y1 = (y0 + 1).min(in_h - 1);
+            let row0 = &in_data[y0 * 3 * in_w..(y0 + 1) * 3 * in_w];
+            let row1 = &in_data[y1 * 3 * in_w..(y1 + 1) * 3 * in_w];
+            let (out_body, out_rest) = out_row.split_at_mut(3 * body);
+
+            let chunks = row0
+                .chunks_exact(6 * PIXELS)
+                .zip(row1.chunks_exact(6 * PIXELS))
+                .zip(out_body.chunks_exact_mut(3 * PIXELS));
+            for ((chunk0, chunk1), out_chunk) in chunks {
+                downscale_chunk(chunk0, chunk1, out_chunk);
+            }
+
+            for (ox, out_pix) in (body..out_w).zip(out_rest.chunks_exact_mut(3)) {
+                let x0 = 3 * 2 * ox;
+                let x1 = 3 * (2 * ox + 1).min(in_w - 1);
+                let left0 = &row0[x0..x0 + 3];
+                let right0 = &row0[x1..x1 + 3];
+                let left1 = &row1[x0..x0 + 3];
+                let right1 = &row1[x1..x1 + 3];
+                for c in 0..3 {
+                    let sum = left0[c] + right0[c] + left1[c] + right1[c];
+                    out_pix[c] = sum * 0.25f32;
+                }
+            }
+        }
+
+        LinearRgb::new(buffer, out_w, out_h).expect("Resolution and data size match")
+    }
+}
+
+// Averages the 2x2 blocks of `PIXELS` output pixels, whose input pixels are
+// the interleaved RGB values `row0` and `row1`. The left and right pixels of
+// the blocks are separated first, so that the same values of the four pixels
+// of a block end up in the same lane.
+fn downscale_chunk(row0: &[f32], row1: &[f32], out: &mut [f32]) {
+    let mut lanes = [[0.0f32; 3 * PIXELS]; 4];
+    for (i, (pair0, pair1)) in row0.chunks_exact(6).zip(row1.chunks_exact(6)).enumerate() {
+        let pixel = 3 * i..3 * i + 3;
+        lanes[0][pixel.clone()].copy_from_slice(&pair0[..3]);
+        lanes[1][pixel.clone()].copy_from_slice(&pair0[3..]);
+        lanes[2][pixel.clone()].copy_from_slice(&pair1[..3]);
+        lanes[3][pixel].copy_from_slice(&pair1[3..]);
+    }
+
+    let vector = |values: &[f32; 3 * PIXELS], i: usize| {
+        f32x8::from(<[f32; 8]>::try_from(&values[8 * i..8 * i + 8]).expect("Slice has 8 values"))
+    };
+    let [ref left0, ref right0, ref left1, ref right1] = lanes;
+    for (i, out) in out.chunks_exact_mut(8).enumerate() {
+        let sum = vector(left0, i) + vector(right0, i) + vector(left1, i) + vector(right1, i);
+        out.copy_from_slice((sum * f32x8::splat(0.25f32)).as_array_ref());
+    }
+}
+
+// Downscales with `downscaler`, making sure it kept to the required size.
+pub fn downscale_checked(
+    downscaler: &dyn Downscaler,
+    image: &LinearRgb,
+    buffer: Vec<[f32; 3]>,
+) -> LinearRgb {
+    let out = downscaler.downscale_into(image, buffer);
+    assert!(
+        out.width() == (image.width() + 1) / 2 && out.height() == (image.height() + 1) / 2,
+        "{downscaler:?} returned an image of the wrong size"
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+    use crate::{compute_frame_ssimulacra2, Reference, Ssimulacra2};
+
+    // Takes the top left pixel of every 2x2 block.
+    #[derive(Debug)]
+    struct PointDownscaler;
+
+    impl Downscaler for PointDownscaler {
+        fn downscale(&self, image: &LinearRgb) -> LinearRgb {
+            let out_w = (image.width() + 1) / 2;
+            let out_h = (image.height() + 1) / 2;
+            let data = (0..out_h)
+                .flat_map(|y| (0..out_w).map(move |x| (x, y)))
+                .map(|(x, y)| image.data()[2 * y * image.width() + 2 * x])
+                .collect();
+            LinearRgb::new(data, out_w, out_h).unwrap()
+        }
+    }
+
+    // A transcription of the downscaling loop of libjxl's `ssimulacra2.cc`,
+    // pixel by pixel. It pins the order of the sums, but is no substitute
+    // for output of libjxl itself.
+    fn downscale_like_libjxl(image: &LinearRgb) -> Vec<[f32; 3]> {
+        let (in_w, in_h) = (image.width(), image.height());
+        let (out_w, out_h) = ((in_w + 1) / 2, (in_h + 1) / 2);
+        let mut out = Vec::with_capacity(out_w * out_h);
+        for oy in 0..out_h {
+            for ox in 0..out_w {
+                out.push([0usize, 1, 2].map(|c| {
+                    let mut sum = 0.0f32;
+                    for iy in 0..2usize {
+                        for ix in 0..2usize {
+                            let x = (ox * 2 + ix).min(in_w - 1);
+                            let y = (oy * 2 + iy).min(in_h - 1);
+                            sum += image.data()[y * in_w + x][c];
+                        }
+                    }
+                    sum * 0.25f32
+                }));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn box_downscaler_odd_size() {
+        let image = make_image(141, 7, 11);
+        let out = BoxDownscaler.downscale_into(&image, vec![[1.0f32; 3]; 3]);
+        assert_eq!((out.width(), out.height()), (71, 4));
+        assert_eq!(out.data(), &downscale_like_libjxl(&image)[..]);
+    }
+
+    #[test]
+    fn box_downscaler_matches_libjxl_loop() {
+        let sizes = [
+            (1, 1),
+            (1, 2),
+            (2, 1),
+            (3, 3),
+            (9, 8),
+            (8, 9),
+            (32, 2),
+            (35, 3),
+            (1919, 1079),
+            (1920, 1080),
+        ];
+        for (width, height) in sizes {
+            let image = make_image(width, height, 13);
+            let out = BoxDownscaler.downscale(&image);
+            assert_eq!(
+                (out.width(), out.height()),
+                ((width + 1) / 2, (height + 1) / 2)
+            );
+            // Bitwise, including the rounding of the sums.
+            assert!(
+                out.data() == &downscale_like_libjxl(&image)[..],
+                "{width}x{height}"
+            );
+        }
+    }
+
+    #[test]
+    fn custom_downscaler() {
+        let source = make_image(67, 45, 3);
+        let distorted = make_image(67, 45, 5);
+
+        let default = compute_frame_ssimulacra2(source.clone(), distorted.clone()).unwrap();
+        let boxed = Reference::with_downscaler(source.clone(), BoxDownscaler)
+            .unwrap()
+            .compare(distorted.clone())
+            .unwrap();
+        assert!((default - boxed).abs() < f64::EPSILON);
+
+        let point = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .with_downscaler(PointDownscaler)
+            .score(source, distorted)
+            .unwrap();
+        assert!((default - point).abs() > 1e-3f64);
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use yuvxyb::LinearRgb;
+
+use crate::{
+    linear_rgb_to_planar_xyb, Blur, BoxDownscaler, Downscaler, Ssimulacra2Error, NUM_SCALES,
+};
+
+/// Writes the intermediate planes of comparing `distorted` against `source`
+/// to the directory `dir`, for diffing them against other implementations
+/// such as libjxl's `ssimulacra2` tool.
+///
+/// For every scale `N`, each plane is written as a 16-bit PGM file named
+/// `scaleN_<plane>_<channel>.pgm`, with its values mapped from the minimum
+/// and maximum given in a `# range <min> <max>` comment of the header. The
+/// planes are, in this order:
+/// - `source` and `distorted`: the linear RGB input of the scale
+/// - `source_xyb` and `distorted_xyb`: the same converted to positive XYB
+/// - `mu1` and `mu2`: the blurred XYB planes
+/// - `sigma11`, `sigma22` and `sigma12`: the blurred products of the XYB
+///   planes of the source, the distorted image, and of both
+///
+/// All planes of a scale are also written as the frames of a grey
+/// `scaleN.y4m` video in the same order, red or X first, each mapped to
+/// 8 bits on its own, for flipping through them in a video player.
+///
+/// # Errors
+/// - If the images cannot be converted to linear RGB, are smaller than 8x8
+///   pixels or do not have the same size
+/// - If a file cannot be written
+pub fn dump_planes<T, U>(source: T, distorted: U, dir: &Path) -> io::Result<()>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let (img1, img2) = convert(source, distorted)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    for_each_scale(img1, img2, |scale, width, height, planes| {
+        let mut y4m = BufWriter::new(File::create(dir.join(format!("scale{scale}.y4m")))?);
+        writeln!(y4m, "YUV4MPEG2 W{width} H{height} F1:1 Ip A1:1 Cmono")?;
+        for &(name, ref channels) in planes {
+            for (c, plane) in channels.iter().enumerate() {
+                let path = dir.join(format!("scale{scale}_{name}_{c}.pgm"));
+                write_pgm(&path, plane, width, height)?;
+                write_y4m_frame(&mut y4m, plane)?;
+            }
+        }
+        y4m.flush()
+    })
+}
+
+/// The checksum of one intermediate plane, see [`plane_checksums`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneChecksum {
+    /// The scale of the plane, 0 being the full resolution.
+    pub scale: usize,
+    /// The name of the plane, as in the file names of [`dump_planes`].
+    pub plane: &'static str,
+    /// The 64-bit FNV-1a hash of the bits of all values of the three
+    /// channels, in order.
+    pub checksum: u64,
+}
+
+/// Computes checksums of the intermediate planes of comparing `distorted`
+/// against `source`, the same planes that [`dump_planes`] writes.
+///
+/// The checksums cover the exact bits of every value, so any change of the
+/// arithmetic, e.g. of the order of additions in a SIMD loop, changes them.
+/// They are meant for proving that an optimization is bit-exact; see the
+/// `golden` example, which records them for a fixed set of images.
+///
+/// # Errors
+/// - If the images cannot be converted to linear RGB, are smaller than 8x8
+///   pixels or do not have the same size
+pub fn plane_checksums<T, U>(
+    source: T,
+    distorted: U,
+) -> Result<Vec<PlaneChecksum>, Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let (img1, img2) = convert(source, distorted)?;
+    let mut checksums = Vec::new();
+    for_each_scale(img1, img2, |scale, _, _, planes| {
+        for &(plane, ref channels) in planes {
+            checksums.push(PlaneChecksum {
+                scale,
+                plane,
+                checksum: fnv1a(
+                    channels
+                        .iter()
+                        .flatten()
+                        .flat_map(|v| v.to_bits().to_le_bytes()),
+                ),
+            });
+        }
+        Ok::<_, Ssimulacra2Error>(())
+    })?;
+    Ok(checksums)
+}
+
+fn convert<T, U>(source: T, distorted: U) -> Result<(LinearRgb, LinearRgb), Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let (Ok(img1), Ok(img2)) = (LinearRgb::try_from(source), LinearRgb::try_from(distorted)) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    if img1.width() != img2.width() || img1.height() != img2.height() {
+        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+    }
+    if img1.width() < 8 || img1.height() < 8 {
+        return Err(Ssimulacra2Error::InvalidImageSize);
+    }
+    Ok((img1, img2))
+}
+
+// The named planes of one scale, each with its three channels.
+type Planes = [(&'static str, [Vec<f32>; 3])];
+
+// Computes the intermediate planes of every scale and passes them to `f`,
+// along with the scale and its width and height.
+fn for_each_scale<E>(
+    mut img1: LinearRgb,
+    mut img2: LinearRgb,
+    mut f: impl FnMut(usize, usize, usize, &Planes) -> Result<(), E>,
+) -> Result<(), E> {
+    for scale in 0..NUM_SCALES {
+        let width = img1.width();
+        let height = img1.height();
+        let mut planes = Vec::new();
+        let rgb =
+            |img: &LinearRgb| [0usize, 1, 2].map(|c| img.data().iter().map(|pix| pix[c]).collect());
+        planes.push(("source", rgb(&img1)));
+        planes.push(("distorted", rgb(&img2)));
+
+        let mut xyb1: [Vec<f32>; 3] = Default::default();
+        let mut xyb2: [Vec<f32>; 3] = Default::default();
+        linear_rgb_to_planar_xyb(&img1, &mut xyb1);
+        linear_rgb_to_planar_xyb(&img2, &mut xyb2);
+        let mut blur = Blur::new(width, height);
+        let mu1 = blur.blur(&xyb1);
+        let mu2 = blur.blur(&xyb2);
+        let sigma11 = blur.blur_product(&xyb1, &xyb1);
+        let sigma22 = blur.blur_product(&xyb2, &xyb2);
+        let sigma12 = blur.blur_product(&xyb1, &xyb2);
+        planes.push(("source_xyb", xyb1));
+        planes.push(("distorted_xyb", xyb2));
+        planes.push(("mu1", mu1));
+        planes.push(("mu2", mu2));
+        planes.push(("sigma11", sigma11));
+        planes.push(("sigma22", sigma22));
+        planes.push(("sigma12", sigma12));
+        f(scale, width, height, &planes)?;
+
+        // The same rule as for scoring decides whether there is another scale.
+        if scale + 1 == NUM_SCALES || width < 8 || height < 8 {
+            break;
+        }
+        img1 = BoxDownscaler.downscale(&img1);
+        img2 = BoxDownscaler.downscale(&img2);
+    }
+    Ok(())
+}
+
+// The 64-bit FNV-1a hash of `bytes`. Unlike a `PlaneHasher`, this does not
+// depend on the `xxhash` feature, so the checksums stay the same.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3u64);
+    }
+    hash
+}
+
+fn range(plane: &[f32]) -> (f32, f32) {
+    plane
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        })
+}
+
+// Maps `v` from `min..=max` to `0..=max_value`.
+fn quantize(v: f32, (min, max): (f32, f32), max_value: f32) -> f32 {
+    if max > min {
+        ((v - min) / (max - min) * max_value).round()
+    } else {
+        0.0
+    }
+}
+
+fn write_pgm(path: &Path, plane: &[f32], width: usize, height: usize) -> io::Result<()> {
+    let range = range(plane);
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "P5")?;
+    writeln!(out, "# range {:e} {:e}", range.0, range.1)?;
+    writeln!(out, "{width} {height}")?;
+    writeln!(out, "65535")?;
+    for &v in plane {
+        out.write_all(&(quantize(v, range, 65535.0) as u16).to_be_bytes())?;
+    }
+    out.flush()
+}
+
+fn write_y4m_frame(out: &mut impl Write, plane: &[f32]) -> io::Result<()> {
+    let range = range(plane);
+    out.write_all(b"FRAME\n")?;
+    let bytes: Vec<u8> = plane
+        .iter()
+        .map(|&v| quantize(v, range, 255.0) as u8)
+        .collect();
+    out.write_all(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn dumps_all_scales() {
+        let dir = std::env::temp_dir().join(format!("ssimulacra2-dump-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let make = |offset: f32| {
+            let data = (0..40usize * 18)
+                .map(|i| [0usize, 1, 2].map(|c| ((i * 7 + c) % 40) as f32 / 40.0f32 + offset))
+                .collect();
+            LinearRgb::new(data, 40, 18).unwrap()
+        };
+        dump_planes(make(0.0), make(0.05), &dir).unwrap();
+
+        // 40x18, 20x9 and 10x5
+        for (scale, (width, height)) in [(40usize, 18usize), (20, 9), (10, 5)]
+            .into_iter()
+            .enumerate()
+        {
+            let pgm = fs::read(dir.join(format!("scale{scale}_sigma12_2.pgm"))).unwrap();
+            let header = format!("{width} {height}\n65535\n");
+            let header_end = pgm
+                .windows(header.len())
+                .position(|window| window == header.as_bytes())
+                .unwrap()
+                + header.len();
+            assert!(pgm.starts_with(b"P5\n# range "));
+            assert_eq!(pgm.len() - header_end, width * height * 2);
+
+            // Nine planes of three channels each.
+            let y4m = fs::read(dir.join(format!("scale{scale}.y4m"))).unwrap();
+            let header = format!("YUV4MPEG2 W{width} H{height} F1:1 Ip A1:1 Cmono\n");
+            assert!(y4m.starts_with(header.as_bytes()));
+            assert_eq!(y4m.len(), header.len() + 9 * 3 * (6 + width * height));
+        }
+        assert!(!dir.join("scale3.y4m").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let checksums = plane_checksums(make(0.0), make(0.05)).unwrap();
+        assert_eq!(checksums.len(), 3 * 9);
+        assert_eq!(checksums[3 * 9 - 1].plane, "sigma12");
+        assert_eq!(checksums, plane_checksums(make(0.0), make(0.05)).unwrap());
+        // Identical images have identical planes.
+        let same = plane_checksums(make(0.0), make(0.0)).unwrap();
+        assert_eq!(same[0].checksum, same[1].checksum);
+        assert_ne!(same[0].checksum, checksums[1].checksum);
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+
+use yuvxyb::{LinearRgb, Xyb};
+
+use crate::{error_to_score, Blur, Ssimulacra2, Ssimulacra2Error};
+
+// Weight of the mean masked gradient loss relative to the weighted sub-scores
+// of SSIMULACRA2 in the raw error.
+const BANDING_WEIGHT: f64 = 5000.0f64;
+
+// How often the planes are blurred before their gradients are taken. Four
+// passes of the SSIMULACRA2 blur are about a Gaussian with a sigma of 3.
+const BLUR_PASSES: usize = 4;
+
+// The local contrast of the source, as the sum of the absolute gradients of
+// the Y plane, at which the weight of a gradient loss is halved. Smooth
+// gradients of 8-bit content are well below it.
+const CONTRAST_THRESHOLD: f32 = 0.01f32;
+
+/// A SSIMULACRA2 score with an extra penalty for banding, see
+/// [`banding_sensitive_score`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandingScore {
+    /// The adjusted score. It is on the same scale as SSIMULACRA2, but is
+    /// never higher than the standard score and must not be reported as a
+    /// SSIMULACRA2 score.
+    pub score: f64,
+    /// The standard SSIMULACRA2 score of the same images.
+    pub ssimulacra2: f64,
+    /// The banding penalty that was added to the raw error of SSIMULACRA2
+    /// before mapping it to the score. It is 0.0 for identical images.
+    pub banding: f64,
+}
+
+/// Computes a non-standard variant of SSIMULACRA2 which is more sensitive to
+/// banding, e.g. in dark gradients of heavily quantized images.
+///
+/// Banding replaces a smooth gradient by flat steps. Each step is a small,
+/// low-contrast error, which SSIMULACRA2 penalizes only slightly. This variant
+/// adds an extra full-resolution term to the raw error: how much of the
+/// gradient of the source's Y plane is missing in the distorted image, after
+/// blurring both so that fine noise hardly changes the gradients, weighted by
+/// how smooth the source is around each pixel. Since XYB is roughly
+/// perceptually uniform, the same step in linear light is larger in dark
+/// areas than in bright ones.
+///
+/// The weights of the extra term were chosen by hand, not fitted to
+/// subjective ratings, so the adjusted score is only meant for comparing
+/// encoder settings which trade banding against other artifacts.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to linear RGB successfully
+/// - If the images contain values for which no score can be computed
+/// - If the image is smaller than 8x8 pixels
+pub fn banding_sensitive_score<T, U>(
+    source: T,
+    distorted: U,
+) -> Result<BandingScore, Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let Ok(source) = LinearRgb::try_from(source) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let Ok(distorted) = LinearRgb::try_from(distorted) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let width = source.width();
+    let height = source.height();
+    let detailed = Ssimulacra2::new(width, height)?.score_borrowed(&source, &distorted)?;
+
+    // The gradients of the blurred planes are those of the low frequency
+    // band, which fine noise hardly changes.
+    let source = luma(source);
+    let (source_dx, source_dy) = gradients(&source, width);
+    let activity = source_dx
+        .iter()
+        .zip(&source_dy)
+        .map(|(dx, dy)| dx.abs() + dy.abs())
+        .collect();
+    let mut blur = Blur::new(width, height);
+    let mut planes = [source, luma(distorted), activity];
+    for _ in 0..BLUR_PASSES {
+        planes = blur.blur(&planes);
+    }
+    let [source, distorted, activity] = planes;
+    let (source_dx, source_dy) = gradients(&source, width);
+    let (distorted_dx, distorted_dy) = gradients(&distorted, width);
+
+    let sum: f64 = (0..width * height)
+        .map(|i| {
+            let norm = source_dx[i].hypot(source_dy[i]);
+            if norm <= 0.0f32 {
+                return 0.0f64;
+            }
+            // The part of the source gradient that is missing in the
+            // distorted image, which is most of it inside a band.
+            let diff_dx = distorted_dx[i] - source_dx[i];
+            let diff_dy = distorted_dy[i] - source_dy[i];
+            let lost = (-diff_dx.mul_add(source_dx[i], diff_dy * source_dy[i]) / norm).max(0.0f32);
+            f64::from(lost / (1.0f32 + activity[i] / CONTRAST_THRESHOLD))
+        })
+        .sum();
+    let banding = BANDING_WEIGHT * sum / (width * height) as f64;
+    if !banding.is_finite() {
+        return Err(Ssimulacra2Error::NonFiniteScore);
+    }
+
+    let error: f64 = detailed.scales.iter().sum();
+    Ok(BandingScore {
+        score: error_to_score(error + banding).min(detailed.score),
+        ssimulacra2: detailed.score,
+        banding,
+    })
+}
+
+// The Y plane of `img` in XYB.
+fn luma(img: LinearRgb) -> Vec<f32> {
+    Xyb::from(img).data().iter().map(|pix| pix[1]).collect()
+}
+
+// The forward differences of `plane` along the rows and the columns, which
+// are 0.0 at the right and bottom edges.
+fn gradients(plane: &[f32], width: usize) -> (Vec<f32>, Vec<f32>) {
+    let dx = plane
+        .chunks_exact(width)
+        .flat_map(|row| {
+            row.windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .chain(core::iter::once(0.0f32))
+        })
+        .collect();
+    let dy = plane
+        .iter()
+        .zip(plane.get(width..).unwrap_or_default())
+        .map(|(above, below)| below - above)
+        .chain(core::iter::repeat(0.0f32).take(width.min(plane.len())))
+        .collect();
+    (dx, dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dark horizontal gradient, quantized to `levels` steps over its range,
+    // or with pseudo-random noise of the given amplitude added.
+    fn gradient(levels: Option<f32>, noise: f32) -> LinearRgb {
+        let data = (0..512u32 * 64)
+            .map(|i| {
+                let mut v = (i % 512) as f32 / 511.0f32;
+                if let Some(levels) = levels {
+                    v = (v * levels).round() / levels;
+                }
+                let hash = i.wrapping_mul(2_654_435_761) >> 16u32;
+                let n = (hash % 1000) as f32 / 500.0f32 - 1.0f32;
+                [n.mul_add(noise, 0.02f32 * v).max(0.0f32); 3]
+            })
+            .collect();
+        LinearRgb::new(data, 512, 64).unwrap()
+    }
+
+    #[test]
+    fn penalizes_banding() {
+        let identical = banding_sensitive_score(gradient(None, 0.0), gradient(None, 0.0)).unwrap();
+        assert!(identical.banding.abs() < 1e-9f64);
+        assert!((identical.score - 100.0f64).abs() < 1e-9f64);
+
+        // Bands of 16 pixels, and noise that SSIMULACRA2 considers worse.
+        let banded =
+            banding_sensitive_score(gradient(None, 0.0), gradient(Some(32.0), 0.0)).unwrap();
+        let noisy = banding_sensitive_score(gradient(None, 0.0), gradient(None, 0.002)).unwrap();
+        assert!(banded.ssimulacra2 > noisy.ssimulacra2);
+        assert!(banded.banding > noisy.banding);
+        let banded_penalty = banded.ssimulacra2 - banded.score;
+        let noisy_penalty = noisy.ssimulacra2 - noisy.score;
+        assert!(
+            banded_penalty > 1.5f64 * noisy_penalty,
+            "{banded_penalty} {noisy_penalty}"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use alloc::vec::Vec;
+
+use yuvxyb::{LinearRgb, Xyb};
+
+use crate::{Blur, DistortionMap, Ssimulacra2Error};
+
+// Weights of the differences in the low and high frequency bands of the X, Y
+// and B planes. Each is roughly the inverse of a just noticeable difference,
+// so that a distance of 1.0 is about the threshold of visibility.
+const LOW_FREQUENCY_WEIGHTS: [f32; 3] = [1400.0f32, 100.0f32, 30.0f32];
+const HIGH_FREQUENCY_WEIGHTS: [f32; 3] = [1400.0f32, 150.0f32, 15.0f32];
+
+// How strongly the high frequency activity of the source, in the units of the
+// weighted differences, hides differences in the same band. An activity of
+// five just noticeable differences halves the weight of a difference.
+const MASKING_STRENGTH: f32 = 0.2f32;
+
+/// A Butteraugli-style distance between two images, see
+/// [`max_norm_distortion`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxNormDistortion {
+    /// The largest distance of any pixel. Around 1.0 the difference starts to
+    /// become visible; higher values mean more visible distortion.
+    pub max: f64,
+    /// The 3-norm of the distances of all pixels, which summarizes the
+    /// distortion of the whole image while still emphasizing its worst parts.
+    pub p_norm: f64,
+    /// The column and row of the pixel with the largest distance.
+    pub max_position: (usize, usize),
+    /// The distance of every pixel.
+    pub map: DistortionMap,
+}
+
+/// Computes a Butteraugli-style distance between `source` and `distorted`,
+/// reporting the distortion of the worst pixel instead of an average.
+///
+/// Both images are converted to XYB and split into a low and a high frequency
+/// band with the same Gaussian blur that SSIMULACRA2 uses. The differences of
+/// the bands are weighted per plane, the high frequency differences are
+/// masked by the activity of the source in that band, and the weighted
+/// differences of every pixel are combined into one distance.
+///
+/// This is a single-scale approximation of the idea behind Butteraugli, not an
+/// implementation of it; the values are in the same range, but are not
+/// comparable to those of the `butteraugli` tool.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to linear RGB successfully
+/// - If the images contain values for which no distance can be computed
+/// - If the image is smaller than 8x8 pixels
+pub fn max_norm_distortion<T, U>(
+    source: T,
+    distorted: U,
+) -> Result<MaxNormDistortion, Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let Ok(source) = LinearRgb::try_from(source) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let Ok(distorted) = LinearRgb::try_from(distorted) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let width = source.width();
+    let height = source.height();
+    if width != distorted.width() || height != distorted.height() {
+        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+    }
+    if width < 8 || height < 8 {
+        return Err(Ssimulacra2Error::InvalidImageSize);
+    }
+
+    let mut blur = Blur::new(width, height);
+    let mut source = planar_xyb(source);
+    let mut distorted = planar_xyb(distorted);
+    let source_low = blur.blur(&source);
+    let distorted_low = blur.blur(&distorted);
+    // The planes now hold the high frequency bands.
+    for (plane, low) in source.iter_mut().zip(&source_low) {
+        subtract(plane, low);
+    }
+    for (plane, low) in distorted.iter_mut().zip(&distorted_low) {
+        subtract(plane, low);
+    }
+    let activity = blur.blur(
+        &source
+            .clone()
+            .map(|plane| plane.iter().map(|v| v.abs()).collect()),
+    );
+
+    let mut map = DistortionMap::new(width, height);
+    for (i, distance) in map.data_mut().iter_mut().enumerate() {
+        let mut sum = 0.0f32;
+        for c in 0..3 {
+            let low = (source_low[c][i] - distorted_low[c][i]) * LOW_FREQUENCY_WEIGHTS[c];
+            let masking =
+                MASKING_STRENGTH.mul_add(activity[c][i] * HIGH_FREQUENCY_WEIGHTS[c], 1.0f32);
+            let high = (source[c][i] - distorted[c][i]) * HIGH_FREQUENCY_WEIGHTS[c] / masking;
+            sum += low.mul_add(low, high * high);
+        }
+        *distance = sum.sqrt();
+    }
+
+    let (max_index, max) =
+        map.data()
+            .iter()
+            .enumerate()
+            .fold((0, 0.0f32), |(max_index, max), (i, &distance)| {
+                if distance > max {
+                    (i, distance)
+                } else {
+                    (max_index, max)
+                }
+            });
+    let cubes: f64 = map.data().iter().map(|&d| f64::from(d).powi(3)).sum();
+    let p_norm = (cubes / (width * height) as f64).cbrt();
+    if !p_norm.is_finite() {
+        return Err(Ssimulacra2Error::NonFiniteScore);
+    }
+
+    Ok(MaxNormDistortion {
+        max: f64::from(max),
+        p_norm,
+        max_position: (max_index % width, max_index / width),
+        map,
+    })
+}
+
+// Converts `img` to XYB, with each component in its own plane.
+fn planar_xyb(img: LinearRgb) -> [Vec<f32>; 3] {
+    let xyb = Xyb::from(img);
+    [0, 1, 2].map(|c| xyb.data().iter().map(|pix| pix[c]).collect())
+}
+
+fn subtract(plane: &mut [f32], other: &[f32]) {
+    for (value, &other) in plane.iter_mut().zip(other) {
+        *value -= other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_image(value: impl Fn(usize, usize) -> [f32; 3]) -> LinearRgb {
+        let data = (0..48 * 32).map(|i| value(i % 48, i / 48)).collect();
+        LinearRgb::new(data, 48, 32).unwrap()
+    }
+
+    fn texture(x: usize, y: usize) -> [f32; 3] {
+        let v = ((x * 7 + y * 13) % 23) as f32 / 46.0 + 0.1;
+        [v, v * 0.9, v * 0.8]
+    }
+
+    #[test]
+    fn identical_images() {
+        let distortion = max_norm_distortion(make_image(texture), make_image(texture)).unwrap();
+        assert!(distortion.max.abs() < 1e-6f64);
+        assert!(distortion.p_norm.abs() < 1e-6f64);
+    }
+
+    #[test]
+    fn localized_distortion() {
+        let distorted = make_image(|x, y| {
+            let [r, g, b] = texture(x, y);
+            if (30..34).contains(&x) && (10..14).contains(&y) {
+                [r + 0.05, g, b]
+            } else {
+                [r, g, b]
+            }
+        });
+        let distortion = max_norm_distortion(make_image(texture), distorted).unwrap();
+        let (x, y) = distortion.max_position;
+        assert!((28..36).contains(&x) && (8..16).contains(&y), "{x}, {y}");
+        assert!((f64::from(distortion.map.max_value()) - distortion.max).abs() < 1e-6f64);
+        // The worst pixel stands out from the norm of the whole image.
+        assert!(distortion.max > 3.0f64 * distortion.p_norm);
+        assert!(distortion.max > 1.0f64, "{}", distortion.max);
+    }
+
+    #[test]
+    fn stronger_distortion() {
+        let noisy = |amplitude: f32| {
+            make_image(|x, y| {
+                let noise = ((x * 31 + y * 17) % 7) as f32 / 3.0 - 1.0;
+                texture(x, y).map(|v| noise.mul_add(amplitude, v))
+            })
+        };
+        let less = max_norm_distortion(make_image(texture), noisy(0.002)).unwrap();
+        let more = max_norm_distortion(make_image(texture), noisy(0.02)).unwrap();
+        assert!(less.max < more.max && less.p_norm < more.p_norm);
+        assert_eq!(
+            max_norm_distortion(
+                make_image(texture),
+                LinearRgb::new(vec![[0.0; 3]; 64], 8, 8).unwrap()
+            ),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! Experimental metrics built on the public blur and color conversion APIs of
+//! this crate.
+//!
+//! These metrics give a second opinion next to the SSIMULACRA2 score, but
+//! unlike it they are not calibrated against subjective ratings. Their output
+//! may change in any release, so don't compare values computed by different
+//! versions of the crate.
+
+mod banding;
+mod max_norm;
+mod pyramid;
+
+pub use banding::{banding_sensitive_score, BandingScore};
+pub use max_norm::{max_norm_distortion, MaxNormDistortion};
+pub use pyramid::{pyramid_score, Pyramid, PyramidScale, PyramidScore, SCALE_WEIGHTS};
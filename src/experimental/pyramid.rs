@@ -0,0 +1,369 @@
+use alloc::vec::Vec;
+
+use yuvxyb::LinearRgb;
+
+use crate::channel::{compare_channels, ChannelScratch, SourceChannel};
+use crate::downscale::{BoxDownscaler, Downscaler};
+use crate::{
+    error_to_score, linear_rgb_to_planar_xyb, weight_index, Blur, ResizeFilter, Ssimulacra2Error,
+    NUM_SCALES, WEIGHT,
+};
+
+/// The number of weights of a scale: one for each of the 3 channels, 2 norms
+/// and 3 error maps, indexed by `(channel * 2 + norm) * 3 + map`.
+pub const SCALE_WEIGHTS: usize = 18;
+
+/// One scale of a [`Pyramid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PyramidScale {
+    /// How much smaller the scale is than the full resolution image, e.g. 2.0
+    /// for half the width and height.
+    pub factor: f64,
+    /// The weights of the sub-scores of the scale, see [`SCALE_WEIGHTS`], or
+    /// `None` to interpolate them from the standard weights.
+    pub weights: Option<[f64; SCALE_WEIGHTS]>,
+}
+
+/// The set of scales at which [`pyramid_score`] compares two images.
+///
+/// SSIMULACRA2 compares images at six scales, each half the size of the
+/// previous one. A pyramid can use other factors, fewer or more scales, and
+/// its own weights for each scale. Scales whose weights are not given get the
+/// standard weights of the same size, interpolated between the standard
+/// scales in octaves and multiplied by the octaves the scale spans, so that
+/// denser pyramids do not weigh coarse detail more.
+///
+/// Every scale is downscaled from the previous one, with the box filter of
+/// the reference implementation for factors of exactly two and with the
+/// filter set by [`with_filter`][Self::with_filter] otherwise. The
+/// [standard](Self::standard) pyramid therefore scores images of at least
+/// 256x256 pixels, which are compared at all six scales, exactly like
+/// SSIMULACRA2, while the scores of any other pyramid are not comparable to
+/// SSIMULACRA2 scores.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pyramid {
+    scales: Vec<PyramidScale>,
+    filter: ResizeFilter,
+}
+
+impl Pyramid {
+    /// Creates a pyramid of the given scales.
+    ///
+    /// # Panics
+    /// - If there are no scales
+    /// - If a factor is not finite, is less than 1.0, or is not larger than
+    ///   the factor of the previous scale
+    #[must_use]
+    pub fn new(scales: Vec<PyramidScale>) -> Self {
+        assert!(!scales.is_empty(), "A pyramid needs at least one scale");
+        let mut previous = 1.0f64;
+        for (i, scale) in scales.iter().enumerate() {
+            assert!(
+                scale.factor.is_finite()
+                    && scale.factor >= 1.0f64
+                    && (i == 0 || scale.factor > previous),
+                "Scale factors must be increasing and at least 1.0"
+            );
+            previous = scale.factor;
+        }
+        Pyramid {
+            scales,
+            filter: ResizeFilter::Area,
+        }
+    }
+
+    /// The six scales of SSIMULACRA2, from 1:1 to 1:32.
+    #[must_use]
+    pub fn standard() -> Self {
+        Self::geometric(2.0f64, NUM_SCALES)
+    }
+
+    /// Creates a pyramid of `count` scales starting at the full resolution,
+    /// each `ratio` times smaller than the previous one, with interpolated
+    /// weights.
+    ///
+    /// # Panics
+    /// - If `count` is zero
+    /// - If `ratio` is not finite or not larger than 1.0 and `count` is
+    ///   larger than one
+    #[must_use]
+    pub fn geometric(ratio: f64, count: usize) -> Self {
+        let mut factor = 1.0f64;
+        let scales = (0..count)
+            .map(|_| {
+                let scale = PyramidScale {
+                    factor,
+                    weights: None,
+                };
+                factor *= ratio;
+                scale
+            })
+            .collect();
+        Self::new(scales)
+    }
+
+    /// Sets the filter for downscaling by factors other than two. The default
+    /// is [`ResizeFilter::Area`].
+    #[must_use]
+    pub const fn with_filter(mut self, filter: ResizeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// The scales of the pyramid.
+    #[must_use]
+    pub fn scales(&self) -> &[PyramidScale] {
+        &self.scales
+    }
+
+    /// The weights of every scale, as given or interpolated.
+    #[must_use]
+    pub fn weights(&self) -> Vec<[f64; SCALE_WEIGHTS]> {
+        self.scales
+            .iter()
+            .enumerate()
+            .map(|(i, scale)| {
+                scale.weights.unwrap_or_else(|| {
+                    // The octaves up to the next scale, or those of the
+                    // previous step for the last scale.
+                    let octaves = match (self.scales.get(i + 1), i.checked_sub(1)) {
+                        (Some(next), _) => (next.factor / scale.factor).log2(),
+                        (None, Some(previous)) => {
+                            (scale.factor / self.scales[previous].factor).log2()
+                        }
+                        (None, None) => 1.0f64,
+                    };
+                    interpolated_weights(scale.factor).map(|weight| weight * octaves)
+                })
+            })
+            .collect()
+    }
+
+    // The width and height of the scale with the given factor, rounded up
+    // like the halving of the standard scales.
+    fn size(factor: f64, width: usize, height: usize) -> (usize, usize) {
+        let side = |len: usize| ((len as f64 / factor).ceil() as usize).max(1);
+        (side(width), side(height))
+    }
+
+    // Downscales `img` from the scale with factor `from` to the one with
+    // factor `to`.
+    fn downscale(&self, img: &LinearRgb, from: f64, to: f64, size: (usize, usize)) -> LinearRgb {
+        let halved = ((img.width() + 1) / 2, (img.height() + 1) / 2);
+        #[allow(clippy::float_cmp)]
+        let doubled = to == 2.0f64 * from;
+        if doubled && size == halved {
+            BoxDownscaler.downscale(img)
+        } else {
+            self.filter.resize(img, size.0, size.1)
+        }
+    }
+}
+
+impl Default for Pyramid {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+// The standard weights of a scale with the given factor, interpolated
+// linearly in octaves between the standard scales and clamped to the finest
+// and coarsest of them.
+fn interpolated_weights(factor: f64) -> [f64; SCALE_WEIGHTS] {
+    let octave = factor.log2().clamp(0.0f64, (NUM_SCALES - 1) as f64);
+    let lower = (octave.floor() as usize).min(NUM_SCALES - 1);
+    let upper = (lower + 1).min(NUM_SCALES - 1);
+    let fraction = octave - lower as f64;
+    let mut weights = [0.0f64; SCALE_WEIGHTS];
+    for c in 0..3 {
+        for norm in 0..2 {
+            for map in 0..3 {
+                let low = WEIGHT[weight_index(c, lower, norm, map, NUM_SCALES)];
+                let high = WEIGHT[weight_index(c, upper, norm, map, NUM_SCALES)];
+                weights[(c * 2 + norm) * 3 + map] = fraction.mul_add(high - low, low);
+            }
+        }
+    }
+    weights
+}
+
+/// A score computed with a [`Pyramid`], see [`pyramid_score`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyramidScore {
+    /// The score, on the same scale as SSIMULACRA2. It is only a SSIMULACRA2
+    /// score for the standard pyramid.
+    pub score: f64,
+    /// The weighted error of each compared scale, including the final
+    /// scaling factor, like [`DetailedScore::scales`][crate::DetailedScore::scales].
+    pub scales: Vec<f64>,
+    /// The width and height of each compared scale.
+    pub sizes: Vec<(usize, usize)>,
+}
+
+/// Compares `source` and `distorted` at the scales of `pyramid` instead of the
+/// standard six scales.
+///
+/// Like SSIMULACRA2, a scale is only compared if the previous one is at least
+/// 8x8 pixels, so small images skip the coarsest scales of the pyramid. Unlike
+/// SSIMULACRA2, the remaining scales keep their own weights.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to linear RGB successfully
+/// - If the images contain values for which no score can be computed
+/// - If the image is smaller than 8x8 pixels
+pub fn pyramid_score<T, U>(
+    source: T,
+    distorted: U,
+    pyramid: &Pyramid,
+) -> Result<PyramidScore, Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let Ok(mut source) = LinearRgb::try_from(source) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let Ok(mut distorted) = LinearRgb::try_from(distorted) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let width = source.width();
+    let height = source.height();
+    if width != distorted.width() || height != distorted.height() {
+        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+    }
+    if width < 8 || height < 8 {
+        return Err(Ssimulacra2Error::InvalidImageSize);
+    }
+
+    let weights = pyramid.weights();
+    let mut channels: [ChannelScratch; 3] = Default::default();
+    let mut factor = 1.0f64;
+    let mut result = PyramidScore {
+        score: 0.0f64,
+        scales: Vec::with_capacity(weights.len()),
+        sizes: Vec::with_capacity(weights.len()),
+    };
+    let mut errors = Vec::with_capacity(weights.len());
+    for scale in pyramid.scales() {
+        if source.width() < 8 || source.height() < 8 {
+            break;
+        }
+        let size = Pyramid::size(scale.factor, width, height);
+        if size != (source.width(), source.height()) {
+            source = pyramid.downscale(&source, factor, scale.factor, size);
+            distorted = pyramid.downscale(&distorted, factor, scale.factor, size);
+        }
+        factor = scale.factor;
+        errors.push(compare_scale(&source, &distorted, &mut channels));
+        result.sizes.push(size);
+    }
+
+    // The sub-scores are summed in the same order as by SSIMULACRA2, so that
+    // the standard pyramid gives the same score.
+    let mut error = 0.0f64;
+    for c in 0..3 {
+        for (scale, weights) in errors.iter().zip(&weights) {
+            for norm in 0..2 {
+                for map in 0..3 {
+                    let i = (c * 2 + norm) * 3 + map;
+                    error = weights[i].mul_add(scale[i].abs(), error);
+                }
+            }
+        }
+    }
+    if !error.is_finite() {
+        return Err(Ssimulacra2Error::NonFiniteScore);
+    }
+    result.score = error_to_score(error * 0.956_238_261_683_484_4_f64);
+    result.scales = errors
+        .iter()
+        .zip(&weights)
+        .map(|(scale, weights)| {
+            let mut error = 0.0f64;
+            for (weight, value) in weights.iter().zip(scale) {
+                error = weight.mul_add(value.abs(), error);
+            }
+            error * 0.956_238_261_683_484_4_f64
+        })
+        .collect();
+    Ok(result)
+}
+
+// The sub-scores of one scale, in the order of the weights.
+fn compare_scale(
+    source: &LinearRgb,
+    distorted: &LinearRgb,
+    channels: &mut [ChannelScratch; 3],
+) -> [f64; SCALE_WEIGHTS] {
+    let width = source.width();
+    let height = source.height();
+    let mut blur = Blur::new(width, height);
+    let mut img1 = [Vec::new(), Vec::new(), Vec::new()];
+    linear_rgb_to_planar_xyb(source, &mut img1);
+    let sigma_sq = blur.blur_product(&img1, &img1);
+    let mu = blur.blur(&img1);
+    let mut img2 = [Vec::new(), Vec::new(), Vec::new()];
+    linear_rgb_to_planar_xyb(distorted, &mut img2);
+
+    let source = [0usize, 1, 2].map(|c| SourceChannel {
+        img: &img1[c],
+        mu: &mu[c],
+        sigma_sq: &sigma_sq[c],
+    });
+    let img2 = [0usize, 1, 2].map(|c| img2[c].as_slice());
+    let errors = compare_channels(width, height, source, img2, channels, None, false);
+    let mut values = [0.0f64; SCALE_WEIGHTS];
+    for (c, errors) in errors.iter().enumerate() {
+        for norm in 0..2 {
+            let i = (c * 2 + norm) * 3;
+            values[i] = errors.ssim[norm];
+            values[i + 1] = errors.edge_diff[norm];
+            values[i + 2] = errors.edge_diff[norm + 2];
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::crossed_wave_image;
+    use crate::Ssimulacra2;
+
+    #[test]
+    fn standard_pyramid_matches_ssimulacra2() {
+        let (source, distorted) = (
+            crossed_wave_image(300, 270, 0.0),
+            crossed_wave_image(300, 270, 0.2),
+        );
+        let detailed = Ssimulacra2::new(300, 270)
+            .unwrap()
+            .score_detailed(source.clone(), distorted.clone())
+            .unwrap();
+        let pyramid = pyramid_score(source, distorted, &Pyramid::standard()).unwrap();
+        assert_eq!(pyramid.score.to_bits(), detailed.score.to_bits());
+        assert_eq!(pyramid.scales.len(), NUM_SCALES);
+        assert_eq!(pyramid.sizes[1], (150, 135));
+    }
+
+    #[test]
+    fn interpolates_weights() {
+        let standard = Pyramid::standard().weights();
+        let dense = Pyramid::geometric(2.0f64.sqrt(), 11).weights();
+        for (i, weights) in dense.iter().enumerate().step_by(2) {
+            for (dense, standard) in weights.iter().zip(&standard[i / 2]) {
+                assert!(dense.mul_add(2.0f64, -standard).abs() < 1e-12f64);
+            }
+        }
+
+        let (source, distorted) = (
+            crossed_wave_image(120, 90, 0.0),
+            crossed_wave_image(120, 90, 0.2),
+        );
+        let identical = pyramid_score(source.clone(), source.clone(), &Pyramid::geometric(1.5, 5));
+        assert!((identical.unwrap().score - 100.0f64).abs() < 1e-9f64);
+        let score = pyramid_score(source, distorted, &Pyramid::geometric(1.5, 5)).unwrap();
+        assert_eq!(score.sizes[1], (80, 60));
+        assert!(score.score < 100.0f64);
+    }
+}
@@ -0,0 +1,523 @@
+//! C API for linking against this crate from C and C++, e.g. from `FFmpeg`
+//! filters, `GStreamer` elements or encoders. The matching header is
+//! `include/ssimulacra2.h`, which is generated with `cbindgen`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    ColorPrimaries, Frame, FrameRef, MatrixCoefficients, Pixel, Plane, ScoreAggregator,
+    Ssimulacra2, Ssimulacra2Error, TransferCharacteristic, Yuv, YuvConfig,
+};
+
+/// Result of a call into the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ssimulacra2Status {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// The color configuration is invalid or does not match the plane data.
+    InvalidConfig = 2,
+    /// The images are smaller than 8x8 pixels.
+    InvalidImageSize = 3,
+    /// The images could not be converted to linear RGB.
+    ConversionFailed = 4,
+    /// An unexpected internal error occurred.
+    Panic = 5,
+    /// No frames have been scored yet.
+    NoFrames = 6,
+    /// The comparison was cancelled.
+    Cancelled = 7,
+}
+
+/// Color configuration of the YUV frames passed to a context.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ssimulacra2Config {
+    /// Bits per sample, between 8 and 16. Samples of more than 8 bits are
+    /// stored as native endian 16-bit values.
+    pub bit_depth: u8,
+    /// Horizontal chroma subsampling, as a power of two (1 for 4:2:0 and 4:2:2).
+    pub subsampling_x: u8,
+    /// Vertical chroma subsampling, as a power of two (1 for 4:2:0).
+    pub subsampling_y: u8,
+    /// Whether the samples use the full range instead of the limited (TV) range.
+    pub full_range: bool,
+    /// Matrix coefficients, as defined in ITU-T H.273.
+    pub matrix_coefficients: u8,
+    /// Transfer characteristics, as defined in ITU-T H.273.
+    pub transfer_characteristics: u8,
+    /// Color primaries, as defined in ITU-T H.273.
+    pub color_primaries: u8,
+}
+
+/// The Y, U and V planes of a frame.
+///
+/// Each plane starts at `data[i]`, with rows `stride[i]` bytes apart. The
+/// stride may be negative for bottom-up images, as with the `linesize` of `FFmpeg` frames.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ssimulacra2Planes {
+    /// Pointers to the first sample of each plane.
+    pub data: [*const u8; 3],
+    /// Distance between the starts of two rows of each plane, in bytes.
+    pub stride: [isize; 3],
+}
+
+/// Statistics over all frames scored by a context.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ssimulacra2Summary {
+    /// Number of frames which were scored.
+    pub frames: usize,
+    /// Arithmetic mean of all scores.
+    pub mean: f64,
+    /// Harmonic mean of all scores, or NaN if any score is not positive.
+    pub harmonic_mean: f64,
+    /// Population standard deviation of all scores.
+    pub std_dev: f64,
+    /// Lowest score.
+    pub min: f64,
+    /// Highest score.
+    pub max: f64,
+    /// 5th percentile.
+    pub p5: f64,
+}
+
+/// A scoring context for frames of one resolution and color configuration.
+///
+/// Create it with `ssimulacra2_new` and free it with `ssimulacra2_free`.
+/// A context reuses its buffers between frames and must not be used from
+/// multiple threads at the same time. It also keeps the scores of all frames,
+/// which `ssimulacra2_summary` summarizes, e.g. when closing a filter.
+#[derive(Debug)]
+pub struct Ssimulacra2Context {
+    session: Ssimulacra2,
+    config: YuvConfig,
+    scores: ScoreAggregator,
+}
+
+impl Ssimulacra2Config {
+    fn to_yuv_config(self) -> Option<YuvConfig> {
+        if !(8..=16).contains(&self.bit_depth) || self.subsampling_x > 1 || self.subsampling_y > 1 {
+            return None;
+        }
+
+        Some(YuvConfig {
+            bit_depth: self.bit_depth,
+            subsampling_x: self.subsampling_x,
+            subsampling_y: self.subsampling_y,
+            full_range: self.full_range,
+            matrix_coefficients: MatrixCoefficients::from_u8(self.matrix_coefficients)?,
+            transfer_characteristics: TransferCharacteristic::from_u8(
+                self.transfer_characteristics,
+            )?,
+            color_primaries: ColorPrimaries::from_u8(self.color_primaries)?,
+        })
+    }
+}
+
+impl From<Ssimulacra2Error> for Ssimulacra2Status {
+    fn from(err: Ssimulacra2Error) -> Self {
+        match err {
+            Ssimulacra2Error::InvalidImageSize | Ssimulacra2Error::NonMatchingImageDimensions => {
+                Ssimulacra2Status::InvalidImageSize
+            }
+            Ssimulacra2Error::LinearRgbConversionFailed
+            | Ssimulacra2Error::NonFiniteScore
+            | Ssimulacra2Error::NonFiniteInput { .. } => Ssimulacra2Status::ConversionFailed,
+            Ssimulacra2Error::InvalidFrameConfig
+            | Ssimulacra2Error::InvalidArrayShape
+            | Ssimulacra2Error::InvalidBufferLength
+            | Ssimulacra2Error::EmptyComposite
+            | Ssimulacra2Error::ThreadPoolCreationFailed
+            | Ssimulacra2Error::InvalidSearchRange => Ssimulacra2Status::InvalidConfig,
+            Ssimulacra2Error::Cancelled => Ssimulacra2Status::Cancelled,
+        }
+    }
+}
+
+/// Creates a context for scoring frames of the given size and color configuration.
+///
+/// Returns NULL if `config` is NULL or invalid, or if the frames are smaller
+/// than 8x8 pixels.
+///
+/// # Safety
+/// `config` must be NULL or point to a valid `Ssimulacra2Config`.
+#[no_mangle]
+pub unsafe extern "C" fn ssimulacra2_new(
+    width: u32,
+    height: u32,
+    config: *const Ssimulacra2Config,
+) -> *mut Ssimulacra2Context {
+    // SAFETY: The caller guarantees that a non-null `config` is valid.
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        return ptr::null_mut();
+    };
+    let Some(config) = config.to_yuv_config() else {
+        return ptr::null_mut();
+    };
+    let Ok(Ok(session)) = catch_unwind(|| Ssimulacra2::new(width as usize, height as usize)) else {
+        return ptr::null_mut();
+    };
+
+    Box::into_raw(Box::new(Ssimulacra2Context {
+        session,
+        config,
+        scores: ScoreAggregator::new(),
+    }))
+}
+
+/// Computes the SSIMULACRA2 score of `distorted` against `source` and
+/// writes it to `score`.
+///
+/// # Safety
+/// - `ctx` must be NULL or a context returned by `ssimulacra2_new` that has
+///   not been freed yet, and must not be used concurrently.
+/// - `source` and `distorted` must be NULL or point to planes which cover
+///   the resolution and subsampling the context was created with, with the
+///   chroma planes of odd sizes rounded up.
+/// - `score` must be NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ssimulacra2_score(
+    ctx: *mut Ssimulacra2Context,
+    source: *const Ssimulacra2Planes,
+    distorted: *const Ssimulacra2Planes,
+    score: *mut f64,
+) -> Ssimulacra2Status {
+    // SAFETY: The caller guarantees that all non-null pointers are valid, and
+    // that `ctx` is not aliased.
+    let (Some(ctx), Some(source), Some(distorted), Some(score)) = (unsafe {
+        (
+            ctx.as_mut(),
+            source.as_ref(),
+            distorted.as_ref(),
+            score.as_mut(),
+        )
+    }) else {
+        return Ssimulacra2Status::NullPointer;
+    };
+    if source
+        .data
+        .iter()
+        .chain(&distorted.data)
+        .any(|p| p.is_null())
+    {
+        return Ssimulacra2Status::NullPointer;
+    }
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        // SAFETY: The caller guarantees that the planes cover the resolution
+        // of the context.
+        unsafe {
+            if ctx.config.bit_depth > 8 {
+                score_planes::<u16>(ctx, source, distorted)
+            } else {
+                score_planes::<u8>(ctx, source, distorted)
+            }
+        }
+    }));
+
+    match result {
+        Ok(Ok(value)) => {
+            ctx.scores.push(ctx.scores.len(), value);
+            *score = value;
+            Ssimulacra2Status::Ok
+        }
+        Ok(Err(status)) => status,
+        Err(_) => Ssimulacra2Status::Panic,
+    }
+}
+
+/// Writes statistics over all frames scored by `ctx` so far to `summary`.
+///
+/// # Safety
+/// - `ctx` must be NULL or a context returned by `ssimulacra2_new` that has
+///   not been freed yet.
+/// - `summary` must be NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ssimulacra2_summary(
+    ctx: *const Ssimulacra2Context,
+    summary: *mut Ssimulacra2Summary,
+) -> Ssimulacra2Status {
+    // SAFETY: The caller guarantees that all non-null pointers are valid.
+    let (Some(ctx), Some(summary)) = (unsafe { (ctx.as_ref(), summary.as_mut()) }) else {
+        return Ssimulacra2Status::NullPointer;
+    };
+    let Some(stats) = ctx.scores.summary() else {
+        return Ssimulacra2Status::NoFrames;
+    };
+
+    *summary = Ssimulacra2Summary {
+        frames: stats.frames,
+        mean: stats.mean,
+        harmonic_mean: stats.harmonic_mean.unwrap_or(f64::NAN),
+        std_dev: stats.std_dev,
+        min: stats.min,
+        max: stats.max,
+        p5: stats.p5,
+    };
+    Ssimulacra2Status::Ok
+}
+
+/// Frees a context created by `ssimulacra2_new`. Passing NULL is a no-op.
+///
+/// # Safety
+/// `ctx` must be NULL or a context returned by `ssimulacra2_new` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ssimulacra2_free(ctx: *mut Ssimulacra2Context) {
+    if !ctx.is_null() {
+        // SAFETY: The caller guarantees that `ctx` was created by `Box::into_raw`
+        // in `ssimulacra2_new` and is not used afterwards.
+        drop(unsafe { Box::from_raw(ctx) });
+    }
+}
+
+unsafe fn score_planes<T: Pixel + FromBytes>(
+    ctx: &mut Ssimulacra2Context,
+    source: &Ssimulacra2Planes,
+    distorted: &Ssimulacra2Planes,
+) -> Result<f64, Ssimulacra2Status> {
+    let config = ctx.config;
+    let width = ctx.session.width();
+    let height = ctx.session.height();
+
+    // SAFETY: Forwarded from the caller.
+    let (source, distorted) = unsafe {
+        (
+            copy_frame::<T>(source, width, height, config),
+            copy_frame::<T>(distorted, width, height, config),
+        )
+    };
+    // `Yuv` only takes sizes divisible by the subsampling, `FrameRef` also
+    // reads the rounded-up chroma planes of odd sizes.
+    if width % (1 << config.subsampling_x) != 0 || height % (1 << config.subsampling_y) != 0 {
+        let (Ok(source), Ok(distorted)) = (
+            FrameRef::new(&source, config),
+            FrameRef::new(&distorted, config),
+        ) else {
+            return Err(Ssimulacra2Status::InvalidConfig);
+        };
+        return Ok(ctx.session.score(source, distorted)?);
+    }
+    let (Ok(source), Ok(distorted)) = (Yuv::new(source, config), Yuv::new(distorted, config))
+    else {
+        return Err(Ssimulacra2Status::InvalidConfig);
+    };
+
+    Ok(ctx.session.score(source, distorted)?)
+}
+
+// Copies the planes into an owned frame. Every row is read separately, so the
+// last row of a plane does not need to be padded to the full stride.
+unsafe fn copy_frame<T: Pixel + FromBytes>(
+    planes: &Ssimulacra2Planes,
+    width: usize,
+    height: usize,
+    config: YuvConfig,
+) -> Frame<T> {
+    let ss_x = usize::from(config.subsampling_x);
+    let ss_y = usize::from(config.subsampling_y);
+    let chroma_width = (width + ss_x) >> ss_x;
+    let chroma_height = (height + ss_y) >> ss_y;
+
+    let mut frame = Frame {
+        planes: [
+            Plane::new(width, height, 0, 0, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+        ],
+    };
+
+    for (i, plane) in frame.planes.iter_mut().enumerate() {
+        let plane_width = plane.cfg.width;
+        let row_bytes = plane_width * std::mem::size_of::<T>();
+        for (y, row) in plane.rows_iter_mut().enumerate() {
+            // SAFETY: The caller guarantees that every row of the plane is
+            // readable for `row_bytes` bytes.
+            let src = unsafe {
+                slice::from_raw_parts(
+                    planes.data[i].offset(y as isize * planes.stride[i]),
+                    row_bytes,
+                )
+            };
+            for (pix, bytes) in row[..plane_width]
+                .iter_mut()
+                .zip(src.chunks_exact(std::mem::size_of::<T>()))
+            {
+                *pix = T::from_bytes(bytes);
+            }
+        }
+    }
+
+    frame
+}
+
+trait FromBytes {
+    fn from_bytes(bytes: &[u8]) -> Self;
+}
+
+impl FromBytes for u8 {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+}
+
+impl FromBytes for u16 {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        u16::from_ne_bytes([bytes[0], bytes[1]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_frame_ssimulacra2;
+
+    fn make_plane(width: usize, height: usize, stride: usize, seed: usize) -> Vec<u8> {
+        let mut data = vec![0u8; stride * height];
+        for (y, row) in data.chunks_exact_mut(stride).enumerate() {
+            for (x, pix) in row[..width].iter_mut().enumerate() {
+                *pix = (16 + (x * 7 + y * 13) * seed % 220) as u8;
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn score_matches_rust_api() {
+        let (width, height) = (64usize, 48usize);
+        let config = Ssimulacra2Config {
+            bit_depth: 8,
+            subsampling_x: 1,
+            subsampling_y: 1,
+            full_range: false,
+            matrix_coefficients: 1,
+            transfer_characteristics: 1,
+            color_primaries: 1,
+        };
+        let source: Vec<_> = (0..3usize)
+            .map(|i| {
+                let w = if i == 0 { width } else { width / 2 };
+                let h = if i == 0 { height } else { height / 2 };
+                make_plane(w, h, w + 16, 3)
+            })
+            .collect();
+        let distorted: Vec<_> = (0..3usize)
+            .map(|i| {
+                let w = if i == 0 { width } else { width / 2 };
+                let h = if i == 0 { height } else { height / 2 };
+                make_plane(w, h, w + 16, 5)
+            })
+            .collect();
+        let strides = [width + 16, width / 2 + 16, width / 2 + 16].map(|s| s as isize);
+        let planes = |data: &[Vec<u8>]| Ssimulacra2Planes {
+            data: [data[0].as_ptr(), data[1].as_ptr(), data[2].as_ptr()],
+            stride: strides,
+        };
+
+        let mut score = 0.0f64;
+        // SAFETY: All pointers are valid and the planes cover the whole frame.
+        unsafe {
+            let ctx = ssimulacra2_new(width as u32, height as u32, &config);
+            assert!(!ctx.is_null());
+            let status = ssimulacra2_score(ctx, &planes(&source), &planes(&distorted), &mut score);
+            assert_eq!(status, Ssimulacra2Status::Ok);
+            assert_eq!(
+                ssimulacra2_score(ctx, ptr::null(), &planes(&distorted), &mut score),
+                Ssimulacra2Status::NullPointer
+            );
+            let mut summary = Ssimulacra2Summary {
+                frames: 0,
+                mean: 0.0,
+                harmonic_mean: 0.0,
+                std_dev: 0.0,
+                min: 0.0,
+                max: 0.0,
+                p5: 0.0,
+            };
+            assert_eq!(
+                ssimulacra2_summary(ctx, &mut summary),
+                Ssimulacra2Status::Ok
+            );
+            assert_eq!(summary.frames, 1);
+            assert!((summary.mean - score).abs() < f64::EPSILON);
+            ssimulacra2_free(ctx);
+            assert!(ssimulacra2_new(4, 4, &config).is_null());
+        }
+
+        let config = config.to_yuv_config().unwrap();
+        // SAFETY: As above.
+        let (source, distorted) = unsafe {
+            (
+                copy_frame::<u8>(&planes(&source), width, height, config),
+                copy_frame::<u8>(&planes(&distorted), width, height, config),
+            )
+        };
+        let expected = compute_frame_ssimulacra2(
+            Yuv::new(source, config).unwrap(),
+            Yuv::new(distorted, config).unwrap(),
+        )
+        .unwrap();
+        assert!((score - expected).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn odd_sizes() {
+        // 4:2:0 with a last chroma column and row for one luma sample each.
+        let (width, height) = (63usize, 47usize);
+        let (chroma_width, chroma_height) = (32usize, 24usize);
+        let config = Ssimulacra2Config {
+            bit_depth: 8,
+            subsampling_x: 1,
+            subsampling_y: 1,
+            full_range: false,
+            matrix_coefficients: 1,
+            transfer_characteristics: 1,
+            color_primaries: 1,
+        };
+        let make = |seed: usize| {
+            [
+                make_plane(width, height, width, seed),
+                make_plane(chroma_width, chroma_height, chroma_width, seed),
+                make_plane(chroma_width, chroma_height, chroma_width, seed),
+            ]
+        };
+        let (source, distorted) = (make(3), make(5));
+        let planes = |data: &[Vec<u8>; 3]| Ssimulacra2Planes {
+            data: [data[0].as_ptr(), data[1].as_ptr(), data[2].as_ptr()],
+            stride: [width, chroma_width, chroma_width].map(|s| s as isize),
+        };
+
+        let mut score = 0.0f64;
+        // SAFETY: All pointers are valid and the planes cover the whole frame.
+        unsafe {
+            let ctx = ssimulacra2_new(width as u32, height as u32, &config);
+            assert!(!ctx.is_null());
+            let status = ssimulacra2_score(ctx, &planes(&source), &planes(&distorted), &mut score);
+            assert_eq!(status, Ssimulacra2Status::Ok);
+            ssimulacra2_free(ctx);
+        }
+
+        let config = config.to_yuv_config().unwrap();
+        // SAFETY: As above.
+        let (source, distorted) = unsafe {
+            (
+                copy_frame::<u8>(&planes(&source), width, height, config),
+                copy_frame::<u8>(&planes(&distorted), width, height, config),
+            )
+        };
+        let expected = compute_frame_ssimulacra2(
+            FrameRef::new(&source, config).unwrap(),
+            FrameRef::new(&distorted, config).unwrap(),
+        )
+        .unwrap();
+        assert!((score - expected).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,19 @@
+// Runtime detection of the FMA instructions of x86 CPUs, which builds for the
+// baseline target cannot use.
+//
+// Multiply-adds have to be fused to give the same values on every target.
+// Without FMA instructions, each one is a call to `fmaf` or an emulation in
+// double precision, which also keeps the loops around them from being
+// vectorized. The loops that suffer most, the XYB conversion and the
+// comparison of several distorted images at once, are therefore compiled a
+// second time with `#[target_feature(enable = "avx2,fma")]` and picked at
+// runtime when the CPU supports it, which most x86 CPUs since 2013 do. The
+// functions they call have to be `#[inline(always)]`, as others are not
+// compiled again with FMA. Both compute the same values.
+
+// Whether the CPU supports FMA and AVX2. The result is cached by the standard
+// library, so this is cheap enough to check for every row.
+#[inline]
+pub fn detected() -> bool {
+    std::arch::is_x86_feature_detected!("fma") && std::arch::is_x86_feature_detected!("avx2")
+}
@@ -0,0 +1,446 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use yuvxyb::{
+    CastFromPrimitive, ColorPrimaries, Frame, LinearRgb, MatrixCoefficients, Pixel,
+    TransferCharacteristic, YuvConfig,
+};
+
+use crate::transfer::decode_rgb;
+use crate::Ssimulacra2Error;
+
+/// A borrowed [`v_frame`](https://docs.rs/v_frame) frame together with the
+/// color information needed to interpret it.
+///
+/// Converting a [`FrameRef`] into [`LinearRgb`] reads the planes in place,
+/// honoring their stride, padding and chroma decimation. Unlike going through
+/// [`Yuv`][crate::Yuv], the frame does not need to be cloned or moved, which
+/// avoids a full copy of every frame in rav1e/av-metrics style pipelines.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRef<'a, T: Pixel> {
+    frame: &'a Frame<T>,
+    config: YuvConfig,
+    chroma_siting: Option<ChromaSiting>,
+}
+
+/// The location of subsampled chroma samples relative to the luma samples,
+/// as signalled by e.g. the `chroma_sample_location` of H.264 and HEVC or the
+/// `chroma_sample_position` of AV1.
+///
+/// With a known siting, [`FrameRef`] upsamples chroma by interpolating
+/// between the nearest chroma samples at their actual positions. Assuming the
+/// wrong siting shifts chroma by half a pixel, which shows up as distortion
+/// along every colored edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaSiting {
+    /// Co-sited with the left luma sample horizontally and centered between
+    /// two luma rows vertically. The default of MPEG-2, H.264 and HEVC for 4:2:0.
+    Left,
+    /// Centered between the luma samples in both directions, as in JPEG and MPEG-1.
+    Center,
+    /// Co-sited with the top left luma sample, as in BT.2020 and AV1's `colocated`.
+    TopLeft,
+}
+
+impl ChromaSiting {
+    // Whether chroma samples are co-sited with luma samples horizontally and vertically.
+    const fn cosited(self) -> (bool, bool) {
+        match self {
+            ChromaSiting::Left => (true, false),
+            ChromaSiting::Center => (false, false),
+            ChromaSiting::TopLeft => (true, true),
+        }
+    }
+}
+
+impl<'a, T: Pixel> FrameRef<'a, T> {
+    /// Wraps `frame` with an explicit color configuration.
+    ///
+    /// # Errors
+    /// - If the chroma subsampling in `config` does not match the decimation of the chroma planes
+    /// - If the chroma planes are not the luma size divided by the subsampling, rounded up
+    /// - If the bit depth in `config` is not valid for the pixel type `T`
+    pub fn new(frame: &'a Frame<T>, config: YuvConfig) -> Result<Self, Ssimulacra2Error> {
+        let luma = &frame.planes[0].cfg;
+        let (ss_x, ss_y) = (
+            usize::from(config.subsampling_x),
+            usize::from(config.subsampling_y),
+        );
+        // Odd luma sizes have a last chroma sample for the last luma sample.
+        let planes_match = frame.planes[1..].iter().all(|plane| {
+            plane.cfg.xdec == ss_x
+                && plane.cfg.ydec == ss_y
+                && plane.cfg.width == (luma.width + ss_x) >> ss_x
+                && plane.cfg.height == (luma.height + ss_y) >> ss_y
+        });
+        let max_bit_depth = 8 * core::mem::size_of::<T>() as u8;
+
+        if !planes_match || config.bit_depth < 8 || config.bit_depth > max_bit_depth {
+            return Err(Ssimulacra2Error::InvalidFrameConfig);
+        }
+
+        Ok(FrameRef {
+            frame,
+            config,
+            chroma_siting: None,
+        })
+    }
+
+    /// Upsamples chroma by interpolating between chroma samples at the given
+    /// location, instead of repeating every chroma sample for all luma samples
+    /// it covers. The latter is what [`Yuv`][crate::Yuv] does and the default.
+    #[must_use]
+    pub const fn with_chroma_siting(mut self, siting: ChromaSiting) -> Self {
+        self.chroma_siting = Some(siting);
+        self
+    }
+
+    /// The wrapped frame.
+    #[must_use]
+    pub const fn frame(&self) -> &'a Frame<T> {
+        self.frame
+    }
+
+    /// The color configuration used to interpret the frame.
+    #[must_use]
+    pub const fn config(&self) -> YuvConfig {
+        self.config
+    }
+
+    /// The chroma siting used for upsampling, if set with
+    /// [`with_chroma_siting`][Self::with_chroma_siting].
+    #[must_use]
+    pub const fn chroma_siting(&self) -> Option<ChromaSiting> {
+        self.chroma_siting
+    }
+
+    /// Width of the luma plane.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.frame.planes[0].cfg.width
+    }
+
+    /// Height of the luma plane.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.frame.planes[0].cfg.height
+    }
+}
+
+impl<'a> From<&'a Frame<u8>> for FrameRef<'a, u8> {
+    /// Interprets an 8-bit frame as limited range BT.709, taking the chroma
+    /// subsampling from the planes themselves. Use [`FrameRef::new`] for any
+    /// other configuration, and for high bit depth frames, whose bit depth
+    /// cannot be told from the pixel type.
+    fn from(frame: &'a Frame<u8>) -> Self {
+        FrameRef {
+            frame,
+            config: YuvConfig {
+                bit_depth: 8,
+                subsampling_x: frame.planes[1].cfg.xdec as u8,
+                subsampling_y: frame.planes[1].cfg.ydec as u8,
+                full_range: false,
+                matrix_coefficients: MatrixCoefficients::BT709,
+                transfer_characteristics: TransferCharacteristic::BT1886,
+                color_primaries: ColorPrimaries::BT709,
+            },
+            chroma_siting: None,
+        }
+    }
+}
+
+impl<T: Pixel> TryFrom<FrameRef<'_, T>> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(input: FrameRef<'_, T>) -> Result<Self, Self::Error> {
+        let config = input.config;
+        let (kr, kb) = luma_coefficients(config.matrix_coefficients)
+            .ok_or(Ssimulacra2Error::LinearRgbConversionFailed)?;
+        let kg = 1.0 - kr - kb;
+
+        let (luma_scale, luma_offset) = scale_offset(config.bit_depth, config.full_range, false);
+        let (chroma_scale, chroma_offset) = scale_offset(config.bit_depth, config.full_range, true);
+
+        let width = input.width();
+        let height = input.height();
+        let ss_x = config.subsampling_x;
+        let ss_y = config.subsampling_y;
+        let [ref y_plane, ref u_plane, ref v_plane] = input.frame.planes;
+        let y_origin = y_plane.data_origin();
+        let u_origin = u_plane.data_origin();
+        let v_origin = v_plane.data_origin();
+
+        let (cosited_x, cosited_y) = input.chroma_siting.map_or((None, None), |siting| {
+            let (x, y) = siting.cosited();
+            (Some(x), Some(y))
+        });
+        let x_taps = chroma_taps(width, u_plane.cfg.width, ss_x, cosited_x);
+        let y_taps = chroma_taps(height, u_plane.cfg.height, ss_y, cosited_y);
+
+        let mut data = vec![[0.0f32; 3]; width * height];
+        for ((y, out_row), &(row0, row1, wy)) in
+            data.chunks_exact_mut(width).enumerate().zip(&y_taps)
+        {
+            let y_row = &y_origin[y * y_plane.cfg.stride..][..width];
+            let u_rows = (
+                &u_origin[row0 * u_plane.cfg.stride..],
+                &u_origin[row1 * u_plane.cfg.stride..],
+            );
+            let v_rows = (
+                &v_origin[row0 * v_plane.cfg.stride..],
+                &v_origin[row1 * v_plane.cfg.stride..],
+            );
+            let chroma = |rows: (&[T], &[T]), (col0, col1, wx): (usize, usize, f32)| {
+                let sample = |row: &[T]| {
+                    let a = to_f32(row[col0], chroma_scale, chroma_offset);
+                    let b = to_f32(row[col1], chroma_scale, chroma_offset);
+                    (b - a).mul_add(wx, a)
+                };
+                let top = sample(rows.0);
+                (sample(rows.1) - top).mul_add(wy, top).clamp(-0.5, 0.5)
+            };
+
+            for ((out, &luma), &taps) in out_row.iter_mut().zip(y_row.iter()).zip(&x_taps) {
+                let luma = to_f32(luma, luma_scale, luma_offset).clamp(0.0, 1.0);
+                let cb = chroma(u_rows, taps);
+                let cr = chroma(v_rows, taps);
+
+                let r = 2.0f32.mul_add(-kr, 2.0).mul_add(cr, luma);
+                let b = 2.0f32.mul_add(-kb, 2.0).mul_add(cb, luma);
+                let g = kb.mul_add(-b, kr.mul_add(-r, luma)) / kg;
+                *out = [r, g, b];
+            }
+        }
+
+        decode_rgb(
+            data,
+            width,
+            height,
+            config.transfer_characteristics,
+            config.color_primaries,
+        )
+    }
+}
+
+// For each of `len` luma positions along one axis, the two chroma samples to
+// interpolate between and the weight of the second. Without a siting, the
+// chroma sample covering the luma position is repeated.
+fn chroma_taps(
+    len: usize,
+    chroma_len: usize,
+    shift: u8,
+    cosited: Option<bool>,
+) -> Vec<(usize, usize, f32)> {
+    let last = chroma_len.saturating_sub(1);
+    let factor = f32::from(1u8 << shift);
+    (0..len)
+        .map(|i| {
+            let Some(cosited) = cosited else {
+                let nearest = (i >> shift).min(last);
+                return (nearest, nearest, 0.0);
+            };
+            // The position of the luma sample in units of chroma samples.
+            let pos = if cosited {
+                i as f32 / factor
+            } else {
+                (i as f32 + 0.5) / factor - 0.5
+            }
+            .max(0.0);
+            let first = (pos as usize).min(last);
+            let second = (first + 1).min(last);
+            (first, second, pos - first as f32)
+        })
+        .collect()
+}
+
+// Kr and Kb for the non-constant-luminance matrices, as in ITU-T H.273.
+const fn luma_coefficients(matrix: MatrixCoefficients) -> Option<(f32, f32)> {
+    Some(match matrix {
+        MatrixCoefficients::BT470M => (0.3, 0.11),
+        MatrixCoefficients::ST240M => (0.212, 0.087),
+        MatrixCoefficients::BT470BG | MatrixCoefficients::ST170M => (0.299, 0.114),
+        MatrixCoefficients::BT709 => (0.2126, 0.0722),
+        MatrixCoefficients::BT2020NonConstantLuminance => (0.2627, 0.0593),
+        _ => return None,
+    })
+}
+
+// Returns the scale and offset mapping integer code values to 0.0..=1.0 for
+// luma and -0.5..=0.5 for chroma.
+fn scale_offset(bit_depth: u8, full_range: bool, chroma: bool) -> (f32, f32) {
+    let shift = bit_depth - 8;
+    let range = if full_range {
+        f64::from((1u32 << bit_depth) - 1)
+    } else if chroma {
+        f64::from(224u32 << shift)
+    } else {
+        f64::from(219u32 << shift)
+    };
+    let offset = if chroma {
+        f64::from(1u32 << (bit_depth - 1))
+    } else if full_range {
+        0.0f64
+    } else {
+        f64::from(16u32 << shift)
+    };
+
+    ((1.0 / range) as f32, (-offset / range) as f32)
+}
+
+#[inline(always)]
+fn to_f32<T: Pixel>(val: T, scale: f32, offset: f32) -> f32 {
+    f32::from(u16::cast_from(val)).mul_add(scale, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_frame_ssimulacra2, Plane, Yuv};
+
+    fn make_frame(width: usize, height: usize, seed: u16) -> Frame<u16> {
+        let mut frame: Frame<u16> = Frame {
+            planes: [
+                Plane::new(width, height, 0, 0, 16, 16),
+                Plane::new((width + 1) / 2, (height + 1) / 2, 1, 1, 8, 8),
+                Plane::new((width + 1) / 2, (height + 1) / 2, 1, 1, 8, 8),
+            ],
+        };
+        for (i, plane) in frame.planes.iter_mut().enumerate() {
+            let width = plane.cfg.width;
+            for (y, row) in plane.rows_iter_mut().enumerate() {
+                for (x, pix) in row[..width].iter_mut().enumerate() {
+                    *pix = 64 + ((x * 7 + y * 13 + i * 31) as u16 * seed) % 876;
+                }
+            }
+        }
+        frame
+    }
+
+    // Limited range BT.709 with 10 bits and the given chroma subsampling.
+    const fn ten_bit(subsampling_x: u8, subsampling_y: u8) -> YuvConfig {
+        YuvConfig {
+            bit_depth: 10,
+            subsampling_x,
+            subsampling_y,
+            full_range: false,
+            matrix_coefficients: MatrixCoefficients::BT709,
+            transfer_characteristics: TransferCharacteristic::BT1886,
+            color_primaries: ColorPrimaries::BT709,
+        }
+    }
+
+    #[test]
+    fn frame_ref_matches_yuv() {
+        let source = make_frame(64, 48, 3);
+        let distorted = make_frame(64, 48, 5);
+
+        let config = ten_bit(1, 1);
+        let borrowed = compute_frame_ssimulacra2(
+            FrameRef::new(&source, config).unwrap(),
+            FrameRef::new(&distorted, config).unwrap(),
+        )
+        .unwrap();
+
+        let owned = compute_frame_ssimulacra2(
+            Yuv::new(source, config).unwrap(),
+            Yuv::new(distorted, config).unwrap(),
+        )
+        .unwrap();
+
+        assert!(
+            (borrowed - owned).abs() < 0.01f64,
+            "Borrowed {borrowed:.6} not equal to owned {owned:.6}"
+        );
+    }
+
+    #[test]
+    fn odd_sizes() {
+        // The chroma planes of a 63x47 frame are those of a 64x48 frame, and
+        // so is the conversion within the 63x47 pixels.
+        let even = make_frame(64, 48, 3);
+        let mut odd = make_frame(63, 47, 3);
+        odd.planes[1] = even.planes[1].clone();
+        odd.planes[2] = even.planes[2].clone();
+        let config = ten_bit(1, 1);
+
+        let expected = LinearRgb::try_from(FrameRef::new(&even, config).unwrap()).unwrap();
+        let linear = LinearRgb::try_from(FrameRef::new(&odd, config).unwrap()).unwrap();
+        assert_eq!((linear.width(), linear.height()), (63, 47));
+        for (row, expected_row) in linear.data().chunks(63).zip(expected.data().chunks(64)) {
+            assert_eq!(row, &expected_row[..63]);
+        }
+
+        // Chroma planes rounded down miss the last column and row.
+        odd.planes[1] = Plane::new(31, 23, 1, 1, 8, 8);
+        assert_eq!(
+            FrameRef::new(&odd, config).err(),
+            Some(Ssimulacra2Error::InvalidFrameConfig)
+        );
+    }
+
+    #[test]
+    fn chroma_siting() {
+        // A frame with flat luma and chroma ramps, subsampled from a 4:4:4
+        // frame of the same ramps by averaging each 2x2 block, i.e. sited at
+        // the center of the block.
+        let ramp = |x: usize, y: usize| (400 + x * 4 + y * 2) as u16;
+        let full: Frame<u16> = Frame {
+            planes: [0usize, 1, 2].map(|i| {
+                let mut plane = Plane::new(64, 48, 0, 0, 0, 0);
+                for (y, row) in plane.rows_iter_mut().enumerate() {
+                    for (x, pix) in row[..64].iter_mut().enumerate() {
+                        *pix = if i == 0 { 502 } else { ramp(x, y) };
+                    }
+                }
+                plane
+            }),
+        };
+        let sub: Frame<u16> = Frame {
+            planes: [0usize, 1, 2].map(|i| {
+                if i == 0 {
+                    return full.planes[0].clone();
+                }
+                let mut plane = Plane::new(32, 24, 1, 1, 0, 0);
+                for (y, row) in plane.rows_iter_mut().enumerate() {
+                    for (x, pix) in row[..32].iter_mut().enumerate() {
+                        let sum: u16 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                            .iter()
+                            .map(|&(dx, dy)| ramp(2 * x + dx, 2 * y + dy))
+                            .sum();
+                        *pix = sum / 4;
+                    }
+                }
+                plane
+            }),
+        };
+
+        let expected = LinearRgb::try_from(FrameRef::new(&full, ten_bit(0, 0)).unwrap()).unwrap();
+        let error = |siting: Option<ChromaSiting>| {
+            let mut frame = FrameRef::new(&sub, ten_bit(1, 1)).unwrap();
+            if let Some(siting) = siting {
+                frame = frame.with_chroma_siting(siting);
+            }
+            assert_eq!(frame.chroma_siting(), siting);
+            let linear = LinearRgb::try_from(frame).unwrap();
+            // Away from the borders, where chroma cannot be interpolated.
+            linear
+                .data()
+                .iter()
+                .zip(expected.data())
+                .enumerate()
+                .filter(|&(i, _)| {
+                    let (x, y) = (i % 64, i / 64);
+                    (2..62).contains(&x) && (2..46).contains(&y)
+                })
+                .flat_map(|(_, (a, b))| (0..3).map(move |c| (a[c] - b[c]).abs()))
+                .fold(0.0f32, f32::max)
+        };
+
+        let center = error(Some(ChromaSiting::Center));
+        assert!(center < 1e-3, "{center}");
+        for siting in [None, Some(ChromaSiting::Left), Some(ChromaSiting::TopLeft)] {
+            assert!(error(siting) > 4.0 * center.max(1e-4), "{siting:?}");
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use yuvxyb::LinearRgb;
+
+use crate::{DetailedScore, Progress, Ssimulacra2, Ssimulacra2Error};
+
+type FrameResult<R> = (usize, Result<R, Ssimulacra2Error>);
+
+/// Scores the frames of a video on a pool of worker threads.
+///
+/// Frame pairs are pulled from an iterator on the calling thread, so decoding
+/// stays sequential and runs ahead of the workers by up to `queue_depth`
+/// frames. Conversion to linear RGB and scoring happen on the workers, each of
+/// which owns a [`Ssimulacra2`] session. Scores are reported in the order in
+/// which the frames were produced, regardless of which worker finishes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePipeline {
+    threads: usize,
+    queue_depth: usize,
+    distortion_maps: bool,
+    companion_metrics: bool,
+}
+
+impl Default for FramePipeline {
+    /// Uses one worker per available CPU core.
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        FramePipeline {
+            threads,
+            queue_depth: 2 * threads,
+            distortion_maps: false,
+            companion_metrics: false,
+        }
+    }
+}
+
+impl FramePipeline {
+    /// Creates a pipeline with the given number of worker threads, which
+    /// decodes up to two frames per worker ahead.
+    #[must_use]
+    pub const fn new(threads: NonZeroUsize) -> Self {
+        FramePipeline {
+            threads: threads.get(),
+            queue_depth: 2 * threads.get(),
+            distortion_maps: false,
+            companion_metrics: false,
+        }
+    }
+
+    /// Sets the number of decoded frames which may wait for a free worker.
+    /// Higher values smooth out variations in decoding speed at the cost of memory.
+    #[must_use]
+    pub const fn with_queue_depth(self, queue_depth: NonZeroUsize) -> Self {
+        FramePipeline {
+            queue_depth: queue_depth.get(),
+            ..self
+        }
+    }
+
+    /// Sets whether to compute a [`DistortionMap`][crate::DistortionMap] for
+    /// every frame, which is returned in [`DetailedScore::map`].
+    #[must_use]
+    pub const fn with_distortion_maps(self, distortion_maps: bool) -> Self {
+        FramePipeline {
+            distortion_maps,
+            ..self
+        }
+    }
+
+    /// Sets whether to compute PSNR and SSIM for every frame, which are
+    /// returned in [`DetailedScore::companion`]. See
+    /// [`Ssimulacra2::with_companion_metrics`].
+    #[must_use]
+    pub const fn with_companion_metrics(self, companion_metrics: bool) -> Self {
+        FramePipeline {
+            companion_metrics,
+            ..self
+        }
+    }
+
+    /// Number of worker threads.
+    #[must_use]
+    pub const fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Maximum number of decoded frames waiting for a worker.
+    #[must_use]
+    pub const fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// The peak memory, in bytes, of scoring frames of the given width and
+    /// height with this pipeline, e.g. for planning how many pipelines fit on
+    /// a machine.
+    ///
+    /// Every worker needs the [`required_memory`][Ssimulacra2::required_memory]
+    /// of its session. With distortion maps, the maps of all frames being
+    /// scored or waiting to be reported in order are added. The frames waiting
+    /// in the queue are not counted, as their size depends on their type.
+    #[must_use]
+    pub fn required_memory(&self, width: usize, height: usize) -> usize {
+        let mut bytes = self.threads * Ssimulacra2::required_memory(width, height);
+        if self.distortion_maps {
+            let frames = self.threads + self.queue_depth;
+            bytes += frames * width * height * mem::size_of::<f32>();
+        }
+        bytes
+    }
+
+    /// Scores every `(frame, source, distorted)` item of `frames`, calling
+    /// `on_score` with the frame index and its score in the order of `frames`.
+    ///
+    /// # Errors
+    /// Returns the first error in the order of `frames`. No further scores are
+    /// reported after it and the remaining frames are not scored.
+    ///
+    /// # Panics
+    /// - If scoring panics on one of the worker threads
+    pub fn run<I, T, U, F>(&self, frames: I, on_score: F) -> Result<(), Ssimulacra2Error>
+    where
+        I: IntoIterator<Item = (usize, T, U)>,
+        T: Send,
+        U: Send,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+        F: FnMut(usize, DetailedScore),
+    {
+        let jobs = frames
+            .into_iter()
+            .map(|(frame, source, distorted)| (frame, (source, distorted)));
+        self.run_jobs(
+            jobs,
+            |session, (source, distorted)| self.score(session, source, distorted),
+            on_score,
+        )
+    }
+
+    /// Runs `work` on every `(frame, job)` item of `jobs` on the workers,
+    /// calling `on_result` with the frame index and the result of its job in
+    /// the order of `jobs`.
+    ///
+    /// Every worker passes its own `state` to `work`, which starts out as
+    /// [`Default`], e.g. the `Option<Ssimulacra2>` that [`score`][Self::score]
+    /// keeps its session in. This is what [`run`][Self::run] is built on, and
+    /// allows computing other per-frame metrics, such as
+    /// [`compute_frame_xpsnr`][crate::compute_frame_xpsnr], on the same workers.
+    ///
+    /// # Errors
+    /// Returns the first error in the order of `jobs`. No further results are
+    /// reported after it and the remaining jobs are not run.
+    ///
+    /// # Panics
+    /// - If `work` panics on one of the worker threads
+    pub fn run_jobs<I, J, S, R, W, F>(
+        &self,
+        jobs: I,
+        work: W,
+        mut on_result: F,
+    ) -> Result<(), Ssimulacra2Error>
+    where
+        I: IntoIterator<Item = (usize, J)>,
+        J: Send,
+        S: Default,
+        R: Send,
+        W: Fn(&mut S, J) -> Result<R, Ssimulacra2Error> + Sync,
+        F: FnMut(usize, R),
+    {
+        let (job_tx, job_rx) = mpsc::sync_channel::<(usize, usize, J)>(self.queue_depth);
+        let (result_tx, result_rx) = mpsc::channel::<(usize, FrameResult<R>)>();
+        let job_rx = Mutex::new(job_rx);
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let (job_rx, stop, work, result_tx) = (&job_rx, &stop, &work, result_tx.clone());
+                scope.spawn(move || {
+                    let mut state = S::default();
+                    while !stop.load(Ordering::Relaxed) {
+                        let job = job_rx.lock().expect("No worker panicked").recv();
+                        let Ok((seq, frame, job)) = job else {
+                            break;
+                        };
+                        let result = work(&mut state, job);
+                        if result_tx.send((seq, (frame, result))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(result_tx);
+
+            let mut reorder = Reorder::default();
+            let result = (|| {
+                for (seq, (frame, job)) in jobs.into_iter().enumerate() {
+                    for (seq, result) in result_rx.try_iter() {
+                        reorder.push(seq, result, &mut on_result)?;
+                    }
+                    if job_tx.send((seq, frame, job)).is_err() {
+                        break;
+                    }
+                }
+                drop(job_tx);
+
+                for (seq, result) in result_rx {
+                    reorder.push(seq, result, &mut on_result)?;
+                }
+                Ok(())
+            })();
+
+            stop.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    /// Scores a frame pair with the settings of this pipeline, in `session`,
+    /// which is created for the first frame and resizes itself if the
+    /// resolution changes. Use this in the `work` of [`run_jobs`][Self::run_jobs].
+    ///
+    /// # Errors
+    /// - If the source and distorted frame width and height do not match
+    /// - If the source or distorted frame cannot be converted to XYB successfully
+    /// - If the frames contain values for which no score can be computed
+    /// - If the frames are smaller than 8x8 pixels
+    pub fn score<T, U>(
+        &self,
+        session: &mut Option<Ssimulacra2>,
+        source: T,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        let Ok(source) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+
+        let session = if let Some(existing) = session.take() {
+            session.insert(existing)
+        } else {
+            let new = Ssimulacra2::new(source.width(), source.height())?;
+            session.insert(new.with_companion_metrics(self.companion_metrics))
+        };
+        if self.distortion_maps {
+            session.score_with_map(source, distorted)
+        } else {
+            session.score_detailed(source, distorted)
+        }
+    }
+
+    /// Like [`run`][Self::run], but also calls `on_progress` after every
+    /// scored frame, e.g. to update a progress bar. Pass the number of frames
+    /// in `frames` as `total_frames` to get an estimate of the remaining time.
+    ///
+    /// # Errors
+    /// Returns the first error in the order of `frames`. No further scores are
+    /// reported after it and the remaining frames are not scored.
+    ///
+    /// # Panics
+    /// - If scoring panics on one of the worker threads
+    pub fn run_with_progress<I, T, U, F, P>(
+        &self,
+        frames: I,
+        total_frames: Option<usize>,
+        mut on_score: F,
+        mut on_progress: P,
+    ) -> Result<(), Ssimulacra2Error>
+    where
+        I: IntoIterator<Item = (usize, T, U)>,
+        T: Send,
+        U: Send,
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+        F: FnMut(usize, DetailedScore),
+        P: FnMut(Progress),
+    {
+        let start = Instant::now();
+        let mut frames_done = 0;
+        let mut score_sum = 0.0f64;
+
+        self.run(frames, |frame, detailed| {
+            frames_done += 1;
+            score_sum += detailed.score;
+            on_score(frame, detailed);
+            on_progress(Progress {
+                frames_done,
+                total_frames,
+                elapsed: start.elapsed(),
+                mean_score: score_sum / frames_done as f64,
+            });
+        })
+    }
+}
+
+// Buffers results which arrive out of order until all frames before them are done.
+struct Reorder<R> {
+    next: usize,
+    pending: BTreeMap<usize, FrameResult<R>>,
+}
+
+impl<R> Default for Reorder<R> {
+    fn default() -> Self {
+        Reorder {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<R> Reorder<R> {
+    fn push<F: FnMut(usize, R)>(
+        &mut self,
+        seq: usize,
+        result: FrameResult<R>,
+        on_result: &mut F,
+    ) -> Result<(), Ssimulacra2Error> {
+        self.pending.insert(seq, result);
+        while let Some((frame, result)) = self.pending.remove(&self.next) {
+            self.next += 1;
+            on_result(frame, result?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_frame_ssimulacra2;
+
+    #[test]
+    #[cfg_attr(target_os = "wasi", ignore = "WASI has no threads")]
+    fn scores_in_order() {
+        let make = |offset: f32| {
+            LinearRgb::new(
+                (0..32usize * 24)
+                    .map(|i| {
+                        let v = ((i * 5) % 32) as f32 / 32.0f32;
+                        [v, (v + offset).min(1.0f32), 0.5f32]
+                    })
+                    .collect(),
+                32,
+                24,
+            )
+            .unwrap()
+        };
+        let offsets = [0.0f32, 0.02f32, 0.1f32, 0.05f32, 0.3f32, 0.01f32];
+
+        let mut scores = Vec::new();
+        FramePipeline::new(NonZeroUsize::new(3).unwrap())
+            .run(
+                offsets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &offset)| (i * 2, make(0.0f32), make(offset))),
+                |frame, detailed| scores.push((frame, detailed.score)),
+            )
+            .unwrap();
+
+        assert_eq!(scores.len(), offsets.len());
+        for (i, (&(frame, score), &offset)) in scores.iter().zip(offsets.iter()).enumerate() {
+            let expected = compute_frame_ssimulacra2(make(0.0f32), make(offset)).unwrap();
+            assert_eq!(frame, i * 2);
+            assert!((score - expected).abs() < f64::EPSILON);
+        }
+
+        let pipeline = FramePipeline::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(
+            pipeline.required_memory(32, 24),
+            3 * Ssimulacra2::required_memory(32, 24)
+        );
+        assert_eq!(
+            pipeline.with_distortion_maps(true).required_memory(32, 24),
+            pipeline.required_memory(32, 24) + 9 * 32 * 24 * 4
+        );
+
+        let tiny = LinearRgb::new(vec![[0.0f32; 3]; 16], 4, 4).unwrap();
+        let result = FramePipeline::default().run([(0, tiny.clone(), tiny)], |_, _| {});
+        assert_eq!(result, Err(Ssimulacra2Error::InvalidImageSize));
+    }
+}
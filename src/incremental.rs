@@ -0,0 +1,332 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::array;
+use core::ops::Range;
+
+use yuvxyb::LinearRgb;
+
+use crate::blur::Tile;
+use crate::channel::{compare_tiles, merge_tiles, ChannelSums};
+use crate::downscale::downscale_checked;
+use crate::{
+    linear_rgb_rows_to_xyb_image, linear_rgb_to_xyb_image, ImageF32, Msssim, MsssimScale,
+    Reference, Ssimulacra2Error,
+};
+
+/// A rectangle of pixels which changed since the last update of an
+/// [`IncrementalScore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DirtyRect {
+    /// The leftmost column of the rectangle.
+    pub x: usize,
+    /// The top row of the rectangle.
+    pub y: usize,
+    /// The number of columns of the rectangle.
+    pub width: usize,
+    /// The number of rows of the rectangle.
+    pub height: usize,
+}
+
+impl DirtyRect {
+    /// Whether the rectangle contains no pixels.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// The score of a distorted image against a [`Reference`] which is kept up to
+/// date while the image changes in small regions, e.g. while it is being
+/// edited in an image editor.
+///
+/// The distorted image is compared in tiles of rows at every scale, as with
+/// [`Ssimulacra2::with_tiling`][crate::Ssimulacra2::with_tiling]. An
+/// [update][Self::update] only converts the changed rows to XYB and compares
+/// the tiles whose blurred rows depend on them, including those within the
+/// radius of the blur, at every scale the change reaches. The score is
+/// exactly that of comparing the whole image with
+/// [`Reference::with_tiling`] enabled.
+///
+/// The XYB image of every scale and the downscaled images are kept, so this
+/// holds about twice the memory of a comparison.
+#[derive(Debug, Clone)]
+pub struct IncrementalScore {
+    reference: Reference,
+    scales: Vec<DistortedScale>,
+    msssim: Msssim,
+}
+
+// The distorted image at one scale.
+#[derive(Debug, Clone)]
+struct DistortedScale {
+    // The image in linear RGB, to tell which rows a change reaches, except
+    // for the first scale, which the caller passes in.
+    linear: Option<LinearRgb>,
+    xyb: ImageF32,
+    // The sums of the errors of every tile of every channel.
+    sums: [Vec<ChannelSums>; 3],
+}
+
+impl IncrementalScore {
+    /// Compares all of `distorted` against `reference`.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    pub fn new(reference: Reference, distorted: &LinearRgb) -> Result<Self, Ssimulacra2Error> {
+        check_size(&reference, distorted)?;
+        let distorted = reference.check_distorted(Cow::Borrowed(distorted))?;
+
+        let mut incremental = IncrementalScore {
+            scales: Vec::with_capacity(reference.num_scales()),
+            msssim: Msssim::default(),
+            reference,
+        };
+        let mut img = distorted;
+        for scale in 0..incremental.reference.num_scales() {
+            let (width, height) = incremental.reference.scale_size(scale);
+            let next = (scale + 1 < incremental.reference.num_scales())
+                .then(|| downscale_checked(incremental.reference.downscaler(), &img, Vec::new()));
+
+            let mut xyb = ImageF32::default();
+            linear_rgb_to_xyb_image(&img, &mut xyb, incremental.reference.cube_root());
+            let tiles = tiles(height);
+            let jobs: Vec<_> = (0..3usize)
+                .flat_map(|c| tiles.iter().map(move |tile| (c, tile.clone())))
+                .collect();
+            let sums = compare_tiles(
+                width,
+                incremental.reference.source_channels(scale),
+                [0usize, 1, 2].map(|c| xyb.plane(c)),
+                &jobs,
+            );
+            let sums = array::from_fn(|c| sums[c * tiles.len()..(c + 1) * tiles.len()].to_vec());
+            incremental.scales.push(DistortedScale {
+                linear: match img {
+                    Cow::Owned(img) => Some(img),
+                    Cow::Borrowed(_) => None,
+                },
+                xyb,
+                sums,
+            });
+
+            match next {
+                Some(next) => img = Cow::Owned(next),
+                None => break,
+            }
+        }
+
+        incremental.sum_scales()?;
+        Ok(incremental)
+    }
+
+    /// The current score of the distorted image.
+    #[must_use]
+    pub fn score(&self) -> f64 {
+        self.msssim.score()
+    }
+
+    /// The reference the distorted image is compared against.
+    #[must_use]
+    pub const fn reference(&self) -> &Reference {
+        &self.reference
+    }
+
+    /// Updates the score to that of `distorted`, which only differs from the
+    /// image of the last update, or of [`new`][Self::new], within `dirty`,
+    /// and returns it.
+    ///
+    /// Tiles span whole rows, so only the rows of `dirty` matter. The parts
+    /// of it outside the image are ignored. Validation, if enabled, checks
+    /// all of `distorted`.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    pub fn update(
+        &mut self,
+        distorted: &LinearRgb,
+        dirty: DirtyRect,
+    ) -> Result<f64, Ssimulacra2Error> {
+        check_size(&self.reference, distorted)?;
+        let distorted = self.reference.check_distorted(Cow::Borrowed(distorted))?;
+        if dirty.is_empty() || dirty.x >= distorted.width() {
+            return Ok(self.score());
+        }
+
+        let mut rows = dirty.y.min(distorted.height())
+            ..dirty.y.saturating_add(dirty.height).min(distorted.height());
+        for scale in 0..self.scales.len() {
+            if rows.is_empty() {
+                break;
+            }
+            // The image of this scale is taken out while it is downscaled
+            // into the next one, which replaces its image.
+            let img = self.scales[scale]
+                .linear
+                .take()
+                .map_or_else(|| Cow::Borrowed(&*distorted), Cow::Owned);
+            self.update_scale(scale, &img, rows.clone());
+
+            let next_rows = match self.scales.get_mut(scale + 1) {
+                Some(&mut DistortedScale {
+                    linear: Some(ref mut old),
+                    ..
+                }) => {
+                    let new = downscale_checked(self.reference.downscaler(), &img, Vec::new());
+                    let changed = changed_rows(old, &new);
+                    *old = new;
+                    changed
+                }
+                _ => 0..0,
+            };
+            if let Cow::Owned(img) = img {
+                self.scales[scale].linear = Some(img);
+            }
+            rows = next_rows;
+        }
+
+        self.sum_scales()?;
+        Ok(self.score())
+    }
+
+    // Converts `rows` of `img` to XYB and compares the tiles they reach.
+    fn update_scale(&mut self, scale: usize, img: &LinearRgb, rows: Range<usize>) {
+        let (width, height) = self.reference.scale_size(scale);
+        let distorted = &mut self.scales[scale];
+        linear_rgb_rows_to_xyb_image(
+            img,
+            &mut distorted.xyb,
+            rows.clone(),
+            self.reference.cube_root(),
+        );
+
+        let tiles: Vec<_> = tiles(height)
+            .into_iter()
+            .enumerate()
+            .filter(|tile| tile.1.window.start < rows.end && rows.start < tile.1.window.end)
+            .collect();
+        let jobs: Vec<_> = (0..3usize)
+            .flat_map(|c| tiles.iter().map(move |tile| (c, tile.1.clone())))
+            .collect();
+        let sums = compare_tiles(
+            width,
+            self.reference.source_channels(scale),
+            [0usize, 1, 2].map(|c| distorted.xyb.plane(c)),
+            &jobs,
+        );
+        for (i, sums) in sums.into_iter().enumerate() {
+            let (index, _) = tiles[i % tiles.len()];
+            distorted.sums[i / tiles.len()][index] = sums;
+        }
+    }
+
+    // Adds up the sums of the tiles of every scale.
+    fn sum_scales(&mut self) -> Result<(), Ssimulacra2Error> {
+        self.msssim.scales.clear();
+        for (scale, distorted) in self.scales.iter().enumerate() {
+            let (width, height) = self.reference.scale_size(scale);
+            let errors = array::from_fn(|c| merge_tiles(&distorted.sums[c], width * height));
+            self.msssim.scales.push(MsssimScale::from_channels(&errors));
+        }
+
+        // Infinite inputs turn into NaN errors, which would otherwise be
+        // reported as a perfect score.
+        if !self.msssim.is_finite() {
+            return Err(Ssimulacra2Error::NonFiniteScore);
+        }
+        Ok(())
+    }
+}
+
+const fn check_size(reference: &Reference, distorted: &LinearRgb) -> Result<(), Ssimulacra2Error> {
+    if distorted.width() != reference.width() || distorted.height() != reference.height() {
+        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+    }
+    Ok(())
+}
+
+// The tiles of a scale of `height` rows, as compared by `Reference::with_tiling`.
+fn tiles(height: usize) -> Vec<Tile> {
+    if Tile::is_tiled(height, true) {
+        (0..Tile::count(height))
+            .map(|index| Tile::new(index, height))
+            .collect()
+    } else {
+        alloc::vec![Tile::whole(height)]
+    }
+}
+
+// The rows in which the images of the same size differ, from the first to the
+// last one.
+fn changed_rows(old: &LinearRgb, new: &LinearRgb) -> Range<usize> {
+    let width = new.width();
+    let differs = |y: &usize| {
+        let row = y * width..(y + 1) * width;
+        let old: &[u32] = bytemuck::cast_slice(&old.data()[row.clone()]);
+        let new: &[u32] = bytemuck::cast_slice(&new.data()[row]);
+        old != new
+    };
+    let Some(first) = (0..new.height()).find(differs) else {
+        return 0..0;
+    };
+    let last = (first..new.height()).rev().find(differs).unwrap_or(first);
+    first..last + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+
+    #[test]
+    fn updates_match_full_comparisons() {
+        // Three tiles at the first scale, one at the others.
+        let (width, height) = (24usize, 600usize);
+        let source = make_image(width, height, 3);
+        let reference = Reference::new(source).unwrap().with_tiling(true);
+        let mut distorted = make_image(width, height, 5);
+        let mut incremental = IncrementalScore::new(reference.clone(), &distorted).unwrap();
+        assert_eq!(
+            incremental.score().to_bits(),
+            reference.compare_borrowed(&distorted).unwrap().to_bits()
+        );
+
+        // Strokes within a tile, across tiles and at the bottom edge.
+        let mut data = distorted.data().to_vec();
+        for (i, dirty) in [(5, 300, 4, 3), (0, 250, 24, 20), (20, 590, 10, 20)]
+            .into_iter()
+            .enumerate()
+        {
+            let (x, y, w, h) = dirty;
+            for row in y..(y + h).min(height) {
+                for pix in &mut data[row * width + x..row * width + (x + w).min(width)] {
+                    *pix = [0.1f32 * i as f32; 3];
+                }
+            }
+            distorted = LinearRgb::new(data.clone(), width, height).unwrap();
+            let dirty = DirtyRect {
+                x,
+                y,
+                width: w,
+                height: h,
+            };
+            let score = incremental.update(&distorted, dirty).unwrap();
+            assert_eq!(
+                score.to_bits(),
+                reference.compare_borrowed(&distorted).unwrap().to_bits()
+            );
+        }
+
+        assert_eq!(
+            incremental.update(&distorted, DirtyRect::default()),
+            Ok(incremental.score())
+        );
+        assert_eq!(
+            incremental.update(&make_image(8, 8, 1), DirtyRect::default()),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+    }
+}
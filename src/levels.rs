@@ -0,0 +1,167 @@
+use alloc::vec::Vec;
+
+use wide::f32x8;
+use yuvxyb::LinearRgb;
+
+use crate::companion::srgb_encode;
+use crate::TransferFunction;
+
+// Width and height of the blocks whose mean values the levels are fitted to.
+// Averaging first keeps noise and other fine distortion of the distorted image
+// from pulling the gain towards zero.
+const BLOCK_SIZE: usize = 8;
+
+/// The global gain and offset by which the levels of the distorted image were
+/// matched to those of the source, see
+/// [`Ssimulacra2::with_level_normalization`][crate::Ssimulacra2::with_level_normalization].
+///
+/// They apply to the sRGB-encoded values in `0.0..=1.0` of all three channels:
+/// each normalized value is `value * gain + offset`. A gain of 1.0 and an
+/// offset of 0.0 mean that the levels already matched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelFit {
+    /// The factor by which the contrast of the distorted image was changed.
+    pub gain: f64,
+    /// The value added to the brightness of the distorted image after the
+    /// gain was applied.
+    pub offset: f64,
+}
+
+impl LevelFit {
+    // Fits the gain and offset which map the block means of `distorted` to
+    // those of `source` with the least squared error.
+    pub(crate) fn fit(source: &LinearRgb, distorted: &LinearRgb) -> Self {
+        let source = block_means(source);
+        let distorted = block_means(distorted);
+        let count = source.len() as f64;
+        let mean_source = source.iter().sum::<f64>() / count;
+        let mean_distorted = distorted.iter().sum::<f64>() / count;
+        let mut covariance = 0.0f64;
+        let mut variance = 0.0f64;
+        for (&s, &d) in source.iter().zip(&distorted) {
+            covariance = (d - mean_distorted).mul_add(s - mean_source, covariance);
+            variance = (d - mean_distorted).mul_add(d - mean_distorted, variance);
+        }
+        // A flat image only determines the offset.
+        let gain = if variance > 1e-12f64 {
+            covariance / variance
+        } else {
+            1.0f64
+        };
+        LevelFit {
+            gain,
+            offset: gain.mul_add(-mean_distorted, mean_source),
+        }
+    }
+
+    // Applies the gain and offset to the sRGB-encoded values of `img`,
+    // clamping them to `0.0..=1.0`.
+    pub(crate) fn apply(self, img: &mut LinearRgb) {
+        let gain = f32x8::splat(self.gain as f32);
+        let offset = f32x8::splat(self.offset as f32);
+        for pixels in img.data_mut().chunks_mut(8) {
+            let mut channels = [[0.0f32; 8]; 3];
+            for (c, channel) in channels.iter_mut().enumerate() {
+                for (value, pix) in channel.iter_mut().zip(pixels.iter()) {
+                    *value = pix[c];
+                }
+                let encoded = srgb_encode(f32x8::from(*channel));
+                *channel = encoded
+                    .mul_add(gain, offset)
+                    .max(f32x8::ZERO)
+                    .min(f32x8::ONE)
+                    .to_array();
+                TransferFunction::Srgb.to_linear(channel);
+            }
+            for (i, pix) in pixels.iter_mut().enumerate() {
+                *pix = channels.map(|channel| channel[i]);
+            }
+        }
+    }
+}
+
+// The mean sRGB-encoded value of every channel of every block of `img`,
+// including the partial blocks at the right and bottom edges.
+fn block_means(img: &LinearRgb) -> Vec<f64> {
+    let width = img.width();
+    let columns = (width + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    let mut means = Vec::new();
+    for rows in img.data().chunks(BLOCK_SIZE * width) {
+        let mut sums = alloc::vec![[0.0f64; 3]; columns];
+        for row in rows.chunks_exact(width) {
+            for (x, pixels) in row.chunks(8).enumerate() {
+                for c in 0..3 {
+                    let mut channel = [0.0f32; 8];
+                    for (value, pix) in channel.iter_mut().zip(pixels) {
+                        *value = pix[c];
+                    }
+                    let encoded = srgb_encode(f32x8::from(channel));
+                    for (i, &value) in encoded.as_array_ref()[..pixels.len()].iter().enumerate() {
+                        sums[(x * 8 + i) / BLOCK_SIZE][c] += f64::from(value);
+                    }
+                }
+            }
+        }
+        let block_height = rows.len() / width;
+        for (column, sums) in sums.iter().enumerate() {
+            let block_width = BLOCK_SIZE.min(width - column * BLOCK_SIZE);
+            let pixels = (block_width * block_height) as f64;
+            means.extend(sums.iter().map(|sum| sum / pixels));
+        }
+    }
+    means
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ssimulacra2;
+
+    // A colorful pattern, with the levels of its sRGB-encoded values changed
+    // by `gain` and `offset`.
+    fn image(gain: f32, offset: f32) -> LinearRgb {
+        let data = (0..61usize * 45)
+            .map(|i| {
+                let (x, y) = (i % 61, i / 61);
+                let mut pix = [
+                    (x * 3 % 61) as f32 / 61.0f32,
+                    (y * 5 % 45) as f32 / 45.0f32,
+                    ((x + y) % 17) as f32 / 17.0f32,
+                ];
+                for value in &mut pix {
+                    *value = value.mul_add(0.8f32, 0.1f32).mul_add(gain, offset);
+                }
+                TransferFunction::Srgb.to_linear(&mut pix);
+                pix
+            })
+            .collect();
+        LinearRgb::new(data, 61, 45).unwrap()
+    }
+
+    #[test]
+    fn recovers_levels() {
+        let fit = LevelFit::fit(&image(1.0, 0.0), &image(0.9, 0.03));
+        assert!((fit.gain - 1.0f64 / 0.9f64).abs() < 1e-3f64, "{fit:?}");
+        assert!((fit.offset + 0.03f64 / 0.9f64).abs() < 1e-3f64, "{fit:?}");
+
+        let mut session = Ssimulacra2::new(61, 45).unwrap();
+        let plain = session
+            .score_detailed(image(1.0, 0.0), image(0.9, 0.03))
+            .unwrap();
+        assert!(plain.levels.is_none());
+        let mut session = session.with_level_normalization(true);
+        let normalized = session
+            .score_detailed(image(1.0, 0.0), image(0.9, 0.03))
+            .unwrap();
+        assert_eq!(normalized.levels, Some(fit));
+        assert!(normalized.score > 99.0f64, "{}", normalized.score);
+        assert!(plain.score < normalized.score - 5.0f64, "{}", plain.score);
+
+        let identical = session
+            .score_detailed(image(1.0, 0.0), image(1.0, 0.0))
+            .unwrap();
+        let fit = identical.levels.unwrap();
+        assert!((fit.gain - 1.0f64).abs() < 1e-6f64 && fit.offset.abs() < 1e-6f64);
+    }
+}
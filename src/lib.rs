@@ -1,3 +1,5 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
 #![deny(clippy::all)]
 #![warn(clippy::nursery)]
 #![warn(clippy::pedantic)]
@@ -22,6 +24,7 @@
 #![warn(clippy::filetype_is_file)]
 #![warn(clippy::float_cmp_const)]
 #![warn(clippy::if_then_some_else_none)]
+#![warn(clippy::implicit_clone)]
 #![warn(clippy::lossy_float_literal)]
 #![warn(clippy::map_err_ignore)]
 #![warn(clippy::mem_forget)]
@@ -32,28 +35,163 @@
 #![warn(clippy::rest_pat_in_fully_bound_structs)]
 #![warn(clippy::same_name_method)]
 #![warn(clippy::str_to_string)]
-#![warn(clippy::string_to_string)]
 #![warn(clippy::undocumented_unsafe_blocks)]
 #![warn(clippy::unnecessary_self_imports)]
 #![warn(clippy::unneeded_field_pattern)]
 #![warn(clippy::use_debug)]
 #![warn(clippy::verbose_file_reads)]
 
+extern crate alloc;
+
+mod aligned;
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "av_metrics")]
+pub mod av_metrics;
 mod blur;
+mod builder;
+#[cfg(feature = "std")]
+mod cache;
+mod cancel;
+mod channel;
+mod companion;
+mod composite;
+mod crop;
+mod denoise;
+mod downscale;
+#[cfg(feature = "dump")]
+mod dump;
+#[cfg(feature = "experimental")]
+pub mod experimental;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(target_feature = "fma")
+))]
+mod fma;
+#[cfg(feature = "v_frame")]
+mod frame;
+#[cfg(feature = "std")]
+mod frame_pipeline;
+mod hash;
+mod incremental;
+mod levels;
+mod map;
+pub mod pipeline;
+mod planar;
+#[cfg(feature = "gstreamer")]
+pub mod plugin;
+mod pool;
+mod prefetch;
+mod preprocess;
+mod progress;
+mod quality;
+mod reference;
+#[cfg(feature = "report")]
+mod report;
+mod resize;
+mod scene;
+mod score;
+mod search;
+mod selection;
+mod session;
+mod shift;
+mod source;
+mod srgb8;
+mod stats;
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+mod sve;
+mod temporal;
+#[cfg(test)]
+mod test_images;
+mod transfer;
+mod viewing;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod xpsnr;
+mod xyb;
+#[cfg(feature = "y4m")]
+mod y4m_source;
 
+pub use aligned::{AlignedPlane, PLANE_ALIGNMENT};
+#[cfg(feature = "ndarray")]
+pub use array::ArrayImage;
+#[cfg(feature = "tokio")]
+pub use asynchronous::{score_async, AsyncSsimulacra2};
 pub use blur::Blur;
+pub use builder::Ssimulacra2Builder;
+#[cfg(feature = "std")]
+pub use cache::{CacheKey, DiskScoreCache, MemoryScoreCache, ScoreCache};
+pub use cancel::CancellationToken;
+pub use companion::CompanionMetrics;
+pub use composite::{ComponentScore, CompositeMetric, CompositeScore, Metric};
+pub use crop::Crop;
+pub use denoise::Denoise;
+pub use downscale::{BoxDownscaler, Downscaler};
+#[cfg(feature = "dump")]
+pub use dump::{dump_planes, plane_checksums, PlaneChecksum};
+#[cfg(feature = "v_frame")]
+pub use frame::{ChromaSiting, FrameRef};
+#[cfg(feature = "std")]
+pub use frame_pipeline::FramePipeline;
+pub use hash::PlaneHasher;
+pub use incremental::{DirtyRect, IncrementalScore};
+pub use levels::LevelFit;
+pub use map::{Clip, ColorRamp, DistortionMap, Heatmap};
+pub use planar::ImageF32;
+pub use pool::{PoolStats, ScratchPool};
+pub use preprocess::Preprocess;
+pub use progress::Progress;
+pub use quality::{approximate_jnd, QualityBand};
+pub use reference::Reference;
+#[cfg(feature = "report")]
+pub use report::Report;
+pub use resize::{Rescale, ResizeFilter};
+pub use scene::{LumaHistogram, SceneDetector};
+pub use score::DetailedScore;
+pub use search::{QualitySearch, SearchResult};
+pub use selection::FrameSelection;
+pub use session::{Settings, Ssimulacra2};
+pub use shift::{detect_shift, Shift, ShiftCheck};
+pub use source::{FrameSource, Frames};
+pub use srgb8::Srgb8Image;
+pub use stats::{FrameScore, SceneSummary, ScoreAggregator, ScoreSummary, TemporalPooling};
+pub use temporal::{TemporalScore, TemporalSession};
+pub use transfer::TransferFunction;
+pub use viewing::Viewing;
+pub use xpsnr::{compute_frame_xpsnr, XpsnrScore};
+pub use xyb::CubeRoot;
+#[cfg(feature = "y4m")]
+pub use y4m_source::Y4mSource;
 pub use yuvxyb::{CastFromPrimitive, Frame, LinearRgb, Pixel, Plane, Rgb, Xyb, Yuv};
 pub use yuvxyb::{ColorPrimaries, MatrixCoefficients, TransferCharacteristic, YuvConfig};
 
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
 // How often to downscale and score the input images.
 // Each scaling step will downscale by a factor of two.
 const NUM_SCALES: usize = 6;
 
+// Planes of fewer values, i.e. of images smaller than 128x128 pixels, are
+// blurred and compared on the calling thread. Handing them to the thread pool
+// takes longer than processing them, which dominates scoring thumbnails.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_VALUES: usize = 128 * 128;
+
 /// Errors which can occur when attempting to calculate a SSIMULACRA2 score from two input images.
+///
+/// New variants may be added in minor releases, so matches on it need a
+/// wildcard arm.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
 pub enum Ssimulacra2Error {
-    /// The conversion from input image to [LinearRgb] (via [TryFrom]) returned an [Err].
-    /// Note that the conversion from LinearRgb to [Xyb] cannot fail, which means that
+    /// The conversion from input image to [`LinearRgb`] (via [`TryFrom`]) returned an [Err].
+    /// Note that the conversion from `LinearRgb` to [Xyb] cannot fail, which means that
     /// this is the only point of failure regarding image conversion.
     #[error("Failed to convert input image to linear RGB")]
     LinearRgbConversionFailed,
@@ -63,9 +201,67 @@ pub enum Ssimulacra2Error {
     NonMatchingImageDimensions,
 
     /// One of the input images has a width and/or height of less than 8 pixels.
-    /// This is not currently supported by the SSIMULACRA2 metric.
+    ///
+    /// The metric compares images at up to six scales, each halving the
+    /// previous one while that is at least 8x8 pixels. For smaller images, the
+    /// reference implementation compares no scale at all and reports a
+    /// perfect score regardless of the distortion, so they are rejected instead.
     #[error("Images must be at least 8x8 pixels")]
     InvalidImageSize,
+
+    /// The color configuration supplied for a borrowed frame does not match its planes,
+    /// e.g. because of a different chroma subsampling or an unsupported bit depth.
+    #[error("Frame configuration does not match the frame data")]
+    InvalidFrameConfig,
+
+    /// An array passed as an image has neither a channels-first (`3×H×W`)
+    /// nor a channels-last (`H×W×3`) shape.
+    #[error("Array must have a shape of 3xHxW or HxWx3")]
+    InvalidArrayShape,
+
+    /// A buffer passed as an image does not have the length implied by its width and height.
+    #[error("Image buffer length does not match the image width and height")]
+    InvalidBufferLength,
+
+    /// The errors of the images are not finite, so no score can be computed.
+    /// This happens if an input image contains infinite or NaN values.
+    #[error("Images contain values for which no score can be computed")]
+    NonFiniteScore,
+
+    /// An input image contains a NaN or infinite value in the given channel
+    /// of its linear RGB conversion. Only reported when input validation is
+    /// enabled, see [`Ssimulacra2::with_validation`].
+    #[error(
+        "Non-finite value in channel {channel} of the {} image at ({x}, {y})",
+        if *.distorted { "distorted" } else { "source" }
+    )]
+    NonFiniteInput {
+        /// Whether the value is in the distorted image instead of the source.
+        distorted: bool,
+        /// The channel of the value: 0 for red, 1 for green and 2 for blue.
+        channel: usize,
+        /// The column of the pixel.
+        x: usize,
+        /// The row of the pixel.
+        y: usize,
+    },
+
+    /// The comparison was aborted through a [`CancellationToken`].
+    #[error("The comparison was cancelled")]
+    Cancelled,
+
+    /// A [`CompositeMetric`] without any component was computed.
+    #[error("A composite metric needs at least one component")]
+    EmptyComposite,
+
+    /// The thread pool requested with [`Ssimulacra2Builder::with_threads`]
+    /// could not be created.
+    #[error("Failed to create the thread pool")]
+    ThreadPoolCreationFailed,
+
+    /// A [`QualitySearch`] was run over an empty range of quality settings.
+    #[error("The range of quality settings to search is empty")]
+    InvalidSearchRange,
 }
 
 /// Computes the SSIMULACRA2 score for a given input frame and the distorted
@@ -74,269 +270,268 @@ pub enum Ssimulacra2Error {
 /// # Errors
 /// - If the source and distorted image width and height do not match
 /// - If the source or distorted image cannot be converted to XYB successfully
+/// - If the images contain values for which no score can be computed
 /// - If the image is smaller than 8x8 pixels
 pub fn compute_frame_ssimulacra2<T, U>(source: T, distorted: U) -> Result<f64, Ssimulacra2Error>
 where
     LinearRgb: TryFrom<T> + TryFrom<U>,
 {
-    let Ok(mut img1) = LinearRgb::try_from(source) else {
-        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
-    };
-
-    let Ok(mut img2) = LinearRgb::try_from(distorted) else {
-        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
-    };
-
-    if img1.width() != img2.width() || img1.height() != img2.height() {
-        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
-    }
-
-    if img1.width() < 8 || img1.height() < 8 {
-        return Err(Ssimulacra2Error::InvalidImageSize);
-    }
-
-    let mut width = img1.width();
-    let mut height = img1.height();
-
-    let mut mul = [
-        vec![0.0f32; width * height],
-        vec![0.0f32; width * height],
-        vec![0.0f32; width * height],
-    ];
-    let mut blur = Blur::new(width, height);
-    let mut msssim = Msssim::default();
-
-    for scale in 0..NUM_SCALES {
-        if width < 8 || height < 8 {
-            break;
-        }
-
-        if scale > 0 {
-            img1 = downscale_by_2(&img1);
-            img2 = downscale_by_2(&img2);
-            width = img1.width();
-            height = img2.height();
-        }
-        for c in &mut mul {
-            c.truncate(width * height);
-        }
-        blur.shrink_to(width, height);
-
-        let mut img1 = Xyb::from(img1.clone());
-        let mut img2 = Xyb::from(img2.clone());
-
-        make_positive_xyb(&mut img1);
-        make_positive_xyb(&mut img2);
-
-        // SSIMULACRA2 works with the data in a planar format,
-        // so we need to convert to that.
-        let img1 = xyb_to_planar(&img1);
-        let img2 = xyb_to_planar(&img2);
-
-        image_multiply(&img1, &img1, &mut mul);
-        let sigma1_sq = blur.blur(&mul);
+    let reference = Reference::new(source)?;
+    reference.compare(distorted)
+}
 
-        image_multiply(&img2, &img2, &mut mul);
-        let sigma2_sq = blur.blur(&mul);
+/// Computes the SSIMULACRA2 score for a given input frame and the distorted
+/// version of that frame, together with a [`DistortionMap`] showing where the
+/// distortion is located.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to XYB successfully
+/// - If the images contain values for which no score can be computed
+/// - If the image is smaller than 8x8 pixels
+pub fn compute_frame_ssimulacra2_map<T, U>(
+    source: T,
+    distorted: U,
+) -> Result<(f64, DistortionMap), Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let reference = Reference::new(source)?;
+    reference.compare_with_map(distorted)
+}
 
-        image_multiply(&img1, &img2, &mut mul);
-        let sigma12 = blur.blur(&mul);
+// Returns an error for the first NaN or infinite value in `img`.
+fn check_finite(img: &LinearRgb, distorted: bool) -> Result<(), Ssimulacra2Error> {
+    let non_finite = img.data().iter().enumerate().find_map(|(i, pix)| {
+        let channel = pix.iter().position(|value| !value.is_finite())?;
+        Some((i, channel))
+    });
+    let Some((i, channel)) = non_finite else {
+        return Ok(());
+    };
 
-        let mu1 = blur.blur(&img1);
-        let mu2 = blur.blur(&img2);
+    Err(Ssimulacra2Error::NonFiniteInput {
+        distorted,
+        channel,
+        x: i % img.width(),
+        y: i / img.width(),
+    })
+}
 
-        let avg_ssim = ssim_map(width, height, &mu1, &mu2, &sigma1_sq, &sigma2_sq, &sigma12);
-        let avg_edgediff = edge_diff_map(width, height, &img1, &mu1, &img2, &mu2);
-        msssim.scales.push(MsssimScale {
-            avg_ssim,
-            avg_edgediff,
-        });
+// Converts `img` to XYB and shifts it to positive values, splitting it into
+// `planes` as SSIMULACRA2 works with planar data.
+fn linear_rgb_to_planar_xyb(img: &LinearRgb, planes: &mut [Vec<f32>; 3]) {
+    for plane in planes.iter_mut() {
+        plane.resize(img.data().len(), 0.0f32);
     }
+    let [ref mut x, ref mut y, ref mut b] = *planes;
+    xyb::linear_rgb_to_positive_xyb(img.data(), [x, y, b], CubeRoot::Exact);
+}
 
-    Ok(msssim.score())
+// Like `linear_rgb_to_planar_xyb`, but writes the planes to `out`, which is
+// resized to the image, and computes the cube roots with `cube_root`.
+fn linear_rgb_to_xyb_image(img: &LinearRgb, out: &mut ImageF32, cube_root: CubeRoot) {
+    out.reset(img.width(), img.height(), 3);
+    xyb::linear_rgb_to_positive_xyb(img.data(), out.split_planes_mut(), cube_root);
 }
 
-// Get all components in more or less 0..1 range
-// Range of Rec2020 with these adjustments:
-//  X: 0.017223..0.998838
-//  Y: 0.010000..0.855303
-//  B: 0.048759..0.989551
-// Range of sRGB:
-//  X: 0.204594..0.813402
-//  Y: 0.010000..0.855308
-//  B: 0.272295..0.938012
-// The maximum pixel-wise difference has to be <= 1 for the ssim formula to make
-// sense.
-fn make_positive_xyb(xyb: &mut Xyb) {
-    for pix in xyb.data_mut().iter_mut() {
-        pix[2] = (pix[2] - pix[1]) + 0.55;
-        pix[0] = (pix[0]).mul_add(14.0, 0.42);
-        pix[1] += 0.01;
-    }
+// Converts the pixels of `rows` of `img` into `out`, which already holds the
+// XYB image of `img`, giving the same values as converting all of it with
+// `linear_rgb_to_xyb_image`.
+fn linear_rgb_rows_to_xyb_image(
+    img: &LinearRgb,
+    out: &mut ImageF32,
+    rows: core::ops::Range<usize>,
+    cube_root: CubeRoot,
+) {
+    let width = img.width();
+    xyb::linear_rgb_to_positive_xyb_range(
+        img.data(),
+        out.split_planes_mut(),
+        rows.start * width..rows.end * width,
+        cube_root,
+    );
 }
 
-fn xyb_to_planar(xyb: &Xyb) -> [Vec<f32>; 3] {
-    let mut out1 = vec![0.0f32; xyb.width() * xyb.height()];
-    let mut out2 = vec![0.0f32; xyb.width() * xyb.height()];
-    let mut out3 = vec![0.0f32; xyb.width() * xyb.height()];
-    for (((i, o1), o2), o3) in xyb
-        .data()
-        .iter()
-        .copied()
-        .zip(out1.iter_mut())
-        .zip(out2.iter_mut())
-        .zip(out3.iter_mut())
-    {
-        *o1 = i[0];
-        *o2 = i[1];
-        *o3 = i[2];
-    }
+#[inline(always)]
+fn ssim_error(mu1: f32, mu2: f32, s11: f32, s22: f32, s12: f32) -> f64 {
+    const C2: f32 = 0.0009f32;
 
-    [out1, out2, out3]
+    let mu11 = mu1 * mu1;
+    let mu22 = mu2 * mu2;
+    let mu12 = mu1 * mu2;
+    let mu_diff = mu1 - mu2;
+
+    // Correction applied compared to the original SSIM formula, which has:
+    //   luma_err = 2 * mu1 * mu2 / (mu1^2 + mu2^2)
+    //            = 1 - (mu1 - mu2)^2 / (mu1^2 + mu2^2)
+    // The denominator causes error in the darks (low mu1 and mu2) to weigh
+    // more than error in the brights (high mu1 and mu2). This would make
+    // sense if values correspond to linear luma. However, the actual values
+    // are either gamma-compressed luma (which supposedly is already
+    // perceptually uniform) or chroma (where weighing green more than red
+    // or blue more than yellow does not make any sense at all). So it is
+    // better to simply drop this denominator.
+    let num_m = mu_diff.mul_add(-mu_diff, 1.0f32);
+    let num_s = 2f32.mul_add(s12 - mu12, C2);
+    let denom_s = (s11 - mu11) + (s22 - mu22) + C2;
+    // Use 1 - SSIM' so it becomes an error score instead of a quality
+    // index. This makes it make sense to compute an L_4 norm.
+    let d = 1.0f64 - f64::from((num_m * num_s) / denom_s);
+    d.max(0.0)
 }
 
-fn image_multiply(img1: &[Vec<f32>; 3], img2: &[Vec<f32>; 3], out: &mut [Vec<f32>; 3]) {
-    for ((plane1, plane2), out_plane) in img1.iter().zip(img2.iter()).zip(out.iter_mut()) {
-        for ((&p1, &p2), o) in plane1.iter().zip(plane2.iter()).zip(out_plane.iter_mut()) {
-            *o = p1 * p2;
-        }
-    }
+#[inline(always)]
+fn edge_diff(p1: f32, mu1: f32, p2: f32, mu2: f32) -> f64 {
+    (1.0 + f64::from((p2 - mu2).abs())) / (1.0 + f64::from((p1 - mu1).abs())) - 1.0
 }
 
-fn downscale_by_2(in_data: &LinearRgb) -> LinearRgb {
-    const SCALE: usize = 2;
-    let in_w = in_data.width();
-    let in_h = in_data.height();
-    let out_w = (in_w + SCALE - 1) / SCALE;
-    let out_h = (in_h + SCALE - 1) / SCALE;
-    let mut out_data = vec![[0.0f32; 3]; out_w * out_h];
-    let normalize = 1f32 / (SCALE * SCALE) as f32;
-
-    let in_data = &in_data.data();
-    for oy in 0..out_h {
-        for ox in 0..out_w {
-            for c in 0..3 {
-                let mut sum = 0f32;
-                for iy in 0..SCALE {
-                    for ix in 0..SCALE {
-                        let x = (ox * SCALE + ix).min(in_w - 1);
-                        let y = (oy * SCALE + iy).min(in_h - 1);
-                        let in_pix = in_data[y * in_w + x];
-
-                        sum += in_pix[c];
-                    }
-                }
-                let out_pix = &mut out_data[oy * out_w + ox];
-                out_pix[c] = sum * normalize;
-            }
-        }
-    }
+// See `Msssim::score` for how these weights were obtained.
+const WEIGHT: [f64; 108] = [
+    0.0,
+    0.000_737_660_670_740_658_6,
+    0.0,
+    0.0,
+    0.000_779_348_168_286_730_9,
+    0.0,
+    0.0,
+    0.000_437_115_573_010_737_9,
+    0.0,
+    1.104_172_642_665_734_6,
+    0.000_662_848_341_292_71,
+    0.000_152_316_327_837_187_52,
+    0.0,
+    0.001_640_643_745_659_975_4,
+    0.0,
+    1.842_245_552_053_929_8,
+    11.441_172_603_757_666,
+    0.0,
+    0.000_798_910_943_601_516_3,
+    0.000_176_816_438_078_653,
+    0.0,
+    1.878_759_497_954_638_7,
+    10.949_069_906_051_42,
+    0.0,
+    0.000_728_934_699_150_807_2,
+    0.967_793_708_062_683_3,
+    0.0,
+    0.000_140_034_242_854_358_84,
+    0.998_176_697_785_496_7,
+    0.000_319_497_559_344_350_53,
+    0.000_455_099_211_379_206_3,
+    0.0,
+    0.0,
+    0.001_364_876_616_324_339_8,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    7.466_890_328_078_848,
+    0.0,
+    17.445_833_984_131_262,
+    0.000_623_560_163_404_146_6,
+    0.0,
+    0.0,
+    6.683_678_146_179_332,
+    0.000_377_244_079_796_112_96,
+    1.027_889_937_768_264,
+    225.205_153_008_492_74,
+    0.0,
+    0.0,
+    19.213_238_186_143_016,
+    0.001_140_152_458_661_836_1,
+    0.001_237_755_635_509_985,
+    176.393_175_984_506_94,
+    0.0,
+    0.0,
+    24.433_009_998_704_76,
+    0.285_208_026_121_177_57,
+    0.000_448_543_692_383_340_8,
+    0.0,
+    0.0,
+    0.0,
+    34.779_063_444_837_72,
+    44.835_625_328_877_896,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.000_868_055_657_329_169_8,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.000_531_319_187_435_874_7,
+    0.0,
+    0.000_165_338_141_613_791_12,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.000_417_917_180_325_133_6,
+    0.001_729_082_823_472_283_3,
+    0.0,
+    0.002_082_700_584_663_643_7,
+    0.0,
+    0.0,
+    8.826_982_764_996_862,
+    23.192_433_439_989_26,
+    0.0,
+    95.108_049_881_108_6,
+    0.986_397_803_440_068_2,
+    0.983_438_279_246_535_3,
+    0.001_228_640_504_827_849_3,
+    171.266_725_589_730_7,
+    0.980_785_887_243_537_9,
+    0.0,
+    0.0,
+    0.0,
+    0.000_513_006_458_899_067_9,
+    0.0,
+    0.000_108_540_578_584_115_37,
+];
 
-    LinearRgb::new(out_data, out_w, out_h).expect("Resolution and data size match")
+// Index into `WEIGHT` for the given channel, scale, norm (0 = 1-norm, 1 = 4-norm)
+// and error map (0 = SSIM, 1 = ringing, 2 = blurring), when `num_scales` scales
+// were scored in total.
+const fn weight_index(c: usize, scale: usize, norm: usize, map: usize, num_scales: usize) -> usize {
+    ((c * num_scales + scale) * 2 + norm) * 3 + map
 }
 
-fn ssim_map(
-    width: usize,
-    height: usize,
-    m1: &[Vec<f32>; 3],
-    m2: &[Vec<f32>; 3],
-    s11: &[Vec<f32>; 3],
-    s22: &[Vec<f32>; 3],
-    s12: &[Vec<f32>; 3],
-) -> [f64; 3 * 2] {
-    const C2: f32 = 0.0009f32;
+// Maps the scaled sum of the weighted sub-scores, as returned by
+// `Msssim::scale_errors` and `Msssim::plane_errors`, to the 0..100 range of the
+// final score.
+pub(crate) fn error_to_score(error: f64) -> f64 {
+    let ssim = (6.248_496_625_763_138e-5 * error * error).mul_add(
+        error,
+        2.326_765_642_916_932f64.mul_add(error, -0.020_884_521_182_843_837 * error * error),
+    );
 
-    let one_per_pixels = 1.0f64 / (width * height) as f64;
-    let mut plane_averages = [0f64; 3 * 2];
-
-    for c in 0..3 {
-        let mut sum1 = [0.0f64; 2];
-        for (row_m1, (row_m2, (row_s11, (row_s22, row_s12)))) in m1[c].chunks_exact(width).zip(
-            m2[c].chunks_exact(width).zip(
-                s11[c]
-                    .chunks_exact(width)
-                    .zip(s22[c].chunks_exact(width).zip(s12[c].chunks_exact(width))),
-            ),
-        ) {
-            for x in 0..width {
-                let mu1 = row_m1[x];
-                let mu2 = row_m2[x];
-                let mu11 = mu1 * mu1;
-                let mu22 = mu2 * mu2;
-                let mu12 = mu1 * mu2;
-                let mu_diff = mu1 - mu2;
-
-                // Correction applied compared to the original SSIM formula, which has:
-                //   luma_err = 2 * mu1 * mu2 / (mu1^2 + mu2^2)
-                //            = 1 - (mu1 - mu2)^2 / (mu1^2 + mu2^2)
-                // The denominator causes error in the darks (low mu1 and mu2) to weigh
-                // more than error in the brights (high mu1 and mu2). This would make
-                // sense if values correspond to linear luma. However, the actual values
-                // are either gamma-compressed luma (which supposedly is already
-                // perceptually uniform) or chroma (where weighing green more than red
-                // or blue more than yellow does not make any sense at all). So it is
-                // better to simply drop this denominator.
-                let num_m = mu_diff.mul_add(-mu_diff, 1.0f32);
-                let num_s = 2f32.mul_add(row_s12[x] - mu12, C2);
-                let denom_s = (row_s11[x] - mu11) + (row_s22[x] - mu22) + C2;
-                // Use 1 - SSIM' so it becomes an error score instead of a quality
-                // index. This makes it make sense to compute an L_4 norm.
-                let mut d = 1.0f64 - f64::from((num_m * num_s) / denom_s);
-                d = d.max(0.0);
-                sum1[0] += d;
-                sum1[1] += d.powi(4);
-            }
-        }
-        plane_averages[c * 2] = one_per_pixels * sum1[0];
-        plane_averages[c * 2 + 1] = (one_per_pixels * sum1[1]).sqrt().sqrt();
+    if ssim > 0.0f64 {
+        ssim.powf(0.627_633_646_783_138_7)
+            .mul_add(-10.0f64, 100.0f64)
+    } else {
+        100.0f64
     }
-
-    plane_averages
 }
 
-fn edge_diff_map(
-    width: usize,
-    height: usize,
-    img1: &[Vec<f32>; 3],
-    mu1: &[Vec<f32>; 3],
-    img2: &[Vec<f32>; 3],
-    mu2: &[Vec<f32>; 3],
-) -> [f64; 3 * 4] {
-    let one_per_pixels = 1.0f64 / (width * height) as f64;
-    let mut plane_averages = [0f64; 3 * 4];
-
-    for c in 0..3 {
-        let mut sum1 = [0.0f64; 4];
-        for (row1, (row2, (rowm1, rowm2))) in img1[c].chunks_exact(width).zip(
-            img2[c]
-                .chunks_exact(width)
-                .zip(mu1[c].chunks_exact(width).zip(mu2[c].chunks_exact(width))),
-        ) {
-            for x in 0..width {
-                let d1: f64 = (1.0 + f64::from((row2[x] - rowm2[x]).abs()))
-                    / (1.0 + f64::from((row1[x] - rowm1[x]).abs()))
-                    - 1.0;
-
-                // d1 > 0: distorted has an edge where original is smooth
-                //         (indicating ringing, color banding, blockiness, etc)
-                let artifact = d1.max(0.0);
-                sum1[0] += artifact;
-                sum1[1] += artifact.powi(4);
-
-                // d1 < 0: original has an edge where distorted is smooth
-                //         (indicating smoothing, blurring, smearing, etc)
-                let detail_lost = (-d1).max(0.0);
-                sum1[2] += detail_lost;
-                sum1[3] += detail_lost.powi(4);
-            }
-        }
-        plane_averages[c * 4] = one_per_pixels * sum1[0];
-        plane_averages[c * 4 + 1] = (one_per_pixels * sum1[1]).sqrt().sqrt();
-        plane_averages[c * 4 + 2] = one_per_pixels * sum1[2];
-        plane_averages[c * 4 + 3] = (one_per_pixels * sum1[3]).sqrt().sqrt();
-    }
+// Raw errors around the inflection point of the polynomial of
+// `error_to_score`, where it is so flat that rounding can map a larger error
+// to a slightly higher score. Elsewhere, larger errors give lower scores.
+pub(crate) const FLAT_ERRORS: RangeInclusive<f64> = 105.0f64..=118.0f64;
 
-    plane_averages
+// Whether a partial raw `error` rules out reaching `target`, however much
+// error is still to be added to it. This is only decided outside of
+// `FLAT_ERRORS`.
+pub(crate) fn misses_target(error: f64, target: f64) -> bool {
+    !FLAT_ERRORS.contains(&error) && error_to_score(error) < target
 }
 
 #[derive(Debug, Clone, Default)]
@@ -350,7 +545,29 @@ struct MsssimScale {
     pub avg_edgediff: [f64; 3 * 4],
 }
 
+impl MsssimScale {
+    // The errors of a scale from those of its three XYB channels.
+    fn from_channels(errors: &[channel::ChannelErrors; 3]) -> Self {
+        let mut scale = MsssimScale::default();
+        for (c, errors) in errors.iter().enumerate() {
+            scale.avg_ssim[c * 2..(c + 1) * 2].copy_from_slice(&errors.ssim);
+            scale.avg_edgediff[c * 4..(c + 1) * 4].copy_from_slice(&errors.edge_diff);
+        }
+        scale
+    }
+}
+
 impl Msssim {
+    fn is_finite(&self) -> bool {
+        self.scales.iter().all(|scale| {
+            scale
+                .avg_ssim
+                .iter()
+                .chain(&scale.avg_edgediff)
+                .all(|err| err.is_finite())
+        })
+    }
+
     // The final score is based on a weighted sum of 108 sub-scores:
     // - for 6 scales (1:1 to 1:32)
     // - for 6 scales (1:1 to 1:32, downsampled in linear RGB)
@@ -381,119 +598,12 @@ impl Msssim {
     //    TID2013:   0.6590 | 0.8445 | 0.8471
     //    KADID-10k: 0.6175 | 0.8133 | 0.8030
     //    KonFiG(F): 0.7668 | 0.9194 | 0.9136
-    #[allow(clippy::too_many_lines)]
     pub fn score(&self) -> f64 {
-        const WEIGHT: [f64; 108] = [
-            0.0,
-            0.000_737_660_670_740_658_6,
-            0.0,
-            0.0,
-            0.000_779_348_168_286_730_9,
-            0.0,
-            0.0,
-            0.000_437_115_573_010_737_9,
-            0.0,
-            1.104_172_642_665_734_6,
-            0.000_662_848_341_292_71,
-            0.000_152_316_327_837_187_52,
-            0.0,
-            0.001_640_643_745_659_975_4,
-            0.0,
-            1.842_245_552_053_929_8,
-            11.441_172_603_757_666,
-            0.0,
-            0.000_798_910_943_601_516_3,
-            0.000_176_816_438_078_653,
-            0.0,
-            1.878_759_497_954_638_7,
-            10.949_069_906_051_42,
-            0.0,
-            0.000_728_934_699_150_807_2,
-            0.967_793_708_062_683_3,
-            0.0,
-            0.000_140_034_242_854_358_84,
-            0.998_176_697_785_496_7,
-            0.000_319_497_559_344_350_53,
-            0.000_455_099_211_379_206_3,
-            0.0,
-            0.0,
-            0.001_364_876_616_324_339_8,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            7.466_890_328_078_848,
-            0.0,
-            17.445_833_984_131_262,
-            0.000_623_560_163_404_146_6,
-            0.0,
-            0.0,
-            6.683_678_146_179_332,
-            0.000_377_244_079_796_112_96,
-            1.027_889_937_768_264,
-            225.205_153_008_492_74,
-            0.0,
-            0.0,
-            19.213_238_186_143_016,
-            0.001_140_152_458_661_836_1,
-            0.001_237_755_635_509_985,
-            176.393_175_984_506_94,
-            0.0,
-            0.0,
-            24.433_009_998_704_76,
-            0.285_208_026_121_177_57,
-            0.000_448_543_692_383_340_8,
-            0.0,
-            0.0,
-            0.0,
-            34.779_063_444_837_72,
-            44.835_625_328_877_896,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.000_868_055_657_329_169_8,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.000_531_319_187_435_874_7,
-            0.0,
-            0.000_165_338_141_613_791_12,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            0.000_417_917_180_325_133_6,
-            0.001_729_082_823_472_283_3,
-            0.0,
-            0.002_082_700_584_663_643_7,
-            0.0,
-            0.0,
-            8.826_982_764_996_862,
-            23.192_433_439_989_26,
-            0.0,
-            95.108_049_881_108_6,
-            0.986_397_803_440_068_2,
-            0.983_438_279_246_535_3,
-            0.001_228_640_504_827_849_3,
-            171.266_725_589_730_7,
-            0.980_785_887_243_537_9,
-            0.0,
-            0.0,
-            0.0,
-            0.000_513_006_458_899_067_9,
-            0.0,
-            0.000_108_540_578_584_115_37,
-        ];
+        error_to_score(self.error())
+    }
 
+    // The raw error which `score` maps to the 0..100 range.
+    pub fn error(&self) -> f64 {
         let mut ssim = 0.0f64;
 
         let mut i = 0usize;
@@ -510,21 +620,53 @@ impl Msssim {
             }
         }
 
-        ssim *= 0.956_238_261_683_484_4_f64;
-        ssim = (6.248_496_625_763_138e-5 * ssim * ssim).mul_add(
-            ssim,
-            2.326_765_642_916_932f64.mul_add(ssim, -0.020_884_521_182_843_837 * ssim * ssim),
-        );
+        ssim * 0.956_238_261_683_484_4_f64
+    }
 
-        if ssim > 0.0f64 {
-            ssim = ssim
-                .powf(0.627_633_646_783_138_7)
-                .mul_add(-10.0f64, 100.0f64);
-        } else {
-            ssim = 100.0f64;
-        }
+    // The weighted sum of the sub-scores of each scale, including the final
+    // scaling factor. Summing them gives the raw error that `score` maps to
+    // the 0..100 range.
+    pub fn scale_errors(&self) -> Vec<f64> {
+        let num_scales = self.scales.len();
+        self.scales
+            .iter()
+            .enumerate()
+            .map(|(s, scale)| {
+                let mut error = 0.0f64;
+                for c in 0..3 {
+                    for n in 0..2 {
+                        error = WEIGHT[weight_index(c, s, n, 0, num_scales)]
+                            .mul_add(scale.avg_ssim[c * 2 + n].abs(), error);
+                        error = WEIGHT[weight_index(c, s, n, 1, num_scales)]
+                            .mul_add(scale.avg_edgediff[c * 4 + n].abs(), error);
+                        error = WEIGHT[weight_index(c, s, n, 2, num_scales)]
+                            .mul_add(scale.avg_edgediff[c * 4 + n + 2].abs(), error);
+                    }
+                }
+                error * 0.956_238_261_683_484_4_f64
+            })
+            .collect()
+    }
 
-        ssim
+    // The weighted sum of the sub-scores of each of the X, Y and B components
+    // over all scales, including the final scaling factor. Like the scale
+    // errors, they sum to the raw error that `score` maps to the 0..100 range.
+    pub fn plane_errors(&self) -> [f64; 3] {
+        let num_scales = self.scales.len();
+        [0, 1, 2].map(|c| {
+            let mut error = 0.0f64;
+            for (s, scale) in self.scales.iter().enumerate() {
+                for n in 0..2 {
+                    error = WEIGHT[weight_index(c, s, n, 0, num_scales)]
+                        .mul_add(scale.avg_ssim[c * 2 + n].abs(), error);
+                    error = WEIGHT[weight_index(c, s, n, 1, num_scales)]
+                        .mul_add(scale.avg_edgediff[c * 4 + n].abs(), error);
+                    error = WEIGHT[weight_index(c, s, n, 2, num_scales)]
+                        .mul_add(scale.avg_edgediff[c * 4 + n + 2].abs(), error);
+                }
+            }
+            error * 0.956_238_261_683_484_4_f64
+        })
     }
 }
 
@@ -533,6 +675,7 @@ mod tests {
     use std::path::PathBuf;
 
     use super::*;
+    use crate::test_images::ramp_image;
     use yuvxyb::Rgb;
 
     #[test]
@@ -589,5 +732,328 @@ mod tests {
             (result - expected).abs() < 0.25f64,
             "Result {result:.6} not equal to expected {expected:.6}",
         );
+        // Score with the scalar XYB conversion of `yuvxyb`, which bounds the
+        // error of the vectorized conversion.
+        let scalar = 17.392_219_f64;
+        assert!(
+            (result - scalar).abs() < 2e-3f64,
+            "Result {result:.6} too far from scalar result {scalar:.6}",
+        );
+    }
+
+    #[test]
+    fn test_distortion_map() {
+        let source = LinearRgb::new(
+            (0..64usize * 48)
+                .map(|i| {
+                    let v = (i % 64) as f32 / 64.0f32;
+                    [v, 1.0 - v, 0.5]
+                })
+                .collect(),
+            64,
+            48,
+        )
+        .unwrap();
+        let mut distorted = source.clone();
+        for pix in &mut distorted.data_mut()[64 * 8..64 * 10] {
+            pix[1] = 0.0;
+        }
+
+        let (score, map) = compute_frame_ssimulacra2_map(source.clone(), distorted).unwrap();
+        assert!(score < 100.0f64);
+        assert_eq!((map.width(), map.height()), (64, 48));
+        assert!(map.get(10, 40).unwrap() < map.get(8, 8).unwrap());
+
+        let pixels = map.to_rgb8(ColorRamp::Grayscale, map.max_value());
+        assert_eq!(pixels.len(), 64 * 48);
+        assert!(pixels[40 * 64 + 10][0] < pixels[8 * 64 + 8][0]);
+        assert!(pixels.contains(&[255, 255, 255]));
+        assert_eq!(ColorRamp::Heat.color(0.5f32), [255, 128, 0]);
+
+        let (score, map) = compute_frame_ssimulacra2_map(source.clone(), source).unwrap();
+        assert!((score - 100.0f64).abs() < 0.01f64);
+        assert!(map.data().iter().all(|&v| v.abs() < 1e-3f32));
+    }
+
+    #[test]
+    fn test_small_images() {
+        let sizes =
+            (0..=64usize).flat_map(|w| [0usize, 1, 7, 8, 9, 15, 16, 33, 64].map(|h| (w, h)));
+        for (w, h) in sizes.clone().chain(sizes.map(|(w, h)| (h, w))) {
+            let image = |seed: usize| {
+                let data = (0..w * h)
+                    .map(|i| [0usize, 1, 2].map(|c| ((i * seed + c * 7) % 13) as f32 / 13.0f32))
+                    .collect();
+                LinearRgb::new(data, w, h).unwrap()
+            };
+
+            if w < 8 || h < 8 {
+                assert_eq!(
+                    Reference::new(image(3)).err(),
+                    Some(Ssimulacra2Error::InvalidImageSize),
+                    "{w}x{h}"
+                );
+                assert_eq!(
+                    Ssimulacra2::new(w, h).err(),
+                    Some(Ssimulacra2Error::InvalidImageSize),
+                    "{w}x{h}"
+                );
+                continue;
+            }
+
+            let mut expected_scales = 1usize;
+            let (mut sw, mut sh) = (w, h);
+            while expected_scales < NUM_SCALES && sw >= 8 && sh >= 8 {
+                sw = (sw + 1) / 2;
+                sh = (sh + 1) / 2;
+                expected_scales += 1;
+            }
+            let detailed = Reference::new(image(3))
+                .unwrap()
+                .compare_detailed(image(5))
+                .unwrap();
+            assert_eq!(detailed.scales.len(), expected_scales, "{w}x{h}");
+            assert!(
+                detailed.score.is_finite() && detailed.score < 100.0f64,
+                "{w}x{h}: {}",
+                detailed.score
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_finite_input() {
+        let source = LinearRgb::new(vec![[0.5f32; 3]; 16 * 16], 16, 16).unwrap();
+        let mut distorted = source.clone();
+        let mut session = Ssimulacra2::new(16, 16).unwrap().with_validation(false);
+        // Negative values, including negative infinity, are clamped to zero
+        // like any other out of gamut value.
+        for value in [f32::INFINITY, f32::NAN] {
+            distorted.data_mut()[17] = [value; 3];
+            assert_eq!(
+                session.score(source.clone(), distorted.clone()).err(),
+                Some(Ssimulacra2Error::NonFiniteScore)
+            );
+            assert_eq!(
+                session.score(distorted.clone(), source.clone()).err(),
+                Some(Ssimulacra2Error::NonFiniteScore)
+            );
+        }
+        // `f32::MAX` converts to finite XYB values, as with `yuvxyb`, which
+        // give a finite if meaningless score.
+        distorted.data_mut()[17] = [f32::MAX; 3];
+        assert!(session.score(source.clone(), distorted.clone()).is_ok());
+        assert!(session.score(distorted.clone(), source.clone()).is_ok());
+
+        let mut session = session.with_validation(true);
+        distorted.data_mut()[17] = [0.5f32, f32::NAN, 0.5f32];
+        let err = session
+            .score(source.clone(), distorted.clone())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Ssimulacra2Error::NonFiniteInput {
+                distorted: true,
+                channel: 1,
+                x: 1,
+                y: 1
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Non-finite value in channel 1 of the distorted image at (1, 1)"
+        );
+        assert_eq!(
+            session.score(distorted, source).err(),
+            Some(Ssimulacra2Error::NonFiniteInput {
+                distorted: false,
+                channel: 1,
+                x: 1,
+                y: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_reference_reuse() {
+        let make = |offset: f32| ramp_image(40, 36, offset);
+
+        let reference = Reference::new(make(0.0f32)).unwrap();
+        for offset in [0.01f32, 0.05f32, 0.2f32] {
+            let expected = compute_frame_ssimulacra2(make(0.0f32), make(offset)).unwrap();
+            let result = reference.compare(make(offset)).unwrap();
+            assert!(
+                (result - expected).abs() < f64::EPSILON,
+                "Result {result:.6} not equal to expected {expected:.6}",
+            );
+        }
+
+        assert_eq!(
+            reference.compare(LinearRgb::new(vec![[0.0f32; 3]; 36 * 40], 36, 40).unwrap()),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+    }
+    #[test]
+    fn test_reference_compare_many() {
+        let make = |offset: f32| ramp_image(40, 36, offset);
+
+        let reference = Reference::new(make(0.0f32)).unwrap().with_validation(false);
+        let offsets = [0.01f32, 0.02f32, 0.05f32, 0.1f32, 0.2f32, 0.3f32];
+        let mut distorted: Vec<_> = offsets.iter().map(|&offset| make(offset)).collect();
+        distorted.insert(
+            2,
+            LinearRgb::new(vec![[0.0f32; 3]; 36 * 40], 36, 40).unwrap(),
+        );
+        let mut non_finite = make(0.0f32);
+        non_finite.data_mut()[17] = [f32::INFINITY; 3];
+        distorted.push(non_finite);
+
+        let hash = reference.content_hash();
+        let results = reference.compare_many(distorted);
+        assert_eq!(reference.content_hash(), hash);
+        assert_eq!(results.len(), offsets.len() + 2);
+        assert_eq!(
+            results[2],
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+        assert_eq!(results[7], Err(Ssimulacra2Error::NonFiniteScore));
+        let scores = results[..2].iter().chain(&results[3..7]);
+        for (result, &offset) in scores.zip(&offsets) {
+            // Bitwise the same as comparing one image at a time.
+            let expected = reference.compare(make(offset)).unwrap();
+            assert_eq!(result.unwrap().to_bits(), expected.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_reference_target() {
+        let make = |offset: f32| ramp_image(64, 48, offset);
+
+        // The largest offset scores about -64, with an error in
+        // `FLAT_ERRORS`, where only the full score tells whether the target
+        // is reached.
+        let reference = Reference::new(make(0.0f32)).unwrap();
+        for offset in [0.01f32, 0.1f32, 0.4f32] {
+            let score = reference.compare(make(offset)).unwrap();
+            // Bitwise the same score if the target is reached, even exactly.
+            let reached = reference.compare_with_target(make(offset), score).unwrap();
+            assert_eq!(reached.map(f64::to_bits), Some(score.to_bits()));
+            assert_eq!(
+                reference.compare_with_target(make(offset), score + 1e-9f64),
+                Ok(None)
+            );
+            assert_eq!(
+                reference.compare_with_target(make(offset), f64::NEG_INFINITY),
+                Ok(Some(score))
+            );
+        }
+    }
+
+    #[test]
+    fn test_flat_errors() {
+        // A larger error with a higher score, where the early exit of
+        // `compare_with_target` is not taken.
+        let (error, larger) = (111.41f64, 111.41f64 + 1e-8f64);
+        assert!(error_to_score(larger) > error_to_score(error));
+        assert!(FLAT_ERRORS.contains(&error));
+        assert!(!misses_target(error, error_to_score(larger)));
+
+        // Elsewhere, larger errors give lower scores.
+        let mut last = error_to_score(0.0f64);
+        for error in (1..300_000u32).map(|i| f64::from(i) * 1e-3f64) {
+            let score = error_to_score(error);
+            assert!(score <= last || FLAT_ERRORS.contains(&error));
+            last = score;
+        }
+        assert!(misses_target(100.0f64, error_to_score(100.0f64) + 1e-9f64));
+        assert!(misses_target(120.0f64, error_to_score(120.0f64) + 1e-9f64));
+        assert!(!misses_target(f64::NAN, 100.0f64));
+    }
+
+    #[test]
+    fn test_reference_bands() {
+        let source = LinearRgb::new(
+            (0..40usize * 100)
+                .map(|i| [((i * 7) % 40) as f32 / 40.0f32, 0.5f32, 0.25f32])
+                .collect(),
+            40,
+            100,
+        )
+        .unwrap();
+        // Only the rows of the third band of 32 rows are distorted.
+        let mut distorted = source.clone();
+        for pix in &mut distorted.data_mut()[70 * 40..80 * 40] {
+            pix[0] = 1.0f32 - pix[0];
+        }
+
+        let reference = Reference::new(source.clone()).unwrap();
+        let bands = reference.compare_bands(distorted, 32).unwrap();
+        assert_eq!(bands.len(), 4);
+        assert!(bands[2] < 90.0f64, "{bands:?}");
+        assert!(bands[0] > 99.0f64, "{bands:?}");
+        assert!(bands[2] < bands[1] && bands[2] < bands[3], "{bands:?}");
+
+        let identical = reference.compare_bands(source, 100).unwrap();
+        assert_eq!(identical.len(), 1);
+        assert!((identical[0] - 100.0f64).abs() < 1e-6f64, "{identical:?}");
+    }
+
+    #[test]
+    fn test_detailed_score() {
+        let source = LinearRgb::new(
+            (0..40usize * 36)
+                .map(|i| [((i * 7) % 40) as f32 / 40.0f32, 0.5f32, 0.25f32])
+                .collect(),
+            40,
+            36,
+        )
+        .unwrap();
+        let mut distorted = source.clone();
+        for pix in distorted.data_mut().iter_mut().step_by(3) {
+            pix[0] = 1.0f32 - pix[0];
+        }
+
+        let reference = Reference::new(source).unwrap();
+        let detailed = reference.compare_detailed(distorted.clone()).unwrap();
+        let score = reference.compare(distorted).unwrap();
+
+        assert!((detailed.score - score).abs() < f64::EPSILON);
+        // 40x36, 20x18, 10x9 and 5x4
+        assert_eq!(detailed.scales.len(), 4);
+        assert!(detailed.scales.iter().all(|&e| e > 0.0f64));
+        let scales: f64 = detailed.scales.iter().sum();
+        let planes: f64 = detailed.planes.iter().sum();
+        assert!((scales - planes).abs() < 1e-9f64);
+    }
+
+    #[test]
+    fn test_plane_errors() {
+        let source = LinearRgb::new(
+            (0..48usize * 40)
+                .map(|i| {
+                    let v = ((i * 7) % 48) as f32 / 48.0f32;
+                    [v, v, v]
+                })
+                .collect(),
+            48,
+            40,
+        )
+        .unwrap();
+        // Shifting the blue channel barely changes the luminance, while
+        // scaling all channels equally leaves the chromaticity unchanged.
+        let mut chroma = source.clone();
+        for pix in chroma.data_mut() {
+            pix[2] = (pix[2] + 0.3f32).min(1.0f32);
+        }
+        let mut luma = source.clone();
+        for pix in luma.data_mut() {
+            *pix = pix.map(|v| v * 0.7f32);
+        }
+
+        let reference = Reference::new(source).unwrap();
+        let chroma = reference.compare_detailed(chroma).unwrap().planes;
+        let luma = reference.compare_detailed(luma).unwrap().planes;
+        assert!(chroma[2] > chroma[1], "{chroma:?}");
+        assert!(luma[1] > luma[0] && luma[1] > luma[2], "{luma:?}");
     }
 }
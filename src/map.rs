@@ -0,0 +1,360 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{weight_index, WEIGHT};
+
+/// Per-pixel distortion of the distorted image, at the resolution of the inputs.
+///
+/// Each value is the pixel's weighted contribution to the 1-norm terms of the
+/// full-resolution scale, summed over the three XYB channels and the three
+/// error maps (SSIM, ringing and blurring). The mean of the map therefore equals
+/// the part of the (pre-transform) SSIMULACRA2 error that comes from those terms.
+///
+/// Higher values mean more visible distortion. A value of `0.0` means the pixel
+/// does not differ from the source at all. The values are not on the same scale
+/// as the final score, which is a nonlinear function of all 108 sub-scores.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DistortionMap {
+    data: Vec<f32>,
+    width: usize,
+    height: usize,
+}
+
+impl DistortionMap {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        DistortionMap {
+            data: vec![0.0f32; width * height],
+            width,
+            height,
+        }
+    }
+
+    /// Width of the map in pixels.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the map in pixels.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The map values in row-major order.
+    #[must_use]
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Consumes the map, returning the values in row-major order.
+    #[must_use]
+    pub fn into_data(self) -> Vec<f32> {
+        self.data
+    }
+
+    /// The value at the given position, or [None] if it is out of bounds.
+    #[must_use]
+    pub fn get(&self, x: usize, y: usize) -> Option<f32> {
+        (x < self.width && y < self.height).then(|| self.data[y * self.width + x])
+    }
+
+    /// The largest value of the map.
+    #[must_use]
+    pub fn max_value(&self) -> f32 {
+        self.data.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Renders the map as 8-bit RGB pixels in row-major order.
+    ///
+    /// A value of `0.0` maps to the start of the ramp and `max` to its end,
+    /// larger values are clamped. Pass [`max_value`][Self::max_value] to use
+    /// the full ramp for a single map, or the same value for every frame of a
+    /// video to make their heatmaps comparable.
+    #[must_use]
+    pub fn to_rgb8(&self, ramp: ColorRamp, max: f32) -> Vec<[u8; 3]> {
+        self.positions(0.0, max).map(|t| ramp.color(t)).collect()
+    }
+
+    /// Renders the map as 8-bit RGBA pixels in row-major order, as styled by
+    /// `heatmap`, e.g. for uploading to a texture of a GUI.
+    #[must_use]
+    pub fn to_rgba8(&self, heatmap: &Heatmap) -> Vec<[u8; 4]> {
+        let (min, max) = heatmap.clip.range(self);
+        self.positions(min, max).map(|t| heatmap.rgba(t)).collect()
+    }
+
+    /// Like [`to_rgba8`][Self::to_rgba8], but writes the pixels to `out`,
+    /// four bytes each, e.g. to reuse the buffer of a GUI for every frame.
+    ///
+    /// # Panics
+    /// - If `out` does not hold exactly four bytes per value of the map
+    pub fn render_rgba8_into(&self, heatmap: &Heatmap, out: &mut [u8]) {
+        assert_eq!(
+            out.len(),
+            4 * self.data.len(),
+            "Buffer size must match the map"
+        );
+        let (min, max) = heatmap.clip.range(self);
+        for (t, out) in self.positions(min, max).zip(out.chunks_exact_mut(4)) {
+            out.copy_from_slice(&heatmap.rgba(t));
+        }
+    }
+
+    // The positions on a color ramp from `min` at its start to `max` at its
+    // end of the values of the map, before clamping.
+    fn positions(&self, min: f32, max: f32) -> impl Iterator<Item = f32> + '_ {
+        let scale = if max > min { (max - min).recip() } else { 0.0 };
+        self.data.iter().map(move |&v| (v - min) * scale)
+    }
+
+    pub(crate) fn data_mut(&mut self) -> &mut [f32] {
+        &mut self.data
+    }
+
+    // The weights of the SSIM, ringing and blurring errors of channel `c` in
+    // the 1-norm of the first scale.
+    pub(crate) fn channel_weights(c: usize, num_scales: usize) -> [f64; 3] {
+        [0usize, 1, 2].map(|map| WEIGHT[weight_index(c, 0, 0, map, num_scales)])
+    }
+}
+
+// Checks that the data has the length implied by the width and height.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DistortionMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            data: Vec<f32>,
+            width: usize,
+            height: usize,
+        }
+
+        let Fields {
+            data,
+            width,
+            height,
+        } = Fields::deserialize(deserializer)?;
+        if data.len() != width * height {
+            return Err(serde::de::Error::custom(
+                "map data length does not match its width and height",
+            ));
+        }
+        Ok(DistortionMap {
+            data,
+            width,
+            height,
+        })
+    }
+}
+
+/// How a [`DistortionMap`] is rendered as a heatmap, see
+/// [`DistortionMap::to_rgba8`].
+///
+/// The command line tools render their heatmaps with the same code, so
+/// frontends that use it show the same colors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heatmap {
+    /// The colors from no distortion to the most distortion.
+    pub ramp: ColorRamp,
+    /// The values at the ends of the ramp.
+    pub clip: Clip,
+    /// Make pixels more transparent the less distorted they are, from fully
+    /// transparent at the start of the ramp to opaque at its end, for drawing
+    /// the heatmap over the image. Otherwise, all pixels are opaque.
+    pub overlay: bool,
+}
+
+impl Heatmap {
+    // The color of position `t` of the ramp.
+    fn rgba(&self, t: f32) -> [u8; 4] {
+        let [r, g, b] = self.ramp.color(t);
+        let alpha = if self.overlay {
+            (t.clamp(0.0, 1.0) * 255.0).round() as u8
+        } else {
+            u8::MAX
+        };
+        [r, g, b, alpha]
+    }
+}
+
+/// The values of a [`DistortionMap`] at the start and the end of the color
+/// ramp of a [`Heatmap`]. Values beyond them are clamped to the ends.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Clip {
+    /// From `0.0` to the [largest value][DistortionMap::max_value] of the map.
+    #[default]
+    Max,
+    /// From `0.0` to the given percentile of the values of the map, e.g.
+    /// `99.0`, so that a few outliers do not push all other values to the
+    /// start of the ramp.
+    Percentile(f32),
+    /// From `min` to `max`, e.g. the same for every frame of a video to make
+    /// their heatmaps comparable.
+    Range {
+        /// The value at the start of the ramp.
+        min: f32,
+        /// The value at the end of the ramp.
+        max: f32,
+    },
+}
+
+impl Clip {
+    /// The values at the start and the end of the ramp for `map`.
+    #[must_use]
+    pub fn range(self, map: &DistortionMap) -> (f32, f32) {
+        match self {
+            Clip::Max => (0.0, map.max_value()),
+            Clip::Percentile(percentile) => (0.0, percentile_value(map.data(), percentile)),
+            Clip::Range { min, max } => (min, max),
+        }
+    }
+}
+
+// The value below which `percentile` percent of `values` are, rounded to the
+// nearest value, or `0.0` if there are none.
+fn percentile_value(values: &[f32], percentile: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    let fraction = f64::from(percentile.clamp(0.0, 100.0)) / 100.0f64;
+    let index = (fraction * (sorted.len() - 1) as f64).round() as usize;
+    let (_, value, _) = sorted.select_nth_unstable_by(index, f32::total_cmp);
+    *value
+}
+
+/// Color ramps for rendering a [`DistortionMap`], going from no distortion to
+/// the most distortion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorRamp {
+    /// Black to white.
+    Grayscale,
+    /// Black through red and yellow to white.
+    #[default]
+    Heat,
+    /// Dark blue through green to yellow, which is perceptually uniform and
+    /// readable with color vision deficiencies.
+    Viridis,
+    /// Dark blue through cyan, green and yellow to dark red, a rainbow ramp
+    /// with smooth lightness that tells more levels apart than viridis.
+    Turbo,
+}
+
+impl ColorRamp {
+    /// The color at position `t` of the ramp, where `t` is clamped to `0.0..=1.0`.
+    #[must_use]
+    pub fn color(self, t: f32) -> [u8; 3] {
+        let stops: &[[f32; 3]] = match self {
+            ColorRamp::Grayscale => &GRAYSCALE,
+            ColorRamp::Heat => &HEAT,
+            ColorRamp::Viridis => &VIRIDIS,
+            ColorRamp::Turbo => &TURBO,
+        };
+
+        let pos = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (pos as usize).min(stops.len() - 2);
+        let fraction = pos - i as f32;
+        let (from, to) = (stops[i], stops[i + 1]);
+
+        [0, 1, 2].map(|c| ((to[c] - from[c]).mul_add(fraction, from[c]) * 255.0).round() as u8)
+    }
+}
+
+const GRAYSCALE: [[f32; 3]; 2] = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]];
+
+const HEAT: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [1.0, 1.0, 1.0],
+];
+
+// Sampled from matplotlib's viridis at steps of 1/8.
+const VIRIDIS: [[f32; 3]; 9] = [
+    [0.267_004, 0.004_874, 0.329_415],
+    [0.282_623, 0.140_926, 0.457_517],
+    [0.229_739, 0.322_361, 0.545_706],
+    [0.172_719, 0.448_791, 0.557_885],
+    [0.127_568, 0.566_949, 0.550_556],
+    [0.134_692, 0.658_636, 0.517_649],
+    [0.369_214, 0.788_888, 0.382_914],
+    [0.678_489, 0.863_742, 0.189_503],
+    [0.993_248, 0.906_157, 0.143_936],
+];
+
+// Sampled at steps of 1/16 from the polynomial approximation of Google's
+// turbo published by its author, clamped to `0.0..=1.0`.
+const TURBO: [[f32; 3]; 17] = [
+    [0.135_721, 0.091_403, 0.106_673],
+    [0.287_523, 0.244_062, 0.685_745],
+    [0.268_616, 0.414_773, 0.934_760],
+    [0.197_020, 0.585_511, 0.969_910],
+    [0.148_313, 0.740_465, 0.880_723],
+    [0.162_420, 0.866_364, 0.733_191],
+    [0.250_394, 0.952_795, 0.572_903],
+    [0.401_206, 0.992_534, 0.428_173],
+    [0.588_522, 0.981_864, 0.313_169],
+    [0.777_496, 0.920_903, 0.231_045],
+    [0.931_551, 0.813_924, 0.177_069],
+    [1.0, 0.669_681, 0.141_753],
+    [1.0, 0.501_735, 0.113_986],
+    [0.934_930, 0.328_772, 0.084_155],
+    [0.786_373, 0.174_933, 0.047_287],
+    [0.631_511, 0.070_133, 0.006_168],
+    [0.565_859, 0.050_389, 0.0],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_map(values: &[f32]) -> DistortionMap {
+        let mut map = DistortionMap::new(values.len(), 1);
+        map.data_mut().copy_from_slice(values);
+        map
+    }
+
+    #[test]
+    fn heatmaps() {
+        let map = make_map(&[0.0, 1.0, 2.0, 3.0, 100.0]);
+        let heatmap = Heatmap {
+            ramp: ColorRamp::Grayscale,
+            ..Heatmap::default()
+        };
+        let gray = |pixels: Vec<[u8; 4]>| pixels.iter().map(|p| p[0]).collect::<Vec<_>>();
+        assert_eq!(gray(map.to_rgba8(&heatmap)), [0, 3, 5, 8, 255]);
+        let rgb: Vec<_> = map
+            .to_rgba8(&heatmap)
+            .iter()
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        assert_eq!(rgb, map.to_rgb8(ColorRamp::Grayscale, 100.0));
+
+        // The outlier does not compress the ramp for the other values.
+        let percentile = Heatmap {
+            clip: Clip::Percentile(75.0),
+            ..heatmap
+        };
+        assert_eq!(Clip::Percentile(75.0).range(&map), (0.0, 3.0));
+        assert_eq!(gray(map.to_rgba8(&percentile)), [0, 85, 170, 255, 255]);
+
+        let range = Heatmap {
+            clip: Clip::Range { min: 1.0, max: 3.0 },
+            overlay: true,
+            ..heatmap
+        };
+        let mut out = vec![0u8; 4 * 5];
+        map.render_rgba8_into(&range, &mut out);
+        assert_eq!(
+            out,
+            [0, 0, 0, 0, 0, 0, 0, 0, 128, 128, 128, 128, 255, 255, 255, 255, 255, 255, 255, 255]
+        );
+
+        assert_eq!(ColorRamp::Turbo.color(0.0), [35, 23, 27]);
+        assert_eq!(ColorRamp::Turbo.color(0.5), [150, 250, 80]);
+    }
+}
@@ -0,0 +1,210 @@
+//! The stages of the metric as separate functions, for assembling variants of
+//! it, e.g. with a denoiser in front of the comparison or without some scales.
+//!
+//! [`Reference`][crate::Reference] and [`Ssimulacra2`][crate::Ssimulacra2]
+//! run these stages for up to six scales, starting at full resolution:
+//!
+//! 1. [`downscale`] the linear RGB image of the previous scale by two.
+//! 2. Convert it to XYB planes with [`to_xyb`].
+//! 3. Blur the source planes to their mean, and their squares, computed with
+//!    [`multiply`], to the mean of the squares, using a [`Blur`][crate::Blur]
+//!    of the size of the scale. [`Blur::blur_product`][crate::Blur::blur_product]
+//!    does both in one step.
+//! 4. Reduce the planes of both images to the error norms of the scale with
+//!    [`ScaleStatistics::compute`].
+//!
+//! [`score`] maps the statistics of all scales to the final score. Running
+//! the stages as above gives bitwise the same score as
+//! [`compute_frame_ssimulacra2`][crate::compute_frame_ssimulacra2].
+//!
+//! Planes are arrays of three `Vec<f32>` holding the X, Y and B components of
+//! an image row by row, without padding, so each has `width * height` values.
+
+use alloc::vec::Vec;
+
+use yuvxyb::LinearRgb;
+
+use crate::channel::{compare_channels, ChannelScratch, SourceChannel};
+use crate::{
+    linear_rgb_to_planar_xyb, BoxDownscaler, Downscaler, Msssim, MsssimScale, Ssimulacra2Error,
+    NUM_SCALES,
+};
+
+/// Halves the width and height of `image`, rounding up, by averaging blocks
+/// of 2x2 pixels in linear light, like the [`BoxDownscaler`].
+#[must_use]
+pub fn downscale(image: &LinearRgb) -> LinearRgb {
+    BoxDownscaler.downscale(image)
+}
+
+/// Like [`downscale`], but writes the result into `buffer`, which is resized
+/// to the `((width + 1) / 2) * ((height + 1) / 2)` pixels of the result.
+#[must_use]
+pub fn downscale_into(image: &LinearRgb, buffer: Vec<[f32; 3]>) -> LinearRgb {
+    BoxDownscaler.downscale_into(image, buffer)
+}
+
+/// Converts `image` to XYB, shifted to positive values the way SSIMULACRA2
+/// compares them, and writes the components to `planes`.
+///
+/// The planes are resized to the pixels of the image. Returns the pixel
+/// buffer of `image` for reuse, e.g. with [`downscale_into`].
+pub fn to_xyb(image: LinearRgb, planes: &mut [Vec<f32>; 3]) -> Vec<[f32; 3]> {
+    linear_rgb_to_planar_xyb(&image, planes);
+    image.into_data()
+}
+
+/// Multiplies the planes of two images element-wise into `out`, which is
+/// resized to the length of the planes. Like [`Blur`][crate::Blur], this
+/// takes any number of planes.
+///
+/// # Panics
+/// - If planes of `a` and `b` differ in length
+pub fn multiply<const N: usize>(a: &[Vec<f32>; N], b: &[Vec<f32>; N], out: &mut [Vec<f32>; N]) {
+    for ((a, b), out) in a.iter().zip(b).zip(out.iter_mut()) {
+        assert_eq!(a.len(), b.len(), "Planes must have the same length");
+        out.clear();
+        out.extend(a.iter().zip(b).map(|(a, b)| a * b));
+    }
+}
+
+/// The error norms of one scale, for each of the X, Y and B components.
+///
+/// Each norm is a pair of the 1-norm and the 4-norm over all pixels of the
+/// scale. These are the 18 sub-scores of a scale that [`score`] weighs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScaleStatistics {
+    /// Norms of the SSIM error, without the luminance term of SSIM.
+    pub ssim: [[f64; 2]; 3],
+    /// Norms of the edges the distorted image has where the source is smooth,
+    /// e.g. from ringing, banding or blocking.
+    pub artifact: [[f64; 2]; 3],
+    /// Norms of the edges of the source that are smoothed out in the
+    /// distorted image, e.g. from blurring.
+    pub detail_lost: [[f64; 2]; 3],
+}
+
+impl ScaleStatistics {
+    /// Compares the XYB planes of a scale of the distorted image to those of
+    /// the source, given together with the blurred source (`source_mu`) and
+    /// blurred squares of the source (`source_sigma_sq`). The blurred planes
+    /// of the distorted image are computed row by row along the way.
+    ///
+    /// All planes must have `width * height` values.
+    ///
+    /// # Panics
+    /// - If a plane does not have `width * height` values
+    #[must_use]
+    pub fn compute(
+        width: usize,
+        height: usize,
+        source: &[Vec<f32>; 3],
+        source_mu: &[Vec<f32>; 3],
+        source_sigma_sq: &[Vec<f32>; 3],
+        distorted: &[Vec<f32>; 3],
+    ) -> Self {
+        for planes in [source, source_mu, source_sigma_sq, distorted] {
+            assert!(
+                planes.iter().all(|plane| plane.len() == width * height),
+                "Planes must have width * height values"
+            );
+        }
+
+        let source = [0usize, 1, 2].map(|c| SourceChannel {
+            img: &source[c],
+            mu: &source_mu[c],
+            sigma_sq: &source_sigma_sq[c],
+        });
+        let mut scratch: [ChannelScratch; 3] = Default::default();
+        let distorted = [0usize, 1, 2].map(|c| distorted[c].as_slice());
+        let errors = compare_channels(width, height, source, distorted, &mut scratch, None, false);
+        ScaleStatistics {
+            ssim: errors.map(|errors| errors.ssim),
+            artifact: errors.map(|errors| [errors.edge_diff[0], errors.edge_diff[1]]),
+            detail_lost: errors.map(|errors| [errors.edge_diff[2], errors.edge_diff[3]]),
+        }
+    }
+}
+
+/// Weighs the statistics of up to six scales, starting at full resolution,
+/// and maps them to a score like that of
+/// [`compute_frame_ssimulacra2`][crate::compute_frame_ssimulacra2].
+///
+/// The weights were tuned for six scales, each half the size of the previous
+/// one. Statistics of other scales give scores which are not calibrated.
+///
+/// # Errors
+/// - If any of the statistics is not finite
+///
+/// # Panics
+/// - If more than six scales are given
+pub fn score(scales: &[ScaleStatistics]) -> Result<f64, Ssimulacra2Error> {
+    assert!(scales.len() <= NUM_SCALES, "At most six scales are weighed");
+    let msssim = Msssim {
+        scales: scales
+            .iter()
+            .map(|stats| {
+                let mut scale = MsssimScale::default();
+                for c in 0..3 {
+                    scale.avg_ssim[c * 2..(c + 1) * 2].copy_from_slice(&stats.ssim[c]);
+                    scale.avg_edgediff[c * 4..c * 4 + 2].copy_from_slice(&stats.artifact[c]);
+                    scale.avg_edgediff[c * 4 + 2..(c + 1) * 4]
+                        .copy_from_slice(&stats.detail_lost[c]);
+                }
+                scale
+            })
+            .collect(),
+    };
+    if !msssim.is_finite() {
+        return Err(Ssimulacra2Error::NonFiniteScore);
+    }
+    Ok(msssim.score())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::crossed_wave_image;
+    use crate::{compute_frame_ssimulacra2, Blur};
+
+    #[test]
+    fn stages_match_score() {
+        let (width, height) = (256, 200);
+        let expected = compute_frame_ssimulacra2(
+            crossed_wave_image(width, height, 0.0),
+            crossed_wave_image(width, height, 0.4),
+        )
+        .unwrap();
+
+        let mut source = Some(crossed_wave_image(width, height, 0.0));
+        let mut distorted = Some(crossed_wave_image(width, height, 0.4));
+        let mut scales = Vec::new();
+        while let (Some(img1), Some(img2)) = (source.take(), distorted.take()) {
+            let (width, height) = (img1.width(), img1.height());
+            if scales.len() + 1 < NUM_SCALES && width >= 8 && height >= 8 {
+                source = Some(downscale(&img1));
+                distorted = Some(downscale(&img2));
+            }
+
+            let mut planes1: [Vec<f32>; 3] = Default::default();
+            let mut planes2: [Vec<f32>; 3] = Default::default();
+            to_xyb(img1, &mut planes1);
+            to_xyb(img2, &mut planes2);
+            let mut squares: [Vec<f32>; 3] = Default::default();
+            multiply(&planes1, &planes1, &mut squares);
+            let mut blur = Blur::new(width, height);
+            let mu = blur.blur(&planes1);
+            let sigma_sq = blur.blur(&squares);
+            scales.push(ScaleStatistics::compute(
+                width, height, &planes1, &mu, &sigma_sq, &planes2,
+            ));
+        }
+
+        assert_eq!(scales.len(), NUM_SCALES);
+        assert_eq!(score(&scales).unwrap().to_bits(), expected.to_bits());
+        assert!(scales[0].detail_lost[1][0] > 0.0f64);
+
+        scales[2].ssim[0][1] = f64::NAN;
+        assert_eq!(score(&scales), Err(Ssimulacra2Error::NonFiniteScore));
+    }
+}
@@ -0,0 +1,252 @@
+use core::hash::Hasher;
+
+use crate::aligned::aligned_len;
+use crate::{AlignedPlane, PlaneHasher, Ssimulacra2Error};
+
+/// An image of `f32` planes stored in a single buffer, such as the XYB planes
+/// of an image or their blurred counterparts.
+///
+/// The planes follow each other, each holding `height` rows of `stride`
+/// values, of which the first `width` are pixels. Images created by this
+/// crate have no padding within rows, i.e. a stride equal to the width, so
+/// each plane is a contiguous run of `width * height` values; the planes are
+/// padded to start at multiples of [`PLANE_ALIGNMENT`] bytes. Strided images,
+/// e.g. borrowed from a frame with padded rows, can be blurred with
+/// [`Blur::blur_image_into`] without copying them into this layout first.
+///
+/// [`Blur::blur_image_into`]: crate::Blur::blur_image_into
+/// [`PLANE_ALIGNMENT`]: crate::PLANE_ALIGNMENT
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageF32 {
+    data: AlignedPlane,
+    width: usize,
+    height: usize,
+    stride: usize,
+    // The distance from the start of one plane to the next.
+    plane_len: usize,
+    planes: usize,
+}
+
+impl ImageF32 {
+    /// Creates an image of the given size and number of planes, filled with
+    /// zeroes.
+    #[must_use]
+    pub fn new(width: usize, height: usize, planes: usize) -> Self {
+        Self::from_buffer(AlignedPlane::default(), width, height, planes)
+    }
+
+    /// Wraps `data` holding `planes` planes of `width * height` values each,
+    /// one right after another. A [`Vec<f32>`] is copied into an
+    /// [`AlignedPlane`].
+    ///
+    /// # Errors
+    /// - If the length of `data` does not match the width, height and number
+    ///   of planes
+    pub fn from_vec(
+        data: impl Into<AlignedPlane>,
+        width: usize,
+        height: usize,
+        planes: usize,
+    ) -> Result<Self, Ssimulacra2Error> {
+        Self::from_strided(data, width, height, width, planes)
+    }
+
+    /// Wraps `data` holding `planes` planes of `height` rows of `stride`
+    /// values each, of which the first `width` are pixels, one right after
+    /// another. A [`Vec<f32>`] is copied into an [`AlignedPlane`].
+    ///
+    /// # Errors
+    /// - If the stride is less than the width
+    /// - If the length of `data` does not match the stride, height and number
+    ///   of planes
+    pub fn from_strided(
+        data: impl Into<AlignedPlane>,
+        width: usize,
+        height: usize,
+        stride: usize,
+        planes: usize,
+    ) -> Result<Self, Ssimulacra2Error> {
+        let data = data.into();
+        if stride < width || data.len() != stride * height * planes {
+            return Err(Ssimulacra2Error::InvalidBufferLength);
+        }
+        Ok(ImageF32 {
+            data,
+            width,
+            height,
+            stride,
+            plane_len: stride * height,
+            planes,
+        })
+    }
+
+    // Reuses `buffer` for an image without padding, resizing it as needed.
+    // The values of the image are unspecified.
+    pub(crate) fn from_buffer(
+        mut buffer: AlignedPlane,
+        width: usize,
+        height: usize,
+        planes: usize,
+    ) -> Self {
+        buffer.resize(Self::buffer_len(width, height, planes), 0.0f32);
+        ImageF32 {
+            data: buffer,
+            width,
+            height,
+            stride: width,
+            plane_len: aligned_len(width * height),
+            planes,
+        }
+    }
+
+    // The length of the buffer of an image created by this crate.
+    pub(crate) const fn buffer_len(width: usize, height: usize, planes: usize) -> usize {
+        aligned_len(width * height) * planes
+    }
+
+    // Changes the size of this image to that of an image without padding,
+    // reusing the buffer. The values of the image are unspecified.
+    pub(crate) fn reset(&mut self, width: usize, height: usize, planes: usize) {
+        let buffer = core::mem::take(&mut self.data);
+        *self = Self::from_buffer(buffer, width, height, planes);
+    }
+
+    /// The width of the image in pixels.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the image in pixels.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of values from the start of one row to the next.
+    #[must_use]
+    pub const fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The number of planes of the image.
+    #[must_use]
+    pub const fn planes(&self) -> usize {
+        self.planes
+    }
+
+    /// The values of all planes, one after another, including the padding
+    /// between planes of images created by this crate.
+    #[must_use]
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Returns the buffer of the image, e.g. for reuse.
+    #[must_use]
+    pub fn into_buffer(self) -> AlignedPlane {
+        self.data
+    }
+
+    /// The `height` rows of `stride` values of the plane `c`.
+    ///
+    /// # Panics
+    /// - If the image has no plane `c`
+    #[must_use]
+    pub fn plane(&self, c: usize) -> &[f32] {
+        assert!(c < self.planes, "Plane index out of range");
+        let start = c * self.plane_len;
+        &self.data[start..start + self.stride * self.height]
+    }
+
+    /// Like [`plane`][Self::plane], but mutable.
+    ///
+    /// # Panics
+    /// - If the image has no plane `c`
+    #[must_use]
+    pub fn plane_mut(&mut self, c: usize) -> &mut [f32] {
+        assert!(c < self.planes, "Plane index out of range");
+        let start = c * self.plane_len;
+        &mut self.data[start..start + self.stride * self.height]
+    }
+
+    /// The `width` pixels of row `y` of the plane `c`.
+    ///
+    /// # Panics
+    /// - If the image has no plane `c` or row `y`
+    #[must_use]
+    pub fn row(&self, c: usize, y: usize) -> &[f32] {
+        assert!(y < self.height, "Row index out of range");
+        let start = y * self.stride;
+        &self.plane(c)[start..start + self.width]
+    }
+
+    /// The hash of the size, the number of planes and the pixels of all
+    /// planes of the image, see [`PlaneHasher`]. Padding within and between
+    /// planes is left out, so a strided image has the same hash as a copy of
+    /// it without padding.
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = PlaneHasher::new();
+        hasher.write_u64(self.width as u64);
+        hasher.write_u64(self.height as u64);
+        hasher.write_u64(self.planes as u64);
+        for c in 0..self.planes {
+            for y in 0..self.height {
+                hasher.update(self.row(c, y));
+            }
+        }
+        hasher.finish()
+    }
+
+    // The first `N` planes, which must not be padded.
+    pub(crate) fn split_planes_mut<const N: usize>(&mut self) -> [&mut [f32]; N] {
+        debug_assert_eq!(self.stride, self.width);
+        let len = self.width * self.height;
+        let mut planes = self.data.chunks_mut(self.plane_len);
+        core::array::from_fn(|_| &mut planes.next().expect("Image has enough planes")[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    #[test]
+    fn planes_are_aligned() {
+        let image = ImageF32::new(13, 7, 3);
+        for c in 0..3 {
+            assert_eq!(image.plane(c).as_ptr() as usize % crate::PLANE_ALIGNMENT, 0);
+            assert_eq!(image.plane(c).len(), 13 * 7);
+        }
+    }
+
+    #[test]
+    fn strided_rows() {
+        let data: Vec<f32> = (0..30u16).map(f32::from).collect();
+        let image = ImageF32::from_strided(data, 4, 3, 5, 2).unwrap();
+        assert_eq!(image.plane(1).len(), 15);
+        assert_eq!(image.row(1, 2), &[25.0f32, 26.0, 27.0, 28.0]);
+
+        assert_eq!(
+            ImageF32::from_strided(vec![0.0f32; 30], 6, 3, 5, 2),
+            Err(Ssimulacra2Error::InvalidBufferLength)
+        );
+        assert_eq!(
+            ImageF32::from_vec(vec![0.0f32; 30], 5, 3, 1),
+            Err(Ssimulacra2Error::InvalidBufferLength)
+        );
+
+        let packed: Vec<f32> = (0..30u16).filter(|i| i % 5 != 4).map(f32::from).collect();
+        let packed = ImageF32::from_vec(packed, 4, 3, 2).unwrap();
+        assert_eq!(image.content_hash(), packed.content_hash());
+        assert_ne!(
+            image.content_hash(),
+            ImageF32::from_vec(vec![0.0f32; 24], 4, 3, 2)
+                .unwrap()
+                .content_hash()
+        );
+    }
+}
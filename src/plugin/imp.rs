@@ -0,0 +1,204 @@
+// The subclassing macros generate methods named like the ones of glib's traits.
+#![allow(clippy::same_name_method)]
+
+use gst::glib;
+use gst::glib::once_cell::sync::Lazy;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_base::subclass::prelude::*;
+use gst_video::prelude::*;
+use gst_video::subclass::prelude::*;
+use gst_video::subclass::AggregateFramesToken;
+use num_traits::FromPrimitive;
+use yuvxyb::{
+    ColorPrimaries, Frame, LinearRgb, MatrixCoefficients, Pixel, Plane, TransferCharacteristic,
+    Yuv, YuvConfig,
+};
+
+use crate::Reference;
+
+const FORMATS: &str =
+    "{ I420, Y42B, Y444, I420_10LE, I422_10LE, Y444_10LE, I420_12LE, I422_12LE, Y444_12LE }";
+
+#[derive(Default)]
+pub struct Ssimulacra2Element;
+
+#[glib::object_subclass]
+impl ObjectSubclass for Ssimulacra2Element {
+    const NAME: &'static str = "GstSsimulacra2";
+    type Type = super::Ssimulacra2Element;
+    type ParentType = gst_video::VideoAggregator;
+}
+
+impl ObjectImpl for Ssimulacra2Element {}
+
+impl GstObjectImpl for Ssimulacra2Element {}
+
+impl ElementImpl for Ssimulacra2Element {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static METADATA: Lazy<gst::subclass::ElementMetadata> = Lazy::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "SSIMULACRA2",
+                "Filter/Analyzer/Video",
+                "Scores distorted video streams against the source on sink_0 and posts the \
+                 SSIMULACRA2 scores on the bus",
+                "rust-av developers",
+            )
+        });
+        Some(&*METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: Lazy<Vec<gst::PadTemplate>> = Lazy::new(|| {
+            let caps = format!("video/x-raw, format=(string){FORMATS}")
+                .parse::<gst::Caps>()
+                .expect("Caps are valid");
+
+            vec![
+                gst::PadTemplate::with_gtype(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &caps,
+                    gst_base::AggregatorPad::static_type(),
+                )
+                .expect("Pad template is valid"),
+                gst::PadTemplate::with_gtype(
+                    "sink_%u",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Request,
+                    &caps,
+                    gst_video::VideoAggregatorConvertPad::static_type(),
+                )
+                .expect("Pad template is valid"),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl AggregatorImpl for Ssimulacra2Element {}
+
+impl VideoAggregatorImpl for Ssimulacra2Element {
+    fn aggregate_frames(
+        &self,
+        token: &AggregateFramesToken,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let obj = self.obj();
+        let Some(info) = obj.video_info() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+        let pads: Vec<_> = obj
+            .sink_pads()
+            .into_iter()
+            .filter_map(|pad| pad.downcast::<gst_video::VideoAggregatorPad>().ok())
+            .collect();
+        let Some((source_pad, distorted_pads)) = pads.split_first() else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+        let Some(source) = source_pad.prepared_frame(token) else {
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let Ok(mut out) = gst_video::VideoFrameRef::from_buffer_ref_writable(outbuf, &info) else {
+            return Err(gst::FlowError::Error);
+        };
+        if source.copy(&mut out).is_err() {
+            return Err(gst::FlowError::Error);
+        }
+        drop(out);
+
+        let Some(reference) = to_linear_rgb(&source).and_then(|img| Reference::new(img).ok())
+        else {
+            gst::element_imp_error!(
+                self,
+                gst::StreamError::Format,
+                ["Failed to convert the source frame"]
+            );
+            return Err(gst::FlowError::Error);
+        };
+
+        let mut scores = gst::Structure::builder("scores");
+        for pad in distorted_pads {
+            let Some(distorted) = pad.prepared_frame(token) else {
+                continue;
+            };
+            let Some(score) = to_linear_rgb(&distorted).and_then(|img| reference.compare(img).ok())
+            else {
+                gst::element_imp_error!(
+                    self,
+                    gst::StreamError::Format,
+                    ["Failed to score the frame of {}", pad.name()]
+                );
+                return Err(gst::FlowError::Error);
+            };
+            scores = scores.field(pad.name().as_str(), score);
+        }
+
+        let structure = gst::Structure::builder("ssimulacra2")
+            .field("timestamp", outbuf.pts())
+            .field("scores", scores.build())
+            .build();
+        // Posting only fails if the element has no bus yet, which is not an error.
+        let _ = obj.post_message(gst::message::Element::builder(structure).src(&*obj).build());
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
+
+fn to_linear_rgb(frame: &gst_video::VideoFrameRef<&gst::BufferRef>) -> Option<LinearRgb> {
+    let info = frame.info();
+    let format = frame.format_info();
+    let colorimetry = info.colorimetry();
+    let config = YuvConfig {
+        bit_depth: format.depth()[0] as u8,
+        subsampling_x: format.w_sub()[1] as u8,
+        subsampling_y: format.h_sub()[1] as u8,
+        full_range: colorimetry.range() == gst_video::VideoColorRange::Range0_255,
+        matrix_coefficients: MatrixCoefficients::from_u32(colorimetry.matrix().to_iso())
+            .filter(|&m| m != MatrixCoefficients::Unspecified)
+            .unwrap_or(MatrixCoefficients::BT709),
+        transfer_characteristics: TransferCharacteristic::from_u32(colorimetry.transfer().to_iso())
+            .filter(|&t| t != TransferCharacteristic::Unspecified)
+            .unwrap_or(TransferCharacteristic::BT1886),
+        color_primaries: ColorPrimaries::from_u32(colorimetry.primaries().to_iso())
+            .filter(|&p| p != ColorPrimaries::Unspecified)
+            .unwrap_or(ColorPrimaries::BT709),
+    };
+
+    if config.bit_depth > 8 {
+        LinearRgb::try_from(Yuv::new(copy_frame::<u16>(frame, config)?, config).ok()?).ok()
+    } else {
+        LinearRgb::try_from(Yuv::new(copy_frame::<u8>(frame, config)?, config).ok()?).ok()
+    }
+}
+
+fn copy_frame<T: Pixel>(
+    frame: &gst_video::VideoFrameRef<&gst::BufferRef>,
+    config: YuvConfig,
+) -> Option<Frame<T>> {
+    let ss_x = usize::from(config.subsampling_x);
+    let ss_y = usize::from(config.subsampling_y);
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let chroma_width = (width + ss_x) >> ss_x;
+    let chroma_height = (height + ss_y) >> ss_y;
+
+    let mut out = Frame {
+        planes: [
+            Plane::new(width, height, 0, 0, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+        ],
+    };
+
+    for (i, plane) in out.planes.iter_mut().enumerate() {
+        let data = frame.plane_data(i as u32).ok()?;
+        let stride = usize::try_from(frame.plane_stride()[i]).ok()?;
+        let bytewidth = std::mem::size_of::<T>();
+        plane.copy_from_raw_u8(data, stride, bytewidth);
+    }
+
+    Some(out)
+}
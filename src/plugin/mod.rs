@@ -0,0 +1,54 @@
+//! `GStreamer` plugin with the `ssimulacra2` element.
+//!
+//! The element aggregates two or more video streams. The first sink pad
+//! (`sink_0`) carries the source and every other sink pad a distorted version
+//! of it, e.g. the output of a transcoder. The source frames are passed
+//! through, and an element message named `ssimulacra2` is posted on the bus
+//! for every frame:
+//!
+//! ```text
+//! ssimulacra2, timestamp=(guint64)40000000, scores=(structure)"scores\,\ sink_1\=\(double\)83.4\;"
+//! ```
+//!
+//! Build the crate as a `cdylib` with the `gstreamer` feature to load it as a
+//! plugin, or call [`register`] to use the element from within an application.
+
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    /// The `ssimulacra2` element.
+    pub struct Ssimulacra2Element(ObjectSubclass<imp::Ssimulacra2Element>)
+        @extends gst_video::VideoAggregator, gst_base::Aggregator, gst::Element, gst::Object;
+}
+
+/// Registers the `ssimulacra2` element with `plugin`, or for the whole
+/// application if `plugin` is [None].
+///
+/// # Errors
+/// - If the element could not be registered
+pub fn register(plugin: Option<&gst::Plugin>) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        plugin,
+        "ssimulacra2",
+        gst::Rank::None,
+        Ssimulacra2Element::static_type(),
+    )
+}
+
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    register(Some(plugin))
+}
+
+gst::plugin_define!(
+    ssimulacra2,
+    env!("CARGO_PKG_DESCRIPTION"),
+    plugin_init,
+    env!("CARGO_PKG_VERSION"),
+    "BSD",
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_REPOSITORY")
+);
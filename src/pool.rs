@@ -0,0 +1,188 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::mem;
+
+use crate::{AlignedPlane, ImageF32};
+
+/// A pool of the temporary image buffers used while scoring.
+///
+/// Every [`Ssimulacra2`][crate::Ssimulacra2] session owns a pool holding the
+/// XYB planes, blurred planes and downscaled images of all scales. Buffers
+/// are handed out by size, so each scale of a frame reuses the buffers of the
+/// same scale of the previous frame. After the first frame of a resolution
+/// has been scored, scoring further frames does not allocate any image
+/// buffers; [`stats`][Self::stats] can be used to verify that.
+#[derive(Debug, Default)]
+pub struct ScratchPool {
+    // Free planes by capacity.
+    planes: BTreeMap<usize, Vec<AlignedPlane>>,
+    pixels: Vec<[f32; 3]>,
+    stats: PoolStats,
+}
+
+/// Counters of a [`ScratchPool`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolStats {
+    /// Number of buffers that had to be allocated or grown.
+    pub allocations: usize,
+    /// Number of buffers that were handed out again without allocating.
+    pub reuses: usize,
+    /// Number of free buffers currently held by the pool.
+    pub pooled: usize,
+    /// Total capacity of the free buffers, in bytes.
+    pub pooled_bytes: usize,
+}
+
+impl ScratchPool {
+    /// Creates an empty pool.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counters of this pool.
+    #[must_use]
+    pub const fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Frees all buffers held by the pool. The counters are kept.
+    pub fn clear(&mut self) {
+        self.planes.clear();
+        self.pixels = Vec::new();
+        self.stats.pooled = 0;
+        self.stats.pooled_bytes = 0;
+    }
+
+    // Takes a plane of `len` values, reusing the smallest free buffer that is
+    // large enough. The contents of the plane are unspecified.
+    pub(crate) fn take_plane(&mut self, len: usize) -> AlignedPlane {
+        let Some(capacity) = self
+            .planes
+            .range(len..)
+            .next()
+            .map(|(&capacity, _)| capacity)
+        else {
+            self.stats.allocations += 1;
+            return AlignedPlane::new(len);
+        };
+
+        let free = self.planes.get_mut(&capacity).expect("Size class exists");
+        let mut plane = free.pop().expect("Size classes are not empty");
+        if free.is_empty() {
+            self.planes.remove(&capacity);
+        }
+        self.stats.reuses += 1;
+        self.stats.pooled -= 1;
+        self.stats.pooled_bytes -= capacity * mem::size_of::<f32>();
+
+        plane.resize(len, 0.0f32);
+        plane
+    }
+
+    // Takes an image without padding of the given size.
+    pub(crate) fn take_image(&mut self, width: usize, height: usize, planes: usize) -> ImageF32 {
+        ImageF32::from_buffer(
+            self.take_plane(ImageF32::buffer_len(width, height, planes)),
+            width,
+            height,
+            planes,
+        )
+    }
+
+    // Returns a plane to the pool.
+    pub(crate) fn give_plane(&mut self, plane: AlignedPlane) {
+        let capacity = plane.capacity();
+        if capacity == 0 {
+            return;
+        }
+
+        self.stats.pooled += 1;
+        self.stats.pooled_bytes += capacity * mem::size_of::<f32>();
+        self.planes.entry(capacity).or_default().push(plane);
+    }
+
+    // Returns the buffer of an image to the pool.
+    pub(crate) fn give_image(&mut self, image: ImageF32) {
+        self.give_plane(image.into_buffer());
+    }
+
+    // Takes the buffer for a downscaled image of `len` pixels.
+    pub(crate) fn take_pixels(&mut self, len: usize) -> Vec<[f32; 3]> {
+        let pixels = mem::take(&mut self.pixels);
+        if pixels.capacity() > 0 {
+            self.stats.pooled -= 1;
+            self.stats.pooled_bytes -= pixels.capacity() * mem::size_of::<[f32; 3]>();
+        }
+        if pixels.capacity() < len {
+            self.stats.allocations += 1;
+        } else {
+            self.stats.reuses += 1;
+        }
+        pixels
+    }
+
+    // Returns the pixels of an image that is no longer needed. Only the
+    // largest buffer is kept, as a single downscale is in flight at a time.
+    pub(crate) fn give_pixels(&mut self, pixels: Vec<[f32; 3]>) {
+        if pixels.capacity() <= self.pixels.capacity() {
+            return;
+        }
+
+        if self.pixels.capacity() == 0 {
+            self.stats.pooled += 1;
+        }
+        self.stats.pooled_bytes -= self.pixels.capacity() * mem::size_of::<[f32; 3]>();
+        self.stats.pooled_bytes += pixels.capacity() * mem::size_of::<[f32; 3]>();
+        self.pixels = pixels;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+    use crate::Ssimulacra2;
+
+    #[test]
+    fn reuse_across_frames() {
+        let mut session = Ssimulacra2::new(67, 45).unwrap();
+        let first = session
+            .score(make_image(67, 45, 3), make_image(67, 45, 5))
+            .unwrap();
+        let after_first = session.pool_stats();
+        assert!(after_first.allocations > 0);
+        assert!(after_first.pooled > 0);
+
+        for _ in 0..3usize {
+            let score = session
+                .score(make_image(67, 45, 3), make_image(67, 45, 5))
+                .unwrap();
+            assert!((score - first).abs() < f64::EPSILON);
+        }
+        let stats = session.pool_stats();
+        assert_eq!(stats.allocations, after_first.allocations);
+        assert!(stats.reuses > after_first.reuses);
+        assert_eq!(stats.pooled, after_first.pooled);
+    }
+
+    #[test]
+    fn smallest_fit() {
+        let mut pool = ScratchPool::new();
+        pool.give_plane(AlignedPlane::with_capacity(112));
+        pool.give_plane(AlignedPlane::with_capacity(16));
+        assert_eq!(pool.take_plane(8).capacity(), 16);
+        assert_eq!(pool.take_plane(50).capacity(), 112);
+        assert_eq!(pool.take_plane(50).len(), 50);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                allocations: 1,
+                reuses: 2,
+                pooled: 0,
+                pooled_bytes: 0,
+            }
+        );
+    }
+}
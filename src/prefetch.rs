@@ -0,0 +1,55 @@
+// Software prefetching for loops that stream several planes at once and wait
+// on memory rather than arithmetic, such as the error sums of large images.
+
+// The number of `f32` values in a cache line of 64 bytes.
+pub const LINE: usize = 16;
+
+// Hints the CPU to load the cache line holding `values[index]` into the
+// closest cache. Indices past the end are ignored. This only affects speed.
+#[inline(always)]
+pub fn prefetch(values: &[f32], index: usize) {
+    if let Some(value) = values.get(index) {
+        prefetch_ptr(value);
+    }
+}
+
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+#[inline(always)]
+fn prefetch_ptr(value: &f32) {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::{_mm_prefetch, _MM_HINT_T0};
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+    // SAFETY: prefetching has no effect other than on the caches, and the
+    // pointer comes from a reference.
+    unsafe { _mm_prefetch((value as *const f32).cast(), _MM_HINT_T0) };
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn prefetch_ptr(value: &f32) {
+    // SAFETY: `prfm` only loads the line into the cache, and the pointer
+    // comes from a reference.
+    unsafe {
+        core::arch::asm!(
+            "prfm pldl1keep, [{0}]",
+            in(reg) value as *const f32,
+            options(nostack, readonly, preserves_flags)
+        );
+    };
+}
+
+// Other targets rely on their hardware prefetchers alone.
+#[cfg(not(any(
+    all(
+        any(target_arch = "x86", target_arch = "x86_64"),
+        target_feature = "sse"
+    ),
+    target_arch = "aarch64"
+)))]
+#[inline(always)]
+const fn prefetch_ptr(_value: &f32) {}
@@ -0,0 +1,177 @@
+use yuvxyb::LinearRgb;
+
+use crate::ScratchPool;
+
+/// Preprocessing applied to both images before scoring them, see
+/// [`Ssimulacra2::with_preprocess`][crate::Ssimulacra2::with_preprocess].
+///
+/// SSIMULACRA2 compares images at full resolution first, where ordered or
+/// blue-noise dithering shows up as strong high-frequency noise, although
+/// it is barely visible at normal viewing distances. Low-pass filtering both
+/// images removes most of the dither pattern while keeping the structure it
+/// approximates, so that a dithered image scores close to the undithered one.
+/// This also lowers the penalty for other fine-grained distortion such as
+/// film grain or sharp noise, so it should only be enabled where dithering is
+/// expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preprocess {
+    /// Score the images as they are, like the reference implementation.
+    #[default]
+    None,
+    /// Blur both images with a 3x3 binomial kernel. This removes patterns
+    /// alternating every pixel, such as 2x2 ordered dithering, and weakens
+    /// blue noise, while attenuating details spanning several pixels only
+    /// slightly.
+    Blur,
+    /// Replace every 2x2 block of pixels with its average, as a display
+    /// decimating the image by two would. This removes any dither pattern
+    /// that averages out within the block, at the cost of the finest details.
+    Decimate,
+}
+
+impl Preprocess {
+    // Applies the preprocessing to `img` in place, taking temporary buffers
+    // from `pool`.
+    pub(crate) fn apply(self, img: &mut LinearRgb, pool: &mut ScratchPool) {
+        match self {
+            Preprocess::None => {}
+            Preprocess::Blur => blur(img, pool),
+            Preprocess::Decimate => decimate(img),
+        }
+    }
+}
+
+// The separable [1, 2, 1] / 4 kernel, mirroring the image at the edges so
+// that patterns alternating every pixel cancel out there as well.
+fn blur(img: &mut LinearRgb, pool: &mut ScratchPool) {
+    let width = img.width();
+    let height = img.height();
+    let mut tmp = pool.take_pixels(width * height);
+    tmp.clear();
+    tmp.resize(width * height, [0.0f32; 3]);
+
+    let filter = |a: [f32; 3], b: [f32; 3], c: [f32; 3]| {
+        [0usize, 1, 2].map(|i| 0.5f32.mul_add(b[i], 0.25 * (a[i] + c[i])))
+    };
+    for (row, out) in img
+        .data()
+        .chunks_exact(width)
+        .zip(tmp.chunks_exact_mut(width))
+    {
+        for (x, out) in out.iter_mut().enumerate() {
+            *out = filter(row[mirror(x, -1, width)], row[x], row[mirror(x, 1, width)]);
+        }
+    }
+    for (y, out) in img.data_mut().chunks_exact_mut(width).enumerate() {
+        let above = &tmp[mirror(y, -1, height) * width..][..width];
+        let row = &tmp[y * width..][..width];
+        let below = &tmp[mirror(y, 1, height) * width..][..width];
+        for (x, out) in out.iter_mut().enumerate() {
+            *out = filter(above[x], row[x], below[x]);
+        }
+    }
+
+    pool.give_pixels(tmp);
+}
+
+// The neighbor of `i` in the direction of `step`, reflected at the edges of
+// `0..len`.
+const fn mirror(i: usize, step: isize, len: usize) -> usize {
+    let neighbor = i as isize + step;
+    if neighbor < 0 {
+        1
+    } else if neighbor as usize >= len {
+        len - 2
+    } else {
+        neighbor as usize
+    }
+}
+
+// Averages each 2x2 block, or the part of it inside the image at the right
+// and bottom edges.
+fn decimate(img: &mut LinearRgb) {
+    let width = img.width();
+    let data = img.data_mut();
+    for rows in data.chunks_mut(2 * width) {
+        let rows_len = rows.len() / width;
+        for x in (0..width).step_by(2) {
+            let cols = x..(x + 2).min(width);
+            let pixels = || (0..rows_len).flat_map(|y| cols.clone().map(move |x| y * width + x));
+
+            let mut sum = [0.0f32; 3];
+            for i in pixels() {
+                for (sum, value) in sum.iter_mut().zip(rows[i]) {
+                    *sum += value;
+                }
+            }
+            let count = (rows_len * cols.len()) as f32;
+            let average = sum.map(|sum| sum / count);
+            for i in pixels() {
+                rows[i] = average;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ssimulacra2;
+
+    // A smooth gradient, and the same gradient with the pattern of a 2x2
+    // ordered dither added, as when dithering to a coarser quantization.
+    fn images(width: usize, height: usize) -> (LinearRgb, LinearRgb) {
+        let value = |x: usize, y: usize| 0.2 + 0.5 * (x + y) as f32 / (width + height) as f32;
+        let smooth = (0..width * height)
+            .map(|i| [value(i % width, i / width); 3])
+            .collect();
+        let dithered = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let offset = [[-1.5f32, 0.5], [1.5, -0.5]][y % 2][x % 2];
+                [offset.mul_add(0.1, value(x, y)); 3]
+            })
+            .collect();
+        (
+            LinearRgb::new(smooth, width, height).unwrap(),
+            LinearRgb::new(dithered, width, height).unwrap(),
+        )
+    }
+
+    #[test]
+    fn dithering() {
+        let score = |preprocess| {
+            let (smooth, dithered) = images(64, 48);
+            Ssimulacra2::new(64, 48)
+                .unwrap()
+                .with_preprocess(preprocess)
+                .score(smooth, dithered)
+                .unwrap()
+        };
+        let none = score(Preprocess::None);
+        let blur = score(Preprocess::Blur);
+        let decimate = score(Preprocess::Decimate);
+        assert!(none < 96.0f64, "{none}");
+        assert!(blur > 98.0f64, "{blur}");
+        assert!(decimate > 98.0f64, "{decimate}");
+
+        let (smooth, dithered) = images(64, 48);
+        let expected = crate::compute_frame_ssimulacra2(smooth, dithered).unwrap();
+        assert!((none - expected).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn preserves_flat_images() {
+        let flat = || LinearRgb::new(vec![[0.25f32, 0.5, 0.75]; 9 * 7], 9, 7).unwrap();
+        for preprocess in [Preprocess::Blur, Preprocess::Decimate] {
+            let mut img = flat();
+            preprocess.apply(&mut img, &mut ScratchPool::new());
+            for (pix, expected) in img.data().iter().zip(flat().data()) {
+                for (value, expected) in pix.iter().zip(expected) {
+                    assert!((value - expected).abs() < 1e-6, "{preprocess:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use core::time::Duration;
+
+/// The progress of a video scoring run, as reported by
+/// [`FramePipeline::run_with_progress`][crate::FramePipeline::run_with_progress].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of frames scored so far.
+    pub frames_done: usize,
+    /// Total number of frames that will be scored, if known.
+    pub total_frames: Option<usize>,
+    /// Time since scoring started.
+    pub elapsed: Duration,
+    /// Mean score of the frames scored so far.
+    pub mean_score: f64,
+}
+
+impl Progress {
+    /// Number of frames scored per second so far.
+    #[must_use]
+    pub fn frames_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.frames_done as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time until all frames are scored, assuming the speed so far
+    /// stays the same. This is [None] if the total number of frames is unknown
+    /// or no frames have been scored yet.
+    #[must_use]
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_frames?.saturating_sub(self.frames_done);
+        let fps = self.frames_per_second();
+        (fps > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / fps))
+    }
+
+    /// Fraction of all frames which have been scored, in `0.0..=1.0`.
+    /// This is [None] if the total number of frames is unknown.
+    #[must_use]
+    pub fn fraction(&self) -> Option<f64> {
+        self.total_frames.map(|total| {
+            if total == 0 {
+                1.0f64
+            } else {
+                (self.frames_done as f64 / total as f64).min(1.0)
+            }
+        })
+    }
+}
@@ -0,0 +1,163 @@
+use core::fmt;
+
+/// The interpretation of a range of SSIMULACRA2 scores, as published with the
+/// reference implementation.
+///
+/// Each band starts at the score given for it in the reference README, e.g.
+/// 90 for visually lossless, and ends where the next better band starts.
+/// Bands are ordered from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QualityBand {
+    /// Below 10: extremely low quality, very strong distortion.
+    ExtremelyLow,
+    /// From 10: very low quality.
+    VeryLow,
+    /// From 30: low quality.
+    Low,
+    /// From 50: medium quality.
+    Medium,
+    /// From 70: high quality, artifacts are hard to notice without
+    /// comparing against the original.
+    High,
+    /// From 80: very high quality, indistinguishable from the original in a
+    /// side-by-side comparison at 1:1.
+    VeryHigh,
+    /// From 85: excellent quality, indistinguishable from the original in a
+    /// flip test at 1:1.
+    Excellent,
+    /// From 90: visually lossless, indistinguishable from the original in a
+    /// flicker test at 1:1.
+    VisuallyLossless,
+    /// 100: mathematically lossless.
+    Lossless,
+}
+
+impl QualityBand {
+    const ALL: [QualityBand; 9] = [
+        QualityBand::Lossless,
+        QualityBand::VisuallyLossless,
+        QualityBand::Excellent,
+        QualityBand::VeryHigh,
+        QualityBand::High,
+        QualityBand::Medium,
+        QualityBand::Low,
+        QualityBand::VeryLow,
+        QualityBand::ExtremelyLow,
+    ];
+
+    /// Returns the band `score` falls into. NaN is treated as the lowest band.
+    #[must_use]
+    pub fn from_score(score: f64) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|band| score >= band.min_score())
+            .unwrap_or(QualityBand::ExtremelyLow)
+    }
+
+    /// The lowest score of this band.
+    #[must_use]
+    pub const fn min_score(self) -> f64 {
+        match self {
+            QualityBand::ExtremelyLow => f64::NEG_INFINITY,
+            QualityBand::VeryLow => 10.0,
+            QualityBand::Low => 30.0,
+            QualityBand::Medium => 50.0,
+            QualityBand::High => 70.0,
+            QualityBand::VeryHigh => 80.0,
+            QualityBand::Excellent => 85.0,
+            QualityBand::VisuallyLossless => 90.0,
+            QualityBand::Lossless => 100.0,
+        }
+    }
+
+    /// A short, lowercase label for this band, e.g. `"very high"`.
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            QualityBand::ExtremelyLow => "extremely low",
+            QualityBand::VeryLow => "very low",
+            QualityBand::Low => "low",
+            QualityBand::Medium => "medium",
+            QualityBand::High => "high",
+            QualityBand::VeryHigh => "very high",
+            QualityBand::Excellent => "excellent",
+            QualityBand::VisuallyLossless => "visually lossless",
+            QualityBand::Lossless => "lossless",
+        }
+    }
+}
+
+impl fmt::Display for QualityBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+// Scores and the butteraugli distances of the libjxl encodes they correspond
+// to, as listed in the reference README, from best to worst.
+const JND_POINTS: [(f64, f64); 8] = [
+    (100.0, 0.0),
+    (90.0, 0.5),
+    (85.0, 1.0),
+    (80.0, 1.5),
+    (70.0, 2.5),
+    (50.0, 5.0),
+    (30.0, 9.0),
+    (10.0, 14.0),
+];
+
+/// Converts a score to an approximate distance from the original in units of
+/// just-noticeable differences (JND), where 1 is at the threshold of being
+/// visible and 0 is lossless.
+///
+/// This interpolates between the butteraugli distances of the libjxl encodes
+/// the reference README lists next to the score bands, so it is only a rough
+/// guide. Scores below 10 are extrapolated.
+#[must_use]
+pub fn approximate_jnd(score: f64) -> f64 {
+    let score = score.min(100.0);
+    let segment = JND_POINTS
+        .windows(2)
+        .find(|pair| score >= pair[1].0)
+        .unwrap_or_else(|| &JND_POINTS[JND_POINTS.len() - 2..]);
+    let [(score0, jnd0), (score1, jnd1)] = [segment[0], segment[1]];
+    (score - score0).mul_add((jnd1 - jnd0) / (score1 - score0), jnd0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands() {
+        assert_eq!(QualityBand::from_score(100.0), QualityBand::Lossless);
+        assert_eq!(QualityBand::from_score(99.9), QualityBand::VisuallyLossless);
+        assert_eq!(QualityBand::from_score(90.0), QualityBand::VisuallyLossless);
+        assert_eq!(QualityBand::from_score(84.9), QualityBand::VeryHigh);
+        assert_eq!(QualityBand::from_score(10.0), QualityBand::VeryLow);
+        assert_eq!(QualityBand::from_score(-50.0), QualityBand::ExtremelyLow);
+        assert_eq!(QualityBand::from_score(f64::NAN), QualityBand::ExtremelyLow);
+        assert_eq!(QualityBand::VeryHigh.to_string(), "very high");
+
+        for band in QualityBand::ALL {
+            assert_eq!(QualityBand::from_score(band.min_score()), band);
+        }
+    }
+
+    #[test]
+    fn jnd() {
+        for (score, jnd) in JND_POINTS {
+            assert!((approximate_jnd(score) - jnd).abs() < 1e-12f64);
+        }
+        assert!((approximate_jnd(87.5) - 0.75f64).abs() < 1e-12f64);
+        assert!((approximate_jnd(-10.0) - 19.0f64).abs() < 1e-12f64);
+
+        let mut last = f64::NEG_INFINITY;
+        for score in (-20i32..=100i32).rev() {
+            let jnd = approximate_jnd(f64::from(score));
+            assert!(jnd > last);
+            last = jnd;
+        }
+    }
+}
@@ -0,0 +1,861 @@
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::array;
+
+use yuvxyb::LinearRgb;
+
+use crate::blur::Tile;
+use crate::cancel::check_cancelled;
+use crate::channel::{
+    compare_channels, compare_channels_lanes, compare_tiles, merge_tiles, ChannelErrors,
+    SourceChannel,
+};
+use crate::downscale::downscale_checked;
+use crate::session::Scratch;
+use crate::ScratchPool;
+use crate::{
+    check_finite, linear_rgb_to_xyb_image, misses_target, BoxDownscaler, CancellationToken,
+    CubeRoot, DetailedScore, DistortionMap, Downscaler, ImageF32, Msssim, MsssimScale,
+    ResizeFilter, Ssimulacra2Error, NUM_SCALES,
+};
+
+// The number of distorted images `compare_many` compares in one pass.
+const MAX_LANES: usize = 4;
+
+/// A source image prepared for being compared against many distorted images.
+///
+/// Creating a [`Reference`] converts the source to XYB at every scale and
+/// pre-computes its blurred mean and variance once. Comparing against it only
+/// processes the distorted image, which roughly halves the work per comparison
+/// in encoder loops where the source never changes.
+///
+/// The scores are identical to those of [`compute_frame_ssimulacra2`][crate::compute_frame_ssimulacra2].
+#[derive(Debug, Clone)]
+pub struct Reference {
+    width: usize,
+    height: usize,
+    scales: Vec<ReferenceScale>,
+    downscaler: Arc<dyn Downscaler>,
+    validate: bool,
+    rescale: Option<ResizeFilter>,
+    cube_root: CubeRoot,
+    tiling: bool,
+    cancellation: Option<CancellationToken>,
+}
+
+#[derive(Debug, Clone)]
+struct ReferenceScale {
+    width: usize,
+    height: usize,
+    img: ImageF32,
+    mu: ImageF32,
+    sigma_sq: ImageF32,
+}
+
+impl Reference {
+    /// Prepares `source` for comparisons.
+    ///
+    /// In debug builds, the source is checked for NaN and infinite values.
+    ///
+    /// # Errors
+    /// - If the source image cannot be converted to XYB successfully
+    /// - If the image is smaller than 8x8 pixels
+    /// - If validation is enabled and the source contains NaN or infinite values
+    pub fn new<T>(source: T) -> Result<Self, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T>,
+    {
+        Self::with_downscaler(source, BoxDownscaler)
+    }
+
+    /// Prepares `source` for comparisons, downscaling it and every distorted
+    /// image with `downscaler` instead of the default [`BoxDownscaler`].
+    ///
+    /// # Errors
+    /// - If the source image cannot be converted to XYB successfully
+    /// - If the image is smaller than 8x8 pixels
+    /// - If validation is enabled and the source contains NaN or infinite values
+    pub fn with_downscaler<T, D>(source: T, downscaler: D) -> Result<Self, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T>,
+        D: Downscaler + 'static,
+    {
+        let Ok(img) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        Self::prepare(Cow::Owned(img), Arc::new(downscaler), CubeRoot::Exact)
+    }
+
+    /// Prepares `source` for comparisons, computing the cube roots of the XYB
+    /// conversion of it and of every distorted image with `cube_root` instead
+    /// of [`CubeRoot::Exact`].
+    ///
+    /// # Errors
+    /// - If the source image cannot be converted to XYB successfully
+    /// - If the image is smaller than 8x8 pixels
+    /// - If validation is enabled and the source contains NaN or infinite values
+    pub fn with_cube_root<T>(source: T, cube_root: CubeRoot) -> Result<Self, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T>,
+    {
+        let Ok(img) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        Self::prepare(Cow::Owned(img), Arc::new(BoxDownscaler), cube_root)
+    }
+
+    /// Like [`new`][Self::new], but borrows a source which is already in
+    /// linear RGB, so that the caller keeps it without cloning it. The source
+    /// is only read.
+    ///
+    /// # Errors
+    /// - If the image is smaller than 8x8 pixels
+    /// - If validation is enabled and the source contains NaN or infinite values
+    pub fn from_borrowed(source: &LinearRgb) -> Result<Self, Ssimulacra2Error> {
+        Self::prepare(
+            Cow::Borrowed(source),
+            Arc::new(BoxDownscaler),
+            CubeRoot::Exact,
+        )
+    }
+
+    fn prepare(
+        source: Cow<'_, LinearRgb>,
+        downscaler: Arc<dyn Downscaler>,
+        cube_root: CubeRoot,
+    ) -> Result<Self, Ssimulacra2Error> {
+        if cfg!(debug_assertions) {
+            check_finite(&source, false)?;
+        }
+
+        let mut scratch = Scratch::new(source.width(), source.height());
+        Self::with_scratch(source, downscaler, cube_root, false, &mut scratch, None)
+    }
+
+    // Prepares `source`, checking `cancellation` between the stages of every
+    // scale, and blurring it in tiles if `tiling` is set. The buffers of the
+    // scales prepared so far are returned to the pool if it is cancelled. A
+    // borrowed source is only read, an owned one is handed to the pool once
+    // it has been converted.
+    pub(crate) fn with_scratch(
+        source: Cow<'_, LinearRgb>,
+        downscaler: Arc<dyn Downscaler>,
+        cube_root: CubeRoot,
+        tiling: bool,
+        scratch: &mut Scratch,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<Self, Ssimulacra2Error> {
+        let width = source.width();
+        let height = source.height();
+        if width < 8 || height < 8 {
+            return Err(Ssimulacra2Error::InvalidImageSize);
+        }
+
+        let mut reference = Reference {
+            width,
+            height,
+            scales: Vec::with_capacity(NUM_SCALES),
+            downscaler,
+            validate: cfg!(debug_assertions),
+            rescale: None,
+            cube_root,
+            tiling,
+            cancellation,
+        };
+
+        let mut next = Some(source);
+        for scale in 0..NUM_SCALES {
+            let Some(img) = next.take() else {
+                break;
+            };
+            if let Err(err) = check_cancelled(reference.cancellation.as_ref()) {
+                reference.recycle(&mut scratch.pool);
+                return Err(err);
+            }
+            let width = img.width();
+            let height = img.height();
+
+            // The next scale is downscaled first, so that the buffer of this
+            // one can be reused.
+            if scale + 1 < NUM_SCALES && width >= 8 && height >= 8 {
+                let buffer = scratch
+                    .pool
+                    .take_pixels(((width + 1) / 2) * ((height + 1) / 2));
+                next = Some(Cow::Owned(downscale_checked(
+                    reference.downscaler.as_ref(),
+                    &img,
+                    buffer,
+                )));
+            }
+            scratch.blur.resize(width, height);
+            let pool = &mut scratch.pool;
+
+            let mut planar = pool.take_image(width, height, 3);
+            linear_rgb_to_xyb_image(&img, &mut planar, cube_root);
+            if let Cow::Owned(img) = img {
+                pool.give_pixels(img.into_data());
+            }
+            let mut sigma_sq = pool.take_image(width, height, 3);
+            scratch
+                .blur
+                .blur_image_product_into_tiled(&planar, &planar, &mut sigma_sq, tiling);
+            let mut mu = pool.take_image(width, height, 3);
+            scratch.blur.blur_image_into_tiled(&planar, &mut mu, tiling);
+
+            reference.scales.push(ReferenceScale {
+                width,
+                height,
+                img: planar,
+                mu,
+                sigma_sq,
+            });
+        }
+
+        Ok(reference)
+    }
+
+    /// Enables or disables checking distorted images for NaN and infinite
+    /// values before comparing them. Without the check, such values make the
+    /// comparison fail with [`Ssimulacra2Error::NonFiniteScore`] at best, or
+    /// are silently clamped, without telling where they are.
+    ///
+    /// The check is enabled by default in debug builds only. The source is
+    /// checked when creating the reference in debug builds; use a
+    /// [`Ssimulacra2`][crate::Ssimulacra2] session to check it in release builds.
+    #[must_use]
+    pub const fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Scales distorted images of another resolution to the resolution of the
+    /// reference with `filter`, instead of failing with
+    /// [`Ssimulacra2Error::NonMatchingImageDimensions`]. This is
+    /// [`Rescale::ToSource`][crate::Rescale::ToSource] of a session.
+    #[must_use]
+    pub const fn with_rescale(mut self, filter: ResizeFilter) -> Self {
+        self.rescale = Some(filter);
+        self
+    }
+
+    /// Compares distorted images in tiles of rows, see
+    /// [`Ssimulacra2::with_tiling`][crate::Ssimulacra2::with_tiling]. The
+    /// source was blurred without tiles when creating the reference.
+    #[must_use]
+    pub const fn with_tiling(mut self, tiling: bool) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    /// Checks `token` between the stages of every comparison, failing with
+    /// [`Ssimulacra2Error::Cancelled`] once it has been cancelled.
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Width of the source image.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the source image.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` against this reference.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare<U>(&self, distorted: U) -> Result<f64, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let img = self.convert_distorted(distorted)?;
+        let mut scratch = Scratch::new(self.width, self.height);
+        Ok(self.compare_with_scratch(img, None, &mut scratch)?.score())
+    }
+
+    /// Like [`compare`][Self::compare], but borrows a distorted image which is
+    /// already in linear RGB. The image is only read; if it has to be
+    /// [rescaled][Self::with_rescale], the rescaled copy is compared.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_borrowed(&self, distorted: &LinearRgb) -> Result<f64, Ssimulacra2Error> {
+        let img = self.check_distorted(Cow::Borrowed(distorted))?;
+        let mut scratch = Scratch::new(self.width, self.height);
+        Ok(self.compare_with_scratch(img, None, &mut scratch)?.score())
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` against this reference,
+    /// together with the error of each scale.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_detailed<U>(&self, distorted: U) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let img = self.convert_distorted(distorted)?;
+        let mut scratch = Scratch::new(self.width, self.height);
+        let msssim = self.compare_with_scratch(img, None, &mut scratch)?;
+        Ok(DetailedScore {
+            score: msssim.score(),
+            scales: msssim.scale_errors(),
+            planes: msssim.plane_errors(),
+            map: None,
+            companion: None,
+            levels: None,
+            shift: None,
+            crop: None,
+        })
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` against this reference,
+    /// together with a [`DistortionMap`] showing where the distortion is located.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_with_map<U>(
+        &self,
+        distorted: U,
+    ) -> Result<(f64, DistortionMap), Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let img = self.convert_distorted(distorted)?;
+        let mut map = DistortionMap::new(self.width, self.height);
+        let mut scratch = Scratch::new(self.width, self.height);
+        let score = self
+            .compare_with_scratch(img, Some(&mut map), &mut scratch)?
+            .score();
+        Ok((score, map))
+    }
+
+    /// Like [`compare_detailed`][Self::compare_detailed], but also fills in the
+    /// [`DistortionMap`] of the score.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_detailed_with_map<U>(
+        &self,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let img = self.convert_distorted(distorted)?;
+        let mut map = DistortionMap::new(self.width, self.height);
+        let mut scratch = Scratch::new(self.width, self.height);
+        let msssim = self.compare_with_scratch(img, Some(&mut map), &mut scratch)?;
+        Ok(DetailedScore {
+            score: msssim.score(),
+            scales: msssim.scale_errors(),
+            planes: msssim.plane_errors(),
+            map: Some(map),
+            companion: None,
+            levels: None,
+            shift: None,
+            crop: None,
+        })
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` against this reference if
+    /// it reaches `target`, and returns [`None`] otherwise, for rate control
+    /// loops which only need to know whether an encode is good enough.
+    ///
+    /// The scales are compared from the smallest to the largest. Every scale
+    /// can only add to the error, so once the errors of the scales compared so
+    /// far already map to a score below `target`, the remaining, larger scales
+    /// are skipped. Encodes far below the target are rejected after about a
+    /// third of the work, while those close to it take the full comparison.
+    /// A returned score is the same as that of [`compare`][Self::compare].
+    ///
+    /// The mapping from errors to scores is so flat around a score of -64
+    /// that rounding can give a slightly higher score to a larger error.
+    /// Encodes scoring about -64 after some of the scales are therefore
+    /// always compared in full.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_with_target<U>(
+        &self,
+        distorted: U,
+        target: f64,
+    ) -> Result<Option<f64>, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let img = self.convert_distorted(distorted)?;
+        // Downscaling is cheap next to comparing, so all scales are
+        // downscaled first.
+        let mut images = Vec::with_capacity(self.scales.len());
+        images.push(img);
+        while images.len() < self.scales.len() {
+            let next = downscale_checked(
+                self.downscaler.as_ref(),
+                &images[images.len() - 1],
+                Vec::new(),
+            );
+            images.push(Cow::Owned(next));
+        }
+
+        // Scales which were not compared yet have no error.
+        let mut msssim = Msssim {
+            scales: alloc::vec![MsssimScale::default(); self.scales.len()],
+        };
+        let mut scratch = Scratch::new(self.width, self.height);
+        for (scale, img) in images.iter().enumerate().rev() {
+            check_cancelled(self.cancellation.as_ref())?;
+            msssim.scales[scale] = self.compare_scale(scale, img, &mut scratch);
+            // NaN never compares below the target, so non-finite errors are
+            // reported after all scales.
+            if misses_target(msssim.error(), target) {
+                return Ok(None);
+            }
+        }
+
+        if !msssim.is_finite() {
+            return Err(Ssimulacra2Error::NonFiniteScore);
+        }
+        let score = msssim.score();
+        Ok((score >= target).then_some(score))
+    }
+
+    /// Computes the SSIMULACRA2 score of every horizontal band of `band_rows`
+    /// rows of `distorted` against this reference, from the top down, e.g.
+    /// of every superblock row of an encoder, which rate control can adjust
+    /// the quantizer of.
+    ///
+    /// Each band is scored as if it were an image of its own, from its rows
+    /// at every scale, rounded outwards at the smaller ones, while the blur
+    /// still sees the rows around it. The bands take about as long as one
+    /// [`compare`][Self::compare]. Their scores do not average to the score
+    /// of the whole image, but tell which bands hold most of its distortion.
+    ///
+    /// # Errors
+    /// - If the distorted image width and height do not match the reference
+    /// - If the distorted image cannot be converted to XYB successfully
+    /// - If validation is enabled and the distorted image contains NaN or infinite values
+    /// - If the images contain values for which no score can be computed
+    /// - If the comparison is cancelled
+    pub fn compare_bands<U>(
+        &self,
+        distorted: U,
+        band_rows: usize,
+    ) -> Result<Vec<f64>, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let band_rows = band_rows.max(1);
+        let bands = (self.height + band_rows - 1) / band_rows;
+        let mut msssims = alloc::vec![Msssim::default(); bands];
+
+        let mut next = Some(self.convert_distorted(distorted)?);
+        for (scale, reference) in self.scales.iter().enumerate() {
+            let Some(img) = next.take() else {
+                break;
+            };
+            check_cancelled(self.cancellation.as_ref())?;
+            if scale + 1 < self.scales.len() {
+                let downscaled = downscale_checked(self.downscaler.as_ref(), &img, Vec::new());
+                next = Some(Cow::Owned(downscaled));
+            }
+
+            let (width, height) = (reference.width, reference.height);
+            let tiles: Vec<_> = (0..bands)
+                .map(|band| {
+                    let end = ((band + 1) * band_rows).min(self.height);
+                    let rows = (band * band_rows) >> scale..(end + (1 << scale) - 1) >> scale;
+                    Tile::rows(rows.start..rows.end.min(height), height)
+                })
+                .collect();
+            let jobs: Vec<_> = (0..3usize)
+                .flat_map(|c| tiles.iter().map(move |tile| (c, tile.clone())))
+                .collect();
+            let mut xyb = ImageF32::default();
+            linear_rgb_to_xyb_image(&img, &mut xyb, self.cube_root);
+            let sums = compare_tiles(
+                width,
+                self.source_channels(scale),
+                [0usize, 1, 2].map(|c| xyb.plane(c)),
+                &jobs,
+            );
+
+            for (band, (msssim, tile)) in msssims.iter_mut().zip(&tiles).enumerate() {
+                let errors = array::from_fn(|c| {
+                    merge_tiles(&sums[c * bands + band..][..1], width * tile.rows.len())
+                });
+                msssim.scales.push(MsssimScale::from_channels(&errors));
+            }
+        }
+
+        msssims
+            .iter()
+            .map(|msssim| {
+                if msssim.is_finite() {
+                    Ok(msssim.score())
+                } else {
+                    Err(Ssimulacra2Error::NonFiniteScore)
+                }
+            })
+            .collect()
+    }
+
+    // Compares the distorted image `img` at `scale` against the source.
+    fn compare_scale(&self, scale: usize, img: &LinearRgb, scratch: &mut Scratch) -> MsssimScale {
+        let (width, height) = self.scale_size(scale);
+        let mut planar = scratch.pool.take_image(width, height, 3);
+        linear_rgb_to_xyb_image(img, &mut planar, self.cube_root);
+        let errors = compare_channels(
+            width,
+            height,
+            self.source_channels(scale),
+            [0usize, 1, 2].map(|c| planar.plane(c)),
+            &mut scratch.channels,
+            None,
+            self.tiling,
+        );
+        scratch.pool.give_image(planar);
+        MsssimScale::from_channels(&errors)
+    }
+
+    /// Computes the SSIMULACRA2 scores of several distorted images against
+    /// this reference, e.g. of candidate encodes of the same source.
+    ///
+    /// Up to four images are compared in the same pass over the reference:
+    /// their planes are interleaved and blurred in the lanes of the same row
+    /// buffers, so that the planes of the reference are read once for all of
+    /// them. With the `rayon` feature, the groups of images are compared in
+    /// parallel. The scores are the same as those of
+    /// [`compare`][Self::compare].
+    ///
+    /// Returns the score of every image, or why it could not be scored, in
+    /// the order of `distorted`.
+    pub fn compare_many<I, U>(&self, distorted: I) -> Vec<Result<f64, Ssimulacra2Error>>
+    where
+        I: IntoIterator<Item = U>,
+        LinearRgb: TryFrom<U>,
+    {
+        // The images which cannot be compared keep their error, the others
+        // are compared in groups.
+        let mut results = Vec::new();
+        let mut images = Vec::new();
+        for distorted in distorted {
+            match self.convert_distorted(distorted) {
+                Ok(img) => {
+                    images.push(img);
+                    results.push(None);
+                }
+                Err(err) => results.push(Some(Err(err))),
+            }
+        }
+
+        let compare = |group: &[Cow<'static, LinearRgb>]| {
+            let mut scratch = Scratch::new(self.width, self.height);
+            match *group {
+                [ref a] => self.compare_lanes([a], &mut scratch).to_vec(),
+                [ref a, ref b] => self.compare_lanes([a, b], &mut scratch).to_vec(),
+                [ref a, ref b, ref c] => self.compare_lanes([a, b, c], &mut scratch).to_vec(),
+                [ref a, ref b, ref c, ref d] => {
+                    self.compare_lanes([a, b, c, d], &mut scratch).to_vec()
+                }
+                _ => unreachable!("Groups have one to four images"),
+            }
+        };
+
+        #[cfg(feature = "rayon")]
+        let mut scores = {
+            use rayon::iter::ParallelIterator;
+            use rayon::slice::ParallelSlice;
+
+            images
+                .par_chunks(MAX_LANES)
+                .flat_map_iter(compare)
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let mut scores = images.chunks(MAX_LANES).flat_map(compare);
+
+        results
+            .into_iter()
+            .map(|result| {
+                result
+                    .or_else(|| scores.next())
+                    .unwrap_or_else(|| unreachable!("Every image is scored"))
+            })
+            .collect()
+    }
+
+    // Compares `LANES` distorted images against the source, scale by scale,
+    // with the values of each channel of the images interleaved, see
+    // `compare_channels_lanes`. Scales which are compared in tiles are
+    // compared image by image, which gives the same errors as `compare`.
+    fn compare_lanes<const LANES: usize>(
+        &self,
+        images: [&Cow<'_, LinearRgb>; LANES],
+        scratch: &mut Scratch,
+    ) -> [Result<f64, Ssimulacra2Error>; LANES] {
+        let mut msssims: [Msssim; LANES] = array::from_fn(|_| Msssim::default());
+
+        let mut next: [Option<Cow<'_, LinearRgb>>; LANES] =
+            images.map(|img| Some(Cow::Borrowed(&**img)));
+        for (scale, reference) in self.scales.iter().enumerate() {
+            if let Err(err) = check_cancelled(self.cancellation.as_ref()) {
+                return [Err(err); LANES];
+            }
+            let width = reference.width;
+            let height = reference.height;
+            let Scratch {
+                ref mut pool,
+                ref mut channels,
+                ..
+            } = *scratch;
+
+            // Every image is converted into the same planes, and then copied
+            // into its lane, unless the scale is compared in tiles.
+            let source = self.source_channels(scale);
+            let tiled = Tile::is_tiled(height, self.tiling);
+            let mut planar = pool.take_image(width, height, 3);
+            let mut interleaved = if tiled {
+                ImageF32::default()
+            } else {
+                pool.take_image(width * LANES, height, 3)
+            };
+            let mut errors = [[ChannelErrors::default(); 3]; LANES];
+            for (lane, next) in next.iter_mut().enumerate() {
+                let Some(img) = next.take() else {
+                    continue;
+                };
+                if let Some(next_reference) = self.scales.get(scale + 1) {
+                    let buffer = pool.take_pixels(next_reference.width * next_reference.height);
+                    *next = Some(Cow::Owned(downscale_checked(
+                        self.downscaler.as_ref(),
+                        &img,
+                        buffer,
+                    )));
+                }
+                linear_rgb_to_xyb_image(&img, &mut planar, self.cube_root);
+                if let Cow::Owned(img) = img {
+                    pool.give_pixels(img.into_data());
+                }
+
+                if tiled {
+                    errors[lane] = compare_channels(
+                        width,
+                        height,
+                        source,
+                        [0usize, 1, 2].map(|c| planar.plane(c)),
+                        channels,
+                        None,
+                        self.tiling,
+                    );
+                    continue;
+                }
+                for c in 0..3 {
+                    let pixels = interleaved.plane_mut(c).chunks_exact_mut(LANES);
+                    for (pixel, &value) in pixels.zip(planar.plane(c)) {
+                        pixel[lane] = value;
+                    }
+                }
+            }
+            pool.give_image(planar);
+
+            if !tiled {
+                errors = compare_channels_lanes::<3, LANES>(
+                    width,
+                    height,
+                    source,
+                    [0usize, 1, 2].map(|c| interleaved.plane(c)),
+                    channels,
+                );
+                pool.give_image(interleaved);
+            }
+
+            for (msssim, errors) in msssims.iter_mut().zip(&errors) {
+                msssim.scales.push(MsssimScale::from_channels(errors));
+            }
+        }
+
+        msssims.map(|msssim| {
+            if msssim.is_finite() {
+                Ok(msssim.score())
+            } else {
+                Err(Ssimulacra2Error::NonFiniteScore)
+            }
+        })
+    }
+
+    pub(crate) fn convert_distorted<U>(
+        &self,
+        distorted: U,
+    ) -> Result<Cow<'static, LinearRgb>, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<U>,
+    {
+        let Ok(img) = LinearRgb::try_from(distorted) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        self.check_distorted(Cow::Owned(img))
+    }
+
+    // Rescales `img` to the reference if enabled and validates it.
+    pub(crate) fn check_distorted<'a>(
+        &self,
+        mut img: Cow<'a, LinearRgb>,
+    ) -> Result<Cow<'a, LinearRgb>, Ssimulacra2Error> {
+        if img.width() != self.width || img.height() != self.height {
+            let Some(filter) = self.rescale else {
+                return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+            };
+            img = Cow::Owned(filter.resize(&img, self.width, self.height));
+        }
+        if self.validate {
+            check_finite(&img, true)?;
+        }
+
+        Ok(img)
+    }
+
+    pub(crate) fn compare_with_scratch(
+        &self,
+        distorted: Cow<'_, LinearRgb>,
+        mut map: Option<&mut DistortionMap>,
+        scratch: &mut Scratch,
+    ) -> Result<Msssim, Ssimulacra2Error> {
+        let mut msssim = Msssim::default();
+
+        let mut next = Some(distorted);
+        for (scale, reference) in self.scales.iter().enumerate() {
+            let Some(img) = next.take() else {
+                break;
+            };
+            check_cancelled(self.cancellation.as_ref())?;
+            if let Some(next_reference) = self.scales.get(scale + 1) {
+                let buffer = scratch
+                    .pool
+                    .take_pixels(next_reference.width * next_reference.height);
+                next = Some(Cow::Owned(downscale_checked(
+                    self.downscaler.as_ref(),
+                    &img,
+                    buffer,
+                )));
+            }
+            let width = reference.width;
+            let height = reference.height;
+            let Scratch {
+                ref mut pool,
+                ref mut channels,
+                ..
+            } = *scratch;
+
+            let mut planar = pool.take_image(width, height, 3);
+            linear_rgb_to_xyb_image(&img, &mut planar, self.cube_root);
+            if let Cow::Owned(img) = img {
+                pool.give_pixels(img.into_data());
+            }
+
+            let source = self.source_channels(scale);
+            let map = if scale == 0 { map.as_deref_mut() } else { None };
+            let errors = compare_channels(
+                width,
+                height,
+                source,
+                [0usize, 1, 2].map(|c| planar.plane(c)),
+                channels,
+                map.map(|map| (map, self.scales.len())),
+                self.tiling,
+            );
+            pool.give_image(planar);
+
+            msssim.scales.push(MsssimScale::from_channels(&errors));
+        }
+
+        // Infinite inputs turn into NaN errors, which would otherwise be
+        // reported as a perfect score.
+        if !msssim.is_finite() {
+            return Err(Ssimulacra2Error::NonFiniteScore);
+        }
+        Ok(msssim)
+    }
+
+    // The number of scales the source was prepared at.
+    pub(crate) fn num_scales(&self) -> usize {
+        self.scales.len()
+    }
+
+    // The width and height of the source at `scale`.
+    pub(crate) fn scale_size(&self, scale: usize) -> (usize, usize) {
+        let scale = &self.scales[scale];
+        (scale.width, scale.height)
+    }
+
+    // The channels of the source at `scale`.
+    pub(crate) fn source_channels(&self, scale: usize) -> [SourceChannel<'_>; 3] {
+        let scale = &self.scales[scale];
+        [0usize, 1, 2].map(|c| SourceChannel {
+            img: scale.img.plane(c),
+            mu: scale.mu.plane(c),
+            sigma_sq: scale.sigma_sq.plane(c),
+        })
+    }
+
+    pub(crate) fn downscaler(&self) -> &dyn Downscaler {
+        self.downscaler.as_ref()
+    }
+
+    pub(crate) const fn cube_root(&self) -> CubeRoot {
+        self.cube_root
+    }
+
+    // The hash of all planes of all scales, for checking that comparisons
+    // leave them untouched.
+    #[cfg(test)]
+    #[must_use]
+    pub fn content_hash(&self) -> u64 {
+        crate::PlaneHasher::hash_planes(self.scales.iter().flat_map(|scale| {
+            [&scale.img, &scale.mu, &scale.sigma_sq]
+                .into_iter()
+                .flat_map(|image| (0..image.planes()).map(|c| image.plane(c)))
+        }))
+    }
+
+    // Returns the buffers of all scales to `pool`.
+    pub(crate) fn recycle(self, pool: &mut ScratchPool) {
+        for scale in self.scales {
+            for image in [scale.img, scale.mu, scale.sigma_sq] {
+                pool.give_image(image);
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use base64::Engine;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+
+use crate::{DetailedScore, DistortionMap, Heatmap, ScoreAggregator, ScoreSummary};
+
+// The default width of heatmap thumbnails in pixels.
+const THUMBNAIL_WIDTH: usize = 256;
+
+// The size of the score timelines of videos in pixels.
+const TIMELINE_WIDTH: f64 = 800.0;
+const TIMELINE_HEIGHT: f64 = 200.0;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:0.3em 0.6em;text-align:right;vertical-align:middle}\
+th:first-child,td:first-child{text-align:left}\
+img{image-rendering:pixelated;display:block}\
+svg{background:#fafafa;border:1px solid #ccc}";
+
+/// A self-contained HTML report of a comparison run, e.g. for codec
+/// evaluation write-ups.
+///
+/// Images are listed with their scores, the errors of every scale and XYB
+/// plane, and a thumbnail of their [`DistortionMap`] if they were scored with
+/// one. Videos are listed with a [summary][ScoreSummary] and a timeline of
+/// their frame scores. Thumbnails are embedded as PNG data URIs and timelines
+/// as SVG, so the report is a single file without scripts.
+#[derive(Debug, Clone)]
+pub struct Report {
+    title: String,
+    heatmap: Heatmap,
+    thumbnail_width: usize,
+    images: Vec<ImageEntry>,
+    videos: Vec<VideoEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct ImageEntry {
+    name: String,
+    score: f64,
+    scales: Vec<f64>,
+    planes: Option<[f64; 3]>,
+    // The heatmap thumbnail as a data URI.
+    thumbnail: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct VideoEntry {
+    name: String,
+    summary: Option<ScoreSummary>,
+    // The frame index and score of every frame, in order.
+    frames: Vec<(usize, f64)>,
+}
+
+impl Report {
+    /// Starts an empty report with the given title.
+    #[must_use]
+    pub fn new(title: impl Into<String>) -> Self {
+        Report {
+            title: title.into(),
+            heatmap: Heatmap::default(),
+            thumbnail_width: THUMBNAIL_WIDTH,
+            images: Vec::new(),
+            videos: Vec::new(),
+        }
+    }
+
+    /// Renders the thumbnails of images added from now on with `heatmap`.
+    #[must_use]
+    pub const fn with_heatmap(mut self, heatmap: Heatmap) -> Self {
+        self.heatmap = heatmap;
+        self
+    }
+
+    /// Shrinks the thumbnails of images added from now on to at most `width`
+    /// pixels wide, averaging the values of the map. The default is 256.
+    #[must_use]
+    pub fn with_thumbnail_width(mut self, width: usize) -> Self {
+        self.thumbnail_width = width.max(1);
+        self
+    }
+
+    /// Adds an image with its detailed score. The thumbnail is rendered from
+    /// its map right away, so the map does not have to be kept.
+    pub fn add_image(&mut self, name: impl Into<String>, score: &DetailedScore) {
+        let thumbnail = score.map.as_ref().map(|map| self.thumbnail(map));
+        self.images.push(ImageEntry {
+            name: name.into(),
+            score: score.score,
+            scales: score.scales.clone(),
+            planes: Some(score.planes),
+            thumbnail,
+        });
+    }
+
+    /// Adds an image of which only the score is known, e.g. one taken from a
+    /// [`ScoreCache`][crate::ScoreCache].
+    pub fn add_score(&mut self, name: impl Into<String>, score: f64) {
+        self.images.push(ImageEntry {
+            name: name.into(),
+            score,
+            scales: Vec::new(),
+            planes: None,
+            thumbnail: None,
+        });
+    }
+
+    /// Adds a video with the scores of its frames.
+    pub fn add_video(&mut self, name: impl Into<String>, scores: &ScoreAggregator) {
+        self.videos.push(VideoEntry {
+            name: name.into(),
+            summary: scores.summary(),
+            frames: scores
+                .scores()
+                .iter()
+                .map(|frame| (frame.frame, frame.score))
+                .collect(),
+        });
+    }
+
+    /// The report as an HTML document.
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        // Writing to a string does not fail.
+        let _ = self.write_html(&mut html);
+        html
+    }
+
+    /// Writes the report as an HTML document to `path`.
+    ///
+    /// # Errors
+    /// - If the file cannot be written
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_html())
+    }
+
+    fn write_html(&self, html: &mut String) -> std::fmt::Result {
+        let title = escape(&self.title);
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+        )?;
+
+        if !self.images.is_empty() {
+            self.write_images(html)?;
+        }
+        for video in &self.videos {
+            write_video(html, video)?;
+        }
+
+        html.push_str("</body>\n</html>\n");
+        Ok(())
+    }
+
+    fn write_images(&self, html: &mut String) -> std::fmt::Result {
+        let num_scales = self.images.iter().map(|image| image.scales.len()).max();
+        let num_scales = num_scales.unwrap_or(0);
+        html.push_str("<h2>Images</h2>\n<table>\n<tr><th>Image</th><th>Score</th>");
+        for scale in 0..num_scales {
+            write!(html, "<th>Scale 1:{}</th>", 1usize << scale)?;
+        }
+        html.push_str("<th>X</th><th>Y</th><th>B</th><th>Heatmap</th></tr>\n");
+
+        for image in &self.images {
+            write!(
+                html,
+                "<tr><td>{}</td><td>{:.4}</td>",
+                escape(&image.name),
+                image.score
+            )?;
+            for scale in 0..num_scales {
+                match image.scales.get(scale) {
+                    Some(error) => write!(html, "<td>{error:.4}</td>")?,
+                    None => html.push_str("<td></td>"),
+                }
+            }
+            match image.planes {
+                Some(planes) => {
+                    for error in planes {
+                        write!(html, "<td>{error:.4}</td>")?;
+                    }
+                }
+                None => html.push_str("<td></td><td></td><td></td>"),
+            }
+            match image.thumbnail {
+                Some(ref uri) => write!(html, "<td><img src=\"{uri}\" alt=\"Heatmap\"></td>")?,
+                None => html.push_str("<td></td>"),
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+        Ok(())
+    }
+
+    // Renders `map`, shrunk to the thumbnail width, as a PNG data URI.
+    fn thumbnail(&self, map: &DistortionMap) -> String {
+        let factor = (map.width() + self.thumbnail_width - 1) / self.thumbnail_width;
+        let thumbnail = shrink(map, factor.max(1));
+        let pixels = thumbnail.to_rgba8(&self.heatmap);
+
+        let mut png = Vec::new();
+        // Encoding into memory only fails for images too large for PNG.
+        #[allow(clippy::cast_possible_truncation)]
+        let encoded = PngEncoder::new(&mut png).write_image(
+            bytemuck::cast_slice(&pixels),
+            thumbnail.width() as u32,
+            thumbnail.height() as u32,
+            ColorType::Rgba8,
+        );
+        if encoded.is_err() {
+            png.clear();
+        }
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        )
+    }
+}
+
+fn write_video(html: &mut String, video: &VideoEntry) -> std::fmt::Result {
+    writeln!(html, "<h2>{}</h2>", escape(&video.name))?;
+    if let Some(ref summary) = video.summary {
+        html.push_str(
+            "<table>\n<tr><th>Frames</th><th>Mean</th><th>Harmonic mean</th><th>Std. dev.</th>\
+             <th>Min</th><th>P1</th><th>P5</th><th>P50</th><th>Max</th></tr>\n",
+        );
+        let harmonic_mean = summary
+            .harmonic_mean
+            .map_or_else(String::new, |mean| format!("{mean:.4}"));
+        write!(
+            html,
+            "<tr><td>{}</td><td>{:.4}</td><td>{harmonic_mean}</td><td>{:.4}</td><td>{:.4}</td>\
+             <td>{:.4}</td><td>{:.4}</td><td>{:.4}</td><td>{:.4}</td></tr>\n</table>\n",
+            summary.frames,
+            summary.mean,
+            summary.std_dev,
+            summary.min,
+            summary.p1,
+            summary.p5,
+            summary.p50,
+            summary.max
+        )?;
+    }
+    if video.frames.len() < 2 {
+        return Ok(());
+    }
+
+    // The timeline spans the frames from the first to the last, and the
+    // scores from the lowest, rounded down to a multiple of 10, to 100.
+    let first = video.frames[0].0 as f64;
+    let last = video.frames[video.frames.len() - 1].0 as f64;
+    let lowest = video
+        .frames
+        .iter()
+        .map(|&(_, score)| score)
+        .fold(100.0f64, f64::min);
+    let bottom = (lowest / 10.0f64).floor().min(9.0f64) * 10.0f64;
+    let x = |frame: usize| (frame as f64 - first) / (last - first).max(1.0f64) * TIMELINE_WIDTH;
+    let y = |score: f64| (100.0f64 - score) / (100.0f64 - bottom) * TIMELINE_HEIGHT;
+
+    writeln!(
+        html,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{TIMELINE_WIDTH}\" \
+         height=\"{TIMELINE_HEIGHT}\" viewBox=\"0 0 {TIMELINE_WIDTH} {TIMELINE_HEIGHT}\" \
+         role=\"img\" aria-label=\"Score of every frame\">"
+    )?;
+    // A grid line every 10 points.
+    let lines = ((100.0f64 - bottom) / 10.0f64).round() as usize;
+    for line in 0..=lines {
+        let score = 10.0f64.mul_add(-(line as f64), 100.0f64);
+        let y = y(score);
+        writeln!(
+            html,
+            "<line x1=\"0\" y1=\"{y:.1}\" x2=\"{TIMELINE_WIDTH}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\
+             <text x=\"4\" y=\"{:.1}\" font-size=\"10\" fill=\"#888\">{score}</text>",
+            (y - 2.0f64).max(10.0f64)
+        )?;
+    }
+    html.push_str("<polyline fill=\"none\" stroke=\"#c0392b\" stroke-width=\"1\" points=\"");
+    for &(frame, score) in &video.frames {
+        write!(html, "{:.1},{:.1} ", x(frame), y(score))?;
+    }
+    html.push_str("\"/>\n</svg>\n");
+    Ok(())
+}
+
+// Averages the values of blocks of `factor`x`factor` values of `map`.
+fn shrink(map: &DistortionMap, factor: usize) -> DistortionMap {
+    let width = (map.width() + factor - 1) / factor;
+    let height = (map.height() + factor - 1) / factor;
+    let mut shrunk = DistortionMap::new(width, height);
+    let data = shrunk.data_mut();
+    for y in 0..height {
+        for x in 0..width {
+            let rows = y * factor..((y + 1) * factor).min(map.height());
+            let columns = x * factor..((x + 1) * factor).min(map.width());
+            let count = rows.len() * columns.len();
+            let sum: f32 = rows
+                .flat_map(|row| &map.data()[row * map.width()..][columns.clone()])
+                .sum();
+            data[y * width + x] = sum / count as f32;
+        }
+    }
+    shrunk
+}
+
+// Escapes `text` for HTML text and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_report() {
+        let mut map = DistortionMap::new(600, 10);
+        for (i, value) in map.data_mut().iter_mut().enumerate() {
+            *value = (i % 600) as f32;
+        }
+        let score = DetailedScore {
+            score: 87.5,
+            scales: vec![0.5f64, 0.25f64],
+            planes: [0.1f64, 0.6f64, 0.05f64],
+            map: Some(map),
+            companion: None,
+            levels: None,
+            shift: None,
+            crop: None,
+        };
+        let mut frames = ScoreAggregator::new();
+        for frame in 0..10 {
+            frames.push(frame, 80.0 + frame as f64);
+        }
+
+        let mut report = Report::new("Encoder <comparison>");
+        report.add_image("a&b.png", &score);
+        report.add_score("cached.png", 42.0);
+        report.add_video("video.y4m", &frames);
+        let html = report.to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>Encoder &lt;comparison&gt;</h1>"));
+        assert!(html.contains("<td>a&amp;b.png</td><td>87.5000</td><td>0.5000</td>"));
+        assert!(html.contains("<th>Scale 1:2</th>"));
+        assert_eq!(html.matches("data:image/png;base64,").count(), 1);
+        assert!(html.contains("<td>cached.png</td><td>42.0000</td><td></td>"));
+        assert!(html.contains("<polyline"));
+        assert!(html.ends_with("</html>\n"));
+
+        // Shrunk to 200x4 values by averaging blocks of 3x3 values, or less at
+        // the bottom edge.
+        let thumbnail = shrink(score.map.as_ref().unwrap(), 3);
+        assert_eq!((thumbnail.width(), thumbnail.height()), (200, 4));
+        assert_eq!(thumbnail.get(1, 3), Some(4.0));
+    }
+}
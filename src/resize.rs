@@ -0,0 +1,225 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+use yuvxyb::LinearRgb;
+
+/// How images of different resolutions are brought to the same resolution
+/// before scoring, see [`Ssimulacra2::with_rescale`][crate::Ssimulacra2::with_rescale].
+///
+/// SSIMULACRA2 compares images pixel by pixel, so by default images of
+/// different resolutions are rejected with
+/// [`NonMatchingImageDimensions`][crate::Ssimulacra2Error::NonMatchingImageDimensions].
+/// The recommended policy for scoring an encode at a lower resolution, e.g. a
+/// 1080p encode of a 4K source, is [`Rescale::ToSource`] with
+/// [`ResizeFilter::Lanczos3`]: this scores what a viewer sees when the encode
+/// is upscaled to the display of the source, including the detail lost to the
+/// lower resolution. [`Rescale::ToDistorted`] instead scores the encode
+/// against an ideal rendition at its own resolution, which only measures the
+/// distortion of the encoder; [`ResizeFilter::Area`] is the usual choice for
+/// that downscale.
+///
+/// Images are stretched to the target resolution, so images of different
+/// aspect ratios should be cropped first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rescale {
+    /// Reject images of different resolutions, like the reference
+    /// implementation.
+    #[default]
+    None,
+    /// Scale the distorted image to the resolution of the source.
+    ToSource(ResizeFilter),
+    /// Scale the source to the resolution of the distorted image.
+    ToDistorted(ResizeFilter),
+}
+
+/// The filter used to rescale images, see [`Rescale`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResizeFilter {
+    /// Averages the pixels each output pixel covers, weighted by the covered
+    /// area. This is exact for downscaling by whole factors and does not
+    /// ring, but upscales to blocky results.
+    Area,
+    /// A windowed sinc with three lobes, which keeps images sharp when
+    /// upscaling and avoids aliasing when downscaling, at the cost of slight
+    /// ringing at hard edges. Values that would ring below zero are clamped.
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// Resizes `img` to `width`x`height` pixels in linear light.
+    ///
+    /// # Panics
+    /// - If the width or height is zero
+    #[must_use]
+    pub fn resize(self, img: &LinearRgb, width: usize, height: usize) -> LinearRgb {
+        let in_width = img.width();
+        let in_height = img.height();
+        if width == in_width && height == in_height {
+            return img.clone();
+        }
+
+        let columns = self.contributions(in_width, width);
+        let mut horizontal = Vec::with_capacity(width * in_height);
+        for row in img.data().chunks_exact(in_width) {
+            horizontal.extend(columns.iter().map(|c| c.apply(row)));
+        }
+
+        let rows = self.contributions(in_height, height);
+        let mut data = vec![[0.0f32; 3]; width * height];
+        let mut column = vec![[0.0f32; 3]; in_height];
+        for x in 0..width {
+            for (y, pix) in column.iter_mut().enumerate() {
+                *pix = horizontal[y * width + x];
+            }
+            for (y, contribution) in rows.iter().enumerate() {
+                data[y * width + x] = contribution.apply(&column).map(|v| v.max(0.0f32));
+            }
+        }
+        LinearRgb::new(data, width, height).expect("Resolution and data size match")
+    }
+
+    // The weights of the input pixels contributing to every output pixel
+    // when resizing from `in_len` to `out_len` pixels. Pixels beyond the
+    // edges repeat the edge pixels.
+    fn contributions(self, in_len: usize, out_len: usize) -> Vec<Contribution> {
+        let scale = in_len as f64 / out_len as f64;
+        (0..out_len)
+            .map(|o| {
+                let mut taps: Vec<(isize, f64)> = match self {
+                    ResizeFilter::Area => {
+                        let start = o as f64 * scale;
+                        let end = (o + 1) as f64 * scale;
+                        (start.floor() as isize..end.ceil() as isize)
+                            .map(|i| {
+                                let overlap = end.min((i + 1) as f64) - start.max(i as f64);
+                                (i, overlap)
+                            })
+                            .collect()
+                    }
+                    ResizeFilter::Lanczos3 => {
+                        // Stretch the kernel when downscaling, so that it
+                        // low-pass filters at the output resolution.
+                        let stretch = scale.max(1.0f64);
+                        let center = (o as f64 + 0.5f64).mul_add(scale, -0.5f64);
+                        let support = 3.0f64 * stretch;
+                        ((center - support).ceil() as isize..=(center + support).floor() as isize)
+                            .map(|i| (i, lanczos3((i as f64 - center) / stretch)))
+                            .collect()
+                    }
+                };
+                for tap in &mut taps {
+                    tap.0 = tap.0.clamp(0, in_len as isize - 1);
+                }
+                let start = taps.iter().map(|&(i, _)| i).min().unwrap_or(0);
+                let end = taps.iter().map(|&(i, _)| i).max().unwrap_or(0);
+                let mut weights = vec![0.0f64; (end - start + 1) as usize];
+                for (i, weight) in taps {
+                    weights[(i - start) as usize] += weight;
+                }
+                let sum: f64 = weights.iter().sum();
+                Contribution {
+                    start: start as usize,
+                    weights: weights.iter().map(|w| (w / sum) as f32).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+struct Contribution {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+impl Contribution {
+    fn apply(&self, pixels: &[[f32; 3]]) -> [f32; 3] {
+        let mut out = [0.0f32; 3];
+        for (pix, &weight) in pixels[self.start..].iter().zip(&self.weights) {
+            for (out, value) in out.iter_mut().zip(pix) {
+                *out = value.mul_add(weight, *out);
+            }
+        }
+        out
+    }
+}
+
+fn lanczos3(x: f64) -> f64 {
+    if x.abs() < 1e-9f64 {
+        1.0f64
+    } else if x.abs() < 3.0f64 {
+        let pi_x = PI * x;
+        3.0f64 * pi_x.sin() * (pi_x / 3.0f64).sin() / (pi_x * pi_x)
+    } else {
+        0.0f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Ssimulacra2, Ssimulacra2Error};
+
+    fn image(width: usize, height: usize) -> LinearRgb {
+        let data = (0..width * height)
+            .map(|i| {
+                let x = (i % width) as f32 / width as f32;
+                let y = (i / width) as f32 / height as f32;
+                [x, y, (x * 7.0f32).sin().mul_add(0.25f32, 0.5f32)]
+            })
+            .collect();
+        LinearRgb::new(data, width, height).unwrap()
+    }
+
+    #[test]
+    fn area_averages_blocks() {
+        let img = image(16, 12);
+        let half = ResizeFilter::Area.resize(&img, 8, 6);
+        for (i, pix) in half.data().iter().enumerate() {
+            let (x, y) = (i % 8 * 2, i / 8 * 2);
+            for (c, value) in pix.iter().enumerate() {
+                let sum: f32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|&(dx, dy)| img.data()[(y + dy) * 16 + x + dx][c])
+                    .sum();
+                assert!((value - sum / 4.0f32).abs() < 1e-5f32);
+            }
+        }
+    }
+
+    #[test]
+    fn rescales_before_scoring() {
+        // A flat image stays flat at any resolution.
+        let flat = |width, height| {
+            LinearRgb::new(vec![[0.2f32, 0.4, 0.6]; width * height], width, height).unwrap()
+        };
+        let upscaled = ResizeFilter::Lanczos3.resize(&flat(20, 15), 64, 48);
+        assert!(upscaled
+            .data()
+            .iter()
+            .all(|pix| (pix[1] - 0.4f32).abs() < 1e-5f32));
+
+        let source = image(64, 48);
+        let half = ResizeFilter::Area.resize(&source, 32, 24);
+        let mut session = Ssimulacra2::new(64, 48).unwrap();
+        assert_eq!(
+            session.score(source.clone(), half.clone()),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+        let score = |rescale| {
+            Ssimulacra2::new(64, 48)
+                .unwrap()
+                .with_rescale(rescale)
+                .score(source.clone(), half.clone())
+                .unwrap()
+        };
+        // Downscaling the source the same way gives the same image.
+        let same = score(Rescale::ToDistorted(ResizeFilter::Area));
+        assert!(same > 99.9f64, "{same}");
+        let lanczos = score(Rescale::ToSource(ResizeFilter::Lanczos3));
+        let area = score(Rescale::ToSource(ResizeFilter::Area));
+        assert!(lanczos > 85.0f64 && lanczos > area, "{lanczos} {area}");
+    }
+}
@@ -0,0 +1,224 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use yuvxyb::{CastFromPrimitive, Pixel, Yuv};
+
+// Number of bins of a luma histogram.
+const BINS: usize = 64;
+
+/// The distribution of the luma values of a frame, see [`SceneDetector`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LumaHistogram {
+    bins: Vec<f64>,
+}
+
+impl LumaHistogram {
+    /// Computes the histogram of the luma plane of a frame.
+    #[must_use]
+    pub fn new<T: Pixel>(frame: &Yuv<T>) -> Self {
+        let plane = &frame.data()[0];
+        let shift = frame.config().bit_depth.saturating_sub(6);
+        let (width, height, stride) = (plane.cfg.width, plane.cfg.height, plane.cfg.stride);
+
+        let mut counts = vec![0u64; BINS];
+        let origin = plane.data_origin();
+        for y in 0..height {
+            for &pix in &origin[y * stride..][..width] {
+                let bin = usize::from(u16::cast_from(pix) >> shift);
+                counts[bin.min(BINS - 1)] += 1;
+            }
+        }
+        let pixels = (width * height).max(1) as f64;
+        LumaHistogram {
+            bins: counts.into_iter().map(|n| n as f64 / pixels).collect(),
+        }
+    }
+
+    /// The fraction of pixels whose luma differs between the two frames
+    /// after binning, from 0 for identical histograms to 1 for histograms
+    /// without any overlap.
+    #[must_use]
+    pub fn distance(&self, other: &Self) -> f64 {
+        self.bins
+            .iter()
+            .zip(&other.bins)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f64>()
+            / 2.0f64
+    }
+}
+
+/// Finds scene cuts in a video from the change of the luma histogram between
+/// consecutive frames.
+///
+/// A frame starts a new scene if its histogram differs from that of the
+/// previous frame by more than the threshold. Cuts between shots with similar
+/// brightness are missed, and fast fades or flashes may be taken for cuts,
+/// but the detection is cheap and good enough to group the scores of a video
+/// by scene, see [`ScoreAggregator::scenes`][crate::ScoreAggregator::scenes].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SceneDetector {
+    threshold: f64,
+    min_length: usize,
+    previous: Option<LumaHistogram>,
+    scene_start: usize,
+    cuts: Vec<usize>,
+}
+
+impl Default for SceneDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneDetector {
+    /// The default threshold of [`SceneDetector::with_threshold`].
+    pub const DEFAULT_THRESHOLD: f64 = 0.3;
+
+    /// Creates a detector with the default threshold and no minimum scene
+    /// length.
+    #[must_use]
+    pub const fn new() -> Self {
+        SceneDetector {
+            threshold: Self::DEFAULT_THRESHOLD,
+            min_length: 1,
+            previous: None,
+            scene_start: 0,
+            cuts: Vec::new(),
+        }
+    }
+
+    /// Sets the [`LumaHistogram::distance`] above which a frame starts a new
+    /// scene. Lower thresholds find more cuts.
+    #[must_use]
+    pub fn with_threshold(self, threshold: f64) -> Self {
+        SceneDetector { threshold, ..self }
+    }
+
+    /// Sets the minimum number of frames of a scene, so that flashes and
+    /// fades don't split a scene into many short ones.
+    #[must_use]
+    pub fn with_min_length(self, frames: usize) -> Self {
+        SceneDetector {
+            min_length: frames,
+            ..self
+        }
+    }
+
+    /// Adds the next frame of the video, returning whether it starts a new
+    /// scene. The first frame does not count as a cut.
+    pub fn push<T: Pixel>(&mut self, frame: usize, source: &Yuv<T>) -> bool {
+        self.push_histogram(frame, LumaHistogram::new(source))
+    }
+
+    /// Adds the histogram of the next frame of the video, like
+    /// [`SceneDetector::push`]. This allows the histograms to be computed in
+    /// parallel while frames are added in order.
+    pub fn push_histogram(&mut self, frame: usize, histogram: LumaHistogram) -> bool {
+        let cut = if let Some(ref previous) = self.previous {
+            histogram.distance(previous) > self.threshold
+                && frame >= self.scene_start + self.min_length
+        } else {
+            self.scene_start = frame;
+            false
+        };
+        if cut {
+            self.scene_start = frame;
+            self.cuts.push(frame);
+        }
+        self.previous = Some(histogram);
+        cut
+    }
+
+    /// The frames found to start a new scene, in order.
+    #[must_use]
+    pub fn cuts(&self) -> &[usize] {
+        &self.cuts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yuvxyb::{
+        ColorPrimaries, Frame, MatrixCoefficients, Plane, TransferCharacteristic, YuvConfig,
+    };
+
+    use super::*;
+    use crate::ScoreAggregator;
+
+    fn make_frame(luma: impl Fn(usize, usize) -> u8) -> Yuv<u8> {
+        let mut frame: Frame<u8> = Frame {
+            planes: [
+                Plane::new(32, 32, 0, 0, 0, 0),
+                Plane::new(16, 16, 1, 1, 0, 0),
+                Plane::new(16, 16, 1, 1, 0, 0),
+            ],
+        };
+        for (y, row) in frame.planes[0].rows_iter_mut().enumerate() {
+            for (x, pix) in row.iter_mut().enumerate() {
+                *pix = luma(x, y);
+            }
+        }
+        for plane in &mut frame.planes[1..] {
+            for row in plane.rows_iter_mut() {
+                row.fill(128);
+            }
+        }
+        let config = YuvConfig {
+            bit_depth: 8,
+            subsampling_x: 1,
+            subsampling_y: 1,
+            full_range: false,
+            matrix_coefficients: MatrixCoefficients::BT709,
+            transfer_characteristics: TransferCharacteristic::BT1886,
+            color_primaries: ColorPrimaries::BT709,
+        };
+        Yuv::new(frame, config).unwrap()
+    }
+
+    #[test]
+    fn finds_cuts() {
+        // A dark scene panning slowly, cut to a bright one and back.
+        let dark = |t: usize| make_frame(move |x, y| (16 + (x + y + t) % 48) as u8);
+        let bright = |t: usize| make_frame(move |x, y| (160 + (x * 2 + y + t) % 64) as u8);
+        let frames: Vec<_> = (0..20usize)
+            .map(|t| {
+                if (8..16).contains(&t) {
+                    bright(t)
+                } else {
+                    dark(t)
+                }
+            })
+            .collect();
+
+        let mut detector = SceneDetector::new();
+        let mut cuts = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            if detector.push(i, frame) {
+                cuts.push(i);
+            }
+        }
+        assert_eq!(cuts, [8, 16]);
+        assert_eq!(detector.cuts(), [8, 16]);
+
+        let mut detector = SceneDetector::new().with_min_length(10);
+        for (i, frame) in frames.iter().enumerate() {
+            detector.push(i, frame);
+        }
+        assert_eq!(detector.cuts(), [16]);
+
+        let mut aggregator = ScoreAggregator::new();
+        for frame in 0..20usize {
+            aggregator.push(frame, if frame < 8 { 80.0f64 } else { 60.0f64 });
+        }
+        let scenes = aggregator.scenes(&[8, 16]);
+        assert_eq!(scenes.len(), 3);
+        assert_eq!((scenes[1].start, scenes[1].end), (8, 16));
+        assert_eq!((scenes[2].start, scenes[2].end), (16, 20));
+        assert!((scenes[0].summary.mean - 80.0f64).abs() < 1e-9f64);
+        assert!((scenes[1].summary.mean - 60.0f64).abs() < 1e-9f64);
+        assert_eq!(scenes[2].summary.frames, 4);
+    }
+}
@@ -0,0 +1,43 @@
+use alloc::vec::Vec;
+
+use crate::{CompanionMetrics, Crop, DistortionMap, LevelFit, Shift};
+
+/// A SSIMULACRA2 score together with the per-scale and per-plane errors it was
+/// computed from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DetailedScore {
+    /// The SSIMULACRA2 score, as returned by [`compute_frame_ssimulacra2`][crate::compute_frame_ssimulacra2].
+    pub score: f64,
+    /// The weighted error of every scale that was scored, starting at full
+    /// resolution. Each further scale halves the width and height, rounding
+    /// up, as long as the previous scale is at least 8x8 pixels.
+    ///
+    /// The errors sum to the raw error that is mapped to the final score, so
+    /// they show which scale the distortion mostly comes from. Higher values
+    /// mean more distortion.
+    pub scales: Vec<f64>,
+    /// The weighted error of the X, Y and B planes of the XYB color space,
+    /// summed over all scales. Y is the luminance, while X (red-green) and B
+    /// (blue-yellow) carry the chromaticity, so chroma-only artifacts such as
+    /// those of chroma subsampling mostly raise the errors of X and B.
+    ///
+    /// Like the scale errors, they sum to the raw error that is mapped to the
+    /// final score. Higher values mean more distortion.
+    pub planes: [f64; 3],
+    /// Where the distortion is located, if it was requested.
+    pub map: Option<DistortionMap>,
+    /// PSNR and SSIM of the same images, if they were requested with
+    /// [`Ssimulacra2::with_companion_metrics`][crate::Ssimulacra2::with_companion_metrics].
+    pub companion: Option<CompanionMetrics>,
+    /// The gain and offset the levels of the distorted image were normalized
+    /// with, if this was requested with
+    /// [`Ssimulacra2::with_level_normalization`][crate::Ssimulacra2::with_level_normalization].
+    pub levels: Option<LevelFit>,
+    /// The shift of the distorted image relative to the source, if it was
+    /// checked with [`Ssimulacra2::with_shift_check`][crate::Ssimulacra2::with_shift_check].
+    pub shift: Option<Shift>,
+    /// The borders cropped from both images before scoring, if this was
+    /// requested with [`Ssimulacra2::with_border_crop`][crate::Ssimulacra2::with_border_crop].
+    pub crop: Option<Crop>,
+}
@@ -0,0 +1,213 @@
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use yuvxyb::LinearRgb;
+
+use crate::{Reference, Ssimulacra2Error};
+
+/// A binary search for the lowest encoder quality setting whose encode
+/// reaches a target SSIMULACRA2 score, the core loop of per-image quality
+/// targeting.
+///
+/// [`run`][Self::run] calls an encode callback with quality settings from the
+/// range of the search, and compares the decoded results against a
+/// [`Reference`], which is only processed once. The score is assumed to rise
+/// with the quality setting, so every encode halves the remaining range. For
+/// settings such as CRF or JPEG XL distance, where higher values mean lower
+/// quality, enable [`with_inverted`][Self::with_inverted].
+///
+/// ```
+/// # use ssimulacra2::{LinearRgb, QualitySearch, Reference, Ssimulacra2Error};
+/// # let source = LinearRgb::new(vec![[0.5f32; 3]; 64 * 64], 64, 64).unwrap();
+/// # fn encode_and_decode(source: &LinearRgb, quality: i32) -> Result<LinearRgb, Ssimulacra2Error> {
+/// #     Ok(source.clone())
+/// # }
+/// let reference = Reference::from_borrowed(&source)?;
+/// let result = QualitySearch::new(85.0, 1..=100)
+///     .with_tolerance(0.5)
+///     .run(&reference, |quality| encode_and_decode(&source, quality))?;
+/// println!("quality {} scores {:.2}", result.quality, result.score);
+/// # Ok::<(), Ssimulacra2Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualitySearch {
+    target: f64,
+    qualities: RangeInclusive<i32>,
+    inverted: bool,
+    tolerance: f64,
+}
+
+/// The outcome of a [`QualitySearch`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchResult {
+    /// The quality setting that was found: the lowest one reaching the
+    /// target, or the highest one of the range if none does.
+    pub quality: i32,
+    /// The score of the encode at [`quality`][Self::quality].
+    pub score: f64,
+    /// Whether the score reaches the target.
+    pub reached: bool,
+    /// Every quality setting that was encoded with its score, in the order
+    /// they were tried.
+    pub steps: Vec<(i32, f64)>,
+}
+
+impl QualitySearch {
+    /// Searches `qualities` for the lowest setting scoring at least `target`.
+    #[must_use]
+    pub const fn new(target: f64, qualities: RangeInclusive<i32>) -> Self {
+        QualitySearch {
+            target,
+            qualities,
+            inverted: false,
+            tolerance: 0.0,
+        }
+    }
+
+    /// Treats higher settings as lower quality, as with CRF or JPEG XL
+    /// distance, so that the highest setting reaching the target is found.
+    #[must_use]
+    pub const fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Stops at the first setting scoring within `tolerance` above the
+    /// target, instead of narrowing the range down to one setting. This
+    /// saves encodes when the exact minimum does not matter. The default is 0.
+    #[must_use]
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    /// Runs the search, calling `encode` with a quality setting for every
+    /// step and comparing what it returns against `reference`.
+    ///
+    /// `encode` is called at most about `log2` of the size of the range
+    /// times, and never twice with the same setting.
+    ///
+    /// # Errors
+    /// - If the range of qualities is empty
+    /// - If `encode` fails
+    /// - If an encode cannot be compared against `reference`, e.g. because its
+    ///   width and height differ
+    pub fn run<F, U, E>(&self, reference: &Reference, mut encode: F) -> Result<SearchResult, E>
+    where
+        F: FnMut(i32) -> Result<U, E>,
+        LinearRgb: TryFrom<U>,
+        E: From<Ssimulacra2Error>,
+    {
+        let (first, last) = (*self.qualities.start(), *self.qualities.end());
+        if first > last {
+            return Err(Ssimulacra2Error::InvalidSearchRange.into());
+        }
+
+        let mut steps = Vec::new();
+        let mut score_at = |step: u32| -> Result<f64, E> {
+            let quality = self.quality(step);
+            if let Some(&(_, score)) = steps.iter().find(|&&(tried, _)| tried == quality) {
+                return Ok(score);
+            }
+            let score = reference.compare(encode(quality)?)?;
+            steps.push((quality, score));
+            Ok(score)
+        };
+
+        // Steps count from the lowest quality up, whatever the direction of
+        // the settings is.
+        let (mut low, mut high) = (0, last.abs_diff(first));
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let score = score_at(middle)?;
+            if score < self.target {
+                low = middle + 1;
+            } else if score <= self.target + self.tolerance {
+                (low, high) = (middle, middle);
+            } else {
+                high = middle;
+            }
+        }
+        let score = score_at(low)?;
+
+        Ok(SearchResult {
+            quality: self.quality(low),
+            score,
+            reached: score >= self.target,
+            steps,
+        })
+    }
+
+    // The quality setting of the given step from the lowest quality. Steps
+    // are at most the distance between the ends of the range, so wrapping
+    // arithmetic lands within it.
+    #[allow(clippy::cast_possible_wrap)]
+    const fn quality(&self, step: u32) -> i32 {
+        if self.inverted {
+            self.qualities.end().wrapping_sub(step as i32)
+        } else {
+            self.qualities.start().wrapping_add(step as i32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+
+    // Adds noise which fades out as the quality rises to 64.
+    fn encode(source: &LinearRgb, quality: i32) -> Result<LinearRgb, Ssimulacra2Error> {
+        let Ok(quality) = u8::try_from(quality) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        let strength = f32::from(64 - quality.min(64)) / 256.0;
+        let data = source
+            .data()
+            .iter()
+            .enumerate()
+            .map(|(i, pix)| pix.map(|value| value + strength * ((i * 31 % 13) as f32 / 13.0)))
+            .collect();
+        LinearRgb::new(data, source.width(), source.height())
+            .map_err(|_err| Ssimulacra2Error::LinearRgbConversionFailed)
+    }
+
+    #[test]
+    fn finds_lowest_quality_reaching_target() {
+        let source = make_image(32, 32, 7);
+        let reference = Reference::from_borrowed(&source).unwrap();
+        let scores: Vec<_> = (1i32..=64i32)
+            .map(|levels| reference.compare(encode(&source, levels).unwrap()).unwrap())
+            .collect();
+        assert!(scores.windows(2).all(|pair| pair[0] < pair[1]));
+        let target = 70.0f64;
+        let expected = scores.iter().position(|&score| score >= target).unwrap();
+
+        let result = QualitySearch::new(target, 1i32..=64i32)
+            .run(&reference, |levels| encode(&source, levels))
+            .unwrap();
+        assert_eq!(result.quality, expected as i32 + 1i32);
+        assert!(result.reached);
+        assert_eq!(result.score.to_bits(), scores[expected].to_bits());
+        assert!(result.steps.len() <= 7);
+
+        // With 65 - quality levels, e.g. like a CRF.
+        let inverted = QualitySearch::new(target, 1i32..=64i32)
+            .with_inverted(true)
+            .run(&reference, |crf| encode(&source, 65 - crf))
+            .unwrap();
+        assert_eq!(inverted.quality, 65i32 - result.quality);
+
+        let unreachable = QualitySearch::new(101.0, 1i32..=8i32)
+            .run(&reference, |levels| encode(&source, levels))
+            .unwrap();
+        assert_eq!((unreachable.quality, unreachable.reached), (8i32, false));
+
+        assert_eq!(
+            QualitySearch::new(target, RangeInclusive::new(5i32, 4i32))
+                .run(&reference, |levels| encode(&source, levels)),
+            Err(Ssimulacra2Error::InvalidSearchRange)
+        );
+    }
+}
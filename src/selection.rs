@@ -0,0 +1,148 @@
+use core::num::NonZeroUsize;
+
+/// Selects which frames of a video are scored.
+///
+/// Scoring every frame of a long video is rarely necessary to judge its
+/// quality. A selection can restrict scoring to a range of frames and only
+/// keep every `n`th frame within it, which speeds up scoring by roughly the
+/// same factor. The [`ScoreSummary`][crate::ScoreSummary] of a sampled video
+/// includes a confidence interval for the mean to show how reliable it is.
+///
+/// The default selection contains every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSelection {
+    start: usize,
+    end: Option<usize>,
+    step: usize,
+}
+
+impl Default for FrameSelection {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FrameSelection {
+    /// A selection containing every frame.
+    #[must_use]
+    pub const fn all() -> Self {
+        FrameSelection {
+            start: 0,
+            end: None,
+            step: 1,
+        }
+    }
+
+    /// Restricts the selection to the frames from `start` up to, but not
+    /// including, `end`. If `end` is [None], the range extends to the end of
+    /// the video.
+    #[must_use]
+    pub const fn with_range(self, start: usize, end: Option<usize>) -> Self {
+        FrameSelection { start, end, ..self }
+    }
+
+    /// Restricts the selection to the frames shown from `start` up to, but
+    /// not including, `end` seconds into a video with the given frame rate.
+    /// If `end` is [None], the range extends to the end of the video.
+    #[must_use]
+    pub fn with_time_range(self, start: f64, end: Option<f64>, fps: f64) -> Self {
+        self.with_range(
+            first_frame_at(start, fps),
+            end.map(|end| first_frame_at(end, fps)),
+        )
+    }
+
+    /// Only selects every `step`th frame, counting from the start of the range.
+    #[must_use]
+    pub const fn with_step(self, step: NonZeroUsize) -> Self {
+        FrameSelection {
+            step: step.get(),
+            ..self
+        }
+    }
+
+    /// The first selected frame.
+    #[must_use]
+    pub const fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The frame at which the selection ends (exclusive), if any.
+    #[must_use]
+    pub const fn end(&self) -> Option<usize> {
+        self.end
+    }
+
+    /// The distance between two selected frames.
+    #[must_use]
+    pub const fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Whether the frame with the given index is selected.
+    #[must_use]
+    pub const fn contains(&self, frame: usize) -> bool {
+        frame >= self.start && !self.is_past_end(frame) && (frame - self.start) % self.step == 0
+    }
+
+    /// Whether the frame with the given index, and every frame after it,
+    /// lies past the end of the selection. Decoding can stop at this point.
+    #[must_use]
+    pub const fn is_past_end(&self, frame: usize) -> bool {
+        match self.end {
+            Some(end) => frame >= end,
+            None => false,
+        }
+    }
+
+    /// Number of frames selected from a video with `total_frames` frames.
+    #[must_use]
+    pub fn count(&self, total_frames: usize) -> usize {
+        let end = self.end.map_or(total_frames, |end| end.min(total_frames));
+        (end.saturating_sub(self.start) + self.step - 1) / self.step
+    }
+
+    /// Filters `frames` down to the selected ones, paired with their index.
+    /// The returned iterator stops consuming `frames` past the end of the selection.
+    pub fn apply<I: IntoIterator>(self, frames: I) -> impl Iterator<Item = (usize, I::Item)> {
+        frames
+            .into_iter()
+            .enumerate()
+            .take_while(move |&(i, _)| !self.is_past_end(i))
+            .filter(move |&(i, _)| self.contains(i))
+    }
+}
+
+// Index of the first frame shown at or after `seconds`.
+fn first_frame_at(seconds: f64, fps: f64) -> usize {
+    let frame = (seconds * fps).max(0.0);
+    // Don't skip a frame just because of rounding errors, e.g. in 0.04 * 25.0
+    let rounded = frame.round();
+    if (frame - rounded).abs() < 1e-6 {
+        rounded as usize
+    } else {
+        frame.ceil() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection() {
+        let every_third = FrameSelection::all()
+            .with_range(10, Some(20))
+            .with_step(NonZeroUsize::new(3).unwrap());
+        let selected: Vec<_> = every_third.apply(0..100usize).map(|(i, _)| i).collect();
+        assert_eq!(selected, [10, 13, 16, 19]);
+        assert!(every_third.is_past_end(20));
+        assert_eq!(every_third.count(100), 4);
+        assert_eq!(every_third.count(15), 2);
+
+        let timed = FrameSelection::all().with_time_range(0.04f64, Some(0.2f64), 25.0f64);
+        assert_eq!((timed.start(), timed.end()), (1, Some(5)));
+
+        assert_eq!(FrameSelection::default().apply(0..5usize).count(), 5);
+    }
+}
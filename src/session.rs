@@ -0,0 +1,1110 @@
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+use alloc::{vec, vec::Vec};
+use core::mem;
+
+use yuvxyb::LinearRgb;
+
+use crate::channel::ChannelScratch;
+use crate::{
+    check_finite, AlignedPlane, Blur, BoxDownscaler, CancellationToken, CompanionMetrics, Crop,
+    CubeRoot, Denoise, DetailedScore, DistortionMap, Downscaler, ImageF32, LevelFit, PoolStats,
+    Preprocess, Reference, Rescale, ScratchPool, Shift, ShiftCheck, Ssimulacra2Error, Viewing,
+    NUM_SCALES,
+};
+#[cfg(feature = "std")]
+use crate::{CacheKey, ScoreCache};
+
+/// A scoring session, which reuses its working memory across comparisons.
+///
+/// Scoring with [`compute_frame_ssimulacra2`][crate::compute_frame_ssimulacra2]
+/// allocates its temporary buffers anew for every pair of images. A session
+/// keeps them around instead, which saves the allocations when scoring the
+/// frames of a video one after another. Sessions are not shared between
+/// threads; use one per thread, as [`FramePipeline`][crate::FramePipeline] does.
+///
+/// When the images change resolution, e.g. in a video stream, the session
+/// [resizes][Self::resize] itself to the new one. Buffers of the previous
+/// resolution are kept and reused where they fit.
+///
+/// Options are set with the `with_*` methods below, or all at once with a
+/// [`Ssimulacra2Builder`][crate::Ssimulacra2Builder].
+#[derive(Debug)]
+pub struct Ssimulacra2 {
+    width: usize,
+    height: usize,
+    scratch: Scratch,
+    downscaler: Arc<dyn Downscaler>,
+    validate: bool,
+    preprocess: Preprocess,
+    companion_metrics: bool,
+    normalize_levels: bool,
+    shift_check: ShiftCheck,
+    rescale: Rescale,
+    crop_borders: bool,
+    cube_root: CubeRoot,
+    reference_denoise: Denoise,
+    viewing: Viewing,
+    tiling: bool,
+    cancellation: Option<CancellationToken>,
+    #[cfg(feature = "rayon")]
+    thread_pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+/// The options of a [`Ssimulacra2`] session that change how images are
+/// scored, e.g. to store them next to the scores, see
+/// [`Ssimulacra2::settings`].
+///
+/// The downscaler, the cancellation token and the thread pool cannot be
+/// serialized, so they are not part of the settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Settings {
+    /// See [`Ssimulacra2::with_preprocess`].
+    pub preprocess: Preprocess,
+    /// See [`Ssimulacra2::with_companion_metrics`].
+    pub companion_metrics: bool,
+    /// See [`Ssimulacra2::with_level_normalization`].
+    pub normalize_levels: bool,
+    /// See [`Ssimulacra2::with_shift_check`].
+    pub shift_check: ShiftCheck,
+    /// See [`Ssimulacra2::with_rescale`].
+    pub rescale: Rescale,
+    /// See [`Ssimulacra2::with_border_crop`].
+    pub crop_borders: bool,
+    /// See [`Ssimulacra2::with_cube_root`].
+    pub cube_root: CubeRoot,
+    /// See [`Ssimulacra2::with_reference_denoise`].
+    pub reference_denoise: Denoise,
+    /// See [`Ssimulacra2::with_viewing`].
+    pub viewing: Viewing,
+}
+
+impl Ssimulacra2 {
+    /// Creates a session for images of the given width and height.
+    ///
+    /// # Errors
+    /// - If the width or height is less than 8 pixels
+    pub fn new(width: usize, height: usize) -> Result<Self, Ssimulacra2Error> {
+        if width < 8 || height < 8 {
+            return Err(Ssimulacra2Error::InvalidImageSize);
+        }
+
+        Ok(Ssimulacra2 {
+            width,
+            height,
+            scratch: Scratch::new(width, height),
+            downscaler: Arc::new(BoxDownscaler),
+            validate: cfg!(debug_assertions),
+            preprocess: Preprocess::None,
+            companion_metrics: false,
+            normalize_levels: false,
+            shift_check: ShiftCheck::None,
+            rescale: Rescale::None,
+            crop_borders: false,
+            cube_root: CubeRoot::Exact,
+            reference_denoise: Denoise::None,
+            viewing: Viewing::Native,
+            tiling: false,
+            cancellation: None,
+            #[cfg(feature = "rayon")]
+            thread_pool: None,
+        })
+    }
+
+    /// Creates a session for images of the given width and height, allocating
+    /// all of its working memory up front.
+    ///
+    /// Scoring images of this or a smaller resolution with the default
+    /// [`BoxDownscaler`] then does not allocate any image buffers, so that
+    /// memory-constrained services can bound their memory use when creating
+    /// sessions rather than when scoring. The amount allocated is given by
+    /// [`required_bytes`][Self::required_bytes].
+    ///
+    /// # Errors
+    /// - If the width or height is less than 8 pixels
+    pub fn preallocated(width: usize, height: usize) -> Result<Self, Ssimulacra2Error> {
+        let mut session = Self::new(width, height)?;
+        session.scratch.preallocate(width, height);
+        Ok(session)
+    }
+
+    /// The number of bytes of working memory a [`preallocated`][Self::preallocated]
+    /// session allocates for images of the given width and height.
+    ///
+    /// This covers the buffers held by the session across comparisons. The
+    /// input images, their conversion to [`LinearRgb`] and the
+    /// [`DistortionMap`] of [`score_with_map`][Self::score_with_map] come on
+    /// top, as they belong to the caller.
+    #[must_use]
+    pub fn required_bytes(width: usize, height: usize) -> usize {
+        Scratch::required_bytes(width, height)
+    }
+
+    /// The peak memory, in bytes, of scoring a pair of images of the given
+    /// width and height with a session.
+    ///
+    /// This is the working memory of [`required_bytes`][Self::required_bytes]
+    /// plus both images converted to [`LinearRgb`]. The channels of a scale
+    /// are compared in parallel with the `rayon` feature, but on buffers of
+    /// their own which are already counted, so parallelism within a session
+    /// does not add to this. A [`DistortionMap`] adds 4 bytes per pixel, and
+    /// bookkeeping such as the per-scale results a few kilobytes.
+    #[must_use]
+    pub fn required_memory(width: usize, height: usize) -> usize {
+        Self::required_bytes(width, height) + 2 * width * height * mem::size_of::<[f32; 3]>()
+    }
+
+    /// Downscales the images with `downscaler` instead of the default [`BoxDownscaler`].
+    #[must_use]
+    pub fn with_downscaler<D: Downscaler + 'static>(self, downscaler: D) -> Self {
+        Ssimulacra2 {
+            downscaler: Arc::new(downscaler),
+            ..self
+        }
+    }
+
+    // Like `with_downscaler`, for a downscaler already shared by a
+    // `Ssimulacra2Builder`.
+    pub(crate) fn with_shared_downscaler(self, downscaler: Arc<dyn Downscaler>) -> Self {
+        Ssimulacra2 { downscaler, ..self }
+    }
+
+    /// Enables or disables checking both images for NaN and infinite values
+    /// before scoring them, which reports the first such value with
+    /// [`Ssimulacra2Error::NonFiniteInput`]. Without the check, these values
+    /// make scoring fail with [`Ssimulacra2Error::NonFiniteScore`] at best,
+    /// or are silently clamped.
+    ///
+    /// The check is enabled by default in debug builds only, as it reads the
+    /// whole of both images once more.
+    #[must_use]
+    pub fn with_validation(self, validate: bool) -> Self {
+        Ssimulacra2 { validate, ..self }
+    }
+
+    /// Applies `preprocess` to both images before scoring them, e.g. to not
+    /// penalize dithering more than it is visible. See [`Preprocess`] for the
+    /// available filters; the default is [`Preprocess::None`].
+    #[must_use]
+    pub fn with_preprocess(self, preprocess: Preprocess) -> Self {
+        Ssimulacra2 { preprocess, ..self }
+    }
+
+    /// Enables or disables computing PSNR and SSIM of the images as well,
+    /// which are returned in [`DetailedScore::companion`]. This spares a
+    /// second pass over the images with another tool, e.g. when benchmarking
+    /// video encoders, and adds about a third to the time of a comparison.
+    ///
+    /// The metrics are computed before any [preprocessing][Self::with_preprocess];
+    /// see [`CompanionMetrics`] for how they relate to other implementations.
+    #[must_use]
+    pub fn with_companion_metrics(self, companion_metrics: bool) -> Self {
+        Ssimulacra2 {
+            companion_metrics,
+            ..self
+        }
+    }
+
+    /// Enables or disables matching the levels of the distorted image to
+    /// those of the source before scoring them. A global gain and offset of
+    /// the sRGB-encoded values, as introduced by range or gamma mismatches in
+    /// color space conversions, otherwise lowers the score a lot even when the
+    /// images look the same. The fitted parameters are returned in
+    /// [`DetailedScore::levels`]; see [`LevelFit`] for how they are applied.
+    ///
+    /// Real changes of brightness or contrast are no longer penalized either,
+    /// so this is off by default. The levels are matched before any
+    /// [preprocessing][Self::with_preprocess] and after computing the
+    /// [companion metrics][Self::with_companion_metrics].
+    #[must_use]
+    pub fn with_level_normalization(self, normalize_levels: bool) -> Self {
+        Ssimulacra2 {
+            normalize_levels,
+            ..self
+        }
+    }
+
+    /// Checks the distorted image for a global shift relative to the source
+    /// with [`detect_shift`][crate::detect_shift], and moves it back by the
+    /// shift with [`ShiftCheck::Compensate`]. The result is returned in
+    /// [`DetailedScore::shift`]. The default is [`ShiftCheck::None`].
+    ///
+    /// A shift by even one pixel, e.g. from scaling with the wrong sample
+    /// positions, lowers the score as much as heavy distortion does. When it
+    /// is compensated, the pixels uncovered at the edges are filled by
+    /// repeating the edge of the distorted image. The shift is checked after
+    /// computing the [companion metrics][Self::with_companion_metrics] and
+    /// before [normalizing the levels][Self::with_level_normalization].
+    #[must_use]
+    pub fn with_shift_check(self, shift_check: ShiftCheck) -> Self {
+        Ssimulacra2 {
+            shift_check,
+            ..self
+        }
+    }
+
+    /// Scales the images to the same resolution first if they differ, instead
+    /// of failing with [`Ssimulacra2Error::NonMatchingImageDimensions`]. See
+    /// [`Rescale`] for the available policies; the default is
+    /// [`Rescale::None`].
+    ///
+    /// The session is resized to the resolution the images are scored at.
+    #[must_use]
+    pub fn with_rescale(self, rescale: Rescale) -> Self {
+        Ssimulacra2 { rescale, ..self }
+    }
+
+    /// Enables or disables cropping uniform borders, such as the black bars of
+    /// a letterbox or pillarbox, that only one of the images has. Otherwise
+    /// the bars are compared against the content of the other image, which
+    /// gives a meaningless score. The cropped borders are returned in
+    /// [`DetailedScore::crop`].
+    ///
+    /// On each edge where the borders of the images differ, both images are
+    /// cropped by the wider border, so that they keep the same content.
+    /// Borders of the same width in both images are kept. The images are
+    /// cropped right after [rescaling][Self::with_rescale], so all further
+    /// steps, including [distortion maps][Self::score_with_map], see the
+    /// cropped images.
+    #[must_use]
+    pub fn with_border_crop(self, crop_borders: bool) -> Self {
+        Ssimulacra2 {
+            crop_borders,
+            ..self
+        }
+    }
+
+    /// Computes the cube roots of the XYB conversion with `cube_root`. The
+    /// default is [`CubeRoot::Exact`], which scores like the reference
+    /// implementation. See [`CubeRoot`] for the available methods.
+    #[must_use]
+    pub fn with_cube_root(self, cube_root: CubeRoot) -> Self {
+        Ssimulacra2 { cube_root, ..self }
+    }
+
+    /// Denoises the source image with `denoise` before scoring, so that
+    /// encoders which remove the noise of a noisy capture are not penalized
+    /// for it. See [`Denoise`] for the filter; the default is
+    /// [`Denoise::None`].
+    ///
+    /// Only the source is filtered, with the same filter for every
+    /// comparison. It is denoised after [normalizing the levels][Self::with_level_normalization]
+    /// and before any [preprocessing][Self::with_preprocess].
+    #[must_use]
+    pub fn with_reference_denoise(self, denoise: Denoise) -> Self {
+        Ssimulacra2 {
+            reference_denoise: denoise,
+            ..self
+        }
+    }
+
+    /// Scores the images as seen under the viewing conditions `viewing`,
+    /// e.g. on a phone or a TV instead of a desktop monitor, by resizing both
+    /// images to the pixels per degree the metric assumes. See [`Viewing`];
+    /// the default is [`Viewing::Native`].
+    ///
+    /// The images are resized right after [cropping][Self::with_border_crop],
+    /// so all further steps, including [distortion maps][Self::score_with_map],
+    /// see the resized images. Comparisons fail with
+    /// [`Ssimulacra2Error::InvalidImageSize`] if the resized images are smaller
+    /// than 8x8 pixels.
+    #[must_use]
+    pub fn with_viewing(self, viewing: Viewing) -> Self {
+        Ssimulacra2 { viewing, ..self }
+    }
+
+    /// Applies all of `settings` at once, e.g. to score with the settings
+    /// stored next to earlier scores.
+    #[must_use]
+    pub fn with_settings(self, settings: Settings) -> Self {
+        Ssimulacra2 {
+            preprocess: settings.preprocess,
+            companion_metrics: settings.companion_metrics,
+            normalize_levels: settings.normalize_levels,
+            shift_check: settings.shift_check,
+            rescale: settings.rescale,
+            crop_borders: settings.crop_borders,
+            cube_root: settings.cube_root,
+            reference_denoise: settings.reference_denoise,
+            viewing: settings.viewing,
+            ..self
+        }
+    }
+
+    /// The current settings of this session.
+    #[must_use]
+    pub const fn settings(&self) -> Settings {
+        Settings {
+            preprocess: self.preprocess,
+            companion_metrics: self.companion_metrics,
+            normalize_levels: self.normalize_levels,
+            shift_check: self.shift_check,
+            rescale: self.rescale,
+            crop_borders: self.crop_borders,
+            cube_root: self.cube_root,
+            reference_denoise: self.reference_denoise,
+            viewing: self.viewing,
+        }
+    }
+
+    /// Splits tall images into tiles of rows which are blurred and compared
+    /// in parallel, so that scoring a single large image, e.g. an 8K still,
+    /// keeps more threads busy than the three channels of every scale.
+    ///
+    /// Rows near the edges of a tile are blurred from the rows of the
+    /// neighbouring tiles, and the error sums of the tiles are added up in a
+    /// fixed order, so the score is that of scoring without tiles up to
+    /// rounding, and does not depend on the number of threads. Scales smaller
+    /// than two tiles are not split. Tiling is off by default, as the row
+    /// buffers of every tile are allocated anew for every comparison.
+    #[must_use]
+    pub fn with_tiling(self, tiling: bool) -> Self {
+        Ssimulacra2 { tiling, ..self }
+    }
+
+    /// Checks `token` between the stages of every comparison, failing with
+    /// [`Ssimulacra2Error::Cancelled`] once it has been cancelled.
+    ///
+    /// The session stays usable after a cancelled comparison; the buffers it
+    /// was using are returned to its pool.
+    #[must_use]
+    pub fn with_cancellation(self, token: CancellationToken) -> Self {
+        Ssimulacra2 {
+            cancellation: Some(token),
+            ..self
+        }
+    }
+
+    // Replaces the token checked by further comparisons, for sessions whose
+    // comparisons are cancelled independently, such as those of `AsyncSsimulacra2`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn set_cancellation(&mut self, cancellation: Option<CancellationToken>) {
+        self.cancellation = cancellation;
+    }
+
+    /// Runs the parallel parts of scoring on `thread_pool` instead of the
+    /// global rayon thread pool.
+    ///
+    /// This avoids oversubscribing the CPU when scoring within an application
+    /// that manages its own threads, e.g. an encoder.
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn with_thread_pool(self, thread_pool: Arc<rayon::ThreadPool>) -> Self {
+        Ssimulacra2 {
+            thread_pool: Some(thread_pool),
+            ..self
+        }
+    }
+
+    /// Resizes the working memory of this session to fit images of the given
+    /// width and height, growing buffers as necessary.
+    ///
+    /// Scoring images of another resolution does this automatically, so this
+    /// only needs to be called to allocate the buffers ahead of time.
+    ///
+    /// # Errors
+    /// - If the width or height is less than 8 pixels
+    pub fn resize(&mut self, width: usize, height: usize) -> Result<(), Ssimulacra2Error> {
+        if width < 8 || height < 8 {
+            return Err(Ssimulacra2Error::InvalidImageSize);
+        }
+
+        self.width = width;
+        self.height = height;
+        self.scratch.blur.resize(width, height);
+        Ok(())
+    }
+
+    /// Width of the images last scored by this session.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the images last scored by this session.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Counters of the buffer pool of this session.
+    ///
+    /// Once the first frame has been scored, the number of allocations stays
+    /// the same for further frames of the same or a smaller resolution.
+    #[must_use]
+    pub const fn pool_stats(&self) -> PoolStats {
+        self.scratch.pool.stats()
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the comparison is cancelled
+    pub fn score<T, U>(&mut self, source: T, distorted: U) -> Result<f64, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_detailed(source, distorted)
+            .map(|detailed| detailed.score)
+    }
+
+    /// Like [`score`][Self::score], but looks up the score in `cache` first
+    /// and caches it there after scoring the images otherwise.
+    ///
+    /// The images are converted to linear RGB and hashed either way, which
+    /// takes a small fraction of the time of scoring them. See [`ScoreCache`]
+    /// for what the key covers.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the comparison is cancelled
+    #[cfg(feature = "std")]
+    pub fn score_cached<T, U, C>(
+        &mut self,
+        source: T,
+        distorted: U,
+        cache: &mut C,
+    ) -> Result<f64, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+        C: ScoreCache + ?Sized,
+    {
+        let (Ok(source), Ok(distorted)) =
+            (LinearRgb::try_from(source), LinearRgb::try_from(distorted))
+        else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        let key = CacheKey::new(&source, &distorted, self.settings());
+        if let Some(score) = cache.get(&key) {
+            return Ok(score);
+        }
+        let score = self.score(source, distorted)?;
+        cache.insert(key, score);
+        Ok(score)
+    }
+
+    /// Like [`score_detailed`][Self::score_detailed], but borrows images which
+    /// are already in linear RGB, so that the caller keeps them without
+    /// cloning them.
+    ///
+    /// The images are only read. Steps that change them, such as
+    /// [preprocessing][Self::with_preprocess] or
+    /// [level normalization][Self::with_level_normalization], work on copies,
+    /// which are only made when these steps are enabled.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the comparison is cancelled
+    pub fn score_borrowed(
+        &mut self,
+        source: &LinearRgb,
+        distorted: &LinearRgb,
+    ) -> Result<DetailedScore, Ssimulacra2Error> {
+        self.score_linear(Cow::Borrowed(source), Cow::Borrowed(distorted), false)
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`,
+    /// together with the error of each scale.
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the comparison is cancelled
+    pub fn score_detailed<T, U>(
+        &mut self,
+        source: T,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_inner(source, distorted, false)
+    }
+
+    /// Computes the SSIMULACRA2 score of `distorted` compared to `source`,
+    /// together with the error of each scale and a [`DistortionMap`].
+    ///
+    /// # Errors
+    /// - If the source and distorted image width and height do not match
+    /// - If the width or height of the images is less than 8 pixels
+    /// - If the source or distorted image cannot be converted to XYB successfully
+    /// - If the images contain values for which no score can be computed
+    /// - If validation is enabled and an image contains NaN or infinite values
+    /// - If the comparison is cancelled
+    pub fn score_with_map<T, U>(
+        &mut self,
+        source: T,
+        distorted: U,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        self.score_inner(source, distorted, true)
+    }
+
+    fn score_inner<T, U>(
+        &mut self,
+        source: T,
+        distorted: U,
+        with_map: bool,
+    ) -> Result<DetailedScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        let Ok(source) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        let Ok(distorted) = LinearRgb::try_from(distorted) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        self.score_linear(Cow::Owned(source), Cow::Owned(distorted), with_map)
+    }
+
+    // Scores the images, copying them only if a step before the comparison,
+    // such as preprocessing, has to modify them.
+    fn score_linear(
+        &mut self,
+        mut source: Cow<'_, LinearRgb>,
+        mut distorted: Cow<'_, LinearRgb>,
+        with_map: bool,
+    ) -> Result<DetailedScore, Ssimulacra2Error> {
+        let same_size =
+            source.width() == distorted.width() && source.height() == distorted.height();
+        match self.rescale {
+            Rescale::ToSource(filter) if !same_size => {
+                distorted = Cow::Owned(filter.resize(&distorted, source.width(), source.height()));
+            }
+            Rescale::ToDistorted(filter) if !same_size => {
+                source = Cow::Owned(filter.resize(&source, distorted.width(), distorted.height()));
+            }
+            _ => {}
+        }
+        let crop = (self.crop_borders
+            && source.width() == distorted.width()
+            && source.height() == distorted.height())
+        .then(|| {
+            let crop = Crop::detect(&source, &distorted);
+            if !crop.is_empty() {
+                crop.apply(make_mut(&mut source, &mut self.scratch.pool));
+                crop.apply(make_mut(&mut distorted, &mut self.scratch.pool));
+            }
+            crop
+        });
+        if let Some(scaled) = self.viewing.apply(&source) {
+            source = Cow::Owned(scaled);
+        }
+        if let Some(scaled) = self.viewing.apply(&distorted) {
+            distorted = Cow::Owned(scaled);
+        }
+
+        if source.width() != self.width || source.height() != self.height {
+            self.resize(source.width(), source.height())?;
+        }
+        if distorted.width() != self.width || distorted.height() != self.height {
+            return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+        }
+        if self.validate {
+            check_finite(&source, false)?;
+            check_finite(&distorted, true)?;
+        }
+        let companion = self.companion_metrics.then(|| {
+            let scratch = &mut self.scratch;
+            CompanionMetrics::compute(&source, &distorted, &mut scratch.blur, &mut scratch.pool)
+        });
+        let shift = (self.shift_check != ShiftCheck::None).then(|| {
+            let mut shift = Shift::detect(&source, &distorted);
+            if self.shift_check == ShiftCheck::Compensate && shift.is_significant() {
+                shift.compensate(make_mut(&mut distorted, &mut self.scratch.pool));
+                shift.compensated = true;
+            }
+            shift
+        });
+        let levels = self.normalize_levels.then(|| {
+            let fit = LevelFit::fit(&source, &distorted);
+            fit.apply(make_mut(&mut distorted, &mut self.scratch.pool));
+            fit
+        });
+        if self.reference_denoise != Denoise::None {
+            let pool = &mut self.scratch.pool;
+            self.reference_denoise
+                .apply_pooled(make_mut(&mut source, pool), pool);
+        }
+        if self.preprocess != Preprocess::None {
+            let pool = &mut self.scratch.pool;
+            self.preprocess.apply(make_mut(&mut source, pool), pool);
+            self.preprocess.apply(make_mut(&mut distorted, pool), pool);
+        }
+
+        #[cfg(feature = "rayon")]
+        let detailed = match self.thread_pool.clone() {
+            Some(thread_pool) if source.width() * source.height() >= crate::PARALLEL_MIN_VALUES => {
+                thread_pool.install(|| self.score_images(source, distorted, with_map))
+            }
+            _ => self.score_images(source, distorted, with_map),
+        }?;
+        #[cfg(not(feature = "rayon"))]
+        let detailed = self.score_images(source, distorted, with_map)?;
+        Ok(DetailedScore {
+            companion,
+            levels,
+            shift,
+            crop,
+            ..detailed
+        })
+    }
+
+    fn score_images(
+        &mut self,
+        source: Cow<'_, LinearRgb>,
+        distorted: Cow<'_, LinearRgb>,
+        with_map: bool,
+    ) -> Result<DetailedScore, Ssimulacra2Error> {
+        let reference = Reference::with_scratch(
+            source,
+            Arc::clone(&self.downscaler),
+            self.cube_root,
+            self.tiling,
+            &mut self.scratch,
+            self.cancellation.clone(),
+        )?;
+        let mut map = with_map.then(|| DistortionMap::new(self.width, self.height));
+        let msssim = reference.compare_with_scratch(distorted, map.as_mut(), &mut self.scratch);
+        reference.recycle(&mut self.scratch.pool);
+        let msssim = msssim?;
+        Ok(DetailedScore {
+            score: msssim.score(),
+            scales: msssim.scale_errors(),
+            planes: msssim.plane_errors(),
+            map,
+            companion: None,
+            levels: None,
+            shift: None,
+            crop: None,
+        })
+    }
+}
+
+// The image behind `img`, which is first copied into a buffer of the pool if
+// it is borrowed, so that images of the caller are never modified.
+fn make_mut<'a>(img: &'a mut Cow<'_, LinearRgb>, pool: &mut ScratchPool) -> &'a mut LinearRgb {
+    if let Cow::Borrowed(borrowed) = *img {
+        let mut buffer = pool.take_pixels(borrowed.data().len());
+        buffer.clear();
+        buffer.extend_from_slice(borrowed.data());
+        let copy = LinearRgb::new(buffer, borrowed.width(), borrowed.height())
+            .expect("Resolution and data size match");
+        *img = Cow::Owned(copy);
+    }
+    img.to_mut()
+}
+
+// Temporary buffers used while scoring, sized for the full resolution.
+#[derive(Debug)]
+pub struct Scratch {
+    pub blur: Blur,
+    pub pool: ScratchPool,
+    pub channels: [ChannelScratch; 3],
+}
+
+impl Scratch {
+    pub fn new(width: usize, height: usize) -> Self {
+        Scratch {
+            blur: Blur::new(width, height),
+            pool: ScratchPool::new(),
+            channels: Default::default(),
+        }
+    }
+
+    // Fills the pool with every buffer scoring images of the given size
+    // takes from it, and allocates the row buffers of the channels.
+    fn preallocate(&mut self, width: usize, height: usize) {
+        let (planes, pixels) = pooled_buffers(width, height);
+        for len in planes {
+            self.pool.give_plane(AlignedPlane::new(len));
+        }
+        self.pool.give_pixels(Vec::with_capacity(pixels));
+        self.blur.resize(width, height);
+        for channel in &mut self.channels {
+            channel.reserve(width);
+        }
+    }
+
+    // The number of bytes of the buffers allocated by `preallocate`.
+    fn required_bytes(width: usize, height: usize) -> usize {
+        let (planes, pixels) = pooled_buffers(width, height);
+        let floats = planes.iter().sum::<usize>() + width * height;
+        floats * mem::size_of::<f32>()
+            + pixels * mem::size_of::<[f32; 3]>()
+            + 3 * ChannelScratch::required_bytes(width)
+    }
+}
+
+// The lengths of the planes and of the pixel buffer the pool hands out while
+// scoring images of the given size: for every scale, the XYB, blurred and
+// squared blurred images of the source, which its `Reference` holds on to,
+// and the XYB image of the distorted image, which each scale reuses. Each of
+// these images has its three planes in one buffer. The pixel buffer is large
+// enough for a full resolution image, as needed for preprocessing, and is
+// reused for the downscaled images of every scale.
+fn pooled_buffers(width: usize, height: usize) -> (Vec<usize>, usize) {
+    let mut planes = vec![ImageF32::buffer_len(width, height, 3)];
+    let (mut w, mut h) = (width, height);
+    for scale in 0..NUM_SCALES {
+        planes.extend([ImageF32::buffer_len(w, h, 3); 3]);
+        if scale + 1 == NUM_SCALES || w < 8 || h < 8 {
+            break;
+        }
+        w = (w + 1) / 2;
+        h = (h + 1) / 2;
+    }
+    (planes, width * height)
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    use super::*;
+    use crate::test_images::make_image;
+
+    #[test]
+    fn thread_pool() {
+        let thread_pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .build()
+                .unwrap(),
+        );
+        let expected = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .score(make_image(67, 45, 3), make_image(67, 45, 5))
+            .unwrap();
+
+        let mut session = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .with_thread_pool(Arc::clone(&thread_pool));
+        let score = session
+            .score(make_image(67, 45, 3), make_image(67, 45, 5))
+            .unwrap();
+        assert!((score - expected).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn resolution_change() {
+        let sizes = [(67, 45), (32, 40), (128, 96), (67, 45)];
+        let mut session = Ssimulacra2::new(67, 45).unwrap();
+        for (width, height) in sizes {
+            let expected = Ssimulacra2::new(width, height)
+                .unwrap()
+                .score(make_image(width, height, 3), make_image(width, height, 5))
+                .unwrap();
+            let score = session
+                .score(make_image(width, height, 3), make_image(width, height, 5))
+                .unwrap();
+            assert!((score - expected).abs() < f64::EPSILON);
+            assert_eq!((session.width(), session.height()), (width, height));
+        }
+
+        // Buffers of the largest resolution fit all others.
+        let allocations = session.pool_stats().allocations;
+        for (width, height) in sizes {
+            session
+                .score(make_image(width, height, 3), make_image(width, height, 5))
+                .unwrap();
+        }
+        assert_eq!(session.pool_stats().allocations, allocations);
+
+        assert_eq!(
+            session.score(make_image(16, 16, 3), make_image(16, 17, 5)),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+        assert_eq!(
+            session.score(make_image(7, 16, 3), make_image(7, 16, 5)),
+            Err(Ssimulacra2Error::InvalidImageSize)
+        );
+    }
+
+    #[test]
+    fn preallocated() {
+        let mut session = Ssimulacra2::preallocated(67, 45)
+            .unwrap()
+            .with_preprocess(Preprocess::Blur);
+        let preallocated = session.pool_stats();
+        assert!(preallocated.pooled_bytes < Ssimulacra2::required_bytes(67, 45));
+        assert!(Ssimulacra2::required_bytes(67, 45) < Ssimulacra2::required_bytes(68, 45));
+
+        for (width, height) in [(67, 45), (32, 40), (67, 45)] {
+            session
+                .score(make_image(width, height, 3), make_image(width, height, 5))
+                .unwrap();
+        }
+        let stats = session.pool_stats();
+        assert_eq!(stats.allocations, preallocated.allocations);
+        assert!(stats.pooled_bytes <= preallocated.pooled_bytes);
+    }
+
+    #[test]
+    fn companion_metrics() {
+        let (source, distorted) = (make_image(67, 45, 3), make_image(67, 45, 5));
+        let plain = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .with_preprocess(Preprocess::Blur)
+            .score_detailed(source.clone(), distorted.clone())
+            .unwrap();
+        assert_eq!(plain.companion, None);
+
+        // Computed on the images as given, and in the working memory of
+        // preallocated sessions.
+        let mut session = Ssimulacra2::preallocated(67, 45)
+            .unwrap()
+            .with_preprocess(Preprocess::Blur)
+            .with_companion_metrics(true);
+        let allocations = session.pool_stats().allocations;
+        let detailed = session
+            .score_detailed(source.clone(), distorted.clone())
+            .unwrap();
+        assert_eq!(session.pool_stats().allocations, allocations);
+
+        let expected = CompanionMetrics::compute(
+            &source,
+            &distorted,
+            &mut Blur::new(67, 45),
+            &mut ScratchPool::new(),
+        );
+        assert_eq!(detailed.companion, Some(expected));
+        assert!((detailed.score - plain.score).abs() < f64::EPSILON);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_scores_and_settings() {
+        let settings = Settings {
+            companion_metrics: true,
+            rescale: Rescale::ToSource(crate::ResizeFilter::Lanczos3),
+            ..Settings::default()
+        };
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(serde_json::from_str::<Settings>(&json).unwrap(), settings);
+
+        let mut session = Ssimulacra2::new(67, 45).unwrap().with_settings(settings);
+        assert_eq!(session.settings(), settings);
+        let detailed = session
+            .score_with_map(make_image(67, 45, 3), make_image(67, 45, 5))
+            .unwrap();
+        let json = serde_json::to_string(&detailed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DetailedScore>(&json).unwrap(),
+            detailed
+        );
+
+        // Maps whose data does not fit their size are rejected.
+        let broken = json.replace("\"width\":67", "\"width\":68");
+        assert!(serde_json::from_str::<DetailedScore>(&broken).is_err());
+    }
+
+    #[test]
+    fn borrowed_images_are_not_modified() {
+        let hash =
+            |img: &LinearRgb| crate::PlaneHasher::hash_planes([bytemuck::cast_slice(img.data())]);
+        let source = make_image(67, 45, 3);
+        let mut distorted = make_image(67, 45, 5);
+        // A black bar at the left, which only the distorted image has.
+        for row in distorted.data_mut().chunks_exact_mut(67) {
+            row[..4].fill([0.0f32; 3]);
+        }
+        let hashes = (hash(&source), hash(&distorted));
+
+        let settings = Settings {
+            preprocess: Preprocess::Blur,
+            companion_metrics: true,
+            normalize_levels: true,
+            shift_check: ShiftCheck::Compensate,
+            rescale: Rescale::None,
+            crop_borders: true,
+            cube_root: CubeRoot::Exact,
+            reference_denoise: Denoise::Light,
+            viewing: Viewing::PixelsPerDegree(core::num::NonZeroU16::new(30).unwrap()),
+        };
+        let mut session = Ssimulacra2::new(67, 45).unwrap().with_settings(settings);
+        let borrowed = session.score_borrowed(&source, &distorted).unwrap();
+        assert_eq!((hash(&source), hash(&distorted)), hashes);
+        assert!(!borrowed.crop.unwrap().is_empty());
+
+        let owned = session
+            .score_detailed(source.clone(), distorted.clone())
+            .unwrap();
+        assert_eq!(borrowed.score.to_bits(), owned.score.to_bits());
+        assert_eq!(borrowed.levels, owned.levels);
+
+        let reference = Reference::from_borrowed(&source).unwrap();
+        let score = reference.compare_borrowed(&distorted).unwrap();
+        assert_eq!((hash(&source), hash(&distorted)), hashes);
+        assert_eq!(
+            score.to_bits(),
+            reference.compare(distorted).unwrap().to_bits()
+        );
+    }
+
+    #[test]
+    fn cube_root_methods() {
+        let (source, distorted) = (make_image(67, 45, 3), make_image(67, 45, 5));
+        let exact = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .score_borrowed(&source, &distorted)
+            .unwrap()
+            .score;
+        for cube_root in [CubeRoot::Approximate, CubeRoot::Table] {
+            let mut session = Ssimulacra2::new(67, 45).unwrap().with_cube_root(cube_root);
+            assert_eq!(session.settings().cube_root, cube_root);
+            let score = session.score_borrowed(&source, &distorted).unwrap().score;
+            assert!((score - exact).abs() < 0.1f64, "{score} vs. {exact}");
+
+            // A reference converts distorted images like its source.
+            let reference = Reference::with_cube_root(source.clone(), cube_root).unwrap();
+            let compared = reference.compare_borrowed(&distorted).unwrap();
+            assert_eq!(compared.to_bits(), score.to_bits());
+        }
+    }
+
+    #[test]
+    fn reference_denoise() {
+        let clean: Vec<[f32; 3]> = (0..67usize * 45)
+            .map(|i| {
+                let t = (i % 67) as f32 / 67.0f32;
+                [
+                    0.3f32.mul_add(t, 0.2f32),
+                    0.35f32,
+                    0.2f32.mul_add(-t, 0.5f32),
+                ]
+            })
+            .collect();
+        // Uniform noise from a linear congruential generator, as of a camera
+        // sensor, in every channel.
+        let mut state = 1u64;
+        let noisy = clean
+            .iter()
+            .map(|pix| {
+                pix.map(|v| {
+                    state = state
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+                    let noise = (state >> 40u32) as f32 / (1u32 << 24u32) as f32;
+                    noise.mul_add(0.01f32, v - 0.005f32)
+                })
+            })
+            .collect();
+        let clean = LinearRgb::new(clean, 67, 45).unwrap();
+        let noisy = LinearRgb::new(noisy, 67, 45).unwrap();
+
+        // An encode which removed the noise scores higher against the
+        // denoised source.
+        let mut session = Ssimulacra2::new(67, 45).unwrap();
+        let plain = session.score_borrowed(&noisy, &clean).unwrap().score;
+        let mut session = session.with_reference_denoise(Denoise::Light);
+        let denoised = session.score_borrowed(&noisy, &clean).unwrap().score;
+        assert!(denoised > plain + 0.1f64, "{denoised} vs. {plain}");
+
+        // The same as scoring against the source denoised beforehand.
+        let mut source = noisy;
+        Denoise::Light.apply(&mut source);
+        let expected = Ssimulacra2::new(67, 45)
+            .unwrap()
+            .score_borrowed(&source, &clean)
+            .unwrap()
+            .score;
+        assert_eq!(denoised.to_bits(), expected.to_bits());
+    }
+
+    #[test]
+    fn viewing() {
+        let ppd = |ppd| Viewing::PixelsPerDegree(core::num::NonZeroU16::new(ppd).unwrap());
+        // A smooth gradient, and the gradient with fine noise of one pixel.
+        let source: Vec<[f32; 3]> = (0..96usize * 64)
+            .map(|i| [0.2f32 + 0.4f32 * (i % 96) as f32 / 96.0f32; 3])
+            .collect();
+        let distorted = source
+            .iter()
+            .enumerate()
+            .map(|(i, pix)| {
+                let sign = if (i % 96 + i / 96) % 2 == 0 {
+                    1.0f32
+                } else {
+                    -1.0f32
+                };
+                pix.map(|v| sign.mul_add(0.02f32, v))
+            })
+            .collect();
+        let source = LinearRgb::new(source, 96, 64).unwrap();
+        let distorted = LinearRgb::new(distorted, 96, 64).unwrap();
+        let native = Ssimulacra2::new(96, 64)
+            .unwrap()
+            .score_borrowed(&source, &distorted)
+            .unwrap()
+            .score;
+
+        // On a display with twice the pixels per degree, the distortion is
+        // scored at half the resolution, where it is less visible.
+        let mut session = Ssimulacra2::new(96, 64).unwrap().with_viewing(ppd(80));
+        let phone = session.score_borrowed(&source, &distorted).unwrap().score;
+        assert!(phone > native, "{phone} vs. {native}");
+        let expected = Ssimulacra2::new(48, 32)
+            .unwrap()
+            .score_borrowed(
+                &crate::ResizeFilter::Area.resize(&source, 48, 32),
+                &crate::ResizeFilter::Area.resize(&distorted, 48, 32),
+            )
+            .unwrap()
+            .score;
+        assert_eq!(phone.to_bits(), expected.to_bits());
+
+        let mut session = session.with_viewing(ppd(1000));
+        assert_eq!(
+            session.score_borrowed(&source, &distorted).unwrap_err(),
+            Ssimulacra2Error::InvalidImageSize
+        );
+    }
+
+    #[test]
+    fn tiling() {
+        // Five tiles at the first scale and three at the second.
+        let (width, height) = (40usize, 1100usize);
+        let source = make_image(width, height, 3);
+        let distorted = make_image(width, height, 5);
+        let mut session = Ssimulacra2::new(width, height).unwrap();
+        let whole = session
+            .score_with_map(source.clone(), distorted.clone())
+            .unwrap();
+        let mut session = session.with_tiling(true);
+        let tiled = session.score_with_map(source, distorted).unwrap();
+
+        // The recursive blur of every tile starts anew, without the rounding
+        // errors the blur of the whole plane has accumulated by then, which
+        // the errors of such noisy images amplify.
+        assert!(
+            (whole.score - tiled.score).abs() < 1e-5f64 * whole.score.abs(),
+            "{} vs. {}",
+            whole.score,
+            tiled.score
+        );
+        let total = |map: &DistortionMap| map.data().iter().map(|&v| f64::from(v)).sum::<f64>();
+        let (whole, tiled) = (total(&whole.map.unwrap()), total(&tiled.map.unwrap()));
+        assert!(
+            (whole - tiled).abs() < 1e-4f64 * whole,
+            "{whole} vs. {tiled}"
+        );
+    }
+}
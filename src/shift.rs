@@ -0,0 +1,317 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+use yuvxyb::LinearRgb;
+
+use crate::Ssimulacra2Error;
+
+// The largest width and height of the region of the images that is
+// correlated. Larger images are cropped to their center.
+const MAX_REGION: usize = 512;
+
+// The correlation peak a shift needs to be compensated. Unrelated images
+// have peaks of a few hundredths, images differing only by a shift close to
+// 1.0.
+const MIN_CONFIDENCE: f64 = 0.1;
+
+/// Whether to check the distorted image for a global shift relative to the
+/// source before scoring, see
+/// [`Ssimulacra2::with_shift_check`][crate::Ssimulacra2::with_shift_check].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShiftCheck {
+    /// Score the images as they are, like the reference implementation.
+    #[default]
+    None,
+    /// Detect the shift and report it in
+    /// [`DetailedScore::shift`][crate::DetailedScore::shift], but score the
+    /// images as they are.
+    Detect,
+    /// Detect the shift and move the distorted image back by it before
+    /// scoring, if the detection is confident enough.
+    Compensate,
+}
+
+/// A global translation of the distorted image relative to the source, as
+/// found by [`detect_shift`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Shift {
+    /// The number of pixels the distorted image is moved to the right.
+    pub x: isize,
+    /// The number of pixels the distorted image is moved down.
+    pub y: isize,
+    /// The height of the correlation peak, from 0.0 to 1.0. Images that only
+    /// differ by the shift have a confidence close to 1.0, while values below
+    /// 0.1 mean that no shift could be told apart from the distortion.
+    pub confidence: f64,
+    /// Whether the distorted image was moved back by the shift before
+    /// scoring.
+    pub compensated: bool,
+}
+
+/// Detects a global translation of `distorted` relative to `source` with
+/// phase correlation.
+///
+/// Shifts by a pixel or two, e.g. from a scaler with the wrong sample
+/// positions, are barely visible, but lower the score as much as heavy
+/// distortion would.
+///
+/// The luminance of both images is windowed and correlated in the frequency
+/// domain, which is insensitive to brightness and contrast changes and to
+/// most compression artifacts. Only whole pixels are detected, and at most a
+/// quarter of the width and height of the images, or of a 512x512 pixel
+/// region at their center for larger images.
+///
+/// # Errors
+/// - If the source and distorted image width and height do not match
+/// - If the source or distorted image cannot be converted to linear RGB successfully
+/// - If the image is smaller than 8x8 pixels
+pub fn detect_shift<T, U>(source: T, distorted: U) -> Result<Shift, Ssimulacra2Error>
+where
+    LinearRgb: TryFrom<T> + TryFrom<U>,
+{
+    let Ok(source) = LinearRgb::try_from(source) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let Ok(distorted) = LinearRgb::try_from(distorted) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    if source.width() != distorted.width() || source.height() != distorted.height() {
+        return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+    }
+    if source.width() < 8 || source.height() < 8 {
+        return Err(Ssimulacra2Error::InvalidImageSize);
+    }
+    Ok(Shift::detect(&source, &distorted))
+}
+
+impl Shift {
+    /// Whether the images are shifted against each other, with a confidence
+    /// high enough to compensate the shift.
+    #[must_use]
+    pub fn is_significant(&self) -> bool {
+        (self.x != 0 || self.y != 0) && self.confidence >= MIN_CONFIDENCE
+    }
+
+    // Phase correlation of the central regions of both images.
+    pub(crate) fn detect(source: &LinearRgb, distorted: &LinearRgb) -> Self {
+        let width = region_size(source.width());
+        let height = region_size(source.height());
+        let mut source = windowed_luma(source, width, height);
+        let mut distorted = windowed_luma(distorted, width, height);
+        fft_2d(&mut source, width, height, false);
+        fft_2d(&mut distorted, width, height, false);
+
+        // The normalized cross-power spectrum, whose inverse transform peaks
+        // at the shift.
+        for (s, d) in source.iter_mut().zip(&distorted) {
+            let re = s.0.mul_add(d.0, s.1 * d.1);
+            let im = s.0.mul_add(d.1, -s.1 * d.0);
+            let magnitude = re.hypot(im);
+            *s = if magnitude > 1e-12f64 {
+                (re / magnitude, im / magnitude)
+            } else {
+                (0.0f64, 0.0f64)
+            };
+        }
+        fft_2d(&mut source, width, height, true);
+
+        let wrap = |i: usize, len: usize| {
+            if i > len / 2 {
+                i as isize - len as isize
+            } else {
+                i as isize
+            }
+        };
+        let mut best = Shift {
+            x: 0,
+            y: 0,
+            confidence: f64::NEG_INFINITY,
+            compensated: false,
+        };
+        for (i, value) in source.iter().enumerate() {
+            let x = wrap(i % width, width);
+            let y = wrap(i / width, height);
+            let in_range = x.unsigned_abs() <= width / 4 && y.unsigned_abs() <= height / 4;
+            if in_range && value.0 > best.confidence {
+                best = Shift {
+                    x,
+                    y,
+                    confidence: value.0,
+                    compensated: false,
+                };
+            }
+        }
+        best.confidence = best.confidence.clamp(0.0f64, 1.0f64);
+        best
+    }
+
+    // Moves `img` back by the shift, repeating its edges where pixels are
+    // uncovered.
+    pub(crate) fn compensate(&self, img: &mut LinearRgb) {
+        let width = img.width();
+        let height = img.height();
+        let shifted = img.data().to_vec();
+        let clamp = |i: isize, len: usize| i.clamp(0, len as isize - 1) as usize;
+        for (y, row) in img.data_mut().chunks_exact_mut(width).enumerate() {
+            let from_y = clamp(y as isize + self.y, height);
+            for (x, pix) in row.iter_mut().enumerate() {
+                *pix = shifted[from_y * width + clamp(x as isize + self.x, width)];
+            }
+        }
+    }
+}
+
+// The largest power of two that fits in `len`, up to `MAX_REGION`.
+fn region_size(len: usize) -> usize {
+    let mut size = 1;
+    while size * 2 <= len.min(MAX_REGION) {
+        size *= 2;
+    }
+    size
+}
+
+// The luminance of the central `width`x`height` region of `img`, less its
+// mean and multiplied by a Hann window so that the edges of the region do
+// not correlate, as complex numbers.
+fn windowed_luma(img: &LinearRgb, width: usize, height: usize) -> Vec<(f64, f64)> {
+    let left = (img.width() - width) / 2;
+    let top = (img.height() - height) / 2;
+    let mut luma = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = &img.data()[(top + y) * img.width() + left..][..width];
+        luma.extend(row.iter().map(|pix| {
+            0.0722f64.mul_add(
+                f64::from(pix[2]),
+                0.2126f64.mul_add(f64::from(pix[0]), 0.7152f64 * f64::from(pix[1])),
+            )
+        }));
+    }
+    let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+    let hann =
+        |i: usize, len: usize| 0.5f64.mul_add(-(2.0f64 * PI * i as f64 / len as f64).cos(), 0.5f64);
+    luma.iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let window = hann(i % width, width) * hann(i / width, height);
+            ((value - mean) * window, 0.0f64)
+        })
+        .collect()
+}
+
+// Transforms the rows and then the columns of `data` in place.
+fn fft_2d(data: &mut [(f64, f64)], width: usize, height: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(width) {
+        fft(row, inverse);
+    }
+    let mut column = vec![(0.0f64, 0.0f64); height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+        fft(&mut column, inverse);
+        for (y, value) in column.iter().enumerate() {
+            data[y * width + x] = *value;
+        }
+    }
+}
+
+// An iterative radix-2 FFT of a power-of-two number of complex values. The
+// inverse transform is scaled by the reciprocal of the length.
+fn fft(data: &mut [(f64, f64)], inverse: bool) {
+    let len = data.len();
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1usize;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1usize;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0f64 } else { -1.0f64 };
+    let mut size = 2;
+    while size <= len {
+        let angle = sign * 2.0f64 * PI / size as f64;
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (re, im) = data[start + k + size / 2];
+                let t = (re.mul_add(cos, -im * sin), re.mul_add(sin, im * cos));
+                let u = data[start + k];
+                data[start + k] = (u.0 + t.0, u.1 + t.1);
+                data[start + k + size / 2] = (u.0 - t.0, u.1 - t.1);
+            }
+        }
+        size *= 2;
+    }
+
+    if inverse {
+        for value in data {
+            *value = (value.0 / len as f64, value.1 / len as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ShiftCheck, Ssimulacra2};
+
+    // A pseudo-random texture, moved by `dx` and `dy` pixels.
+    fn image(dx: isize, dy: isize) -> LinearRgb {
+        let data = (0..100isize * 80)
+            .map(|i| {
+                let x = (i % 100 - dx).rem_euclid(100) as u32;
+                let y = (i / 100 - dy).rem_euclid(80) as u32;
+                let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663))
+                    .wrapping_mul(2_654_435_761)
+                    >> 20u32;
+                let v = (hash % 1000) as f32 / 2000.0f32 + 0.25f32;
+                [v, v * 0.8f32, 0.3f32]
+            })
+            .collect();
+        LinearRgb::new(data, 100, 80).unwrap()
+    }
+
+    #[test]
+    fn detects_shift() {
+        let shift = detect_shift(image(0, 0), image(2, -1)).unwrap();
+        assert_eq!((shift.x, shift.y), (2, -1), "{shift:?}");
+        assert!(shift.confidence > 0.5f64, "{shift:?}");
+        assert!(shift.is_significant());
+
+        let none = detect_shift(image(0, 0), image(0, 0)).unwrap();
+        assert_eq!((none.x, none.y), (0, 0));
+        assert!(!none.is_significant());
+    }
+
+    #[test]
+    fn compensates_shift() {
+        let mut session = Ssimulacra2::new(100, 80).unwrap();
+        let plain = session.score_detailed(image(0, 0), image(1, 0)).unwrap();
+        assert!(plain.shift.is_none());
+
+        let mut session = session.with_shift_check(ShiftCheck::Detect);
+        let detected = session.score_detailed(image(0, 0), image(1, 0)).unwrap();
+        let shift = detected.shift.unwrap();
+        assert_eq!((shift.x, shift.y, shift.compensated), (1, 0, false));
+        assert!((detected.score - plain.score).abs() < 1e-9f64);
+
+        let mut session = session.with_shift_check(ShiftCheck::Compensate);
+        let compensated = session.score_detailed(image(0, 0), image(1, 0)).unwrap();
+        assert!(compensated.shift.unwrap().compensated);
+        assert!(
+            compensated.score > plain.score + 10.0f64,
+            "{} {}",
+            compensated.score,
+            plain.score
+        );
+    }
+}
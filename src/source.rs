@@ -0,0 +1,90 @@
+use yuvxyb::{Frame, Pixel, YuvConfig};
+
+/// A source of decoded video frames, e.g. a file reader or a hardware decoder.
+///
+/// Frames are planar [`Frame`]s, whose planes carry their own stride,
+/// padding and chroma decimation, together with the [`YuvConfig`] giving the
+/// bit depth, chroma subsampling, range and color space to interpret them
+/// with. Wrap a frame in a [`FrameRef`][crate::FrameRef] to score it in
+/// place, or in a [`Yuv`][crate::Yuv] to move it to another thread, e.g.
+/// through [`FramePipeline::run`][crate::FramePipeline::run].
+///
+/// [`Y4mSource`][crate::Y4mSource] implements this trait for Y4M files with
+/// the `y4m` feature. Other decoders plug in by implementing it as well. A
+/// VAAPI or NVDEC decoder, for example, would map or download every decoded
+/// surface to system memory and copy its planes row by row with
+/// [`Plane::copy_from_raw_u8`][crate::Plane::copy_from_raw_u8], passing the
+/// pitch of the surface as the source stride. Interleaved chroma, as in NV12
+/// or P010, has to be split into separate planes first, and P010 samples
+/// shifted down to their bit depth.
+pub trait FrameSource {
+    /// The type of the samples of the frames, `u8` for 8-bit video and `u16`
+    /// for higher bit depths.
+    type Pixel: Pixel;
+
+    /// The error returned if a frame cannot be read or decoded.
+    type Error;
+
+    /// Width of the luma plane of the frames.
+    fn width(&self) -> usize;
+
+    /// Height of the luma plane of the frames.
+    fn height(&self) -> usize;
+
+    /// The color configuration to interpret the frames with.
+    fn config(&self) -> YuvConfig;
+
+    /// Number of frames per second, if known.
+    fn frame_rate(&self) -> Option<f64> {
+        None
+    }
+
+    /// Decodes the next frame, or returns [None] at the end of the video.
+    ///
+    /// # Errors
+    /// - If the frame cannot be read or decoded
+    fn next_frame(&mut self) -> Result<Option<Frame<Self::Pixel>>, Self::Error>;
+
+    /// Skips the next frame, returning `false` at the end of the video.
+    /// Sources which can skip a frame without decoding it, e.g. by seeking
+    /// past it, should override this to speed up scoring a selection of
+    /// frames.
+    ///
+    /// # Errors
+    /// - If the frame cannot be read
+    fn skip_frame(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.next_frame()?.is_some())
+    }
+
+    /// An iterator over the remaining frames of the source.
+    fn frames(&mut self) -> Frames<'_, Self>
+    where
+        Self: Sized,
+    {
+        Frames {
+            source: self,
+            failed: false,
+        }
+    }
+}
+
+/// An iterator over the frames of a [`FrameSource`], see
+/// [`FrameSource::frames`]. It ends after the last frame or the first error.
+#[derive(Debug)]
+pub struct Frames<'a, S> {
+    source: &'a mut S,
+    failed: bool,
+}
+
+impl<S: FrameSource> Iterator for Frames<'_, S> {
+    type Item = Result<Frame<S::Pixel>, S::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.source.next_frame().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
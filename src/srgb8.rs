@@ -0,0 +1,138 @@
+use alloc::vec::Vec;
+
+use yuvxyb::LinearRgb;
+
+use crate::{Ssimulacra2Error, TransferFunction};
+
+/// An 8-bit sRGB image with BT.709 primaries, borrowed from interleaved RGB
+/// or RGBA bytes, e.g. a decoded screenshot.
+///
+/// Converting it into [`LinearRgb`] decodes the transfer function with the
+/// cached 256-entry lookup table of [`TransferFunction::Srgb`] and drops the
+/// alpha channel in the same pass, instead of converting every value to float
+/// and evaluating the transfer function for it as [`Rgb`][crate::Rgb] does. The result is
+/// the same up to rounding, but much cheaper to compute, which matters when
+/// scoring many small images.
+#[derive(Debug, Clone, Copy)]
+pub struct Srgb8Image<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+}
+
+impl<'a> Srgb8Image<'a> {
+    /// Wraps interleaved RGB bytes.
+    ///
+    /// # Errors
+    /// - If `data` is not `width * height * 3` bytes long
+    pub const fn from_rgb(
+        data: &'a [u8],
+        width: usize,
+        height: usize,
+    ) -> Result<Self, Ssimulacra2Error> {
+        Self::new(data, width, height, 3)
+    }
+
+    /// Wraps interleaved RGBA bytes. The alpha channel is ignored.
+    ///
+    /// # Errors
+    /// - If `data` is not `width * height * 4` bytes long
+    pub const fn from_rgba(
+        data: &'a [u8],
+        width: usize,
+        height: usize,
+    ) -> Result<Self, Ssimulacra2Error> {
+        Self::new(data, width, height, 4)
+    }
+
+    const fn new(
+        data: &'a [u8],
+        width: usize,
+        height: usize,
+        channels: usize,
+    ) -> Result<Self, Ssimulacra2Error> {
+        if data.len() != width * height * channels {
+            return Err(Ssimulacra2Error::InvalidBufferLength);
+        }
+
+        Ok(Srgb8Image {
+            data,
+            width,
+            height,
+            channels,
+        })
+    }
+
+    /// Width of the image in pixels.
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the image in pixels.
+    #[must_use]
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl TryFrom<Srgb8Image<'_>> for LinearRgb {
+    type Error = Ssimulacra2Error;
+
+    fn try_from(image: Srgb8Image<'_>) -> Result<Self, Self::Error> {
+        let lut = TransferFunction::Srgb.lut(8);
+        let data: Vec<_> = image
+            .data
+            .chunks_exact(image.channels)
+            .map(|pix| {
+                [
+                    lut[usize::from(pix[0])],
+                    lut[usize::from(pix[1])],
+                    lut[usize::from(pix[2])],
+                ]
+            })
+            .collect();
+
+        let Ok(linear) = LinearRgb::new(data, image.width, image.height) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        Ok(linear)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_rgb() {
+        let width = 29;
+        let height = 23;
+        let rgba: Vec<u8> = (0..width * height * 4)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect();
+        let rgb: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|pix| [pix[0], pix[1], pix[2]])
+            .collect();
+
+        let mut expected: Vec<f32> = rgb.iter().map(|&v| f32::from(v) / 255.0).collect();
+        TransferFunction::Srgb.to_linear(&mut expected);
+        let expected: Vec<[f32; 3]> = bytemuck::cast_slice(&expected).to_vec();
+        let from_rgb =
+            LinearRgb::try_from(Srgb8Image::from_rgb(&rgb, width, height).unwrap()).unwrap();
+        let from_rgba =
+            LinearRgb::try_from(Srgb8Image::from_rgba(&rgba, width, height).unwrap()).unwrap();
+
+        // Bitwise, so scores do not depend on the input path.
+        assert!(from_rgb.data() == expected.as_slice());
+        assert!(from_rgba.data() == expected.as_slice());
+        assert_eq!((from_rgb.width(), from_rgb.height()), (width, height));
+
+        assert_eq!(
+            Srgb8Image::from_rgb(&rgba, width, height).err(),
+            Some(Ssimulacra2Error::InvalidBufferLength)
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use alloc::vec::Vec;
+
+/// The score of a single frame of a video.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameScore {
+    /// Index of the frame in the video.
+    pub frame: usize,
+    /// The SSIMULACRA2 score of the frame.
+    pub score: f64,
+}
+
+/// Summary statistics over the scores of multiple frames.
+///
+/// Percentiles are taken from the low end, so `p1` is the score that only the
+/// worst 1% of frames fall below. They are linearly interpolated between the
+/// two closest frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScoreSummary {
+    /// Number of frames which were scored.
+    pub frames: usize,
+    /// Arithmetic mean of all scores.
+    pub mean: f64,
+    /// Harmonic mean of all scores. This is [None] if any score is not
+    /// positive, as the harmonic mean is undefined in that case.
+    pub harmonic_mean: Option<f64>,
+    /// Population standard deviation of all scores.
+    pub std_dev: f64,
+    /// Approximate 95% confidence interval of the mean, as `(lower, upper)`.
+    /// This shows how close the mean of a sampled video is likely to be to the
+    /// mean over all of its frames. It is [None] for fewer than two frames.
+    pub mean_ci95: Option<(f64, f64)>,
+    /// Lowest score.
+    pub min: f64,
+    /// Highest score.
+    pub max: f64,
+    /// 1st percentile.
+    pub p1: f64,
+    /// 5th percentile.
+    pub p5: f64,
+    /// 25th percentile.
+    pub p25: f64,
+    /// 50th percentile (median).
+    pub p50: f64,
+}
+
+/// The summary statistics of the frames of one scene of a video, see
+/// [`ScoreAggregator::scenes`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SceneSummary {
+    /// Index of the first frame of the scene.
+    pub start: usize,
+    /// Index of the frame after the last frame of the scene.
+    pub end: usize,
+    /// Statistics over the scores of the frames of the scene.
+    pub summary: ScoreSummary,
+}
+
+/// A way of pooling the scores of the frames of a video into one score.
+///
+/// The mean of all frames hides brief drops in quality, which are very
+/// visible when watching the video. The other strategies weigh the worst
+/// frames more.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TemporalPooling {
+    /// Arithmetic mean of all scores.
+    Mean,
+    /// Harmonic mean of all scores, which is undefined if any score is not
+    /// positive.
+    HarmonicMean,
+    /// The given percentile (`0.0..=100.0`) of all scores, taken from the low
+    /// end like the percentiles of [`ScoreSummary`].
+    Percentile(f64),
+    /// Minkowski pooling with the given exponent, which must be at least 1.
+    /// The distances of the scores from 100 are averaged in the `p`-norm, so
+    /// that larger exponents weigh the worst frames more. An exponent of 1 is
+    /// the arithmetic mean.
+    Minkowski(f64),
+    /// The lowest mean score of any run of the given number of consecutive
+    /// frames, by frame index. This shows the worst stretch of the video,
+    /// e.g. the worst second when given the frame rate. Videos shorter than
+    /// the window are pooled as a whole.
+    WorstWindow(usize),
+}
+
+/// Collects per-frame scores and computes the statistics commonly reported in
+/// encoder benchmarks.
+///
+/// Frames can be added in any order, which allows scores from parallel workers
+/// to be collected as they arrive.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScoreAggregator {
+    scores: Vec<FrameScore>,
+}
+
+impl ScoreAggregator {
+    /// Creates an empty aggregator.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the score of the given frame.
+    pub fn push(&mut self, frame: usize, score: f64) {
+        self.scores.push(FrameScore { frame, score });
+    }
+
+    /// Number of frames added so far.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Whether no frames have been added yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// All frame scores, in the order they were added.
+    #[must_use]
+    pub fn scores(&self) -> &[FrameScore] {
+        &self.scores
+    }
+
+    /// The given percentile (`0.0..=100.0`) of all scores, or [None] if no frames have been added.
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        percentile_of_sorted(&self.sorted_scores(), percentile)
+    }
+
+    /// The `n` frames with the lowest scores, worst first.
+    #[must_use]
+    pub fn worst(&self, n: usize) -> Vec<FrameScore> {
+        let mut scores = self.scores.clone();
+        scores.sort_by(|a, b| a.score.total_cmp(&b.score).then(a.frame.cmp(&b.frame)));
+        scores.truncate(n);
+        scores
+    }
+
+    /// Computes the summary statistics, or [None] if no frames have been added.
+    #[must_use]
+    pub fn summary(&self) -> Option<ScoreSummary> {
+        let sorted = self.sorted_scores();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let count = sorted.len() as f64;
+
+        let mean = sorted.iter().sum::<f64>() / count;
+        let variance = sorted.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count;
+        let harmonic_mean =
+            (min > 0.0f64).then(|| count / sorted.iter().map(|s| s.recip()).sum::<f64>());
+        let mean_ci95 = (sorted.len() > 1).then(|| {
+            // Normal approximation. The standard error of the mean uses the
+            // sample variance, i.e. `variance * n / (n - 1)`, divided by `n`.
+            let std_err = (variance / (count - 1.0f64)).sqrt();
+            (
+                1.96f64.mul_add(-std_err, mean),
+                1.96f64.mul_add(std_err, mean),
+            )
+        });
+
+        Some(ScoreSummary {
+            frames: sorted.len(),
+            mean,
+            harmonic_mean,
+            std_dev: variance.sqrt(),
+            mean_ci95,
+            min,
+            max,
+            p1: percentile_of_sorted(&sorted, 1.0f64)?,
+            p5: percentile_of_sorted(&sorted, 5.0f64)?,
+            p25: percentile_of_sorted(&sorted, 25.0f64)?,
+            p50: percentile_of_sorted(&sorted, 50.0f64)?,
+        })
+    }
+
+    /// Pools the scores of all frames with the given strategy.
+    ///
+    /// Returns [None] if no frames have been added, or if the result is
+    /// undefined: for the harmonic mean of scores that are not all positive,
+    /// a Minkowski exponent below 1 or a window of 0 frames.
+    #[must_use]
+    pub fn pool(&self, pooling: TemporalPooling) -> Option<f64> {
+        if self.scores.is_empty() {
+            return None;
+        }
+        let count = self.scores.len() as f64;
+        let scores = self.scores.iter().map(|s| s.score);
+
+        match pooling {
+            TemporalPooling::Mean => Some(scores.sum::<f64>() / count),
+            TemporalPooling::HarmonicMean => self
+                .scores
+                .iter()
+                .all(|s| s.score > 0.0f64)
+                .then(|| count / scores.map(f64::recip).sum::<f64>()),
+            TemporalPooling::Percentile(percentile) => self.percentile(percentile),
+            TemporalPooling::Minkowski(p) => (p >= 1.0f64).then(|| {
+                let sum = scores.map(|s| (100.0f64 - s).abs().powf(p)).sum::<f64>();
+                100.0f64 - (sum / count).powf(p.recip())
+            }),
+            TemporalPooling::WorstWindow(window) => {
+                if window == 0 {
+                    return None;
+                }
+                let mut by_frame = self.scores.clone();
+                by_frame.sort_by_key(|s| s.frame);
+                let window = window.min(by_frame.len());
+
+                by_frame
+                    .windows(window)
+                    .map(|run| run.iter().map(|s| s.score).sum::<f64>() / window as f64)
+                    .min_by(f64::total_cmp)
+            }
+        }
+    }
+
+    /// Computes the summary statistics of every scene, given the frames that
+    /// start a new scene, e.g. from [`SceneDetector`][crate::SceneDetector].
+    ///
+    /// The first scene starts at frame 0 and the last one ends after the last
+    /// scored frame. The cuts may be in any order, and cuts outside of these
+    /// frames are ignored. Scenes without any scored frames are left out.
+    #[must_use]
+    pub fn scenes(&self, cuts: &[usize]) -> Vec<SceneSummary> {
+        let Some(end) = self.scores.iter().map(|s| s.frame + 1).max() else {
+            return Vec::new();
+        };
+        let mut cuts: Vec<_> = cuts
+            .iter()
+            .copied()
+            .filter(|&cut| cut > 0 && cut < end)
+            .collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+        let starts = core::iter::once(0).chain(cuts.iter().copied());
+        let ends = cuts.iter().copied().chain([end]);
+
+        starts
+            .zip(ends)
+            .filter_map(|(start, end)| {
+                let scene = ScoreAggregator {
+                    scores: self
+                        .scores
+                        .iter()
+                        .filter(|s| (start..end).contains(&s.frame))
+                        .copied()
+                        .collect(),
+                };
+                Some(SceneSummary {
+                    start,
+                    end,
+                    summary: scene.summary()?,
+                })
+            })
+            .collect()
+    }
+
+    fn sorted_scores(&self) -> Vec<f64> {
+        let mut sorted: Vec<_> = self.scores.iter().map(|s| s.score).collect();
+        sorted.sort_by(f64::total_cmp);
+        sorted
+    }
+}
+
+impl Extend<FrameScore> for ScoreAggregator {
+    fn extend<I: IntoIterator<Item = FrameScore>>(&mut self, iter: I) {
+        self.scores.extend(iter);
+    }
+}
+
+fn percentile_of_sorted(sorted: &[f64], percentile: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let rank = percentile.clamp(0.0, 100.0) / 100.0f64 * last as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+
+    Some((sorted[upper] - sorted[lower]).mul_add(fraction, sorted[lower]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut aggregator = ScoreAggregator::new();
+        assert!(aggregator.summary().is_none());
+
+        for (frame, score) in [90.0f64, 80.0f64, 70.0f64, 60.0f64, 50.0f64]
+            .into_iter()
+            .enumerate()
+        {
+            aggregator.push(frame, score);
+        }
+
+        let summary = aggregator.summary().unwrap();
+        assert_eq!(summary.frames, 5);
+        assert!((summary.mean - 70.0f64).abs() < 1e-9f64);
+        assert!((summary.std_dev - 200.0f64.sqrt()).abs() < 1e-9f64);
+        assert!((summary.p50 - 70.0f64).abs() < 1e-9f64);
+        assert!((summary.p25 - 60.0f64).abs() < 1e-9f64);
+        assert!((summary.p5 - 52.0f64).abs() < 1e-9f64);
+        assert!((summary.harmonic_mean.unwrap() - 67.056_945_183_608_29_f64).abs() < 1e-9f64);
+        let (lower, upper) = summary.mean_ci95.unwrap();
+        assert!(
+            (upper - lower - 2.0f64 * 1.96f64 * 250.0f64.sqrt() / 5.0f64.sqrt()).abs() < 1e-9f64
+        );
+
+        let worst = aggregator.worst(2);
+        assert_eq!(worst[0].frame, 4);
+        assert_eq!(worst[1].frame, 3);
+
+        aggregator.push(5, -10.0f64);
+        assert!(aggregator.summary().unwrap().harmonic_mean.is_none());
+    }
+
+    #[test]
+    fn pooling() {
+        let mut aggregator = ScoreAggregator::new();
+        assert!(aggregator.pool(TemporalPooling::Mean).is_none());
+
+        // A brief drop in an otherwise steady video, added out of order.
+        for frame in (0..20usize).rev() {
+            let score = if (10..13).contains(&frame) {
+                40.0f64
+            } else {
+                90.0f64
+            };
+            aggregator.push(frame, score);
+        }
+
+        let pool = |pooling| aggregator.pool(pooling).unwrap();
+        let mean = pool(TemporalPooling::Mean);
+        assert!((mean - 82.5f64).abs() < 1e-9f64);
+        assert!((pool(TemporalPooling::Minkowski(1.0f64)) - mean).abs() < 1e-9f64);
+        assert!(
+            pool(TemporalPooling::Minkowski(4.0f64)) < pool(TemporalPooling::Minkowski(2.0f64))
+        );
+        assert!(pool(TemporalPooling::Minkowski(2.0f64)) < mean);
+        assert!(pool(TemporalPooling::HarmonicMean) < mean);
+        assert!((pool(TemporalPooling::Percentile(5.0f64)) - 40.0f64).abs() < 1e-9f64);
+        assert!((pool(TemporalPooling::WorstWindow(3)) - 40.0f64).abs() < 1e-9f64);
+        assert!((pool(TemporalPooling::WorstWindow(5)) - 60.0f64).abs() < 1e-9f64);
+        assert!((pool(TemporalPooling::WorstWindow(100)) - mean).abs() < 1e-9f64);
+        assert!(aggregator.pool(TemporalPooling::WorstWindow(0)).is_none());
+        assert!(aggregator
+            .pool(TemporalPooling::Minkowski(0.5f64))
+            .is_none());
+
+        aggregator.push(20, -10.0f64);
+        assert!(aggregator.pool(TemporalPooling::HarmonicMean).is_none());
+    }
+
+    #[test]
+    fn unsorted_scene_cuts() {
+        let mut aggregator = ScoreAggregator::new();
+        for frame in 0..20usize {
+            aggregator.push(frame, frame as f64);
+        }
+
+        let bounds = |cuts: &[usize]| {
+            aggregator
+                .scenes(cuts)
+                .iter()
+                .map(|scene| (scene.start, scene.end, scene.summary.frames))
+                .collect::<Vec<_>>()
+        };
+        let sorted = bounds(&[5, 12]);
+        assert_eq!(sorted, [(0, 5, 5), (5, 12, 7), (12, 20, 8)]);
+        assert_eq!(bounds(&[12, 5]), sorted);
+        assert_eq!(bounds(&[12, 0, 5, 12, 30, 5]), sorted);
+        assert_eq!(bounds(&[20]), [(0, 20, 20)]);
+    }
+}
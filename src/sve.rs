@@ -0,0 +1,19 @@
+// Runtime detection of the Scalable Vector Extension of Arm CPUs, such as
+// Graviton 3 and 4 or recent phones, whose vectors can be wider than the 128
+// bits of NEON.
+//
+// The loops that vectorize well, the vertical passes of the blur, are
+// compiled a second time with `#[target_feature(enable = "sve")]` and picked
+// at runtime when the CPU supports it, so that the compiler can use the wider
+// vectors. The functions they call have to be `#[inline(always)]`, as others
+// are not compiled again with SVE. Other CPUs, and builds without `std`,
+// which cannot detect it, run the loops compiled for the baseline target,
+// i.e. NEON on aarch64. Both compute the same values, as every multiply-add
+// is fused either way.
+
+// Whether the CPU supports SVE. The result is cached by the standard
+// library, so this is cheap enough to check for every row.
+#[inline]
+pub fn detected() -> bool {
+    std::arch::is_aarch64_feature_detected!("sve")
+}
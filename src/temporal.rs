@@ -0,0 +1,149 @@
+use yuvxyb::LinearRgb;
+
+use crate::{Ssimulacra2, Ssimulacra2Error};
+
+/// The scores of a frame scored by a [`TemporalSession`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemporalScore {
+    /// The SSIMULACRA2 score of the frame itself.
+    pub score: f64,
+    /// The SSIMULACRA2 score of the change from the previous frame to this
+    /// one in the distorted video, compared to the change in the source.
+    /// This is [None] for the first frame and after a change of resolution.
+    pub temporal: Option<f64>,
+}
+
+/// Scores the frames of a video and the changes between consecutive frames.
+///
+/// **Experimental**: the temporal scores are not calibrated against
+/// subjective ratings and may change.
+///
+/// Per-frame scores miss flicker, e.g. from an encoder alternating between
+/// two looks of a static scene, as every frame on its own may be close to the
+/// source. For every frame after the first, the session also scores the
+/// difference to the previous distorted frame against the difference to the
+/// previous source frame. Differences are mapped to images around mid-grey,
+/// so a static scene that stays static in the distorted video scores 100,
+/// while flicker lowers the temporal score.
+#[derive(Debug)]
+pub struct TemporalSession {
+    session: Ssimulacra2,
+    previous: Option<(LinearRgb, LinearRgb)>,
+}
+
+impl TemporalSession {
+    /// Creates a session for frames of the given width and height. Frames of
+    /// other resolutions can be scored as well, see [`Ssimulacra2`].
+    ///
+    /// # Errors
+    /// - If the width or height is less than 8 pixels
+    pub fn new(width: usize, height: usize) -> Result<Self, Ssimulacra2Error> {
+        Ok(TemporalSession {
+            session: Ssimulacra2::new(width, height)?,
+            previous: None,
+        })
+    }
+
+    /// Scores the next frame of the video.
+    ///
+    /// # Errors
+    /// - If the source or distorted frame width and height do not match the session
+    /// - If the source or distorted frame cannot be converted to XYB successfully
+    /// - If the frames contain values for which no score can be computed
+    /// - If validation is enabled and a frame contains NaN or infinite values
+    pub fn score<T, U>(
+        &mut self,
+        source: T,
+        distorted: U,
+    ) -> Result<TemporalScore, Ssimulacra2Error>
+    where
+        LinearRgb: TryFrom<T> + TryFrom<U>,
+    {
+        let Ok(source) = LinearRgb::try_from(source) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+        let Ok(distorted) = LinearRgb::try_from(distorted) else {
+            return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+        };
+
+        let score = self.session.score_borrowed(&source, &distorted)?.score;
+        let temporal = match self.previous.take() {
+            Some((previous_source, previous_distorted))
+                if previous_source.width() == source.width()
+                    && previous_source.height() == source.height() =>
+            {
+                Some(self.session.score(
+                    difference(&previous_source, &source),
+                    difference(&previous_distorted, &distorted),
+                )?)
+            }
+            _ => None,
+        };
+        self.previous = Some((source, distorted));
+
+        Ok(TemporalScore { score, temporal })
+    }
+
+    /// Forgets the previous frame, e.g. after seeking or at a scene cut, so
+    /// the next frame has no temporal score.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+// Maps the change from `previous` to `current` to an image around mid-grey.
+fn difference(previous: &LinearRgb, current: &LinearRgb) -> LinearRgb {
+    let data = previous
+        .data()
+        .iter()
+        .zip(current.data())
+        .map(|(prev, cur)| [0usize, 1, 2].map(|c| 0.5f32.mul_add(cur[c] - prev[c], 0.5f32)))
+        .collect();
+    LinearRgb::new(data, current.width(), current.height()).expect("Frame sizes match")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_frame(offset: f32) -> LinearRgb {
+        let data = (0..48usize * 32)
+            .map(|i| {
+                let v = ((i * 7) % 48) as f32 / 96.0f32 + 0.25f32;
+                [v + offset, v, v - offset]
+            })
+            .collect();
+        LinearRgb::new(data, 48, 32).unwrap()
+    }
+
+    #[test]
+    fn flicker() {
+        let mut steady = TemporalSession::new(48, 32).unwrap();
+        let mut flickering = TemporalSession::new(48, 32).unwrap();
+        let mut steady_scores = Vec::new();
+        let mut flickering_scores = Vec::new();
+        for frame in 0..4usize {
+            let offset = if frame % 2 == 0 { 0.02f32 } else { -0.02f32 };
+            steady_scores.push(steady.score(make_frame(0.0), make_frame(0.02)).unwrap());
+            flickering_scores.push(
+                flickering
+                    .score(make_frame(0.0), make_frame(offset))
+                    .unwrap(),
+            );
+        }
+
+        assert!(steady_scores[0].temporal.is_none());
+        for (steady, flickering) in steady_scores.iter().zip(&flickering_scores).skip(1) {
+            // Every frame on its own is about as good, but only the steady
+            // video keeps the static scene static.
+            assert!((steady.score - flickering.score).abs() < 1.0f64);
+            assert!((steady.temporal.unwrap() - 100.0f64).abs() < 1e-9f64);
+            assert!(flickering.temporal.unwrap() < 99.0f64);
+        }
+
+        flickering.reset();
+        let after_reset = flickering.score(make_frame(0.0), make_frame(0.02)).unwrap();
+        assert!(after_reset.temporal.is_none());
+    }
+}
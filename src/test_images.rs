@@ -0,0 +1,52 @@
+// Images shared by the tests of the crate. `tests/memory.rs` includes this
+// file as well, with `LinearRgb` imported at its root.
+
+use crate::LinearRgb;
+
+// An image of values which repeat every 97 pixels, shifted by channel, with
+// `seed` setting the step between pixels. Different seeds give images which
+// are unlike each other.
+pub fn make_image(width: usize, height: usize, seed: usize) -> LinearRgb {
+    let data = (0..width * height)
+        .map(|i| [0usize, 1, 2].map(|c| ((i * seed + c * 17) % 97) as f32 / 97.0))
+        .collect();
+    LinearRgb::new(data, width, height).unwrap()
+}
+
+// An image of a sine wave along its rows, which `phase` shifts, e.g. to get a
+// slightly distorted version of it.
+pub fn wave_image(width: usize, height: usize, phase: f32) -> LinearRgb {
+    let data = (0..width * height)
+        .map(|i| {
+            let v = ((i % width) as f32).mul_add(0.4f32, phase).sin();
+            [v.mul_add(0.2f32, 0.5f32), 0.4f32, v.mul_add(0.1f32, 0.3f32)]
+        })
+        .collect();
+    LinearRgb::new(data, width, height).unwrap()
+}
+
+// An image of sine waves along its rows and columns, of which `phase` shifts
+// the first.
+pub fn crossed_wave_image(width: usize, height: usize, phase: f32) -> LinearRgb {
+    let data = (0..width * height)
+        .map(|i| {
+            let x = (i % width) as f32;
+            let y = (i / width) as f32;
+            let v = x.mul_add(0.3f32, phase).sin().mul_add(0.2f32, 0.5f32);
+            [v, (y * 0.1f32).cos().mul_add(0.2f32, 0.4f32), v * 0.5f32]
+        })
+        .collect();
+    LinearRgb::new(data, width, height).unwrap()
+}
+
+// An image of a ramp along its rows, repeating every `width / 7` pixels, with
+// `offset` added to the green channel, e.g. to get a distorted version of it.
+pub fn ramp_image(width: usize, height: usize, offset: f32) -> LinearRgb {
+    let data = (0..width * height)
+        .map(|i| {
+            let v = ((i * 7) % width) as f32 / width as f32;
+            [v, (v + offset).min(1.0f32), 0.25f32]
+        })
+        .collect();
+    LinearRgb::new(data, width, height).unwrap()
+}
@@ -0,0 +1,300 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use wide::{f32x8, CmpGt, CmpLe, CmpLt};
+use yuvxyb::TransferCharacteristic;
+#[cfg(any(feature = "ndarray", feature = "v_frame", test))]
+use yuvxyb::{ColorPrimaries, LinearRgb, Rgb};
+
+use crate::Ssimulacra2Error;
+
+const SRGB_ALPHA: f32 = 1.055_010_7;
+const SRGB_BETA: f32 = 0.003_041_282_5;
+
+const REC709_ALPHA: f32 = 1.099_296_8;
+const REC709_BETA: f32 = 0.018_053_97;
+
+const ST2084_M1: f32 = 0.159_301_76;
+const ST2084_M2: f32 = 78.843_75;
+const ST2084_C1: f32 = 0.835_937_5;
+const ST2084_C2: f32 = 18.851_563;
+const ST2084_C3: f32 = 18.6875;
+const ST2084_OOTF_SCALE: f32 = 59.490_803;
+
+const ARIB_B67_A: f32 = 0.178_832_77;
+const ARIB_B67_B: f32 = 0.284_668_92;
+const ARIB_B67_C: f32 = 0.559_910_7;
+
+/// A transfer function, decoding encoded values in `0.0..=1.0` to linear light.
+///
+/// The curves follow the definitions used by `yuvxyb`, so that images decoded
+/// here score like the same images passed as [`Rgb`][crate::Rgb]. Float values
+/// are decoded eight at a time with [`to_linear`][Self::to_linear]; for integer
+/// code values, [`lut`][Self::lut] tabulates the same results once per bit depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferFunction {
+    /// The sRGB curve of IEC 61966-2-1.
+    Srgb,
+    /// The BT.1886 EOTF of an ideal display, a pure 2.4 power curve.
+    Bt1886,
+    /// The SMPTE ST 2084 perceptual quantizer, mapped to SDR display light
+    /// with the BT.2390 reference OOTF.
+    Pq,
+    /// The ARIB STD-B67 hybrid log-gamma curve, decoded to scene light.
+    Hlg,
+    /// Values that are already linear.
+    Linear,
+    /// A pure power curve with the given exponent.
+    Gamma(f32),
+}
+
+impl TransferFunction {
+    /// Decodes `values` to linear light in place. Negative values decode to 0,
+    /// except with [`Linear`][Self::Linear], which leaves all values unchanged.
+    pub fn to_linear(self, values: &mut [f32]) {
+        if self == TransferFunction::Linear {
+            return;
+        }
+
+        for chunk in values.chunks_mut(8) {
+            let mut lanes = [0.0f32; 8];
+            lanes[..chunk.len()].copy_from_slice(chunk);
+            let linear = self.to_linear_x8(f32x8::from(lanes));
+            chunk.copy_from_slice(&linear.as_array_ref()[..chunk.len()]);
+        }
+    }
+
+    /// Returns the linear value of every code value of the given bit depth,
+    /// indexed by the code value.
+    ///
+    /// Tables are computed with [`to_linear`][Self::to_linear] on first use.
+    /// With the `std` feature, those of the fixed curves are cached for the
+    /// lifetime of the process, so that further calls for the same function
+    /// and bit depth are cheap. Tables of [`Gamma`][Self::Gamma] curves are
+    /// computed on every call.
+    ///
+    /// # Panics
+    /// - If `bit_depth` is not in `1..=16`
+    /// - If the exponent of a [`Gamma`][Self::Gamma] curve is not finite and
+    ///   positive
+    #[must_use]
+    pub fn lut(self, bit_depth: u8) -> Arc<[f32]> {
+        assert!(
+            (1..=16).contains(&bit_depth),
+            "Bit depth must be between 1 and 16"
+        );
+        if let TransferFunction::Gamma(gamma) = self {
+            assert!(
+                gamma.is_finite() && gamma > 0.0,
+                "Gamma must be finite and positive"
+            );
+            return self.compute_lut(bit_depth);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            // One table per fixed curve and bit depth, at most 80 in total.
+            type Cache = Vec<(TransferFunction, u8, Arc<[f32]>)>;
+            static CACHE: std::sync::Mutex<Cache> = std::sync::Mutex::new(Vec::new());
+
+            let mut cache = CACHE
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if let Some(entry) = cache
+                .iter()
+                .find(|entry| entry.0 == self && entry.1 == bit_depth)
+            {
+                return Arc::clone(&entry.2);
+            }
+            let lut = self.compute_lut(bit_depth);
+            cache.push((self, bit_depth, Arc::clone(&lut)));
+            lut
+        }
+
+        #[cfg(not(feature = "std"))]
+        self.compute_lut(bit_depth)
+    }
+
+    fn compute_lut(self, bit_depth: u8) -> Arc<[f32]> {
+        let max = (1u32 << bit_depth) - 1;
+        let mut lut: Vec<f32> = (0..=max).map(|v| v as f32 / max as f32).collect();
+        self.to_linear(&mut lut);
+        lut.into()
+    }
+
+    #[inline]
+    fn to_linear_x8(self, x: f32x8) -> f32x8 {
+        let zero = f32x8::ZERO;
+        match self {
+            TransferFunction::Srgb => {
+                let x = x.max(zero);
+                let low = x / f32x8::splat(12.92);
+                let high =
+                    ((x + f32x8::splat(SRGB_ALPHA - 1.0)) / f32x8::splat(SRGB_ALPHA)).powf(2.4);
+                x.cmp_lt(f32x8::splat(12.92 * SRGB_BETA)).blend(low, high)
+            }
+            TransferFunction::Bt1886 => power(x, 2.4),
+            TransferFunction::Pq => {
+                // The ST 2084 EOTF gives display light in units of 100 nits.
+                let positive = x.cmp_gt(zero);
+                let xpow = x.max(zero).powf(ST2084_M2.recip());
+                let num = (xpow - f32x8::splat(ST2084_C1)).max(zero);
+                let den = f32x8::splat(-ST2084_C3)
+                    .mul_add(xpow, f32x8::splat(ST2084_C2))
+                    .max(f32x8::splat(f32::EPSILON));
+                let display = positive.blend(power(num / den, ST2084_M1.recip()), zero);
+
+                // Inverse of the reference OOTF: back to BT.1886 encoded values,
+                // then decoded with the inverse of the BT.709 OETF.
+                let v = power(display * f32x8::splat(100.0), 2.4f32.recip());
+                let low = v / f32x8::splat(4.5);
+                let high = ((v + f32x8::splat(REC709_ALPHA - 1.0)) / f32x8::splat(REC709_ALPHA))
+                    .powf(0.45f32.recip());
+                let scene = v.cmp_lt(f32x8::splat(4.5 * REC709_BETA)).blend(low, high);
+                scene / f32x8::splat(ST2084_OOTF_SCALE)
+            }
+            TransferFunction::Hlg => {
+                let x = x.max(zero);
+                let low = x * x * f32x8::splat(1.0 / 3.0);
+                let high = (((x - f32x8::splat(ARIB_B67_C)) / f32x8::splat(ARIB_B67_A)).exp()
+                    + f32x8::splat(ARIB_B67_B))
+                    / f32x8::splat(12.0);
+                x.cmp_le(f32x8::splat(0.5)).blend(low, high)
+            }
+            TransferFunction::Linear => x,
+            TransferFunction::Gamma(gamma) => power(x, gamma),
+        }
+    }
+}
+
+// `x` raised to `exponent`, with values below epsilon mapped to 0.
+#[inline]
+fn power(x: f32x8, exponent: f32) -> f32x8 {
+    let zero = f32x8::ZERO;
+    x.cmp_lt(f32x8::splat(f32::EPSILON))
+        .blend(zero, x.max(zero).powf(exponent))
+}
+
+impl TryFrom<TransferCharacteristic> for TransferFunction {
+    type Error = Ssimulacra2Error;
+
+    /// Maps the transfer characteristics of ITU-T H.273 to the curve `yuvxyb`
+    /// decodes them with. The logarithmic and xvYCC characteristics have no
+    /// equivalent and, like unspecified or reserved ones, return an error.
+    fn try_from(transfer: TransferCharacteristic) -> Result<Self, Self::Error> {
+        Ok(match transfer {
+            TransferCharacteristic::SRGB => TransferFunction::Srgb,
+            TransferCharacteristic::BT1886
+            | TransferCharacteristic::ST170M
+            | TransferCharacteristic::ST240M
+            | TransferCharacteristic::BT2020Ten
+            | TransferCharacteristic::BT2020Twelve => TransferFunction::Bt1886,
+            TransferCharacteristic::BT470M => TransferFunction::Gamma(2.2),
+            TransferCharacteristic::BT470BG => TransferFunction::Gamma(2.8),
+            TransferCharacteristic::PerceptualQuantizer => TransferFunction::Pq,
+            TransferCharacteristic::HybridLogGamma => TransferFunction::Hlg,
+            TransferCharacteristic::Linear => TransferFunction::Linear,
+            _ => return Err(Ssimulacra2Error::LinearRgbConversionFailed),
+        })
+    }
+}
+
+// Converts encoded RGB pixels to linear RGB with BT.709 primaries. Supported
+// transfer characteristics are decoded with `TransferFunction`, others are
+// left to `yuvxyb`, which also converts the primaries.
+#[cfg(any(feature = "ndarray", feature = "v_frame"))]
+pub fn decode_rgb(
+    mut data: Vec<[f32; 3]>,
+    width: usize,
+    height: usize,
+    transfer: TransferCharacteristic,
+    primaries: ColorPrimaries,
+) -> Result<LinearRgb, Ssimulacra2Error> {
+    let transfer = TransferFunction::try_from(transfer).map_or(transfer, |function| {
+        function.to_linear(bytemuck::cast_slice_mut(&mut data));
+        TransferCharacteristic::Linear
+    });
+
+    let Ok(rgb) = Rgb::new(data, width, height, transfer, primaries) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    let Ok(linear) = LinearRgb::try_from(rgb) else {
+        return Err(Ssimulacra2Error::LinearRgbConversionFailed);
+    };
+    Ok(linear)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_yuvxyb() {
+        let values: Vec<f32> = (-8i32..=1032i32).map(|v| v as f32 / 1023.0).collect();
+        let grey: Vec<[f32; 3]> = values.iter().map(|&v| [v; 3]).collect();
+
+        for transfer in [
+            TransferCharacteristic::SRGB,
+            TransferCharacteristic::BT1886,
+            TransferCharacteristic::BT470M,
+            TransferCharacteristic::BT470BG,
+            TransferCharacteristic::PerceptualQuantizer,
+            TransferCharacteristic::HybridLogGamma,
+            TransferCharacteristic::Linear,
+        ] {
+            let function = TransferFunction::try_from(transfer).unwrap();
+            let rgb = Rgb::new(grey.clone(), grey.len(), 1, transfer, ColorPrimaries::BT709);
+            let expected = LinearRgb::try_from(rgb.unwrap()).unwrap();
+
+            let mut linear = values.clone();
+            function.to_linear(&mut linear);
+            for ((&v, &res), exp) in values.iter().zip(&linear).zip(expected.data()) {
+                let exp = exp[0];
+                assert!(
+                    (res - exp).abs() <= 1e-3 * exp.abs().max(1e-3),
+                    "{function:?}({v}) = {res}, expected {exp}"
+                );
+            }
+        }
+
+        assert_eq!(
+            TransferFunction::try_from(TransferCharacteristic::Unspecified),
+            Err(Ssimulacra2Error::LinearRgbConversionFailed)
+        );
+    }
+
+    #[test]
+    fn lut_matches_to_linear() {
+        for function in [TransferFunction::Pq, TransferFunction::Gamma(2.6)] {
+            let lut = function.lut(10);
+            assert_eq!(lut.len(), 1024);
+
+            let mut expected: Vec<f32> = (0u32..1024).map(|v| v as f32 / 1023.0).collect();
+            function.to_linear(&mut expected);
+            assert_eq!(*lut, *expected);
+        }
+        assert_eq!(TransferFunction::Srgb.lut(8).len(), 256);
+
+        // Only the tables of the fixed curves are cached.
+        #[cfg(feature = "std")]
+        {
+            let pq = TransferFunction::Pq;
+            assert!(Arc::ptr_eq(&pq.lut(10), &pq.lut(10)));
+            let gamma = TransferFunction::Gamma(2.6);
+            assert!(!Arc::ptr_eq(&gamma.lut(10), &gamma.lut(10)));
+        }
+    }
+
+    #[test]
+    #[should_panic = "Gamma must be finite and positive"]
+    fn lut_rejects_nan_gamma() {
+        let _ = TransferFunction::Gamma(f32::NAN).lut(8);
+    }
+
+    #[test]
+    #[should_panic = "Gamma must be finite and positive"]
+    fn lut_rejects_negative_gamma() {
+        let _ = TransferFunction::Gamma(-2.2).lut(8);
+    }
+}
@@ -0,0 +1,108 @@
+use core::num::NonZeroU16;
+
+use yuvxyb::LinearRgb;
+
+use crate::ResizeFilter;
+
+/// The viewing conditions images are scored for, see
+/// [`Ssimulacra2::with_viewing`][crate::Ssimulacra2::with_viewing].
+///
+/// SSIMULACRA2 compares images pixel by pixel, as if they were viewed 1:1 on
+/// a desktop monitor. How visible a distortion is depends on how many pixels
+/// fall into a degree of the field of view instead: the same image on a
+/// phone held at arm's length has about twice as many pixels per degree, so
+/// fine distortions are less visible, while a large TV viewed from up close
+/// has fewer, so they are more visible.
+///
+/// With [`Viewing::PixelsPerDegree`], both images are resized by
+/// [`NOMINAL_PIXELS_PER_DEGREE`][Viewing::NOMINAL_PIXELS_PER_DEGREE] divided
+/// by the pixels per degree of the display before scoring, so that one pixel
+/// of the scored images covers as much of the field of view as on the
+/// nominal monitor. Images are downscaled with [`ResizeFilter::Area`] and
+/// upscaled with [`ResizeFilter::Lanczos3`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Viewing {
+    /// Score the pixels as they are, like the reference implementation.
+    #[default]
+    Native,
+    /// Score the images as seen on a display with the given number of pixels
+    /// per degree of the field of view, at the center of the display.
+    PixelsPerDegree(NonZeroU16),
+}
+
+impl Viewing {
+    /// The pixels per degree at which images are scored as they are, roughly
+    /// those of a 24-inch 1080p monitor viewed from 60 cm.
+    pub const NOMINAL_PIXELS_PER_DEGREE: u16 = 40;
+
+    /// The viewing conditions of a display with `pixels_per_inch` viewed from
+    /// `distance_inches`, rounded to whole pixels per degree, e.g. about 80
+    /// for a phone of 460 pixels per inch at 10 inches.
+    ///
+    /// Values too small or too large to round to 1 to 65535 pixels per
+    /// degree, or not finite, are clamped to that range.
+    #[must_use]
+    pub fn from_distance(pixels_per_inch: f64, distance_inches: f64) -> Self {
+        let ppd = 2.0f64 * distance_inches * 0.5f64.to_radians().tan() * pixels_per_inch;
+        let ppd = if ppd.is_nan() {
+            1.0f64
+        } else {
+            ppd.round().clamp(1.0f64, f64::from(u16::MAX))
+        };
+        // The value is in range after clamping.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        NonZeroU16::new(ppd as u16).map_or(Viewing::Native, Viewing::PixelsPerDegree)
+    }
+
+    /// The resolution an image of `width`x`height` pixels is scored at under
+    /// these viewing conditions, rounded to whole pixels.
+    #[must_use]
+    pub fn scaled_size(self, width: usize, height: usize) -> (usize, usize) {
+        let Viewing::PixelsPerDegree(ppd) = self else {
+            return (width, height);
+        };
+        let scale = f64::from(Self::NOMINAL_PIXELS_PER_DEGREE) / f64::from(ppd.get());
+        // Rounded sizes of images that fit in memory.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let scaled = |len: usize| ((len as f64 * scale).round() as usize).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    /// Resizes `img` to its [`scaled_size`][Self::scaled_size], or returns
+    /// `None` if that is its size already.
+    #[must_use]
+    pub fn apply(self, img: &LinearRgb) -> Option<LinearRgb> {
+        let (width, height) = self.scaled_size(img.width(), img.height());
+        if width == img.width() && height == img.height() {
+            return None;
+        }
+        let filter = if width < img.width() {
+            ResizeFilter::Area
+        } else {
+            ResizeFilter::Lanczos3
+        };
+        Some(filter.resize(img, width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_by_pixels_per_degree() {
+        let ppd = |ppd| Viewing::PixelsPerDegree(NonZeroU16::new(ppd).unwrap());
+        assert_eq!(Viewing::Native.scaled_size(1920, 1080), (1920, 1080));
+        assert_eq!(ppd(40).scaled_size(1920, 1080), (1920, 1080));
+        assert_eq!(ppd(80).scaled_size(1920, 1080), (960, 540));
+        assert_eq!(ppd(20).scaled_size(1920, 1080), (3840, 2160));
+        assert_eq!(ppd(60).scaled_size(100, 50), (67, 33));
+
+        assert_eq!(Viewing::from_distance(92.0, 23.6), ppd(38));
+        assert_eq!(Viewing::from_distance(460.0, 10.0), ppd(80));
+        assert_eq!(Viewing::from_distance(0.0, 10.0), ppd(1));
+        assert_eq!(Viewing::from_distance(f64::NAN, 10.0), ppd(1));
+        assert_eq!(Viewing::from_distance(1e9, 1e9), ppd(u16::MAX));
+    }
+}
@@ -0,0 +1,90 @@
+use wasm_bindgen::prelude::*;
+use yuvxyb::LinearRgb;
+
+use crate::{Reference, Srgb8Image, Ssimulacra2Error};
+
+/// Computes the SSIMULACRA2 score of two sRGB images, given as RGBA bytes in
+/// the layout of the `data` of a canvas `ImageData`. The alpha channel is ignored.
+///
+/// # Errors
+/// - If a buffer is not `width * height * 4` bytes long
+/// - If the images are smaller than 8x8 pixels
+#[wasm_bindgen(js_name = computeSsimulacra2)]
+pub fn compute_ssimulacra2(
+    source: &[u8],
+    distorted: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<f64, JsError> {
+    let source = rgba_to_linear(source, width, height)?;
+    let distorted = rgba_to_linear(distorted, width, height)?;
+    Ok(Reference::new(source)?.compare(distorted)?)
+}
+
+/// A source image prepared for being compared against many distorted images,
+/// e.g. while tuning the quality setting of an encoder in the browser.
+#[wasm_bindgen(js_name = Ssimulacra2Reference)]
+pub struct WasmReference {
+    reference: Reference,
+    width: u32,
+    height: u32,
+}
+
+#[wasm_bindgen(js_class = Ssimulacra2Reference)]
+impl WasmReference {
+    /// Prepares an sRGB source image, given as RGBA bytes, for comparisons.
+    ///
+    /// # Errors
+    /// - If the buffer is not `width * height * 4` bytes long
+    /// - If the image is smaller than 8x8 pixels
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &[u8], width: u32, height: u32) -> Result<WasmReference, JsError> {
+        let source = rgba_to_linear(source, width, height)?;
+        Ok(WasmReference {
+            reference: Reference::new(source)?,
+            width,
+            height,
+        })
+    }
+
+    /// Computes the SSIMULACRA2 score of an sRGB image, given as RGBA bytes of
+    /// the same size as the source, against this reference.
+    ///
+    /// # Errors
+    /// - If the buffer is not `width * height * 4` bytes long
+    pub fn compare(&self, distorted: &[u8]) -> Result<f64, JsError> {
+        let distorted = rgba_to_linear(distorted, self.width, self.height)?;
+        Ok(self.reference.compare(distorted)?)
+    }
+}
+
+fn rgba_to_linear(data: &[u8], width: u32, height: u32) -> Result<LinearRgb, Ssimulacra2Error> {
+    LinearRgb::try_from(Srgb8Image::from_rgba(
+        data,
+        width as usize,
+        height as usize,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba_images() {
+        let source: Vec<u8> = (0..32u32 * 24 * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let distorted: Vec<u8> = source.iter().map(|&v| v / 2).collect();
+
+        let reference = WasmReference::new(&source, 32, 24).unwrap();
+        let score = reference.compare(&distorted).unwrap();
+        assert!(score < 100.0f64);
+        assert!(
+            (compute_ssimulacra2(&source, &distorted, 32, 24).unwrap() - score).abs() < 1e-9f64
+        );
+        assert!((reference.compare(&source).unwrap() - 100.0f64).abs() < 1e-6f64);
+        assert_eq!(
+            rgba_to_linear(&source, 32, 23).err(),
+            Some(Ssimulacra2Error::InvalidBufferLength)
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use yuvxyb::{CastFromPrimitive, Pixel, Plane, Yuv};
+
+use crate::Ssimulacra2Error;
+
+// Weight of the temporal activity of a block relative to its spatial activity.
+const TEMPORAL_WEIGHT: u64 = 2;
+
+/// The XPSNR of the planes of a frame, in dB, see [`compute_frame_xpsnr`].
+///
+/// Like PSNR, higher values mean less distortion, and a plane which is
+/// identical to the source has an infinite XPSNR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XpsnrScore {
+    /// XPSNR of the luma plane.
+    pub y: f64,
+    /// XPSNR of the blue-difference chroma plane.
+    pub u: f64,
+    /// XPSNR of the red-difference chroma plane.
+    pub v: f64,
+}
+
+/// Computes the extended perceptually weighted PSNR (XPSNR) of a distorted
+/// frame, as proposed by Helmrich et al. and used in broadcast and AV1 encoder
+/// evaluations next to SSIMULACRA2.
+///
+/// The squared errors of every plane are weighted per block by the inverse of
+/// the visual activity of the source in that block, so that distortion in
+/// flat areas counts more than the same distortion in detailed areas. The
+/// activity is the high-pass filtered luma of the source, plus its difference
+/// to `previous_source` if given, which should be the preceding source frame
+/// of a video. The block size scales with the resolution, from 128x128 pixels
+/// at 3840x2160; for frames too small for blocks of at least 4x4 pixels, the
+/// result equals the PSNR.
+///
+/// The temporal activity is always that of the first order, and the high-pass
+/// filter is applied at full resolution, so the results are close to, but not
+/// bit-exact with, the `xpsnr` filter of `FFmpeg`.
+///
+/// # Errors
+/// - If the source, distorted and previous source frame width and height do not match
+/// - If the frames differ in bit depth or chroma subsampling
+pub fn compute_frame_xpsnr<T: Pixel>(
+    source: &Yuv<T>,
+    distorted: &Yuv<T>,
+    previous_source: Option<&Yuv<T>>,
+) -> Result<XpsnrScore, Ssimulacra2Error> {
+    for other in core::iter::once(distorted).chain(previous_source) {
+        if other.width() != source.width() || other.height() != source.height() {
+            return Err(Ssimulacra2Error::NonMatchingImageDimensions);
+        }
+        let (config, other) = (source.config(), other.config());
+        if config.bit_depth != other.bit_depth
+            || config.subsampling_x != other.subsampling_x
+            || config.subsampling_y != other.subsampling_y
+        {
+            return Err(Ssimulacra2Error::InvalidFrameConfig);
+        }
+    }
+
+    let config = source.config();
+    let weights = BlockWeights::new(source, previous_source);
+    let peak = f64::from((1u32 << config.bit_depth) - 1);
+    let [y, u, v] = [0, 1, 2].map(|i| {
+        let shift = if i == 0 {
+            (0, 0)
+        } else {
+            (config.subsampling_x, config.subsampling_y)
+        };
+        let source = &source.data()[i];
+        let wsse = weighted_squared_error(source, &distorted.data()[i], &weights, shift);
+        if wsse > 0.0f64 {
+            let pixels = (source.cfg.width * source.cfg.height) as f64;
+            10.0f64 * (pixels * peak * peak / wsse).log10()
+        } else {
+            f64::INFINITY
+        }
+    });
+    Ok(XpsnrScore { y, u, v })
+}
+
+// The weight of every block of a frame, in row-major order.
+struct BlockWeights {
+    size: usize,
+    columns: usize,
+    weights: Vec<f64>,
+}
+
+impl BlockWeights {
+    fn new<T: Pixel>(source: &Yuv<T>, previous_source: Option<&Yuv<T>>) -> Self {
+        let width = source.width();
+        let height = source.height();
+        let bit_depth = i32::from(source.config().bit_depth);
+        // The frame size relative to 3840x2160, which the constants of XPSNR
+        // were chosen for.
+        let ratio = (width * height) as f64 / (3840.0f64 * 2160.0f64);
+        let size = 4 * 32.0f64.mul_add(ratio.sqrt(), 0.5f64) as usize;
+        if size < 4 {
+            return BlockWeights {
+                size: width.max(height),
+                columns: 1,
+                weights: vec![1.0f64],
+            };
+        }
+
+        let columns = (width + size - 1) / size;
+        let rows = (height + size - 1) / size;
+        let mut activity = vec![0u64; columns * rows];
+        let luma = to_i32(&source.data()[0]);
+        let previous = previous_source.map(|previous| to_i32(&previous.data()[0]));
+        for y in 0..height {
+            let above = &luma[y.saturating_sub(1) * width..][..width];
+            let row = &luma[y * width..][..width];
+            let below = &luma[(y + 1).min(height - 1) * width..][..width];
+            let block_row = &mut activity[y / size * columns..][..columns];
+            for x in 0..width {
+                let left = x.saturating_sub(1);
+                let right = (x + 1).min(width - 1);
+                let high_pass = 12i32 * row[x]
+                    - 2i32 * (row[left] + row[right] + above[x] + below[x])
+                    - (above[left] + above[right] + below[left] + below[right]);
+                let mut act = u64::from(high_pass.unsigned_abs());
+                if let Some(ref previous) = previous {
+                    let diff = row[x] - previous[y * width + x];
+                    act += TEMPORAL_WEIGHT * u64::from(diff.unsigned_abs());
+                }
+                block_row[x / size] += act;
+            }
+        }
+
+        let min_activity = f64::from(1u32 << (bit_depth - 6i32));
+        let mean_activity = (16.0f64 * 2.0f64.powi(2 * bit_depth - 9) / ratio.sqrt()).sqrt();
+        let weights = activity
+            .iter()
+            .enumerate()
+            .map(|(i, &act)| {
+                let block_width = size.min(width - i % columns * size);
+                let block_height = size.min(height - i / columns * size);
+                let act = act as f64 / (block_width * block_height) as f64;
+                mean_activity / act.max(min_activity)
+            })
+            .collect();
+        BlockWeights {
+            size,
+            columns,
+            weights,
+        }
+    }
+}
+
+// Sums the squared errors of a plane, weighted by the block they fall into.
+// `shift` is the subsampling of the plane relative to the luma plane.
+fn weighted_squared_error<T: Pixel>(
+    source: &Plane<T>,
+    distorted: &Plane<T>,
+    weights: &BlockWeights,
+    shift: (u8, u8),
+) -> f64 {
+    let mut wsse = 0.0f64;
+    for (y, (source, distorted)) in rows(source).zip(rows(distorted)).enumerate() {
+        let block_row = (y << shift.1) / weights.size * weights.columns;
+        let mut block_sse = 0u64;
+        let mut block = 0;
+        for (x, (&a, &b)) in source.iter().zip(distorted).enumerate() {
+            let column = (x << shift.0) / weights.size;
+            if column != block {
+                wsse = weights.weights[block_row + block].mul_add(block_sse as f64, wsse);
+                block_sse = 0;
+                block = column;
+            }
+            let diff = i32::from(u16::cast_from(a)) - i32::from(u16::cast_from(b));
+            block_sse += u64::from(diff.unsigned_abs()).pow(2);
+        }
+        wsse = weights.weights[block_row + block].mul_add(block_sse as f64, wsse);
+    }
+    wsse
+}
+
+fn rows<T: Pixel>(plane: &Plane<T>) -> impl Iterator<Item = &[T]> {
+    let origin = plane.data_origin();
+    let (width, stride) = (plane.cfg.width, plane.cfg.stride);
+    (0..plane.cfg.height).map(move |y| &origin[y * stride..][..width])
+}
+
+fn to_i32<T: Pixel>(plane: &Plane<T>) -> Vec<i32> {
+    rows(plane)
+        .flatten()
+        .map(|&pix| i32::from(u16::cast_from(pix)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use yuvxyb::{ColorPrimaries, Frame, MatrixCoefficients, TransferCharacteristic, YuvConfig};
+
+    use super::*;
+
+    fn make_frame(width: usize, height: usize, luma: impl Fn(usize, usize) -> u8) -> Yuv<u8> {
+        let mut frame: Frame<u8> = Frame {
+            planes: [
+                Plane::new(width, height, 0, 0, 16, 16),
+                Plane::new(width / 2, height / 2, 1, 1, 8, 8),
+                Plane::new(width / 2, height / 2, 1, 1, 8, 8),
+            ],
+        };
+        for (y, row) in frame.planes[0].rows_iter_mut().enumerate() {
+            for (x, pix) in row[..width].iter_mut().enumerate() {
+                *pix = luma(x, y);
+            }
+        }
+        for plane in &mut frame.planes[1..] {
+            for row in plane.rows_iter_mut() {
+                row.fill(128);
+            }
+        }
+        let config = YuvConfig {
+            bit_depth: 8,
+            subsampling_x: 1,
+            subsampling_y: 1,
+            full_range: false,
+            matrix_coefficients: MatrixCoefficients::BT709,
+            transfer_characteristics: TransferCharacteristic::BT1886,
+            color_primaries: ColorPrimaries::BT709,
+        };
+        Yuv::new(frame, config).unwrap()
+    }
+
+    #[test]
+    fn small_frames_give_psnr() {
+        let source = make_frame(32, 32, |x, y| (x * 5 + y * 3) as u8);
+        let distorted = make_frame(32, 32, |x, y| (x * 5 + y * 3) as u8 + 1);
+        let score = compute_frame_xpsnr(&source, &distorted, None).unwrap();
+        // An error of one everywhere is a PSNR of 20 log10(255).
+        let psnr = 20.0f64 * 255.0f64.log10();
+        assert!((score.y - psnr).abs() < 1e-9f64);
+        assert!(score.u.is_infinite() && score.v.is_infinite());
+
+        let identical = compute_frame_xpsnr(&source, &source, Some(&source)).unwrap();
+        assert!(identical.y.is_infinite());
+        assert_eq!(
+            compute_frame_xpsnr(&source, &make_frame(32, 16, |_, _| 0), None),
+            Err(Ssimulacra2Error::NonMatchingImageDimensions)
+        );
+    }
+
+    #[test]
+    fn activity_masks_errors() {
+        // Large enough for blocks of 16x16 pixels. The left half is textured
+        // and the right half flat.
+        let texture = |x: usize, y: usize| {
+            if x < 256 {
+                ((x * 37 + y * 91) % 160) as u8 + 40
+            } else {
+                120
+            }
+        };
+        let noise = |x: usize, y: usize| (x + y) % 2 * 6;
+        let source = make_frame(512, 288, texture);
+        let textured = make_frame(512, 288, |x, y| {
+            texture(x, y) + if x < 256 { noise(x, y) as u8 } else { 0 }
+        });
+        let flat = make_frame(512, 288, |x, y| {
+            texture(x, y) + if x < 256 { 0 } else { noise(x, y) as u8 }
+        });
+        let textured = compute_frame_xpsnr(&source, &textured, None).unwrap();
+        let flat = compute_frame_xpsnr(&source, &flat, None).unwrap();
+        assert!(textured.y > flat.y + 3.0f64, "{} {}", textured.y, flat.y);
+
+        // Motion hides errors as well.
+        let previous = make_frame(512, 288, |x, y| texture((x + 3) % 512, y));
+        let moving = make_frame(512, 288, |x, y| texture(x, y) + noise(x, y) as u8);
+        let still = compute_frame_xpsnr(&source, &moving, None).unwrap();
+        let moving = compute_frame_xpsnr(&source, &moving, Some(&previous)).unwrap();
+        assert!(moving.y > still.y, "{} {}", moving.y, still.y);
+    }
+}
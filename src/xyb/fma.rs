@@ -0,0 +1,104 @@
+// The XYB conversion compiled with FMA and AVX2, see the `fma` module of the
+// crate. `wide` only fuses the multiply-adds of its vectors if FMA is enabled
+// for the whole build, so they are fused lane by lane here, which the
+// compiler turns into FMA instructions.
+
+use core::ops::{Add, Div, Mul, Sub};
+
+use wide::f32x8;
+
+use super::{convert_planes, CubeRoot, Lanes};
+
+// `linear_rgb_to_positive_xyb` compiled with FMA and AVX2.
+#[target_feature(enable = "avx2,fma")]
+pub unsafe fn linear_rgb_to_positive_xyb(
+    pixels: &[[f32; 3]],
+    planes: [&mut [f32]; 3],
+    cube_root: CubeRoot,
+) {
+    convert_planes::<FmaVector>(pixels, planes, cube_root);
+}
+
+// The vectors of `wide`, with multiply-adds fused lane by lane.
+#[derive(Clone, Copy)]
+struct FmaVector(f32x8);
+
+impl Add for FmaVector {
+    type Output = Self;
+
+    #[inline(always)]
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for FmaVector {
+    type Output = Self;
+
+    #[inline(always)]
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul for FmaVector {
+    type Output = Self;
+
+    #[inline(always)]
+    fn mul(self, rhs: Self) -> Self {
+        Self(self.0 * rhs.0)
+    }
+}
+
+impl Div for FmaVector {
+    type Output = Self;
+
+    #[inline(always)]
+    fn div(self, rhs: Self) -> Self {
+        Self(self.0 / rhs.0)
+    }
+}
+
+impl Lanes for FmaVector {
+    const LANES: usize = 8;
+
+    #[inline(always)]
+    fn splat(value: f32) -> Self {
+        Self(f32x8::splat(value))
+    }
+
+    #[inline(always)]
+    fn load(values: &[f32]) -> Self {
+        Self(Lanes::load(values))
+    }
+
+    #[inline(always)]
+    fn store(self, out: &mut [f32]) {
+        Lanes::store(self.0, out);
+    }
+
+    #[inline(always)]
+    fn mul_add(self, m: Self, a: Self) -> Self {
+        let (m, a) = (m.0.as_array_ref(), a.0.as_array_ref());
+        let mut out = *self.0.as_array_ref();
+        for ((out, &m), &a) in out.iter_mut().zip(m).zip(a) {
+            *out = f32::mul_add(*out, m, a);
+        }
+        Self(f32x8::from(out))
+    }
+
+    #[inline(always)]
+    fn clamp_negative(self) -> Self {
+        Self(Lanes::clamp_negative(self.0))
+    }
+
+    #[inline(always)]
+    fn zero_where_zero(self, value: Self) -> Self {
+        Self(Lanes::zero_where_zero(self.0, value.0))
+    }
+
+    #[inline(always)]
+    fn cbrt_guess(self) -> Self {
+        Self(Lanes::cbrt_guess(self.0))
+    }
+}
@@ -0,0 +1,645 @@
+#![allow(clippy::many_single_char_names)]
+
+use core::ops::{Add, Div, Mul, Range, Sub};
+
+use wide::{f32x8, f64x4, i32x8, CmpEq, CmpLt, CmpNe};
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "x86", target_arch = "x86_64"),
+    not(target_feature = "fma"),
+    not(feature = "portable_simd")
+))]
+mod fma;
+#[cfg(feature = "portable_simd")]
+mod portable;
+
+// Opsin absorbance matrix and bias of the XYB color space, as used by `yuvxyb`.
+const M02: f32 = 0.078f32;
+const M00: f32 = 0.30f32;
+const M01: f32 = 1.0f32 - M02 - M00;
+
+const M12: f32 = 0.078f32;
+const M10: f32 = 0.23f32;
+const M11: f32 = 1.0f32 - M12 - M10;
+
+const M20: f32 = 0.243_422_69_f32;
+const M21: f32 = 0.204_767_45_f32;
+const M22: f32 = 1.0f32 - M20 - M21;
+
+const BIAS: f32 = 0.003_793_073_4_f32;
+
+// (127 - 127 / 3 - 0.03306235651) * 2^23, as in FreeBSD's `cbrtf`.
+const CBRT_B1: i32 = 709_958_130;
+
+// Segments of the cube root table per octave of its argument.
+const TABLE_SEGMENTS: usize = 64;
+
+/// How the cube root of the XYB conversion is computed, see
+/// [`Ssimulacra2::with_cube_root`][crate::Ssimulacra2::with_cube_root].
+///
+/// Converting images to XYB is a small part of scoring, so the methods
+/// differ in speed by a few percent end to end, while the approximate ones
+/// change scores by up to about 0.06. See the README for measurements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CubeRoot {
+    /// Refine an initial guess by two Halley iterations in double precision,
+    /// which rounds like `f32::cbrt` and gives the same scores as the scalar
+    /// conversion of `yuvxyb`.
+    #[default]
+    Exact,
+    /// Refine the initial guess by a single Halley iteration, which has a
+    /// relative error of at most 3e-5 and converts images to XYB about twice
+    /// as fast.
+    Approximate,
+    /// Interpolate linearly between the values of a table of 193 cube roots
+    /// built at compile time, which has a relative error of at most 1e-5.
+    /// The table is read one value at a time, which makes the conversion
+    /// slower than with the vectorized methods.
+    Table,
+}
+
+// The vector type the conversion runs on: `wide`'s by default, or the
+// `std::simd` one with the `portable_simd` feature. Both round exactly like
+// the scalar fallback, which converts the pixels that do not fill a vector.
+#[cfg(not(feature = "portable_simd"))]
+type Vector = f32x8;
+#[cfg(feature = "portable_simd")]
+type Vector = core::simd::f32x8;
+
+// The operations the conversion is written in, for vectors of `LANES` values
+// and, with one lane, for `f32` as the scalar fallback.
+trait Lanes:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+{
+    const LANES: usize;
+
+    fn splat(value: f32) -> Self;
+    // Loads exactly `LANES` values.
+    fn load(values: &[f32]) -> Self;
+    // Stores exactly `LANES` values.
+    fn store(self, out: &mut [f32]);
+    // A fused multiply-add, rounded once like `f32::mul_add`, which `yuvxyb`
+    // uses. Vectors compute it with `fused_mul_add` on targets without FMA
+    // instructions.
+    fn mul_add(self, m: Self, a: Self) -> Self;
+    // Replaces negative values by zero, but keeps NaN.
+    fn clamp_negative(self) -> Self;
+    // Zero where `self` is zero, `value` elsewhere.
+    fn zero_where_zero(self, value: Self) -> Self;
+    // The initial guess of the cube root, dividing the exponent by three on
+    // the bit level.
+    fn cbrt_guess(self) -> Self;
+}
+
+impl Lanes for f32 {
+    const LANES: usize = 1;
+
+    #[inline(always)]
+    fn splat(value: f32) -> Self {
+        value
+    }
+
+    #[inline(always)]
+    fn load(values: &[f32]) -> Self {
+        values[0]
+    }
+
+    #[inline(always)]
+    fn store(self, out: &mut [f32]) {
+        out[0] = self;
+    }
+
+    #[inline(always)]
+    fn mul_add(self, m: Self, a: Self) -> Self {
+        f32::mul_add(self, m, a)
+    }
+
+    #[inline(always)]
+    fn clamp_negative(self) -> Self {
+        if self < 0.0f32 {
+            0.0f32
+        } else {
+            self
+        }
+    }
+
+    #[inline(always)]
+    fn zero_where_zero(self, value: Self) -> Self {
+        if self == 0.0f32 {
+            0.0f32
+        } else {
+            value
+        }
+    }
+
+    #[inline(always)]
+    fn cbrt_guess(self) -> Self {
+        let bits = self.to_bits() as i32;
+        f32::from_bits(((bits as f32 * (1.0f32 / 3.0f32)) as i32 + CBRT_B1) as u32)
+    }
+}
+
+impl Lanes for f32x8 {
+    const LANES: usize = 8;
+
+    #[inline(always)]
+    fn splat(value: f32) -> Self {
+        f32x8::splat(value)
+    }
+
+    #[inline(always)]
+    fn load(values: &[f32]) -> Self {
+        let mut lanes = [0.0f32; 8];
+        lanes.copy_from_slice(values);
+        f32x8::from(lanes)
+    }
+
+    #[inline(always)]
+    fn store(self, out: &mut [f32]) {
+        out.copy_from_slice(self.as_array_ref());
+    }
+
+    #[inline(always)]
+    fn mul_add(self, m: Self, a: Self) -> Self {
+        #[cfg(target_feature = "fma")]
+        return f32x8::mul_add(self, m, a);
+        #[cfg(not(target_feature = "fma"))]
+        return f32x8::from(fused_mul_add(
+            *self.as_array_ref(),
+            *m.as_array_ref(),
+            *a.as_array_ref(),
+        ));
+    }
+
+    #[inline(always)]
+    fn clamp_negative(self) -> Self {
+        self.cmp_lt(f32x8::ZERO).blend(f32x8::ZERO, self)
+    }
+
+    #[inline(always)]
+    fn zero_where_zero(self, value: Self) -> Self {
+        self.cmp_eq(f32x8::ZERO).blend(f32x8::ZERO, value)
+    }
+
+    #[inline(always)]
+    fn cbrt_guess(self) -> Self {
+        let bits: i32x8 = bytemuck::cast(self);
+        let guess = (bits.round_float() * f32x8::splat(1.0f32 / 3.0f32)).trunc_int()
+            + i32x8::splat(CBRT_B1);
+        bytemuck::cast(guess)
+    }
+}
+
+// Converts linear RGB pixels to XYB and shifts the result to positive values,
+// writing each component to its own plane of the same length as `pixels`.
+// Eight pixels are converted at a time, including the cube roots.
+//
+// The shift gets all components in more or less 0..1 range.
+// Range of Rec2020 with these adjustments:
+//  X: 0.017223..0.998838
+//  Y: 0.010000..0.855303
+//  B: 0.048759..0.989551
+// Range of sRGB:
+//  X: 0.204594..0.813402
+//  Y: 0.010000..0.855308
+//  B: 0.272295..0.938012
+// The maximum pixel-wise difference has to be <= 1 for the ssim formula to make
+// sense.
+pub fn linear_rgb_to_positive_xyb(
+    pixels: &[[f32; 3]],
+    planes: [&mut [f32]; 3],
+    cube_root: CubeRoot,
+) {
+    #[cfg(all(
+        feature = "std",
+        any(target_arch = "x86", target_arch = "x86_64"),
+        not(target_feature = "fma"),
+        not(feature = "portable_simd")
+    ))]
+    if crate::fma::detected() {
+        // SAFETY: the CPU supports FMA and AVX2.
+        unsafe { fma::linear_rgb_to_positive_xyb(pixels, planes, cube_root) };
+        return;
+    }
+    convert_planes::<Vector>(pixels, planes, cube_root);
+}
+
+// Converts the pixels of `range` like `linear_rgb_to_positive_xyb` converts
+// all of `pixels`, which fill the planes, giving the same values. The range is
+// widened to whole vectors, so that every pixel takes the vector or scalar
+// path it takes when converting all pixels.
+pub fn linear_rgb_to_positive_xyb_range(
+    pixels: &[[f32; 3]],
+    planes: [&mut [f32]; 3],
+    range: Range<usize>,
+    cube_root: CubeRoot,
+) {
+    let body = pixels.len() / Vector::LANES * Vector::LANES;
+    let start = range.start / Vector::LANES * Vector::LANES;
+    let end = if range.end > body {
+        pixels.len()
+    } else {
+        (range.end + Vector::LANES - 1) / Vector::LANES * Vector::LANES
+    };
+    let [out_x, out_y, out_b] = planes;
+    linear_rgb_to_positive_xyb(
+        &pixels[start..end],
+        [
+            &mut out_x[start..end],
+            &mut out_y[start..end],
+            &mut out_b[start..end],
+        ],
+        cube_root,
+    );
+}
+
+// Converts the pixels with vectors of type `V`, and the pixels that do not fill
+// a vector one by one.
+#[inline(always)]
+fn convert_planes<V: Lanes>(pixels: &[[f32; 3]], planes: [&mut [f32]; 3], cube_root: CubeRoot) {
+    let [out_x, out_y, out_b] = planes;
+    for out in [&*out_x, &*out_y, &*out_b] {
+        assert_eq!(
+            out.len(),
+            pixels.len(),
+            "Planes must have one value per pixel"
+        );
+    }
+
+    let body = pixels.len() / V::LANES * V::LANES;
+    convert_pixels::<V>(
+        &pixels[..body],
+        [&mut out_x[..body], &mut out_y[..body], &mut out_b[..body]],
+        cube_root,
+    );
+    convert_pixels::<f32>(
+        &pixels[body..],
+        [&mut out_x[body..], &mut out_y[body..], &mut out_b[body..]],
+        cube_root,
+    );
+}
+
+// Converts a multiple of `V::LANES` pixels.
+#[inline(always)]
+fn convert_pixels<V: Lanes>(
+    pixels: &[[f32; 3]],
+    [out_x, out_y, out_b]: [&mut [f32]; 3],
+    cube_root: CubeRoot,
+) {
+    let neg_bias_cbrt = V::splat(-BIAS.cbrt());
+    let chunks = pixels
+        .chunks_exact(V::LANES)
+        .zip(out_x.chunks_exact_mut(V::LANES))
+        .zip(out_y.chunks_exact_mut(V::LANES))
+        .zip(out_b.chunks_exact_mut(V::LANES));
+    for (((pixels, out_x), out_y), out_b) in chunks {
+        let mut rgb = [[0.0f32; 8]; 3];
+        for (i, pix) in pixels.iter().enumerate() {
+            rgb[0][i] = pix[0];
+            rgb[1][i] = pix[1];
+            rgb[2][i] = pix[2];
+        }
+
+        let [x, y, b] = convert(
+            V::load(&rgb[0][..V::LANES]),
+            V::load(&rgb[1][..V::LANES]),
+            V::load(&rgb[2][..V::LANES]),
+            neg_bias_cbrt,
+            cube_root,
+        );
+        x.store(out_x);
+        y.store(out_y);
+        b.store(out_b);
+    }
+}
+
+#[inline(always)]
+fn convert<V: Lanes>(r: V, g: V, b: V, neg_bias_cbrt: V, cube_root: CubeRoot) -> [V; 3] {
+    let mixed0 = mix(r, g, b, [M00, M01, M02], neg_bias_cbrt, cube_root);
+    let mixed1 = mix(r, g, b, [M10, M11, M12], neg_bias_cbrt, cube_root);
+    let mixed2 = mix(r, g, b, [M20, M21, M22], neg_bias_cbrt, cube_root);
+
+    let half = V::splat(0.5f32);
+    let x = half * (mixed0 - mixed1);
+    let y = half * (mixed0 + mixed1);
+    let b = mixed2;
+
+    [
+        x.mul_add(V::splat(14.0f32), V::splat(0.42f32)),
+        y + V::splat(0.01f32),
+        (b - y) + V::splat(0.55f32),
+    ]
+}
+
+// One row of the opsin absorbance matrix applied to the pixels, followed by
+// the cube root.
+#[inline(always)]
+fn mix<V: Lanes>(
+    r: V,
+    g: V,
+    b: V,
+    [m0, m1, m2]: [f32; 3],
+    neg_bias_cbrt: V,
+    cube_root: CubeRoot,
+) -> V {
+    let mixed = V::splat(m0).mul_add(
+        r,
+        V::splat(m1).mul_add(g, V::splat(m2).mul_add(b, V::splat(BIAS))),
+    );
+    // Clamps negative values to zero, but keeps NaN so that it can be
+    // detected later on.
+    let mixed = mixed.clamp_negative();
+    let root = match cube_root {
+        CubeRoot::Exact => exact_cbrt(mixed),
+        CubeRoot::Approximate => approximate_cbrt(mixed),
+        CubeRoot::Table => table_cbrt(mixed),
+    };
+    root + neg_bias_cbrt
+}
+
+// `a * m + b` rounded once, for targets without FMA instructions. The product
+// of two `f32` values is exact in double precision, so the sum is rounded to
+// double precision and then to single precision. That gives a different result
+// than a single rounding only if the sum is inexact and exactly halfway
+// between two `f32` values, i.e. if its reflection at the rounded sum is an
+// `f32` value as well. Vectors with such a lane are computed with
+// `f32::mul_add` instead, which is slow without FMA instructions.
+#[cfg(not(target_feature = "fma"))]
+#[inline]
+#[allow(clippy::suboptimal_flops)]
+fn fused_mul_add(a: [f32; 8], m: [f32; 8], b: [f32; 8]) -> [f32; 8] {
+    let widen = |values: [f32; 8], half: usize| {
+        let mut wide = [0.0f64; 4];
+        for (wide, &value) in wide.iter_mut().zip(&values[4 * half..]) {
+            *wide = f64::from(value);
+        }
+        f64x4::from(wide)
+    };
+    let round = |values: f64x4| {
+        let mut rounded = [0.0f32; 4];
+        for (rounded, &value) in rounded.iter_mut().zip(values.as_array_ref()) {
+            *rounded = value as f32;
+        }
+        rounded
+    };
+
+    let mut out = [0.0f32; 8];
+    let mut double_rounded = false;
+    for (half, out) in out.chunks_exact_mut(4).enumerate() {
+        let (a, m, b) = (widen(a, half), widen(m, half), widen(b, half));
+        let product = a * m;
+        let sum = product + b;
+        out.copy_from_slice(&round(sum));
+
+        let inexact = (sum - product).cmp_ne(b) | (sum - b).cmp_ne(product);
+        let rounded = f64x4::from([out[0], out[1], out[2], out[3]].map(f64::from));
+        let reflected = f64x4::splat(2.0f64) * sum - rounded;
+        let reflected_f32 = f64x4::from(round(reflected).map(f64::from));
+        let halfway = reflected.cmp_ne(rounded) & reflected_f32.cmp_eq(reflected);
+        double_rounded |= (inexact & halfway).move_mask() != 0_i32;
+    }
+
+    if double_rounded {
+        for (((out, &a), &m), &b) in out.iter_mut().zip(&a).zip(&m).zip(&b) {
+            *out = f32::mul_add(a, m, b);
+        }
+    }
+    out
+}
+
+// Cube root of non-negative values, rounded like `f32::cbrt`, one lane at a
+// time. The initial guess divides the exponent by three on the bit level,
+// which is refined by two Halley iterations in double precision, as in
+// FreeBSD's `cbrtf`. For every `f32`, this gives the same root as `f32::cbrt`
+// with glibc, which `yuvxyb` uses.
+#[inline(always)]
+fn exact_cbrt<V: Lanes>(x: V) -> V {
+    let mut lanes = [0.0f32; 8];
+    x.store(&mut lanes[..V::LANES]);
+    for lane in &mut lanes[..V::LANES] {
+        *lane = exact_cbrt_scalar(*lane);
+    }
+    V::load(&lanes[..V::LANES])
+}
+
+#[inline(always)]
+fn exact_cbrt_scalar(x: f32) -> f32 {
+    // Subnormal values are scaled into the normal range by 2^24, whose cube
+    // root is 2^8, as the initial guess is only good for normal values.
+    let (x, scale) = if x < f32::MIN_POSITIVE {
+        (x * 16_777_216.0f32, 1.0f32 / 256.0f32)
+    } else {
+        (x, 1.0f32)
+    };
+
+    let x64 = f64::from(x);
+    let mut t = f64::from(Lanes::cbrt_guess(x));
+    for _ in 0..2_u32 {
+        let r = t * t * t;
+        t = t * (x64 + x64 + r) / (x64 + r + r);
+    }
+
+    x.zero_where_zero(t as f32 * scale)
+}
+
+// Cube root of non-negative values, with the initial guess of `exact_cbrt`
+// refined by a single Halley iteration in single precision, to about 16 bits.
+#[inline(always)]
+fn approximate_cbrt<V: Lanes>(x: V) -> V {
+    let t = x.cbrt_guess();
+    let r = t * t * t;
+    x.zero_where_zero(t * (x + x + r) / (x + r + r))
+}
+
+// Cube roots of 2^r * (1 + j / TABLE_SEGMENTS) for the octaves r = 0, 1, 2,
+// as the bits of `f32` values in [1, 2], and of 8 as the end of the last
+// segment.
+static CBRT_TABLE: [u32; 3 * TABLE_SEGMENTS + 1] = cbrt_table();
+
+// Computed in integers, as floating point arithmetic is not available in
+// constants.
+const fn cbrt_table() -> [u32; 3 * TABLE_SEGMENTS + 1] {
+    let mut table = [0u32; 3 * TABLE_SEGMENTS + 1];
+    let mut k = 0;
+    while k < table.len() {
+        // The argument times 2^69, whose cube root is the root times 2^23,
+        // the mantissa of a value in [1, 2] including the implicit bit.
+        let n = ((TABLE_SEGMENTS + k % TABLE_SEGMENTS) as u128) << (k / TABLE_SEGMENTS + 63);
+        let mut root = 0u128;
+        let mut bit = 1u128 << 24u32;
+        while bit > 0 {
+            if (root | bit) * (root | bit) * (root | bit) <= n {
+                root |= bit;
+            }
+            bit >>= 1u32;
+        }
+        // Rounds to nearest.
+        if (2 * root + 1) * (2 * root + 1) * (2 * root + 1) <= 8 * n {
+            root += 1;
+        }
+        table[k] = 0x3f80_0000 + (root - (1u128 << 23u32)) as u32;
+        k += 1;
+    }
+    table
+}
+
+// Cube root of non-negative values from `CBRT_TABLE`, one lane at a time.
+#[inline(always)]
+fn table_cbrt<V: Lanes>(x: V) -> V {
+    let mut lanes = [0.0f32; 8];
+    x.store(&mut lanes[..V::LANES]);
+    for lane in &mut lanes[..V::LANES] {
+        *lane = table_cbrt_scalar(*lane);
+    }
+    V::load(&lanes[..V::LANES])
+}
+
+#[inline(always)]
+fn table_cbrt_scalar(x: f32) -> f32 {
+    // Zero, subnormal, infinite and NaN values, which the table does not
+    // cover.
+    if !x.is_normal() {
+        return exact_cbrt_scalar(x);
+    }
+
+    // x = 2^(3q + r) * m with m in [1, 2) and r in 0..3, whose cube root is
+    // 2^q times that of 2^r * m.
+    let bits = x.to_bits();
+    let exponent = (bits >> 23u32) as i32 - 127i32;
+    let q = (exponent + 129i32) / 3i32 - 43i32;
+    let r = (exponent - 3i32 * q) as usize;
+    let mantissa = bits & 0x7f_ffff;
+    let shift = 23u32 - TABLE_SEGMENTS.trailing_zeros();
+    let index = r * TABLE_SEGMENTS + (mantissa >> shift) as usize;
+    let frac = (mantissa & ((1u32 << shift) - 1)) as f32 / (1u32 << shift) as f32;
+
+    let low = f32::from_bits(CBRT_TABLE[index]);
+    let high = f32::from_bits(CBRT_TABLE[index + 1]);
+    let root = Lanes::mul_add(high - low, frac, low);
+    f32::from_bits((root.to_bits() as i32 + (q << 23u32)) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{LinearRgb, Xyb};
+
+    // Checks that `root` is within `tolerance` of `f32::cbrt`, relative to
+    // the cube root.
+    fn check_cbrt<V: Lanes>(root: impl Fn(V) -> V, tolerance: f32) {
+        let values: Vec<f32> = (0..10_003u32)
+            .map(|i| i as f32 / 1000.0f32)
+            .chain([0.0f32, 1e-6f32, 1e-3f32, 100.0f32, 10_000.0f32])
+            .chain([
+                0.125f32, 0.5f32, 2.0f32, 8.0f32, 27.0f32, 64.0f32, 1e-9f32, 1e9f32,
+            ])
+            .collect();
+        for chunk in values.chunks_exact(V::LANES) {
+            let mut result = [0.0f32; 8];
+            root(V::load(chunk)).store(&mut result[..V::LANES]);
+            for (&x, &res) in chunk.iter().zip(&result) {
+                let exp = x.cbrt();
+                assert!(
+                    (res - exp).abs() <= exp * tolerance,
+                    "cbrt({x}) = {res}, expected {exp}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cbrt_accuracy() {
+        check_cbrt(approximate_cbrt::<f32>, 3e-5);
+        check_cbrt(approximate_cbrt::<f32x8>, 3e-5);
+        check_cbrt(table_cbrt::<f32>, 1e-5);
+        check_cbrt(table_cbrt::<f32x8>, 1e-5);
+    }
+
+    #[test]
+    fn exact_cbrt_is_correctly_rounded() {
+        // Every 97th value from the smallest subnormal up to 2^64, which
+        // covers the mantissas of every exponent.
+        let values = (1..0x5f80_0000u32).step_by(97).map(f32::from_bits).chain([
+            0.0f32,
+            f32::MIN_POSITIVE,
+            1.0f32,
+            8.0f32,
+            27.0f32,
+        ]);
+        for x in values {
+            let root = exact_cbrt_scalar(x);
+            // Half a unit in the last place of the root, and the root in
+            // double precision, whose error is far below that.
+            let half_ulp = f64::from(f32::from_bits(root.to_bits() + 1) - root) / 2.0f64;
+            let exp = f64::from(x).cbrt();
+            assert!(
+                (f64::from(root) - exp).abs() <= half_ulp,
+                "cbrt({x:e}) = {root:e}, expected {exp:e}"
+            );
+        }
+    }
+
+    // The bits of the planes of `pixels` converted with `V` and `root`.
+    fn convert_bits<V: Lanes>(pixels: &[[f32; 3]], cube_root: CubeRoot) -> Vec<u32> {
+        let mut planes = [
+            alloc::vec![0.0f32; pixels.len()],
+            alloc::vec![0.0f32; pixels.len()],
+            alloc::vec![0.0f32; pixels.len()],
+        ];
+        let [ref mut x, ref mut y, ref mut b] = planes;
+        convert_pixels::<V>(pixels, [x, y, b], cube_root);
+        planes.iter().flatten().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn vectors_match_scalar() {
+        let pixels: Vec<[f32; 3]> = (0..1000usize)
+            .map(|i| [0usize, 1, 2].map(|c| ((i * 37 + c * 101) % 1201) as f32 / 200.0f32 - 0.5f32))
+            .collect();
+        for cube_root in [CubeRoot::Exact, CubeRoot::Approximate, CubeRoot::Table] {
+            let scalar = convert_bits::<f32>(&pixels, cube_root);
+            assert_eq!(convert_bits::<f32x8>(&pixels, cube_root), scalar);
+            #[cfg(feature = "portable_simd")]
+            assert_eq!(
+                convert_bits::<core::simd::f32x8>(&pixels, cube_root),
+                scalar
+            );
+        }
+    }
+
+    #[test]
+    fn matches_yuvxyb() {
+        // Includes out of gamut and HDR values, and a partial chunk at the end.
+        let pixels: Vec<[f32; 3]> = (0..1003usize)
+            .map(|i| [0usize, 1, 2].map(|c| ((i * 37 + c * 101) % 1201) as f32 / 200.0f32 - 0.5f32))
+            .collect();
+        let mut planes = [
+            alloc::vec![0.0f32; pixels.len()],
+            alloc::vec![0.0f32; pixels.len()],
+            alloc::vec![0.0f32; pixels.len()],
+        ];
+        let [ref mut x, ref mut y, ref mut b] = planes;
+        linear_rgb_to_positive_xyb(&pixels, [x, y, b], CubeRoot::Exact);
+
+        let xyb = Xyb::from(LinearRgb::new(pixels, 1003, 1).unwrap());
+        for (i, pix) in xyb.data().iter().enumerate() {
+            let expected = [
+                pix[0].mul_add(14.0f32, 0.42f32),
+                pix[1] + 0.01f32,
+                (pix[2] - pix[1]) + 0.55f32,
+            ];
+            // Bitwise, as both round every operation the same way.
+            for (plane, exp) in planes.iter().zip(expected) {
+                assert!(
+                    plane[i].to_bits() == exp.to_bits(),
+                    "Pixel {i}: got {}, expected {exp}",
+                    plane[i]
+                );
+            }
+        }
+    }
+}
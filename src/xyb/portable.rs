@@ -0,0 +1,56 @@
+use core::simd::cmp::{SimdPartialEq, SimdPartialOrd};
+use core::simd::num::{SimdFloat, SimdInt, SimdUint};
+use core::simd::{f32x8, i32x8, Select};
+#[cfg(target_feature = "fma")]
+use std::simd::StdFloat;
+
+#[cfg(not(target_feature = "fma"))]
+use super::fused_mul_add;
+use super::{Lanes, CBRT_B1};
+
+impl Lanes for f32x8 {
+    const LANES: usize = 8;
+
+    #[inline]
+    fn splat(value: f32) -> Self {
+        f32x8::splat(value)
+    }
+
+    #[inline]
+    fn load(values: &[f32]) -> Self {
+        f32x8::from_slice(values)
+    }
+
+    #[inline]
+    fn store(self, out: &mut [f32]) {
+        self.copy_to_slice(out);
+    }
+
+    #[inline]
+    fn mul_add(self, m: Self, a: Self) -> Self {
+        #[cfg(target_feature = "fma")]
+        return StdFloat::mul_add(self, m, a);
+        #[cfg(not(target_feature = "fma"))]
+        return f32x8::from_array(fused_mul_add(self.to_array(), m.to_array(), a.to_array()));
+    }
+
+    #[inline]
+    fn clamp_negative(self) -> Self {
+        let zero = f32x8::splat(0.0f32);
+        self.simd_lt(zero).select(zero, self)
+    }
+
+    #[inline]
+    fn zero_where_zero(self, value: Self) -> Self {
+        let zero = f32x8::splat(0.0f32);
+        self.simd_eq(zero).select(zero, value)
+    }
+
+    #[inline]
+    fn cbrt_guess(self) -> Self {
+        let bits: i32x8 = self.to_bits().cast();
+        let third = f32x8::splat(1.0f32 / 3.0f32);
+        let guess = (bits.cast::<f32>() * third).cast::<i32>() + i32x8::splat(CBRT_B1);
+        f32x8::from_bits(guess.cast())
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::mem;
+
+use yuvxyb::{
+    ColorPrimaries, Frame, MatrixCoefficients, Pixel, Plane, TransferCharacteristic, YuvConfig,
+};
+
+use crate::FrameSource;
+
+/// Reads the frames of a Y4M file, see [`FrameSource`].
+///
+/// The chroma subsampling and bit depth are taken from the header, and the
+/// range from its `XCOLORRANGE` parameter. Y4M does not signal the color
+/// space, so the matrix, transfer and primaries are unspecified, which
+/// [`LinearRgb`][crate::LinearRgb] conversion guesses from the resolution.
+/// Monochrome files get neutral chroma planes.
+///
+/// Frames are read as samples of type `T`. `u16` accepts files of any bit
+/// depth, while `u8` only accepts 8-bit files but needs half the memory.
+pub struct Y4mSource<R: Read, T> {
+    decoder: y4m::Decoder<R>,
+    config: YuvConfig,
+    mono: bool,
+    pixel: PhantomData<T>,
+}
+
+impl<R: Read, T> fmt::Debug for Y4mSource<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Y4mSource")
+            .field("width", &self.decoder.get_width())
+            .field("height", &self.decoder.get_height())
+            .field("config", &self.config)
+            .field("mono", &self.mono)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: Read, T: Pixel> Y4mSource<R, T> {
+    /// Reads the header of a Y4M file.
+    ///
+    /// # Errors
+    /// - If the header cannot be read or parsed
+    /// - With [`y4m::Error::BadInput`] if the samples don't fit into `T`
+    pub fn new(reader: R) -> Result<Self, y4m::Error> {
+        let decoder = y4m::Decoder::new(reader)?;
+        if decoder.get_bytes_per_sample() > mem::size_of::<T>() {
+            return Err(y4m::Error::BadInput);
+        }
+
+        let (subsampling_x, subsampling_y) = match decoder.get_colorspace() {
+            y4m::Colorspace::C444 | y4m::Colorspace::C444p10 | y4m::Colorspace::C444p12 => (0, 0),
+            y4m::Colorspace::C422 | y4m::Colorspace::C422p10 | y4m::Colorspace::C422p12 => (1, 0),
+            _ => (1, 1),
+        };
+        let mono = matches!(
+            decoder.get_colorspace(),
+            y4m::Colorspace::Cmono | y4m::Colorspace::Cmono12
+        );
+        let full_range = decoder
+            .get_raw_params()
+            .split(|&b| b == b' ')
+            .any(|param| param.eq_ignore_ascii_case(b"XCOLORRANGE=FULL"));
+        let config = YuvConfig {
+            bit_depth: decoder.get_bit_depth() as u8,
+            subsampling_x,
+            subsampling_y,
+            full_range,
+            matrix_coefficients: MatrixCoefficients::Unspecified,
+            transfer_characteristics: TransferCharacteristic::Unspecified,
+            color_primaries: ColorPrimaries::Unspecified,
+        };
+
+        Ok(Y4mSource {
+            decoder,
+            config,
+            mono,
+            pixel: PhantomData,
+        })
+    }
+
+    /// The parameters of the header, e.g. to read vendor extensions or the
+    /// field order of interlaced video.
+    #[must_use]
+    pub fn raw_params(&self) -> &[u8] {
+        self.decoder.get_raw_params()
+    }
+
+    fn chroma_dimensions(&self) -> (usize, usize) {
+        let ss_x = usize::from(self.config.subsampling_x);
+        let ss_y = usize::from(self.config.subsampling_y);
+        (
+            (self.width() + ss_x) >> ss_x,
+            (self.height() + ss_y) >> ss_y,
+        )
+    }
+}
+
+impl<R: Read, T: Pixel> FrameSource for Y4mSource<R, T> {
+    type Pixel = T;
+    type Error = y4m::Error;
+
+    fn width(&self) -> usize {
+        self.decoder.get_width()
+    }
+
+    fn height(&self) -> usize {
+        self.decoder.get_height()
+    }
+
+    fn config(&self) -> YuvConfig {
+        self.config
+    }
+
+    fn frame_rate(&self) -> Option<f64> {
+        let rate = self.decoder.get_framerate();
+        (rate.num > 0 && rate.den > 0).then(|| rate.num as f64 / rate.den as f64)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame<T>>, y4m::Error> {
+        let (width, height) = (self.width(), self.height());
+        let (chroma_width, chroma_height) = self.chroma_dimensions();
+        let (ss_x, ss_y) = (
+            usize::from(self.config.subsampling_x),
+            usize::from(self.config.subsampling_y),
+        );
+        let (bit_depth, mono) = (self.config.bit_depth, self.mono);
+        let bytes = self.decoder.get_bytes_per_sample();
+
+        let frame = match self.decoder.read_frame() {
+            Ok(frame) => frame,
+            Err(y4m::Error::EOF) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut planes = [
+            Plane::new(width, height, 0, 0, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+            Plane::new(chroma_width, chroma_height, ss_x, ss_y, 0, 0),
+        ];
+        planes[0].copy_from_raw_u8(frame.get_y_plane(), width * bytes, bytes);
+        if mono {
+            let neutral = T::cast_from(1u16 << (bit_depth - 1));
+            planes[1].data_origin_mut().fill(neutral);
+            planes[2].data_origin_mut().fill(neutral);
+        } else {
+            planes[1].copy_from_raw_u8(frame.get_u_plane(), chroma_width * bytes, bytes);
+            planes[2].copy_from_raw_u8(frame.get_v_plane(), chroma_width * bytes, bytes);
+        }
+
+        Ok(Some(Frame { planes }))
+    }
+
+    fn skip_frame(&mut self) -> Result<bool, y4m::Error> {
+        match self.decoder.read_frame() {
+            Ok(_) => Ok(true),
+            Err(y4m::Error::EOF) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_frame_ssimulacra2, Yuv};
+
+    // Encodes frames of the given size as 8-bit 4:2:0 Y4M.
+    fn encode(width: usize, height: usize, frames: &[(u8, u8)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = y4m::encode(width, height, y4m::Ratio::new(25, 1))
+            .with_colorspace(y4m::Colorspace::C420jpeg)
+            .write_header(&mut data)
+            .unwrap();
+        for &(luma, chroma) in frames {
+            let y: Vec<u8> = (0..width * height)
+                .map(|i| luma.wrapping_add((i % width) as u8))
+                .collect();
+            let uv = vec![chroma; (width / 2) * (height / 2)];
+            encoder
+                .write_frame(&y4m::Frame::new([&y, &uv, &uv], None))
+                .unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn reads_frames() {
+        let data = encode(64, 48, &[(16, 128), (32, 100), (48, 140)]);
+        let mut source = Y4mSource::<_, u8>::new(data.as_slice()).unwrap();
+        assert_eq!((source.width(), source.height()), (64, 48));
+        assert_eq!(source.frame_rate(), Some(25.0f64));
+        assert_eq!(source.config().bit_depth, 8);
+        assert_eq!(
+            (source.config().subsampling_x, source.config().subsampling_y),
+            (1, 1)
+        );
+
+        let first = source.next_frame().unwrap().unwrap();
+        assert_eq!(first.planes[0].p(3, 2), 19);
+        assert_eq!(first.planes[1].p(0, 0), 128);
+        assert!(source.skip_frame().unwrap());
+        let frames: Vec<_> = source.frames().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].planes[2].p(5, 5), 140);
+        assert!(source.next_frame().unwrap().is_none());
+
+        let config = Y4mSource::<_, u8>::new(data.as_slice()).unwrap().config();
+        let score = compute_frame_ssimulacra2(
+            Yuv::new(first, config).unwrap(),
+            Yuv::new(frames[0].clone(), config).unwrap(),
+        )
+        .unwrap();
+        assert!(score < 90.0f64);
+
+        // 8-bit samples can be read as `u16` as well.
+        let mut wide = Y4mSource::<_, u16>::new(data.as_slice()).unwrap();
+        assert_eq!(wide.next_frame().unwrap().unwrap().planes[0].p(3, 2), 19);
+    }
+
+    #[test]
+    fn rejects_narrow_samples() {
+        let mut data = Vec::new();
+        y4m::encode(16, 16, y4m::Ratio::new(25, 1))
+            .with_colorspace(y4m::Colorspace::C420p10)
+            .write_header(&mut data)
+            .unwrap();
+        assert!(matches!(
+            Y4mSource::<_, u8>::new(data.as_slice()),
+            Err(y4m::Error::BadInput)
+        ));
+        let source = Y4mSource::<_, u16>::new(data.as_slice()).unwrap();
+        assert_eq!(source.config().bit_depth, 10);
+    }
+}
@@ -0,0 +1,146 @@
+//! Checks that scores and intermediate planes are bit-exact to the golden
+//! vectors in `test_data/golden`, which `examples/golden.rs` regenerates.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ssimulacra2::{ColorPrimaries, Rgb, Ssimulacra2, TransferCharacteristic};
+
+// Maximum difference of the scores on other targets than the one the vectors
+// were generated on, where e.g. fused multiply-adds round differently.
+const TOLERANCE: f64 = 0.001;
+
+fn test_data() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data")
+}
+
+fn read_image(path: &Path) -> Rgb {
+    let img = image::open(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let data = img
+        .to_rgb32f()
+        .chunks_exact(3)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+        .collect();
+    Rgb::new(
+        data,
+        img.width() as usize,
+        img.height() as usize,
+        TransferCharacteristic::SRGB,
+        ColorPrimaries::BT709,
+    )
+    .unwrap()
+}
+
+// The lines of a golden file, and whether it was generated on this target.
+fn read_golden(name: &str) -> (Vec<String>, bool) {
+    let golden = fs::read_to_string(test_data().join("golden").join(name)).unwrap();
+    let target = format!(
+        "# target: {}, fma: {}",
+        std::env::consts::ARCH,
+        cfg!(target_feature = "fma")
+    );
+    let exact = golden.lines().any(|line| line == target);
+    let lines = golden
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .skip(1)
+        .map(String::from)
+        .collect();
+    (lines, exact)
+}
+
+fn regenerate_hint(failures: &[String]) -> String {
+    format!(
+        "{}\nIf the change is intentional, regenerate the vectors with \
+         `cargo run --release --example golden --features dump`",
+        failures.join("\n")
+    )
+}
+
+#[test]
+fn matches_golden_scores() {
+    let (lines, exact) = read_golden("scores.csv");
+    let mut failures = Vec::new();
+    for line in &lines {
+        let fields: Vec<&str> = line.split(',').collect();
+        let [source, distorted, expected, scales] = fields[..] else {
+            panic!("Invalid line in scores.csv: {line}");
+        };
+        let expected: f64 = expected.parse().unwrap();
+        let expected_scales: Vec<f64> = scales
+            .split(' ')
+            .map(|error| error.parse().unwrap())
+            .collect();
+
+        let img1 = read_image(&test_data().join(source));
+        let img2 = read_image(&test_data().join(distorted));
+        let detailed = Ssimulacra2::new(img1.width(), img1.height())
+            .unwrap()
+            .score_detailed(img1, img2)
+            .unwrap();
+        let matches = if exact {
+            detailed.score.to_bits() == expected.to_bits() && detailed.scales == expected_scales
+        } else {
+            (detailed.score - expected).abs() <= TOLERANCE
+        };
+        if !matches {
+            failures.push(format!(
+                "{source} vs {distorted}: {}, expected {expected}",
+                detailed.score
+            ));
+        }
+    }
+
+    assert!(!lines.is_empty());
+    assert!(failures.is_empty(), "{}", regenerate_hint(&failures));
+}
+
+#[cfg(feature = "dump")]
+#[test]
+fn matches_golden_planes() {
+    let (lines, exact) = read_golden("planes.csv");
+    if !exact {
+        // The checksums only hold on the target they were generated on.
+        return;
+    }
+
+    let mut expected = std::collections::BTreeMap::new();
+    for line in &lines {
+        let fields: Vec<&str> = line.split(',').collect();
+        let [source, distorted, scale, plane, checksum] = fields[..] else {
+            panic!("Invalid line in planes.csv: {line}");
+        };
+        expected
+            .entry((source.to_string(), distorted.to_string()))
+            .or_insert_with(Vec::new)
+            .push(format!("{scale},{plane},{checksum}"));
+    }
+
+    let mut failures = Vec::new();
+    for ((source, distorted), expected) in &expected {
+        let checksums: Vec<String> = ssimulacra2::plane_checksums(
+            read_image(&test_data().join(source)),
+            read_image(&test_data().join(distorted)),
+        )
+        .unwrap()
+        .iter()
+        .map(|plane| format!("{},{},{:016x}", plane.scale, plane.plane, plane.checksum))
+        .collect();
+        for (actual, expected) in checksums.iter().zip(expected) {
+            if actual != expected {
+                failures.push(format!(
+                    "{source} vs {distorted}: {actual}, expected {expected}"
+                ));
+            }
+        }
+        if checksums.len() != expected.len() {
+            failures.push(format!(
+                "{source} vs {distorted}: {} planes, expected {}",
+                checksums.len(),
+                expected.len()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", regenerate_hint(&failures));
+}
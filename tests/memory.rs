@@ -0,0 +1,70 @@
+//! Checks the memory reported by `required_memory` against the allocations
+//! actually made while scoring.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ssimulacra2::{LinearRgb, Ssimulacra2};
+
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+// SAFETY: Forwards to the system allocator, only counting the bytes.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout);
+    }
+}
+
+// Only `make_image` is used here.
+#[allow(dead_code)]
+#[path = "../src/test_images.rs"]
+mod test_images;
+
+use test_images::make_image;
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn required_memory() {
+    // Initializes the global thread pool, if any.
+    Ssimulacra2::new(16, 16)
+        .unwrap()
+        .score(make_image(16, 16, 3), make_image(16, 16, 5))
+        .unwrap();
+
+    for (width, height) in [(256usize, 192usize), (331, 97)] {
+        let baseline = CURRENT.load(Ordering::SeqCst);
+        PEAK.store(baseline, Ordering::SeqCst);
+
+        let mut session = Ssimulacra2::preallocated(width, height).unwrap();
+        for _ in 0..2 {
+            session
+                .score(make_image(width, height, 3), make_image(width, height, 5))
+                .unwrap();
+        }
+        drop(session);
+
+        let peak = PEAK.load(Ordering::SeqCst) - baseline;
+        let required = Ssimulacra2::required_memory(width, height);
+        // Small bookkeeping allocations, e.g. of the pool, are not counted.
+        assert!(
+            peak <= required + 16 * 1024,
+            "{width}x{height}: {peak} > {required}"
+        );
+        assert!(
+            peak * 100 >= required * 95,
+            "{width}x{height}: {peak} < {required}"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Property tests of invariants every correct implementation of the metric
+//! has to satisfy, independent of the exact scores.
+
+use proptest::prelude::*;
+use ssimulacra2::{compute_frame_ssimulacra2, LinearRgb};
+
+// Random images of 8x8 to 40x40 pixels, with values in `range`.
+fn image(range: core::ops::Range<f32>) -> impl Strategy<Value = LinearRgb> {
+    (8usize..=40, 8usize..=40).prop_flat_map(move |(width, height)| {
+        prop::collection::vec(prop::array::uniform3(range.clone()), width * height)
+            .prop_map(move |data| LinearRgb::new(data, width, height).unwrap())
+    })
+}
+
+fn score(source: &LinearRgb, distorted: &LinearRgb) -> f64 {
+    compute_frame_ssimulacra2(source.clone(), distorted.clone()).unwrap()
+}
+
+fn map_pixels(img: &LinearRgb, f: impl Fn(usize, [f32; 3]) -> [f32; 3]) -> LinearRgb {
+    let data = img
+        .data()
+        .iter()
+        .enumerate()
+        .map(|(i, &pix)| f(i, pix))
+        .collect();
+    LinearRgb::new(data, img.width(), img.height()).unwrap()
+}
+
+fn flip_horizontally(img: &LinearRgb) -> LinearRgb {
+    let width = img.width();
+    let data = img
+        .data()
+        .chunks_exact(width)
+        .flat_map(|row| row.iter().rev().copied())
+        .collect();
+    LinearRgb::new(data, width, img.height()).unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn identical_images_score_100(img in image(0.0..1.0)) {
+        prop_assert!((score(&img, &img) - 100.0).abs() < 1e-9);
+    }
+
+    // The blur runs in one direction only, so mirrored images do not score
+    // exactly the same, but the difference is small compared to the distance
+    // of the scores from 100. It grows with the distortion, reaching about a
+    // fifth of that distance for flat images.
+    #[test]
+    fn mirroring_barely_changes_scores(
+        source in image(0.1..0.9),
+        noise in prop::collection::vec(-0.1f32..0.1, 40 * 40),
+    ) {
+        let distorted = map_pixels(&source, |i, pix| pix.map(|v| v + noise[i]));
+        let score = score(&source, &distorted);
+        let mirrored = self::score(&flip_horizontally(&source), &flip_horizontally(&distorted));
+        prop_assert!(
+            (score - mirrored).abs() < 0.3 * (100.0 - score.min(mirrored)),
+            "Score {score}, mirrored {mirrored}"
+        );
+    }
+
+    #[test]
+    fn more_noise_scores_lower(
+        source in image(0.25..0.75),
+        noise in prop::collection::vec(prop::array::uniform3(-1.0f32..1.0), 40 * 40),
+        amplitude in 0.01f32..0.1,
+        factor in 1.5f32..3.0,
+    ) {
+        let noisy = |amplitude: f32| {
+            map_pixels(&source, |i, pix| {
+                [0usize, 1, 2].map(|c| noise[i][c].mul_add(amplitude, pix[c]))
+            })
+        };
+        let less = score(&source, &noisy(amplitude));
+        let more = score(&source, &noisy(amplitude * factor));
+        prop_assert!(more < less, "Score {more} with more noise, {less} with less");
+    }
+}
+
+#[cfg(feature = "v_frame")]
+mod frames {
+    use proptest::prelude::*;
+    use ssimulacra2::{compute_frame_ssimulacra2, Frame, FrameRef, Plane};
+
+    fn make_frame(width: usize, height: usize, padding: usize, values: &[[u8; 3]]) -> Frame<u8> {
+        let mut frame = Frame {
+            planes: [
+                Plane::new(width, height, 0, 0, padding, padding),
+                Plane::new(width / 2, height / 2, 1, 1, padding / 2, padding / 2),
+                Plane::new(width / 2, height / 2, 1, 1, padding / 2, padding / 2),
+            ],
+        };
+        for (c, plane) in frame.planes.iter_mut().enumerate() {
+            let plane_width = plane.cfg.width;
+            let plane_height = plane.cfg.height;
+            let stride = plane.cfg.stride;
+            let origin = plane.data_origin_mut();
+            // Garbage in the padding must not leak into the score.
+            origin.fill(255);
+            for y in 0..plane_height {
+                for x in 0..plane_width {
+                    origin[y * stride + x] = values[y * width + x][c];
+                }
+            }
+        }
+        frame
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn padding_does_not_change_scores(
+            half_width in 4usize..=20,
+            half_height in 4usize..=20,
+            padding in 1usize..=16,
+            values in prop::collection::vec(prop::array::uniform3(16u8..=235), 2 * 40 * 40),
+        ) {
+            let (width, height) = (half_width * 2, half_height * 2);
+            let (source, distorted) = values.split_at(40 * 40);
+            let score = |padding| {
+                let source = make_frame(width, height, padding, source);
+                let distorted = make_frame(width, height, padding, distorted);
+                compute_frame_ssimulacra2(FrameRef::from(&source), FrameRef::from(&distorted))
+                    .unwrap()
+            };
+            prop_assert_eq!(score(0).to_bits(), score(padding).to_bits());
+        }
+    }
+}